@@ -12,12 +12,35 @@ fn cfg_os(family: &str) -> bool {
 
 fn find_avahi_compat_dns_sd() {
 	// on unix but not darwin link avahi compat
+	println!("cargo:rustc-check-cfg=cfg(avahi_compat)");
 	if cfg_family("unix")
 	&& !(cfg_os("macos") || cfg_os("ios")) {
 		pkg_config::probe_library("avahi-compat-libdns_sd").unwrap();
+		// avahi compat doesn't provide newer parts of the API
+		println!("cargo:rustc-cfg=avahi_compat");
+	}
+}
+
+fn find_dbus() {
+	// the avahi D-Bus backend talks to the daemon through libdbus
+	if var_os("CARGO_FEATURE_AVAHI_DBUS").is_some()
+	&& cfg_family("unix")
+	&& !(cfg_os("macos") || cfg_os("ios"))
+	&& pkg_config::probe_library("dbus-1").is_err() {
+		println!("cargo:rustc-link-lib=dbus-1");
+	}
+}
+
+fn find_dnsapi() {
+	// DNS-SD API of the windows DNS client
+	if var_os("CARGO_FEATURE_WINDOWS_DNSAPI").is_some()
+	&& cfg_family("windows") {
+		println!("cargo:rustc-link-lib=dnsapi");
 	}
 }
 
 fn main() {
 	find_avahi_compat_dns_sd();
+	find_dbus();
+	find_dnsapi();
 }
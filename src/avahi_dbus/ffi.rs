@@ -0,0 +1,145 @@
+// subset of the libdbus-1 API
+
+use std::os::raw::{c_char,c_int,c_uint,c_void};
+
+// type without an instance
+pub enum DBusConnection{}
+
+// type without an instance
+pub enum DBusMessage{}
+
+pub type DBusBool = u32;
+
+pub type DBusBusType = c_int;
+pub const BUS_SYSTEM : DBusBusType = 1;
+
+pub const TIMEOUT_USE_DEFAULT : c_int = -1;
+
+pub const TYPE_INVALID : c_int = 0;
+pub const TYPE_BYTE : c_int = b'y' as c_int;
+pub const TYPE_UINT16 : c_int = b'q' as c_int;
+pub const TYPE_INT32 : c_int = b'i' as c_int;
+pub const TYPE_UINT32 : c_int = b'u' as c_int;
+pub const TYPE_STRING : c_int = b's' as c_int;
+pub const TYPE_OBJECT_PATH : c_int = b'o' as c_int;
+pub const TYPE_ARRAY : c_int = b'a' as c_int;
+
+#[repr(C)]
+pub struct DBusError {
+	pub name: *const c_char,
+	pub message: *const c_char,
+	// dummy1 - dummy5 bitfields
+	dummy: c_uint,
+	padding1: *mut c_void,
+}
+
+#[repr(C)]
+pub struct DBusMessageIter {
+	dummy1: *mut c_void,
+	dummy2: *mut c_void,
+	dummy3: u32,
+	dummy4: c_int,
+	dummy5: c_int,
+	dummy6: c_int,
+	dummy7: c_int,
+	dummy8: c_int,
+	dummy9: c_int,
+	dummy10: c_int,
+	dummy11: c_int,
+	pad1: c_int,
+	pad2: *mut c_void,
+	pad3: *mut c_void,
+}
+
+extern "C" {
+	pub fn dbus_threads_init_default() -> DBusBool;
+
+	pub fn dbus_error_init(error: *mut DBusError);
+	pub fn dbus_error_free(error: *mut DBusError);
+	pub fn dbus_error_is_set(error: *const DBusError) -> DBusBool;
+
+	pub fn dbus_bus_get_private(
+		bus_type: DBusBusType,
+		error: *mut DBusError
+	) -> *mut DBusConnection;
+	pub fn dbus_bus_add_match(
+		connection: *mut DBusConnection,
+		rule: *const c_char,
+		error: *mut DBusError
+	);
+	pub fn dbus_bus_name_has_owner(
+		connection: *mut DBusConnection,
+		name: *const c_char,
+		error: *mut DBusError
+	) -> DBusBool;
+
+	pub fn dbus_connection_set_exit_on_disconnect(
+		connection: *mut DBusConnection,
+		exit_on_disconnect: DBusBool
+	);
+	pub fn dbus_connection_close(connection: *mut DBusConnection);
+	pub fn dbus_connection_unref(connection: *mut DBusConnection);
+	pub fn dbus_connection_get_unix_fd(
+		connection: *mut DBusConnection,
+		fd: *mut c_int
+	) -> DBusBool;
+	pub fn dbus_connection_read_write(
+		connection: *mut DBusConnection,
+		timeout_milliseconds: c_int
+	) -> DBusBool;
+	pub fn dbus_connection_pop_message(
+		connection: *mut DBusConnection
+	) -> *mut DBusMessage;
+	pub fn dbus_connection_send_with_reply_and_block(
+		connection: *mut DBusConnection,
+		message: *mut DBusMessage,
+		timeout_milliseconds: c_int,
+		error: *mut DBusError
+	) -> *mut DBusMessage;
+
+	pub fn dbus_message_new_method_call(
+		destination: *const c_char,
+		path: *const c_char,
+		iface: *const c_char,
+		method: *const c_char
+	) -> *mut DBusMessage;
+	pub fn dbus_message_unref(message: *mut DBusMessage);
+	pub fn dbus_message_is_signal(
+		message: *mut DBusMessage,
+		iface: *const c_char,
+		signal_name: *const c_char
+	) -> DBusBool;
+	pub fn dbus_message_get_path(message: *mut DBusMessage) -> *const c_char;
+
+	pub fn dbus_message_iter_init_append(
+		message: *mut DBusMessage,
+		iter: *mut DBusMessageIter
+	);
+	pub fn dbus_message_iter_append_basic(
+		iter: *mut DBusMessageIter,
+		arg_type: c_int,
+		value: *const c_void
+	) -> DBusBool;
+	pub fn dbus_message_iter_open_container(
+		iter: *mut DBusMessageIter,
+		arg_type: c_int,
+		contained_signature: *const c_char,
+		sub: *mut DBusMessageIter
+	) -> DBusBool;
+	pub fn dbus_message_iter_close_container(
+		iter: *mut DBusMessageIter,
+		sub: *mut DBusMessageIter
+	) -> DBusBool;
+
+	pub fn dbus_message_iter_init(
+		message: *mut DBusMessage,
+		iter: *mut DBusMessageIter
+	) -> DBusBool;
+	pub fn dbus_message_iter_get_arg_type(iter: *mut DBusMessageIter) -> c_int;
+	pub fn dbus_message_iter_get_basic(iter: *mut DBusMessageIter, value: *mut c_void);
+	pub fn dbus_message_iter_next(iter: *mut DBusMessageIter) -> DBusBool;
+	pub fn dbus_message_iter_recurse(
+		iter: *mut DBusMessageIter,
+		sub: *mut DBusMessageIter
+	);
+}
@@ -0,0 +1,513 @@
+//! Talk to avahi through its D-Bus API instead of the compat library
+//!
+//! The compat library ignores many flags and prints a warning for each
+//! program using it; with the `avahi-dbus` feature browsing, resolving,
+//! querying records and registering services use the native API when
+//! the avahi daemon is reachable on the system bus (falling back to the
+//! compat library otherwise).
+//!
+//! Each operation uses its own private bus connection (like each
+//! `DNSServiceRef` has its own connection to the daemon); closing the
+//! connection frees all objects created on the daemon.
+
+mod ffi;
+mod services;
+
+pub use self::services::*;
+
+use std::ffi::{CStr,CString};
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_char,c_int,c_void};
+use std::ptr::null;
+use std::sync::Once;
+use std::task::Context;
+
+use crate::error::Error;
+use crate::evented::EventedFd;
+use crate::ffi::DNSServiceError;
+use crate::interface::Interface;
+
+const AVAHI_SERVICE : &str = "org.freedesktop.Avahi";
+const AVAHI_SERVER : &str = "org.freedesktop.Avahi.Server";
+const DBUS_SERVICE : &str = "org.freedesktop.DBus";
+
+// AVAHI_IF_UNSPEC / AVAHI_PROTO_UNSPEC
+const UNSPEC : i32 = -1;
+
+fn invalid_message() -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::InvalidData, "unexpected D-Bus message"))
+}
+
+// map D-Bus error names (avahi errors are named after the avahi error
+// codes)
+fn error_from_name(name: &str, message: &str) -> Error {
+	let code = match name {
+		"org.freedesktop.Avahi.CollisionError" => DNSServiceError::NameConflict,
+		"org.freedesktop.Avahi.TimeoutError" => DNSServiceError::Timeout,
+		"org.freedesktop.Avahi.NotSupportedError" => DNSServiceError::Unsupported,
+		"org.freedesktop.Avahi.NoMemoryError" => DNSServiceError::NoMemory,
+		"org.freedesktop.Avahi.NotFoundError" => DNSServiceError::NoSuchName,
+		"org.freedesktop.Avahi.AccessDeniedError"
+		| "org.freedesktop.Avahi.NotPermittedError" => DNSServiceError::NoAuth,
+		"org.freedesktop.Avahi.InvalidInterfaceIndexError" => DNSServiceError::BadInterfaceIndex,
+		"org.freedesktop.Avahi.InvalidFlagsError" => DNSServiceError::BadFlags,
+		"org.freedesktop.DBus.Error.ServiceUnknown"
+		| "org.freedesktop.DBus.Error.NameHasNoOwner" => DNSServiceError::ServiceNotRunning,
+		"org.freedesktop.DBus.Error.NoReply"
+		| "org.freedesktop.DBus.Error.Disconnected" => DNSServiceError::DefunctConnection,
+		_ if name.starts_with("org.freedesktop.Avahi.Invalid") => DNSServiceError::BadParam,
+		_ => return Error::IoError(io::Error::other(format!("{}: {}", name, message))),
+	};
+	Error::KnownError(code)
+}
+
+// map messages of `Failure` signals (only carry the avahi_strerror text)
+fn error_from_failure(message: &str) -> Error {
+	let code = match message {
+		"Timeout reached" => DNSServiceError::Timeout,
+		"Local name collision" => DNSServiceError::NameConflict,
+		"Not found" => DNSServiceError::NoSuchName,
+		_ => return Error::IoError(io::Error::other(message.to_string())),
+	};
+	Error::KnownError(code)
+}
+
+// interface index for avahi; `None` for interfaces only the compat
+// library can handle
+fn avahi_interface(interface: Interface) -> Option<i32> {
+	match interface {
+		Interface::Any => Some(UNSPEC),
+		Interface::Index(ndx) => Some(ndx.into_raw() as i32),
+		_ => None,
+	}
+}
+
+fn from_avahi_interface(interface: i32) -> Interface {
+	if interface < 0 {
+		Interface::Any
+	} else {
+		Interface::from_raw(interface as u32)
+	}
+}
+
+struct DBusError(ffi::DBusError);
+
+impl DBusError {
+	fn new() -> Self {
+		let mut error: ffi::DBusError = unsafe { mem::zeroed() };
+		unsafe { ffi::dbus_error_init(&mut error) };
+		DBusError(error)
+	}
+
+	fn is_set(&self) -> bool {
+		0 != unsafe { ffi::dbus_error_is_set(&self.0) }
+	}
+
+	fn to_error(&self) -> Error {
+		let text = |s: *const c_char| if s.is_null() {
+			String::new()
+		} else {
+			unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+		};
+		error_from_name(&text(self.0.name), &text(self.0.message))
+	}
+}
+
+impl Drop for DBusError {
+	fn drop(&mut self) {
+		unsafe { ffi::dbus_error_free(&mut self.0) };
+	}
+}
+
+fn cstring(s: &str) -> Result<CString, Error> {
+	CString::new(s).map_err(|e| Error::IoError(io::Error::new(io::ErrorKind::InvalidInput, e)))
+}
+
+/// Private connection to the system bus
+pub struct Connection(*mut ffi::DBusConnection);
+
+// libdbus is initialized for threads; the connection is private
+unsafe impl Send for Connection {}
+
+impl Connection {
+	fn system() -> Result<Self, Error> {
+		static INIT: Once = Once::new();
+		INIT.call_once(|| unsafe { ffi::dbus_threads_init_default(); });
+
+		let mut error = DBusError::new();
+		let connection = unsafe { ffi::dbus_bus_get_private(ffi::BUS_SYSTEM, &mut error.0) };
+		if connection.is_null() {
+			return Err(error.to_error());
+		}
+		// libdbus calls `_exit` by default when bus connections break
+		unsafe { ffi::dbus_connection_set_exit_on_disconnect(connection, 0) };
+		Ok(Connection(connection))
+	}
+
+	fn has_owner(&self, name: &str) -> Result<bool, Error> {
+		let name = cstring(name)?;
+		let mut error = DBusError::new();
+		let result = unsafe { ffi::dbus_bus_name_has_owner(self.0, name.as_ptr(), &mut error.0) };
+		if error.is_set() {
+			return Err(error.to_error());
+		}
+		Ok(0 != result)
+	}
+
+	fn add_match(&self, rule: &str) -> Result<(), Error> {
+		let rule = cstring(rule)?;
+		let mut error = DBusError::new();
+		unsafe { ffi::dbus_bus_add_match(self.0, rule.as_ptr(), &mut error.0) };
+		if error.is_set() {
+			return Err(error.to_error());
+		}
+		Ok(())
+	}
+
+	/// Call method and wait for the reply
+	fn call(&self, message: Message) -> Result<Message, Error> {
+		let mut error = DBusError::new();
+		let reply = unsafe { ffi::dbus_connection_send_with_reply_and_block(
+			self.0,
+			message.0,
+			ffi::TIMEOUT_USE_DEFAULT,
+			&mut error.0
+		)};
+		if reply.is_null() {
+			return Err(error.to_error());
+		}
+		Ok(Message(reply))
+	}
+
+	fn fd(&self) -> io::Result<c_int> {
+		let mut fd: c_int = -1;
+		if 0 == unsafe { ffi::dbus_connection_get_unix_fd(self.0, &mut fd) } {
+			return Err(io::Error::new(io::ErrorKind::NotConnected, "D-Bus connection has no fd"));
+		}
+		Ok(fd)
+	}
+
+	// read available data without blocking
+	fn read(&self) -> io::Result<()> {
+		if 0 == unsafe { ffi::dbus_connection_read_write(self.0, 0) } {
+			return Err(Error::KnownError(DNSServiceError::DefunctConnection).into());
+		}
+		Ok(())
+	}
+
+	fn pop_message(&self) -> Option<Message> {
+		let message = unsafe { ffi::dbus_connection_pop_message(self.0) };
+		if message.is_null() {
+			None
+		} else {
+			Some(Message(message))
+		}
+	}
+}
+
+impl Drop for Connection {
+	fn drop(&mut self) {
+		unsafe {
+			ffi::dbus_connection_close(self.0);
+			ffi::dbus_connection_unref(self.0);
+		}
+	}
+}
+
+/// Connect to the system bus if avahi is running
+fn connect() -> Option<Connection> {
+	let connection = Connection::system().ok()?;
+	if !connection.has_owner(AVAHI_SERVICE).ok()? {
+		return None;
+	}
+	// avahi sends signals to the client which created the object, but
+	// older versions broadcast them
+	connection.add_match(&format!("type='signal',sender='{}'", AVAHI_SERVICE)).ok()?;
+	// notice when the avahi daemon goes away
+	connection.add_match(&format!(
+		"type='signal',sender='{}',member='NameOwnerChanged',arg0='{}'",
+		DBUS_SERVICE,
+		AVAHI_SERVICE
+	)).ok()?;
+	Some(connection)
+}
+
+/// D-Bus message
+pub struct Message(*mut ffi::DBusMessage);
+
+impl Message {
+	/// Method call to the avahi daemon
+	fn method_call(path: &str, interface: &str, method: &str) -> Result<Self, Error> {
+		let destination = cstring(AVAHI_SERVICE)?;
+		let path = cstring(path)?;
+		let interface = cstring(interface)?;
+		let method = cstring(method)?;
+		let message = unsafe { ffi::dbus_message_new_method_call(
+			destination.as_ptr(),
+			path.as_ptr(),
+			interface.as_ptr(),
+			method.as_ptr()
+		)};
+		if message.is_null() {
+			return Err(Error::KnownError(DNSServiceError::NoMemory));
+		}
+		Ok(Message(message))
+	}
+
+	fn server_call(method: &str) -> Result<Self, Error> {
+		Self::method_call("/", AVAHI_SERVER, method)
+	}
+
+	fn is_signal(&self, interface: &str, name: &str) -> bool {
+		let (interface, name) = match (CString::new(interface), CString::new(name)) {
+			(Ok(interface), Ok(name)) => (interface, name),
+			_ => return false,
+		};
+		0 != unsafe { ffi::dbus_message_is_signal(self.0, interface.as_ptr(), name.as_ptr()) }
+	}
+
+	fn path(&self) -> Option<&str> {
+		let path = unsafe { ffi::dbus_message_get_path(self.0) };
+		if path.is_null() {
+			return None;
+		}
+		unsafe { CStr::from_ptr(path) }.to_str().ok()
+	}
+
+	fn append(&mut self) -> Append<'_> {
+		let mut iter: ffi::DBusMessageIter = unsafe { mem::zeroed() };
+		unsafe { ffi::dbus_message_iter_init_append(self.0, &mut iter) };
+		Append {
+			iter,
+			_message: PhantomData,
+		}
+	}
+
+	fn args(&self) -> Args<'_> {
+		let mut iter: ffi::DBusMessageIter = unsafe { mem::zeroed() };
+		let valid = 0 != unsafe { ffi::dbus_message_iter_init(self.0, &mut iter) };
+		Args {
+			iter,
+			valid,
+			_message: PhantomData,
+		}
+	}
+}
+
+impl Drop for Message {
+	fn drop(&mut self) {
+		unsafe { ffi::dbus_message_unref(self.0) };
+	}
+}
+
+/// Append arguments to a message
+struct Append<'a> {
+	iter: ffi::DBusMessageIter,
+	_message: PhantomData<&'a mut Message>,
+}
+
+fn append_basic<T>(iter: &mut ffi::DBusMessageIter, arg_type: c_int, value: &T) -> Result<(), Error> {
+	if 0 == unsafe { ffi::dbus_message_iter_append_basic(iter, arg_type, value as *const T as *const c_void) } {
+		return Err(Error::KnownError(DNSServiceError::NoMemory));
+	}
+	Ok(())
+}
+
+impl<'a> Append<'a> {
+	fn i32(mut self, value: i32) -> Result<Self, Error> {
+		append_basic(&mut self.iter, ffi::TYPE_INT32, &value)?;
+		Ok(self)
+	}
+
+	fn u32(mut self, value: u32) -> Result<Self, Error> {
+		append_basic(&mut self.iter, ffi::TYPE_UINT32, &value)?;
+		Ok(self)
+	}
+
+	fn u16(mut self, value: u16) -> Result<Self, Error> {
+		append_basic(&mut self.iter, ffi::TYPE_UINT16, &value)?;
+		Ok(self)
+	}
+
+	fn str(mut self, value: &str) -> Result<Self, Error> {
+		let value = cstring(value)?;
+		let ptr = value.as_ptr();
+		append_basic(&mut self.iter, ffi::TYPE_STRING, &ptr)?;
+		Ok(self)
+	}
+
+	// "aay"
+	fn byte_arrays(mut self, values: &[&[u8]]) -> Result<Self, Error> {
+		let outer_sig = cstring("ay")?;
+		let inner_sig = cstring("y")?;
+		let mut outer: ffi::DBusMessageIter = unsafe { mem::zeroed() };
+		if 0 == unsafe { ffi::dbus_message_iter_open_container(&mut self.iter, ffi::TYPE_ARRAY, outer_sig.as_ptr(), &mut outer) } {
+			return Err(Error::KnownError(DNSServiceError::NoMemory));
+		}
+		for value in values {
+			let mut inner: ffi::DBusMessageIter = unsafe { mem::zeroed() };
+			if 0 == unsafe { ffi::dbus_message_iter_open_container(&mut outer, ffi::TYPE_ARRAY, inner_sig.as_ptr(), &mut inner) } {
+				return Err(Error::KnownError(DNSServiceError::NoMemory));
+			}
+			for byte in value.iter() {
+				append_basic(&mut inner, ffi::TYPE_BYTE, byte)?;
+			}
+			unsafe { ffi::dbus_message_iter_close_container(&mut outer, &mut inner) };
+		}
+		unsafe { ffi::dbus_message_iter_close_container(&mut self.iter, &mut outer) };
+		Ok(self)
+	}
+}
+
+/// Read arguments from a message
+struct Args<'a> {
+	iter: ffi::DBusMessageIter,
+	valid: bool,
+	_message: PhantomData<&'a Message>,
+}
+
+fn get_basic<T: Default>(iter: &mut ffi::DBusMessageIter, arg_type: c_int) -> Result<T, Error> {
+	if arg_type != unsafe { ffi::dbus_message_iter_get_arg_type(iter) } {
+		return Err(invalid_message());
+	}
+	let mut value = T::default();
+	unsafe {
+		ffi::dbus_message_iter_get_basic(iter, &mut value as *mut T as *mut c_void);
+		ffi::dbus_message_iter_next(iter);
+	}
+	Ok(value)
+}
+
+// array of bytes ("ay")
+fn get_bytes(iter: &mut ffi::DBusMessageIter) -> Result<Vec<u8>, Error> {
+	if ffi::TYPE_ARRAY != unsafe { ffi::dbus_message_iter_get_arg_type(iter) } {
+		return Err(invalid_message());
+	}
+	let mut sub: ffi::DBusMessageIter = unsafe { mem::zeroed() };
+	unsafe { ffi::dbus_message_iter_recurse(iter, &mut sub) };
+	let mut result = Vec::new();
+	while ffi::TYPE_INVALID != unsafe { ffi::dbus_message_iter_get_arg_type(&mut sub) } {
+		result.push(get_basic::<u8>(&mut sub, ffi::TYPE_BYTE)?);
+	}
+	unsafe { ffi::dbus_message_iter_next(iter) };
+	Ok(result)
+}
+
+impl<'a> Args<'a> {
+	fn check(&self) -> Result<(), Error> {
+		if !self.valid {
+			return Err(invalid_message());
+		}
+		Ok(())
+	}
+
+	fn i32(&mut self) -> Result<i32, Error> {
+		self.check()?;
+		get_basic(&mut self.iter, ffi::TYPE_INT32)
+	}
+
+	fn u16(&mut self) -> Result<u16, Error> {
+		self.check()?;
+		get_basic(&mut self.iter, ffi::TYPE_UINT16)
+	}
+
+	fn string_of_type(&mut self, arg_type: c_int) -> Result<String, Error> {
+		self.check()?;
+		let ptr: *const c_char = get_basic_ptr(&mut self.iter, arg_type)?;
+		unsafe { CStr::from_ptr(ptr) }.to_str().map(str::to_string).map_err(|_| invalid_message())
+	}
+
+	fn string(&mut self) -> Result<String, Error> {
+		self.string_of_type(ffi::TYPE_STRING)
+	}
+
+	fn object_path(&mut self) -> Result<String, Error> {
+		self.string_of_type(ffi::TYPE_OBJECT_PATH)
+	}
+
+	fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+		self.check()?;
+		get_bytes(&mut self.iter)
+	}
+
+	// "aay"
+	fn byte_arrays(&mut self) -> Result<Vec<Vec<u8>>, Error> {
+		self.check()?;
+		if ffi::TYPE_ARRAY != unsafe { ffi::dbus_message_iter_get_arg_type(&mut self.iter) } {
+			return Err(invalid_message());
+		}
+		let mut sub: ffi::DBusMessageIter = unsafe { mem::zeroed() };
+		unsafe { ffi::dbus_message_iter_recurse(&mut self.iter, &mut sub) };
+		let mut result = Vec::new();
+		while ffi::TYPE_INVALID != unsafe { ffi::dbus_message_iter_get_arg_type(&mut sub) } {
+			result.push(get_bytes(&mut sub)?);
+		}
+		unsafe { ffi::dbus_message_iter_next(&mut self.iter) };
+		Ok(result)
+	}
+}
+
+// strings are returned as pointers into the message
+fn get_basic_ptr(iter: &mut ffi::DBusMessageIter, arg_type: c_int) -> Result<*const c_char, Error> {
+	if arg_type != unsafe { ffi::dbus_message_iter_get_arg_type(iter) } {
+		return Err(invalid_message());
+	}
+	let mut value: *const c_char = null();
+	unsafe {
+		ffi::dbus_message_iter_get_basic(iter, &mut value as *mut *const c_char as *mut c_void);
+		ffi::dbus_message_iter_next(iter);
+	}
+	Ok(value)
+}
+
+/// Handles signals for an avahi object
+trait Handler: Send {
+	/// Object path of the avahi object
+	fn path(&self) -> &str;
+
+	/// Handle signal for the object
+	fn handle(&mut self, connection: &Connection, message: &Message);
+
+	/// Report errors not related to a single signal
+	fn fail(&mut self, error: Error);
+}
+
+/// Running avahi operation
+pub struct Operation {
+	// stop watching the fd before the connection gets closed
+	poll: EventedFd,
+	handler: Box<dyn Handler>,
+	connection: Connection,
+}
+
+impl Operation {
+	fn new(connection: Connection, handler: Box<dyn Handler>) -> Result<Self, Error> {
+		Ok(Operation {
+			poll: EventedFd::new(connection.fd()?)?,
+			handler,
+			connection,
+		})
+	}
+
+	pub fn poll(&mut self, cx: &mut Context) -> io::Result<()> {
+		let connection = &self.connection;
+		self.poll.poll(cx, || connection.read())?;
+		// signals might have been queued while waiting for method
+		// replies, so always check for queued messages
+		while let Some(message) = self.connection.pop_message() {
+			if message.is_signal(DBUS_SERVICE, "NameOwnerChanged") {
+				let mut args = message.args();
+				if let (Ok(name), Ok(_old), Ok(new)) = (args.string(), args.string(), args.string()) {
+					if name == AVAHI_SERVICE && new.is_empty() {
+						self.handler.fail(Error::KnownError(DNSServiceError::ServiceNotRunning));
+					}
+				}
+			} else if message.path() == Some(self.handler.path()) {
+				self.handler.handle(&self.connection, &message);
+			}
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,644 @@
+use futures::channel::mpsc;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+
+use super::{
+	avahi_interface,
+	connect,
+	error_from_failure,
+	from_avahi_interface,
+	Connection,
+	Handler,
+	Message,
+	Operation,
+	UNSPEC,
+};
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	subtype_label,
+	BrowsedFlag,
+	BrowsedFlags,
+	BrowseResult,
+	QueriedRecordFlag,
+	QueriedRecordFlags,
+	QueryRecordResult,
+	RegisterResult,
+	ResolveResult,
+};
+use crate::stream::ServiceStream;
+
+const SERVICE_BROWSER : &str = "org.freedesktop.Avahi.ServiceBrowser";
+const SERVICE_RESOLVER : &str = "org.freedesktop.Avahi.ServiceResolver";
+const RECORD_BROWSER : &str = "org.freedesktop.Avahi.RecordBrowser";
+const ENTRY_GROUP : &str = "org.freedesktop.Avahi.EntryGroup";
+
+// AvahiEntryGroupState
+const ENTRY_GROUP_ESTABLISHED : i32 = 2;
+const ENTRY_GROUP_COLLISION : i32 = 3;
+const ENTRY_GROUP_FAILURE : i32 = 4;
+
+type Sender<T> = mpsc::UnboundedSender<Result<T, Error>>;
+
+fn send<T>(sender: &Sender<T>, item: Result<T, Error>) {
+	// the stream might be gone already
+	let _ = sender.unbounded_send(item);
+}
+
+fn absolute(name: &str) -> String {
+	format!("{}.", name.trim_end_matches('.'))
+}
+
+fn escape_label(label: &str) -> String {
+	let mut result = String::new();
+	for c in label.chars() {
+		if c == '.' || c == '\\' {
+			result.push('\\');
+		}
+		result.push(c);
+	}
+	result
+}
+
+// avahi passes TXT records as list of strings
+fn txt_from_avahi(entries: &[Vec<u8>]) -> Vec<u8> {
+	let mut txt = Vec::new();
+	for entry in entries {
+		let len = entry.len().min(255);
+		txt.push(len as u8);
+		txt.extend_from_slice(&entry[..len]);
+	}
+	if txt.is_empty() {
+		txt.push(0);
+	}
+	txt
+}
+
+fn txt_to_avahi(txt: &[u8]) -> Vec<&[u8]> {
+	let mut entries = Vec::new();
+	let mut rem = txt;
+	while let Some((&len, tail)) = rem.split_first() {
+		let len = (len as usize).min(tail.len());
+		if len > 0 {
+			entries.push(&tail[..len]);
+		}
+		rem = &tail[len..];
+	}
+	entries
+}
+
+fn call_for_path(connection: &Connection, message: Message) -> Result<String, Error> {
+	connection.call(message)?.args().object_path()
+}
+
+// results reported separately for IPv4 and IPv6 are only reported once
+struct Presence<K>(HashMap<K, usize>);
+
+impl<K: Hash+Eq> Presence<K> {
+	fn new() -> Self {
+		Presence(HashMap::new())
+	}
+
+	// whether the item is new
+	fn add(&mut self, key: K) -> bool {
+		let count = self.0.entry(key).or_insert(0);
+		*count += 1;
+		*count == 1
+	}
+
+	// whether the item is gone now
+	fn remove(&mut self, key: K) -> bool {
+		match self.0.get_mut(&key) {
+			None => false,
+			Some(count) if *count > 1 => {
+				*count -= 1;
+				false
+			},
+			Some(_) => {
+				self.0.remove(&key);
+				true
+			},
+		}
+	}
+}
+
+// avahi doesn't have `MoreComing`; results until `AllForNow` are
+// collected and sent as a burst instead
+struct Burst<T> {
+	pending: Option<Vec<T>>,
+	set_more_coming: fn(&mut T),
+}
+
+impl<T> Burst<T> {
+	fn new(set_more_coming: fn(&mut T)) -> Self {
+		Burst {
+			pending: Some(Vec::new()),
+			set_more_coming,
+		}
+	}
+
+	fn push(&mut self, sender: &Sender<T>, item: T) {
+		match self.pending.as_mut() {
+			Some(pending) => pending.push(item),
+			None => send(sender, Ok(item)),
+		}
+	}
+
+	fn finish(&mut self, sender: &Sender<T>) {
+		let pending = match self.pending.take() {
+			Some(pending) => pending,
+			None => return,
+		};
+		let len = pending.len();
+		for (ndx, mut item) in pending.into_iter().enumerate() {
+			if ndx + 1 < len {
+				(self.set_more_coming)(&mut item);
+			}
+			send(sender, Ok(item));
+		}
+	}
+}
+
+struct BrowseHandler {
+	path: String,
+	sender: Sender<BrowseResult>,
+	presence: Presence<(i32, String, String, String)>,
+	burst: Burst<BrowseResult>,
+}
+
+impl BrowseHandler {
+	fn item(&mut self, message: &Message, added: bool) -> Result<(), Error> {
+		let mut args = message.args();
+		let interface = args.i32()?;
+		let _protocol = args.i32()?;
+		let name = args.string()?;
+		let reg_type = args.string()?;
+		let domain = args.string()?;
+
+		let key = (interface, name, reg_type, domain);
+		let changed = if added {
+			self.presence.add(key.clone())
+		} else {
+			self.presence.remove(key.clone())
+		};
+		if !changed {
+			return Ok(());
+		}
+		let (interface, name, reg_type, domain) = key;
+		let flags = if added {
+			BrowsedFlags::from(BrowsedFlag::Add)
+		} else {
+			BrowsedFlags::none()
+		};
+		self.burst.push(&self.sender, BrowseResult {
+			flags,
+			interface: from_avahi_interface(interface),
+			service_name: name,
+			reg_type: absolute(&reg_type),
+			domain: absolute(&domain),
+			received_at: crate::Timestamp::now(),
+		});
+		Ok(())
+	}
+}
+
+impl Handler for BrowseHandler {
+	fn path(&self) -> &str {
+		&self.path
+	}
+
+	fn handle(&mut self, _connection: &Connection, message: &Message) {
+		let result = if message.is_signal(SERVICE_BROWSER, "ItemNew") {
+			self.item(message, true)
+		} else if message.is_signal(SERVICE_BROWSER, "ItemRemove") {
+			self.item(message, false)
+		} else if message.is_signal(SERVICE_BROWSER, "AllForNow") {
+			self.burst.finish(&self.sender);
+			Ok(())
+		} else if message.is_signal(SERVICE_BROWSER, "Failure") {
+			message.args().string().and_then(|e| Err(error_from_failure(&e)))
+		} else {
+			Ok(())
+		};
+		if let Err(e) = result {
+			self.fail(e);
+		}
+	}
+
+	fn fail(&mut self, error: Error) {
+		send(&self.sender, Err(error));
+	}
+}
+
+/// Browse through avahi; `None` if avahi isn't available through D-Bus
+pub fn browse(
+	interface: Interface,
+	reg_type: &str,
+	subtype: Option<&str>,
+	domain: Option<&str>
+) -> io::Result<Option<ServiceStream<BrowseResult>>> {
+	let interface = match avahi_interface(interface) {
+		Some(interface) => interface,
+		None => return Ok(None),
+	};
+	let connection = match connect() {
+		Some(connection) => connection,
+		None => return Ok(None),
+	};
+
+	let reg_type = match subtype {
+		Some(subtype) => format!("{}._sub.{}", subtype_label(subtype), reg_type),
+		None => reg_type.to_string(),
+	};
+	let mut message = Message::server_call("ServiceBrowserNew")?;
+	message.append()
+		.i32(interface)?
+		.i32(UNSPEC)?
+		.str(&reg_type)?
+		.str(domain.unwrap_or(""))?
+		.u32(0)?;
+	let path = call_for_path(&connection, message)?;
+
+	Ok(Some(ServiceStream::new_dbus(move |sender| {
+		Operation::new(connection, Box::new(BrowseHandler {
+			path,
+			sender,
+			presence: Presence::new(),
+			burst: Burst::new(|r: &mut BrowseResult| r.flags |= BrowsedFlag::MoreComing),
+		}))
+	})?))
+}
+
+struct ResolveHandler {
+	path: String,
+	sender: Sender<ResolveResult>,
+	// avahi reports the same result for each address family
+	last: Option<ResolveResult>,
+}
+
+impl ResolveHandler {
+	fn found(&mut self, message: &Message) -> Result<(), Error> {
+		let mut args = message.args();
+		let interface = args.i32()?;
+		let _protocol = args.i32()?;
+		let name = args.string()?;
+		let reg_type = args.string()?;
+		let domain = args.string()?;
+		let host = args.string()?;
+		let _address_protocol = args.i32()?;
+		let _address = args.string()?;
+		let port = args.u16()?;
+		let txt = args.byte_arrays()?;
+
+		let result = ResolveResult {
+			interface: from_avahi_interface(interface),
+			fullname: format!("{}.{}{}", escape_label(&name), absolute(&reg_type), absolute(&domain)),
+			host_target: absolute(&host),
+			port,
+			txt: txt_from_avahi(&txt),
+			received_at: crate::Timestamp::now(),
+		};
+		if self.last.as_ref() != Some(&result) {
+			self.last = Some(result.clone());
+			send(&self.sender, Ok(result));
+		}
+		Ok(())
+	}
+}
+
+impl Handler for ResolveHandler {
+	fn path(&self) -> &str {
+		&self.path
+	}
+
+	fn handle(&mut self, _connection: &Connection, message: &Message) {
+		let result = if message.is_signal(SERVICE_RESOLVER, "Found") {
+			self.found(message)
+		} else if message.is_signal(SERVICE_RESOLVER, "Failure") {
+			message.args().string().and_then(|e| Err(error_from_failure(&e)))
+		} else {
+			Ok(())
+		};
+		if let Err(e) = result {
+			self.fail(e);
+		}
+	}
+
+	fn fail(&mut self, error: Error) {
+		send(&self.sender, Err(error));
+	}
+}
+
+/// Resolve through avahi; `None` if avahi isn't available through D-Bus
+pub fn resolve(
+	interface: Interface,
+	name: &str,
+	reg_type: &str,
+	domain: &str
+) -> io::Result<Option<ServiceStream<ResolveResult>>> {
+	let interface = match avahi_interface(interface) {
+		Some(interface) => interface,
+		None => return Ok(None),
+	};
+	let connection = match connect() {
+		Some(connection) => connection,
+		None => return Ok(None),
+	};
+
+	let mut message = Message::server_call("ServiceResolverNew")?;
+	message.append()
+		.i32(interface)?
+		.i32(UNSPEC)?
+		.str(name)?
+		.str(reg_type)?
+		.str(domain)?
+		.i32(UNSPEC)?
+		.u32(0)?;
+	let path = call_for_path(&connection, message)?;
+
+	Ok(Some(ServiceStream::new_dbus(move |sender| {
+		Operation::new(connection, Box::new(ResolveHandler {
+			path,
+			sender,
+			last: None,
+		}))
+	})?))
+}
+
+struct QueryRecordHandler {
+	path: String,
+	sender: Sender<QueryRecordResult>,
+	presence: Presence<(i32, String, u16, u16, Vec<u8>)>,
+	burst: Burst<QueryRecordResult>,
+}
+
+impl QueryRecordHandler {
+	fn item(&mut self, message: &Message, added: bool) -> Result<(), Error> {
+		let mut args = message.args();
+		let interface = args.i32()?;
+		let _protocol = args.i32()?;
+		let name = args.string()?;
+		let rr_class = args.u16()?;
+		let rr_type = args.u16()?;
+		let rdata = args.bytes()?;
+
+		let key = (interface, name, rr_class, rr_type, rdata);
+		let changed = if added {
+			self.presence.add(key.clone())
+		} else {
+			self.presence.remove(key.clone())
+		};
+		if !changed {
+			return Ok(());
+		}
+		let (interface, name, rr_class, rr_type, rdata) = key;
+		let flags = if added {
+			QueriedRecordFlags::from(QueriedRecordFlag::Add)
+		} else {
+			QueriedRecordFlags::none()
+		};
+		self.burst.push(&self.sender, QueryRecordResult {
+			flags,
+			interface: from_avahi_interface(interface),
+			fullname: absolute(&name),
+			rr_type: RecordType::from(rr_type),
+			rr_class: RecordClass::from(rr_class),
+			rdata,
+			// not available through D-Bus
+			ttl: 0,
+			received_at: crate::Timestamp::now(),
+		});
+		Ok(())
+	}
+}
+
+impl Handler for QueryRecordHandler {
+	fn path(&self) -> &str {
+		&self.path
+	}
+
+	fn handle(&mut self, _connection: &Connection, message: &Message) {
+		let result = if message.is_signal(RECORD_BROWSER, "ItemNew") {
+			self.item(message, true)
+		} else if message.is_signal(RECORD_BROWSER, "ItemRemove") {
+			self.item(message, false)
+		} else if message.is_signal(RECORD_BROWSER, "AllForNow") {
+			self.burst.finish(&self.sender);
+			Ok(())
+		} else if message.is_signal(RECORD_BROWSER, "Failure") {
+			message.args().string().and_then(|e| Err(error_from_failure(&e)))
+		} else {
+			Ok(())
+		};
+		if let Err(e) = result {
+			self.fail(e);
+		}
+	}
+
+	fn fail(&mut self, error: Error) {
+		send(&self.sender, Err(error));
+	}
+}
+
+/// Query records through avahi; `None` if avahi isn't available
+/// through D-Bus
+///
+/// Avahi doesn't report the TTL of records; it is always `0`.
+pub fn query_record(
+	interface: Interface,
+	fullname: &str,
+	rr_type: RecordType,
+	rr_class: RecordClass
+) -> io::Result<Option<ServiceStream<QueryRecordResult>>> {
+	let interface = match avahi_interface(interface) {
+		Some(interface) => interface,
+		None => return Ok(None),
+	};
+	let connection = match connect() {
+		Some(connection) => connection,
+		None => return Ok(None),
+	};
+
+	let mut message = Message::server_call("RecordBrowserNew")?;
+	message.append()
+		.i32(interface)?
+		.i32(UNSPEC)?
+		.str(fullname)?
+		.u16(rr_class.into())?
+		.u16(rr_type.into())?
+		.u32(0)?;
+	let path = call_for_path(&connection, message)?;
+
+	Ok(Some(ServiceStream::new_dbus(move |sender| {
+		Operation::new(connection, Box::new(QueryRecordHandler {
+			path,
+			sender,
+			presence: Presence::new(),
+			burst: Burst::new(|r: &mut QueryRecordResult| r.flags |= QueriedRecordFlag::MoreComing),
+		}))
+	})?))
+}
+
+struct ServiceEntry {
+	interface: i32,
+	name: String,
+	reg_type: String,
+	subtypes: Vec<String>,
+	domain: String,
+	host: String,
+	port: u16,
+	txt: Vec<u8>,
+}
+
+impl ServiceEntry {
+	// add service to the (empty) entry group and publish it
+	fn publish(&self, connection: &Connection, path: &str) -> Result<(), Error> {
+		let mut message = Message::method_call(path, ENTRY_GROUP, "AddService")?;
+		message.append()
+			.i32(self.interface)?
+			.i32(UNSPEC)?
+			.u32(0)?
+			.str(&self.name)?
+			.str(&self.reg_type)?
+			.str(&self.domain)?
+			.str(&self.host)?
+			.u16(self.port)?
+			.byte_arrays(&txt_to_avahi(&self.txt))?;
+		connection.call(message)?;
+
+		for subtype in &self.subtypes {
+			let mut message = Message::method_call(path, ENTRY_GROUP, "AddServiceSubtype")?;
+			message.append()
+				.i32(self.interface)?
+				.i32(UNSPEC)?
+				.u32(0)?
+				.str(&self.name)?
+				.str(&self.reg_type)?
+				.str(&self.domain)?
+				.str(&format!("{}._sub.{}", subtype, self.reg_type))?;
+			connection.call(message)?;
+		}
+
+		connection.call(Message::method_call(path, ENTRY_GROUP, "Commit")?)?;
+		Ok(())
+	}
+}
+
+struct RegisterHandler {
+	path: String,
+	sender: Sender<RegisterResult>,
+	service: ServiceEntry,
+	no_auto_rename: bool,
+	// domain reported in results
+	domain: String,
+}
+
+impl RegisterHandler {
+	fn state_changed(&mut self, connection: &Connection, message: &Message) -> Result<(), Error> {
+		let mut args = message.args();
+		let state = args.i32()?;
+		let error = args.string()?;
+
+		match state {
+			ENTRY_GROUP_ESTABLISHED => {
+				send(&self.sender, Ok(RegisterResult {
+					name: self.service.name.clone(),
+					reg_type: absolute(&self.service.reg_type),
+					domain: absolute(&self.domain),
+					received_at: crate::Timestamp::now(),
+				}));
+			},
+			ENTRY_GROUP_COLLISION if self.no_auto_rename => {
+				return Err(Error::KnownError(crate::ffi::DNSServiceError::NameConflict));
+			},
+			ENTRY_GROUP_COLLISION => {
+				// rename like the DNS-SD daemons do
+				let mut message = Message::server_call("GetAlternativeServiceName")?;
+				message.append().str(&self.service.name)?;
+				self.service.name = connection.call(message)?.args().string()?;
+				connection.call(Message::method_call(&self.path, ENTRY_GROUP, "Reset")?)?;
+				self.service.publish(connection, &self.path)?;
+			},
+			ENTRY_GROUP_FAILURE => return Err(error_from_failure(&error)),
+			_ => (),
+		}
+		Ok(())
+	}
+}
+
+impl Handler for RegisterHandler {
+	fn path(&self) -> &str {
+		&self.path
+	}
+
+	fn handle(&mut self, connection: &Connection, message: &Message) {
+		if message.is_signal(ENTRY_GROUP, "StateChanged") {
+			if let Err(e) = self.state_changed(connection, message) {
+				self.fail(e);
+			}
+		}
+	}
+
+	fn fail(&mut self, error: Error) {
+		send(&self.sender, Err(error));
+	}
+}
+
+/// Register service through avahi; `None` if avahi isn't available
+/// through D-Bus
+pub fn register(
+	no_auto_rename: bool,
+	interface: Interface,
+	name: Option<&str>,
+	reg_type: &str,
+	subtypes: &[&str],
+	domain: Option<&str>,
+	host: Option<&str>,
+	port: u16,
+	txt: &[u8]
+) -> io::Result<Option<ServiceStream<RegisterResult>>> {
+	let interface = match avahi_interface(interface) {
+		Some(interface) => interface,
+		None => return Ok(None),
+	};
+	let connection = match connect() {
+		Some(connection) => connection,
+		None => return Ok(None),
+	};
+
+	// like the DNS-SD daemons default to the host name
+	let name = match name {
+		Some(name) => name.to_string(),
+		None => connection.call(Message::server_call("GetHostName")?)?.args().string()?,
+	};
+	let result_domain = match domain {
+		Some(domain) => domain.to_string(),
+		None => connection.call(Message::server_call("GetDomainName")?)?.args().string()?,
+	};
+
+	let service = ServiceEntry {
+		interface,
+		name,
+		reg_type: reg_type.to_string(),
+		subtypes: subtypes.iter().map(|s| subtype_label(s).to_string()).collect(),
+		domain: domain.unwrap_or("").to_string(),
+		host: host.unwrap_or("").to_string(),
+		port,
+		txt: txt.to_vec(),
+	};
+
+	let path = call_for_path(&connection, Message::server_call("EntryGroupNew")?)?;
+	service.publish(&connection, &path)?;
+
+	Ok(Some(ServiceStream::new_dbus(move |sender| {
+		Operation::new(connection, Box::new(RegisterHandler {
+			path,
+			sender,
+			service,
+			no_auto_rename,
+			domain: result_domain,
+		}))
+	})?))
+}
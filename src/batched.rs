@@ -0,0 +1,211 @@
+use futures::{self,FutureExt,Stream,StreamExt};
+use futures_timer::Delay;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::service::{
+	AddrInfoFlag,
+	AddrInfoResult,
+	Browse,
+	BrowsedFlag,
+	BrowseResult,
+	EnumeratedFlag,
+	EnumerateDomains,
+	EnumerateResult,
+	QueriedRecordFlag,
+	QueryRecord,
+	QueryRecordResult,
+};
+
+/// Results which can indicate more results are pending
+pub trait MoreComing {
+	/// Whether the `MoreComing` flag is set
+	fn more_coming(&self) -> bool;
+}
+
+impl MoreComing for AddrInfoResult {
+	fn more_coming(&self) -> bool {
+		self.flags & AddrInfoFlag::MoreComing
+	}
+}
+
+impl MoreComing for BrowseResult {
+	fn more_coming(&self) -> bool {
+		self.flags & BrowsedFlag::MoreComing
+	}
+}
+
+impl MoreComing for EnumerateResult {
+	fn more_coming(&self) -> bool {
+		self.flags & EnumeratedFlag::MoreComing
+	}
+}
+
+impl MoreComing for QueryRecordResult {
+	fn more_coming(&self) -> bool {
+		self.flags & QueriedRecordFlag::MoreComing
+	}
+}
+
+/// `futures::Stream` extension to simplify building
+/// [`Batched`](struct.Batched.html)
+pub trait BatchedTrait: Stream+Sized {
+	/// Create new [`Batched`](struct.Batched.html)
+	fn batched<T>(self) -> Batched<Self, T>
+	where T: MoreComing, Self: Stream<Item = Result<T, Error>>+Unpin;
+}
+
+impl<S: Stream> BatchedTrait for S {
+	fn batched<T>(self) -> Batched<Self, T>
+	where T: MoreComing, Self: Stream<Item = Result<T, Error>>+Unpin
+	{
+		Batched::new(self)
+	}
+}
+
+/// Collect results as long as `MoreComing` is set
+///
+/// Yields all results of a burst at once, i.e. when a result without
+/// `MoreComing` arrives.  Errors are passed through immediately; when
+/// the underlying stream ends pending results are yielded.
+pub struct Batched<S, T> {
+	stream: S,
+	batch: Vec<T>,
+}
+
+// results are never pinned
+impl<S: Unpin, T> Unpin for Batched<S, T> {}
+
+impl<T: MoreComing, S: Stream<Item = Result<T, Error>>+Unpin> Batched<S, T> {
+	/// Create new `Batched`.
+	///
+	/// Also see [`BatchedTrait::batched`](trait.BatchedTrait.html#method.batched).
+	pub fn new(stream: S) -> Self {
+		Batched {
+			stream,
+			batch: Vec::new(),
+		}
+	}
+}
+
+impl<T: MoreComing, S: Stream<Item = Result<T, Error>>+Unpin> futures::Stream for Batched<S, T> {
+	type Item = Result<Vec<T>, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			match this.stream.poll_next_unpin(cx) {
+				Poll::Pending => return Poll::Pending,
+				Poll::Ready(None) => {
+					if this.batch.is_empty() {
+						return Poll::Ready(None);
+					}
+					return Poll::Ready(Some(Ok(mem::take(&mut this.batch))));
+				},
+				Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+				Poll::Ready(Some(Ok(item))) => {
+					let more_coming = item.more_coming();
+					this.batch.push(item);
+					if !more_coming {
+						return Poll::Ready(Some(Ok(mem::take(&mut this.batch))));
+					}
+				},
+			}
+		}
+	}
+}
+
+/// Collect results until the first one without `MoreComing`
+///
+/// Resolves with the results of the first burst, i.e. what the daemon
+/// currently knows about.  If the daemon knows nothing it won't send
+/// any result; use [`timeout`](#method.timeout) to not wait forever
+/// in that case.  If the underlying stream ends the results collected
+/// so far are returned; errors are returned immediately.
+///
+/// Results are returned as reported (removals included).
+pub struct Snapshot<S, T> {
+	stream: S,
+	results: Vec<T>,
+	timeout: Option<Delay>,
+}
+
+// results are never pinned
+impl<S: Unpin, T> Unpin for Snapshot<S, T> {}
+
+impl<T: MoreComing, S: Stream<Item = Result<T, Error>>+Unpin> Snapshot<S, T> {
+	/// Create new `Snapshot`.
+	pub fn new(stream: S) -> Self {
+		Snapshot {
+			stream,
+			results: Vec::new(),
+			timeout: None,
+		}
+	}
+
+	/// Return the results collected so far after the given duration
+	/// (possibly none)
+	pub fn timeout(mut self, duration: Duration) -> Self {
+		self.timeout = Some(Delay::new(duration));
+		self
+	}
+}
+
+impl<T: MoreComing, S: Stream<Item = Result<T, Error>>+Unpin> Future for Snapshot<S, T> {
+	type Output = Result<Vec<T>, Error>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = &mut *self;
+		loop {
+			match this.stream.poll_next_unpin(cx) {
+				Poll::Pending => break,
+				Poll::Ready(None) => return Poll::Ready(Ok(mem::take(&mut this.results))),
+				Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+				Poll::Ready(Some(Ok(item))) => {
+					let more_coming = item.more_coming();
+					this.results.push(item);
+					if !more_coming {
+						return Poll::Ready(Ok(mem::take(&mut this.results)));
+					}
+				},
+			}
+		}
+		let timed_out = this.timeout.as_mut().is_some_and(|t| t.poll_unpin(cx).is_ready());
+		if timed_out {
+			Poll::Ready(Ok(mem::take(&mut this.results)))
+		} else {
+			Poll::Pending
+		}
+	}
+}
+
+impl Browse {
+	/// Collect currently visible services
+	///
+	/// See [`Snapshot`](struct.Snapshot.html).
+	pub fn initial_snapshot(self) -> Snapshot<Self, BrowseResult> {
+		Snapshot::new(self)
+	}
+}
+
+impl QueryRecord {
+	/// Collect currently known records
+	///
+	/// See [`Snapshot`](struct.Snapshot.html).
+	pub fn initial_snapshot(self) -> Snapshot<Self, QueryRecordResult> {
+		Snapshot::new(self)
+	}
+}
+
+impl EnumerateDomains {
+	/// Collect currently known domains
+	///
+	/// See [`Snapshot`](struct.Snapshot.html).
+	pub fn initial_snapshot(self) -> Snapshot<Self, EnumerateResult> {
+		Snapshot::new(self)
+	}
+}
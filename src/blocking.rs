@@ -0,0 +1,171 @@
+//! Synchronous wrappers for use without an async runtime
+//!
+//! The operations are driven by the background thread waiting for
+//! events from the daemon; the calling thread just blocks until the next
+//! result (or the timeout) arrives.
+//!
+//! Don't use these from within an async task: they block the executor.
+
+use futures::future::{self,Either};
+use futures::{executor,Future,Stream,StreamExt};
+use futures_timer::Delay;
+use std::io;
+use std::time::Duration;
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	browse,
+	enumerate_domains,
+	get_addr_info,
+	query_record,
+	resolve,
+	Browse,
+	BrowseFlags,
+	Enumerate,
+	EnumerateDomains,
+	GetAddrInfo,
+	GetAddrInfoFlags,
+	Protocols,
+	QueryRecord,
+	QueryRecordFlags,
+	RegisterBuilder,
+	RegisterResult,
+	Registration,
+	Resolve,
+	ResolveFlags,
+};
+
+fn timed_out() -> Error {
+	Error::IoError(io::Error::new(io::ErrorKind::TimedOut, "timed out"))
+}
+
+// block until `future` completes, or return `None` after `timeout`
+fn block_on_timeout<F: Future + Unpin>(future: F, timeout: Option<Duration>) -> Option<F::Output> {
+	match timeout {
+		None => Some(executor::block_on(future)),
+		Some(timeout) => match executor::block_on(future::select(future, Delay::new(timeout))) {
+			Either::Left((output, _)) => Some(output),
+			Either::Right(((), _)) => None,
+		},
+	}
+}
+
+/// Iterator over the results of a stream
+///
+/// Each call to `next` blocks until the next result arrives; with a
+/// [`timeout`](#method.timeout) iteration stops if no result arrives in
+/// time.  Iteration can be continued after a timeout.
+pub struct Blocking<S> {
+	stream: S,
+	timeout: Option<Duration>,
+}
+
+impl<S> Blocking<S> {
+	/// Iterate over the results of `stream`
+	pub fn new(stream: S) -> Self {
+		Blocking {
+			stream,
+			timeout: None,
+		}
+	}
+
+	/// Stop iterating if no result arrived within `timeout` (default:
+	/// wait forever)
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	/// Get the underlying stream back
+	pub fn into_inner(self) -> S {
+		self.stream
+	}
+}
+
+impl<S: Stream + Unpin> Iterator for Blocking<S> {
+	type Item = S::Item;
+
+	fn next(&mut self) -> Option<S::Item> {
+		block_on_timeout(self.stream.next(), self.timeout).flatten()
+	}
+}
+
+/// Browse for services, yielding results synchronously
+///
+/// See [`browse`](../fn.browse.html).
+pub fn browse_blocking(
+	flags: BrowseFlags,
+	interface: Interface,
+	reg_type: &str,
+	subtype: Option<&str>,
+	domain: Option<&str>
+) -> io::Result<Blocking<Browse>> {
+	Ok(Blocking::new(browse(flags, interface, reg_type, subtype, domain)?))
+}
+
+/// Resolve a service, yielding results synchronously
+///
+/// Usually only the first result is needed:
+/// `resolve_blocking(...)?.timeout(duration).next()`.
+///
+/// See [`resolve`](../fn.resolve.html).
+pub fn resolve_blocking(
+	flags: ResolveFlags,
+	interface: Interface,
+	name: &str,
+	reg_type: &str,
+	domain: &str
+) -> io::Result<Blocking<Resolve>> {
+	Ok(Blocking::new(resolve(flags, interface, name, reg_type, domain)?))
+}
+
+/// Query for a record, yielding results synchronously
+///
+/// See [`query_record`](../fn.query_record.html).
+pub fn query_record_blocking(
+	flags: QueryRecordFlags,
+	interface: Interface,
+	fullname: &str,
+	rr_type: RecordType,
+	rr_class: RecordClass
+) -> io::Result<Blocking<QueryRecord>> {
+	Ok(Blocking::new(query_record(flags, interface, fullname, rr_type, rr_class)?))
+}
+
+/// Look up the addresses of a host, yielding results synchronously
+///
+/// See [`get_addr_info`](../fn.get_addr_info.html).
+pub fn get_addr_info_blocking(
+	flags: GetAddrInfoFlags,
+	interface: Interface,
+	protocol: Protocols,
+	hostname: &str
+) -> io::Result<Blocking<GetAddrInfo>> {
+	Ok(Blocking::new(get_addr_info(flags, interface, protocol, hostname)?))
+}
+
+/// Enumerate domains, yielding results synchronously
+///
+/// See [`enumerate_domains`](../fn.enumerate_domains.html).
+pub fn enumerate_domains_blocking(
+	enumerate: Enumerate,
+	interface: Interface
+) -> io::Result<Blocking<EnumerateDomains>> {
+	Ok(Blocking::new(enumerate_domains(enumerate, interface)?))
+}
+
+/// Register a service and wait for the registration to complete
+///
+/// Fails with an error of kind `TimedOut` if the daemon didn't confirm
+/// the registration within `timeout`.
+///
+/// See [`RegisterBuilder::start`](../struct.RegisterBuilder.html#method.start).
+pub fn register_blocking(
+	builder: &RegisterBuilder,
+	timeout: Option<Duration>
+) -> Result<(Registration, RegisterResult), Error> {
+	let register = builder.start()?;
+	block_on_timeout(register, timeout).unwrap_or_else(|| Err(timed_out()))
+}
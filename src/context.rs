@@ -0,0 +1,226 @@
+use futures::channel::oneshot;
+use futures::FutureExt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc,Mutex};
+use std::task::{Context,Poll};
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::interface::Interface;
+use crate::service::{
+	browse,
+	enumerate_domains,
+	get_addr_info,
+	query_record,
+	resolve,
+	Browse,
+	BrowseFlags,
+	Enumerate,
+	EnumerateDomains,
+	GetAddrInfo,
+	GetAddrInfoFlags,
+	Protocols,
+	QueryRecord,
+	QueryRecordFlags,
+	Register,
+	RegisterBuilder,
+	Resolve,
+	ResolveFlags,
+};
+use crate::stop_handle::StopHandle;
+
+struct Inner {
+	// `None` after shutdown
+	operations: Option<Vec<StopHandle>>,
+}
+
+/// Context tracking the operations spawned from it
+///
+/// [`shutdown`](#method.shutdown) stops all outstanding operations
+/// (including registrations), so an application can exit without
+/// leaving services published.  Cloning is cheap; clones share the
+/// tracked operations.
+///
+/// Operations are released from the daemon even if their owner doesn't
+/// poll or drop them anymore (see [`StopHandle`](struct.StopHandle.html));
+/// only [`Record`](struct.Record.html) handles of a registration keep the
+/// service published after a shutdown.
+#[derive(Clone,Default)]
+pub struct DnsSd(Arc<Mutex<Inner>>);
+
+impl DnsSd {
+	/// Create new context without operations
+	pub fn new() -> Self {
+		DnsSd::default()
+	}
+
+	// stops the operation if the context was shut down
+	pub(crate) fn track(&self, stop: StopHandle) -> io::Result<()> {
+		let mut inner = self.0.lock().unwrap_or_else(|e| e.into_inner());
+		match inner.operations {
+			Some(ref mut operations) => {
+				operations.retain(|op| !op.is_finished());
+				operations.push(stop);
+				Ok(())
+			},
+			None => {
+				stop.stop();
+				Err(io::Error::other("dnssd context was shut down"))
+			},
+		}
+	}
+
+	/// Number of operations which are neither stopped nor dropped
+	pub fn active_operations(&self) -> usize {
+		let inner = self.0.lock().unwrap_or_else(|e| e.into_inner());
+		inner.operations.as_ref().map_or(0, |operations| {
+			operations.iter().filter(|op| !op.is_finished()).count()
+		})
+	}
+
+	/// Whether [`shutdown`](#method.shutdown) was called
+	pub fn is_shut_down(&self) -> bool {
+		self.0.lock().unwrap_or_else(|e| e.into_inner()).operations.is_none()
+	}
+
+	/// Browse for services
+	///
+	/// See [`browse`](fn.browse.html).
+	pub fn browse(
+		&self,
+		flags: BrowseFlags,
+		interface: Interface,
+		reg_type: &str,
+		subtype: Option<&str>,
+		domain: Option<&str>
+	) -> io::Result<Browse> {
+		let op = browse(flags, interface, reg_type, subtype, domain)?;
+		self.track(op.stop_handle())?;
+		Ok(op)
+	}
+
+	/// Resolve a service
+	///
+	/// See [`resolve`](fn.resolve.html).
+	pub fn resolve(
+		&self,
+		flags: ResolveFlags,
+		interface: Interface,
+		name: &str,
+		reg_type: &str,
+		domain: &str
+	) -> io::Result<Resolve> {
+		let op = resolve(flags, interface, name, reg_type, domain)?;
+		self.track(op.stop_handle())?;
+		Ok(op)
+	}
+
+	/// Query for a record
+	///
+	/// See [`query_record`](fn.query_record.html).
+	pub fn query_record(
+		&self,
+		flags: QueryRecordFlags,
+		interface: Interface,
+		fullname: &str,
+		rr_type: RecordType,
+		rr_class: RecordClass
+	) -> io::Result<QueryRecord> {
+		let op = query_record(flags, interface, fullname, rr_type, rr_class)?;
+		self.track(op.stop_handle())?;
+		Ok(op)
+	}
+
+	/// Look up the addresses of a host
+	///
+	/// See [`get_addr_info`](fn.get_addr_info.html).
+	pub fn get_addr_info(
+		&self,
+		flags: GetAddrInfoFlags,
+		interface: Interface,
+		protocol: Protocols,
+		hostname: &str
+	) -> io::Result<GetAddrInfo> {
+		let op = get_addr_info(flags, interface, protocol, hostname)?;
+		self.track(op.stop_handle())?;
+		Ok(op)
+	}
+
+	/// Enumerate domains
+	///
+	/// See [`enumerate_domains`](fn.enumerate_domains.html).
+	pub fn enumerate_domains(&self, enumerate: Enumerate, interface: Interface) -> io::Result<EnumerateDomains> {
+		let op = enumerate_domains(enumerate, interface)?;
+		self.track(op.stop_handle())?;
+		Ok(op)
+	}
+
+	/// Register a service
+	///
+	/// See [`RegisterBuilder::start`](struct.RegisterBuilder.html#method.start).
+	pub fn register(&self, builder: &RegisterBuilder) -> io::Result<Register> {
+		let op = builder.clone().start()?;
+		self.track(op.stop_handle())?;
+		Ok(op)
+	}
+
+	/// Stop all outstanding operations
+	///
+	/// The returned future completes once the daemon resources of all
+	/// operations were released (which might block, e.g. for goodbye
+	/// packets of the built-in mDNS engine, so it is done in a separate
+	/// thread).  Operations can't be spawned from the context (or its
+	/// clones) afterwards.
+	pub fn shutdown(&self) -> Shutdown {
+		let operations = self.0.lock().unwrap_or_else(|e| e.into_inner()).operations.take();
+		let operations = match operations {
+			Some(operations) if !operations.is_empty() => operations,
+			_ => return Shutdown(None),
+		};
+		let (sender, receiver) = oneshot::channel();
+		let handles = operations.clone();
+		let spawned = std::thread::Builder::new()
+			.name("dnssd shutdown".to_string())
+			.spawn(move || {
+				for op in handles {
+					op.stop();
+				}
+				let _ = sender.send(());
+			});
+		match spawned {
+			Ok(_) => Shutdown(Some(receiver)),
+			Err(_) => {
+				// no thread: stop them here
+				for op in operations {
+					op.stop();
+				}
+				Shutdown(None)
+			},
+		}
+	}
+}
+
+impl Default for Inner {
+	fn default() -> Self {
+		Inner { operations: Some(Vec::new()) }
+	}
+}
+
+/// Pending shutdown of a [`DnsSd`](struct.DnsSd.html) context
+///
+/// See [`DnsSd::shutdown`](struct.DnsSd.html#method.shutdown).
+pub struct Shutdown(Option<oneshot::Receiver<()>>);
+
+impl Future for Shutdown {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		match self.0.as_mut() {
+			// the sender is only dropped after sending (or if the thread
+			// panicked); done either way
+			Some(receiver) => receiver.poll_unpin(cx).map(|_| ()),
+			None => Poll::Ready(()),
+		}
+	}
+}
@@ -7,7 +7,7 @@ use std::io;
 pub unsafe fn from_cstr(s: *const c_char) -> io::Result<&'static str> {
 	ffi::CStr::from_ptr(s)
 		.to_str()
-		.map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+		.map_err(io::Error::other)
 }
 
 #[derive(Clone,Debug)]
@@ -0,0 +1,147 @@
+use std::cmp;
+use std::fmt;
+use std::hash;
+
+macro_rules! dns_enum {
+	($(#[$attr:meta])* $name:ident: $($(#[$case_attr:meta])* $case:ident = $value:expr,)*) => (
+		$(#[$attr])*
+		#[derive(Clone,Copy,Debug)]
+		pub enum $name {
+			$(
+				$(#[$case_attr])*
+				$case,
+			)*
+			/// Any other value
+			///
+			/// Should only be used for values without a named variant;
+			/// comparisons use the numeric value though.
+			Other(u16),
+		}
+
+		impl From<u16> for $name {
+			fn from(value: u16) -> Self {
+				match value {
+					$($value => $name::$case,)*
+					_ => $name::Other(value),
+				}
+			}
+		}
+
+		impl From<$name> for u16 {
+			fn from(value: $name) -> Self {
+				match value {
+					$($name::$case => $value,)*
+					$name::Other(value) => value,
+				}
+			}
+		}
+
+		impl PartialEq for $name {
+			fn eq(&self, other: &Self) -> bool {
+				u16::from(*self) == u16::from(*other)
+			}
+		}
+
+		impl Eq for $name {}
+
+		impl PartialOrd for $name {
+			fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+				Some(self.cmp(other))
+			}
+		}
+
+		impl Ord for $name {
+			fn cmp(&self, other: &Self) -> cmp::Ordering {
+				u16::from(*self).cmp(&u16::from(*other))
+			}
+		}
+
+		impl hash::Hash for $name {
+			fn hash<H: hash::Hasher>(&self, state: &mut H) {
+				u16::from(*self).hash(state)
+			}
+		}
+
+		// serialized as numeric value
+		#[cfg(feature = "serde")]
+		impl ::serde::Serialize for $name {
+			fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.serialize_u16(u16::from(*self))
+			}
+		}
+
+		#[cfg(feature = "serde")]
+		impl<'de> ::serde::Deserialize<'de> for $name {
+			fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				Ok(u16::deserialize(deserializer)?.into())
+			}
+		}
+	);
+}
+
+dns_enum!{
+	/// DNS record type
+	///
+	/// See [IANA DNS Resource Record (RR) TYPEs](https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-4).
+	RecordType:
+	/// IPv4 address
+	A = 1,
+	/// authoritative name server
+	NS = 2,
+	/// canonical name
+	CNAME = 5,
+	/// start of a zone of authority
+	SOA = 6,
+	/// domain name pointer
+	PTR = 12,
+	/// host information
+	HINFO = 13,
+	/// mail exchange
+	MX = 15,
+	/// text strings
+	TXT = 16,
+	/// IPv6 address
+	AAAA = 28,
+	/// service location
+	SRV = 33,
+	/// EDNS option
+	OPT = 41,
+	/// next secure record
+	NSEC = 47,
+	/// request for all records
+	ANY = 255,
+}
+
+dns_enum!{
+	/// DNS record class
+	///
+	/// See [IANA DNS CLASSes](https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-2).
+	RecordClass:
+	/// Internet
+	IN = 1,
+	/// Chaos
+	CH = 3,
+	/// Hesiod
+	HS = 4,
+	/// request for any class
+	ANY = 255,
+}
+
+// unknown values use the generic notation from RFC 3597
+impl fmt::Display for RecordType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RecordType::Other(value) => write!(f, "TYPE{}", value),
+			_ => fmt::Debug::fmt(self, f),
+		}
+	}
+}
+
+impl fmt::Display for RecordClass {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RecordClass::Other(value) => write!(f, "CLASS{}", value),
+			_ => fmt::Debug::fmt(self, f),
+		}
+	}
+}
@@ -0,0 +1,397 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash,Hasher};
+use std::io;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::service::{
+	BrowseResult,
+	EnumerateResult,
+	RegisterResult,
+	ResolveResult,
+};
+
+fn invalid_input(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// length of the (escaped) labels in wire format; `None` if an escape
+// sequence is broken
+fn label_lengths(name: &str) -> Option<Vec<usize>> {
+	let bytes = name.as_bytes();
+	let mut lengths = Vec::new();
+	let mut len = 0;
+	let mut pos = 0;
+	while pos < bytes.len() {
+		match bytes[pos] {
+			b'.' => {
+				lengths.push(len);
+				len = 0;
+				pos += 1;
+			},
+			b'\\' => {
+				let digits = bytes.get(pos + 1..pos + 4).filter(|d| d.iter().all(u8::is_ascii_digit));
+				pos += match digits {
+					Some(_) => 4,
+					None if pos + 1 < bytes.len() => 2,
+					None => return None,
+				};
+				len += 1;
+			},
+			_ => {
+				len += 1;
+				pos += 1;
+			},
+		}
+	}
+	lengths.push(len);
+	Some(lengths)
+}
+
+// whether name ends with a dot that isn't escaped
+fn has_trailing_dot(name: &str) -> bool {
+	match name.strip_suffix('.') {
+		Some(rest) => rest.bytes().rev().take_while(|&c| c == b'\\').count() % 2 == 0,
+		None => false,
+	}
+}
+
+/// Domain name in the (escaped) presentation format used by DNS-SD
+///
+/// Always stored in absolute form with a trailing dot, so `"local"` and
+/// `"local."` are the same domain.  Comparison (and hashing) ignores
+/// ASCII case, as DNS does.
+///
+/// Dereferences to `str`, so it can be passed wherever the API expects
+/// a domain (`Some(&*domain)` for optional domains).
+#[derive(Clone)]
+pub struct DomainName(String);
+
+impl DomainName {
+	/// Validate and normalize domain name
+	///
+	/// Labels must not be empty or exceed 63 bytes, and the complete
+	/// name must not exceed 255 bytes (in wire format).  `""` and `"."`
+	/// are the root domain.
+	pub fn new(name: &str) -> io::Result<Self> {
+		if name.is_empty() || name == "." {
+			return Ok(DomainName(".".to_string()));
+		}
+		let name = if has_trailing_dot(name) {
+			name.to_string()
+		} else {
+			format!("{}.", name)
+		};
+		let lengths = label_lengths(&name[..name.len() - 1]).ok_or_else(|| invalid_input("invalid escape sequence in domain name"))?;
+		if lengths.iter().any(|&len| len == 0 || len > 63) {
+			return Err(invalid_input("invalid label length in domain name"));
+		}
+		// length bytes, labels and the final root label
+		if lengths.iter().map(|len| 1 + len).sum::<usize>() + 1 > 255 {
+			return Err(invalid_input("domain name longer than 255 bytes"));
+		}
+		Ok(DomainName(name))
+	}
+
+	// names reported by the daemon are valid; only normalize
+	pub(crate) fn from_daemon(name: &str) -> Self {
+		DomainName::new(name).unwrap_or_else(|_| DomainName(name.to_string()))
+	}
+
+	/// Name with trailing dot
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+
+	/// Whether this is the root domain `"."`
+	pub fn is_root(&self) -> bool {
+		self.0 == "."
+	}
+
+	/// Whether this is the multicast domain `local.`
+	pub fn is_local(&self) -> bool {
+		self.0.eq_ignore_ascii_case("local.")
+	}
+}
+
+impl Deref for DomainName {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl AsRef<str> for DomainName {
+	fn as_ref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl FromStr for DomainName {
+	type Err = io::Error;
+
+	fn from_str(s: &str) -> io::Result<Self> {
+		DomainName::new(s)
+	}
+}
+
+impl PartialEq for DomainName {
+	fn eq(&self, other: &Self) -> bool {
+		self.0.eq_ignore_ascii_case(&other.0)
+	}
+}
+
+impl Eq for DomainName {}
+
+impl PartialEq<str> for DomainName {
+	fn eq(&self, other: &str) -> bool {
+		DomainName::new(other).is_ok_and(|other| *self == other)
+	}
+}
+
+impl<'a> PartialEq<&'a str> for DomainName {
+	fn eq(&self, other: &&'a str) -> bool {
+		*self == **other
+	}
+}
+
+impl PartialOrd for DomainName {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for DomainName {
+	fn cmp(&self, other: &Self) -> Ordering {
+		let lower = |c: &u8| c.to_ascii_lowercase();
+		self.0.as_bytes().iter().map(lower).cmp(other.0.as_bytes().iter().map(lower))
+	}
+}
+
+impl Hash for DomainName {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		for c in self.0.bytes() {
+			state.write_u8(c.to_ascii_lowercase());
+		}
+		state.write_u8(0xff);
+	}
+}
+
+impl fmt::Debug for DomainName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(&self.0, f)
+	}
+}
+
+impl fmt::Display for DomainName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+/// Host name, e.g. the target of a service
+///
+/// Like a [`DomainName`](struct.DomainName.html) stored with a trailing
+/// dot and compared ignoring ASCII case (so `"MyPrinter.local"` equals
+/// `"myprinter.local."`); use
+/// [`without_trailing_dot`](#method.without_trailing_dot) for APIs
+/// which don't accept the absolute form.
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct Hostname(DomainName);
+
+impl Hostname {
+	/// Validate and normalize host name
+	///
+	/// Same rules as for [`DomainName::new`](struct.DomainName.html#method.new),
+	/// but the root domain is not a valid host name.
+	pub fn new(name: &str) -> io::Result<Self> {
+		let name = DomainName::new(name)?;
+		if name.is_root() {
+			return Err(invalid_input("empty host name"));
+		}
+		Ok(Hostname(name))
+	}
+
+	pub(crate) fn from_daemon(name: &str) -> Self {
+		Hostname(DomainName::from_daemon(name))
+	}
+
+	/// Fully qualified name with trailing dot (`"myprinter.local."`)
+	pub fn as_fqdn(&self) -> &str {
+		&self.0
+	}
+
+	/// Name without trailing dot (`"myprinter.local"`)
+	pub fn without_trailing_dot(&self) -> &str {
+		&self.0[..self.0.len() - 1]
+	}
+
+	/// Name as [`DomainName`](struct.DomainName.html)
+	pub fn domain_name(&self) -> &DomainName {
+		&self.0
+	}
+}
+
+impl FromStr for Hostname {
+	type Err = io::Error;
+
+	fn from_str(s: &str) -> io::Result<Self> {
+		Hostname::new(s)
+	}
+}
+
+impl PartialEq<str> for Hostname {
+	fn eq(&self, other: &str) -> bool {
+		self.0 == *other
+	}
+}
+
+impl<'a> PartialEq<&'a str> for Hostname {
+	fn eq(&self, other: &&'a str) -> bool {
+		self.0 == **other
+	}
+}
+
+impl fmt::Debug for Hostname {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(&self.0, f)
+	}
+}
+
+impl fmt::Display for Hostname {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Display::fmt(&self.0, f)
+	}
+}
+
+impl BrowseResult {
+	/// Domain the service was found in
+	pub fn domain_name(&self) -> DomainName {
+		DomainName::from_daemon(&self.domain)
+	}
+}
+
+impl EnumerateResult {
+	/// Name of the domain
+	pub fn domain_name(&self) -> DomainName {
+		DomainName::from_daemon(&self.domain)
+	}
+}
+
+impl RegisterResult {
+	/// Domain the service was registered in
+	pub fn domain_name(&self) -> DomainName {
+		DomainName::from_daemon(&self.domain)
+	}
+}
+
+impl ResolveResult {
+	/// Host providing the service
+	pub fn hostname(&self) -> Hostname {
+		Hostname::from_daemon(&self.host_target)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::hash_map::DefaultHasher;
+
+	fn hash<T: Hash>(value: &T) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		value.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	fn domain(name: &str) -> DomainName {
+		DomainName::new(name).unwrap()
+	}
+
+	#[test]
+	fn normalize() {
+		assert_eq!(domain("local").as_str(), "local.");
+		assert_eq!(domain("local.").as_str(), "local.");
+		assert_eq!(domain("example.com").as_str(), "example.com.");
+		assert!(domain("").is_root());
+		assert!(domain(".").is_root());
+		assert!(domain("LOCAL").is_local());
+		assert!(!domain("example.local").is_local());
+
+		// escaped dot isn't a trailing dot, escaped backslash before a dot is
+		assert_eq!(domain("a\\.").as_str(), "a\\..");
+		assert_eq!(domain("a\\\\.").as_str(), "a\\\\.");
+	}
+
+	#[test]
+	fn invalid() {
+		for name in &["a..b", ".a", "..", "a\\", "a\\\\\\"] {
+			let e = DomainName::new(name).unwrap_err();
+			assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+		}
+	}
+
+	#[test]
+	fn lengths() {
+		let label = "x".repeat(63);
+		assert!(DomainName::new(&label).is_ok());
+		assert!(DomainName::new(&format!("{}x", label)).is_err());
+		// escape sequences count as single byte
+		assert!(DomainName::new(&format!("{}\\032", &label[1..])).is_ok());
+		assert!(DomainName::new(&format!("{}\\.", label)).is_err());
+
+		// 4 length bytes, 4 * 63 - 1 bytes of labels and the root label:
+		// 256 bytes
+		let name = [label.as_str(); 4].join(".");
+		assert!(DomainName::new(&name[1..]).is_err());
+		assert!(DomainName::new(&name[2..]).is_ok());
+	}
+
+	#[test]
+	fn compare() {
+		assert_eq!(domain("Example.COM"), domain("example.com."));
+		assert_eq!(hash(&domain("Example.COM")), hash(&domain("example.com.")));
+		assert_eq!(domain("local"), "local.");
+		assert_eq!(domain("local."), "LOCAL");
+		assert!(domain("local") != "other");
+		assert!(domain("local") != "a..b");
+		assert!(domain("A.example") < domain("b.example"));
+		assert_eq!(domain("ABC").cmp(&domain("abc")), Ordering::Equal);
+	}
+
+	#[test]
+	fn display_round_trip() {
+		for name in &[".", "local.", "Example.com.", "My\\032Printer.local.", "a\\.b.local."] {
+			let d = domain(name);
+			assert_eq!(d.to_string(), *name);
+			assert_eq!(d.to_string().parse::<DomainName>().unwrap().as_str(), *name);
+			assert_eq!(format!("{:?}", d), format!("{:?}", name));
+		}
+	}
+
+	#[test]
+	fn hostname() {
+		let host = Hostname::new("MyPrinter.local").unwrap();
+		assert_eq!(host.as_fqdn(), "MyPrinter.local.");
+		assert_eq!(host.without_trailing_dot(), "MyPrinter.local");
+		assert_eq!(host, "myprinter.local.");
+		assert_eq!(host, Hostname::new("myprinter.LOCAL.").unwrap());
+		assert_eq!(hash(&host), hash(&Hostname::new("myprinter.LOCAL.").unwrap()));
+		assert!(host.domain_name().as_str() == "MyPrinter.local.");
+		assert_eq!(host.to_string(), "MyPrinter.local.");
+		assert_eq!(host.to_string().parse::<Hostname>().unwrap(), host);
+
+		assert!(Hostname::new("").is_err());
+		assert!(Hostname::new(".").is_err());
+		assert!(Hostname::new("a..local").is_err());
+	}
+
+	#[test]
+	fn from_daemon() {
+		assert_eq!(Hostname::from_daemon("host.local").as_fqdn(), "host.local.");
+		assert_eq!(DomainName::from_daemon("local.").as_str(), "local.");
+		// keeps names it can't validate
+		assert_eq!(DomainName::from_daemon("a..b").as_str(), "a..b");
+	}
+}
@@ -1,23 +1,43 @@
 use std::fmt;
 use std::error;
+use std::hash::{Hash,Hasher};
 use std::io;
 
-use ffi;
+use crate::ffi;
 
 /// API Error
-#[derive(Clone,Copy,Eq,PartialEq,Hash)]
+///
+/// Either an error code reported by the DNS-SD library (see
+/// [`DNSServiceError`](enum.DNSServiceError.html) for the known codes)
+/// or some other I/O error (e.g. while waiting for events from the
+/// daemon).
+///
+/// Converts to and from `std::io::Error`; an `Error` converted to an
+/// `io::Error` is unwrapped again when converted back.  Functions
+/// returning `io::Result` store the `Error` inside the `io::Error`
+/// (with a matching [`kind`](https://doc.rust-lang.org/std/io/struct.Error.html#method.kind)
+/// where there is one), so callers can branch on the DNS-SD error code
+/// through `e.get_ref().and_then(|e| e.downcast_ref::<Error>())` (or
+/// [`Error::from_io`](#method.from_io)).
+///
+/// Errors compare (and hash) by their error code; other I/O errors
+/// only by their [`kind`](https://doc.rust-lang.org/std/io/struct.Error.html#method.kind).
+/// Cloning an I/O error keeps its kind and message, but not its
+/// source.
 pub enum Error {
 	/// If error code used some recognized name
 	KnownError(ffi::DNSServiceError),
 	/// Unrecognized error codes
 	UnknownError(i32),
+	/// Other I/O error
+	IoError(io::Error),
 }
 impl Error {
 	/// Check if a raw error code represents an error, and convert it
 	/// accordingly.  (Not all codes are treated as an error, including
 	/// `0`).
 	pub fn from(value: ffi::DNSServiceErrorType) -> Result<(), Error> {
-		if let Some(_) = ffi::DNSServiceNoError::try_from(value) {
+		if ffi::DNSServiceNoError::try_from(value).is_some() {
 			Ok(())
 		} else {
 			match ffi::DNSServiceError::try_from(value) {
@@ -26,11 +46,136 @@ impl Error {
 			}
 		}
 	}
+
+	/// DNS-SD error stored inside an `io::Error` (if any)
+	pub fn from_io(e: &io::Error) -> Option<&Error> {
+		e.get_ref().and_then(|inner| inner.downcast_ref::<Error>())
+	}
+
+	/// Raw `kDNSServiceErr_*` error code (`None` for other I/O errors)
+	pub fn code(&self) -> Option<i32> {
+		match *self {
+			Error::KnownError(e) => Some(e as i32),
+			Error::UnknownError(code) => Some(code),
+			Error::IoError(_) => None,
+		}
+	}
+
+	/// Known error code, also for an `UnknownError` holding a code
+	/// which is known (`None` for other codes and I/O errors)
+	///
+	/// Use this (instead of matching `KnownError`) to classify errors
+	/// the same way they compare.
+	pub fn known_code(&self) -> Option<ffi::DNSServiceError> {
+		match *self {
+			Error::KnownError(e) => Some(e),
+			Error::UnknownError(code) => ffi::DNSServiceError::try_from(code),
+			Error::IoError(_) => None,
+		}
+	}
+
+	/// Whether the service name is already in use
+	pub fn is_name_conflict(&self) -> bool {
+		self.known_code() == Some(ffi::DNSServiceError::NameConflict)
+	}
+
+	/// Whether retrying the operation later might succeed
+	///
+	/// True for temporary failures (like a daemon which isn't running
+	/// or a broken connection to it, timeouts or missing network
+	/// infrastructure) and for I/O errors of a transient kind.  Errors
+	/// caused by the request itself (bad parameters, name conflicts,
+	/// unsupported operations) are not transient, and neither are
+	/// operations stopped through a [`StopHandle`](struct.StopHandle.html).
+	pub fn is_transient(&self) -> bool {
+		use crate::ffi::DNSServiceError::*;
+
+		match *self {
+			Error::IoError(ref e) => matches!(e.kind(),
+				io::ErrorKind::WouldBlock
+				| io::ErrorKind::TimedOut
+				| io::ErrorKind::ConnectionReset
+				| io::ErrorKind::ConnectionAborted
+				| io::ErrorKind::ConnectionRefused
+				| io::ErrorKind::BrokenPipe
+			),
+			_ => matches!(self.known_code(), Some(
+				Transient
+				| ServiceNotRunning
+				| DefunctConnection
+				| Timeout
+				| NoMemory
+				| NoRouter
+				| NATTraversal
+				| Firewall
+			)),
+		}
+	}
 }
 
 impl From<Error> for io::Error {
 	fn from(e: Error) -> Self {
-		io::Error::new(io::ErrorKind::Other, e)
+		use crate::ffi::DNSServiceError::*;
+
+		// allow detecting common errors without digging into the error
+		let code = match e {
+			Error::IoError(e) => return e,
+			_ => e.known_code(),
+		};
+		let kind = match code {
+			Some(NameConflict | AlreadyRegistered) => io::ErrorKind::AlreadyExists,
+			Some(NoSuchName | NoSuchRecord | NoSuchKey) => io::ErrorKind::NotFound,
+			Some(BadParam | BadFlags | BadInterfaceIndex | Invalid) => io::ErrorKind::InvalidInput,
+			Some(Refused | NoAuth | Firewall) => io::ErrorKind::PermissionDenied,
+			Some(Unsupported | NATPortMappingUnsupported) => io::ErrorKind::Unsupported,
+			Some(NoMemory) => io::ErrorKind::OutOfMemory,
+			Some(Timeout) => io::ErrorKind::TimedOut,
+			Some(ServiceNotRunning) => io::ErrorKind::ConnectionRefused,
+			Some(DefunctConnection) => io::ErrorKind::ConnectionReset,
+			_ => io::ErrorKind::Other,
+		};
+		io::Error::new(kind, e)
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+			*e.into_inner().unwrap().downcast::<Error>().unwrap()
+		} else {
+			Error::IoError(e)
+		}
+	}
+}
+
+impl Clone for Error {
+	fn clone(&self) -> Self {
+		match *self {
+			Error::KnownError(e) => Error::KnownError(e),
+			Error::UnknownError(code) => Error::UnknownError(code),
+			Error::IoError(ref e) => Error::IoError(io::Error::new(e.kind(), e.to_string())),
+		}
+	}
+}
+
+impl PartialEq for Error {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Error::IoError(a), Error::IoError(b)) => a.kind() == b.kind(),
+			(Error::IoError(_), _) | (_, Error::IoError(_)) => false,
+			_ => self.code() == other.code(),
+		}
+	}
+}
+
+impl Eq for Error {}
+
+impl Hash for Error {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		match *self {
+			Error::IoError(ref e) => e.kind().hash(state),
+			_ => self.code().hash(state),
+		}
 	}
 }
 
@@ -39,6 +184,7 @@ impl fmt::Debug for Error {
 		match *self {
 			Error::KnownError(ffi_err) => write!(f, "known error {:?}: {}", ffi_err, ffi_err),
 			Error::UnknownError(e) => write!(f, "unknown error code: {:?}", e),
+			Error::IoError(ref e) => write!(f, "io error: {:?}", e),
 		}
 	}
 }
@@ -47,24 +193,23 @@ impl fmt::Display for Error {
 		match *self {
 			Error::KnownError(ffi_err) => write!(f, "{}", ffi_err),
 			Error::UnknownError(e) => write!(f, "unknown error code: {:?}", e),
+			Error::IoError(ref e) => write!(f, "{}", e),
 		}
 	}
 }
 impl error::Error for Error {
-	fn description(&self) -> &str {
-		""
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			Error::IoError(ref e) => Some(e),
+			_ => None,
+		}
 	}
 }
 
 impl fmt::Display for ffi::DNSServiceError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}", error::Error::description(self))
-	}
-}
-impl error::Error for ffi::DNSServiceError {
-	fn description(&self) -> &str {
-		use ffi::DNSServiceError::*;
-		match *self {
+		use crate::ffi::DNSServiceError::*;
+		f.write_str(match *self {
 			Unknown               => "unknown error",
 			NoSuchName            => "no such name",
 			NoMemory              => "out of memory",
@@ -78,14 +223,92 @@ impl error::Error for ffi::DNSServiceError {
 			AlreadyRegistered     => "already registered",
 			NameConflict          => "name conflict",
 			Invalid               => "invalid",
+			Firewall              => "blocked by firewall",
 			Incompatible          => "client library incompatible with daemon",
 			BadInterfaceIndex     => "bad interface index",
 			Refused               => "refused",
 			NoSuchRecord          => "no such record",
 			NoAuth                => "no auth",
 			NoSuchKey             => "no such key",
-			NoValue               => "no value",
-			BufferTooSmall        => "buffer too small",
-		}
+			NATTraversal          => "NAT traversal failed",
+			DoubleNAT             => "double NAT",
+			BadTime               => "bad time",
+			BadSig                => "bad signature",
+			BadKey                => "bad key",
+			Transient             => "transient failure",
+			ServiceNotRunning     => "service not running",
+			NATPortMappingUnsupported => "NAT port mapping not supported by router",
+			NATPortMappingDisabled => "NAT port mapping disabled on router",
+			NoRouter              => "no router",
+			PollingMode           => "polling mode",
+			Timeout               => "timeout",
+			DefunctConnection     => "defunct connection",
+		})
+	}
+}
+impl error::Error for ffi::DNSServiceError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::hash_map::DefaultHasher;
+
+	fn hash(e: &Error) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		e.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	#[test]
+	fn compare() {
+		let known = Error::KnownError(ffi::DNSServiceError::NameConflict);
+		assert_eq!(known, Error::from(ffi::DNSServiceError::NameConflict as i32).unwrap_err());
+		// unknown codes are mapped to known errors when possible
+		assert_eq!(known, Error::UnknownError(ffi::DNSServiceError::NameConflict as i32));
+		assert_eq!(hash(&known), hash(&Error::UnknownError(-65548)));
+		assert_ne!(known, Error::KnownError(ffi::DNSServiceError::Timeout));
+		assert_ne!(Error::UnknownError(-1), Error::UnknownError(-2));
+
+		let io = Error::IoError(io::Error::new(io::ErrorKind::TimedOut, "a"));
+		assert_eq!(io, Error::IoError(io::Error::new(io::ErrorKind::TimedOut, "b")));
+		assert_eq!(hash(&io), hash(&Error::IoError(io::ErrorKind::TimedOut.into())));
+		assert_ne!(io, Error::IoError(io::ErrorKind::Other.into()));
+		assert_ne!(io, Error::KnownError(ffi::DNSServiceError::Timeout));
+	}
+
+	#[test]
+	fn classify_unknown_errors() {
+		// classification agrees with comparison
+		let conflict = Error::UnknownError(ffi::DNSServiceError::NameConflict as i32);
+		assert_eq!(conflict.known_code(), Some(ffi::DNSServiceError::NameConflict));
+		assert!(conflict.is_name_conflict());
+		assert_eq!(io::Error::from(conflict).kind(), io::ErrorKind::AlreadyExists);
+		assert!(Error::UnknownError(ffi::DNSServiceError::ServiceNotRunning as i32).is_transient());
+		assert!(crate::reconnect::is_disconnect(&Error::UnknownError(ffi::DNSServiceError::DefunctConnection as i32)));
+
+		let unknown = Error::UnknownError(-1);
+		assert_eq!(unknown.known_code(), None);
+		assert!(!unknown.is_name_conflict());
+		assert!(!unknown.is_transient());
+		assert_eq!(Error::IoError(io::ErrorKind::TimedOut.into()).known_code(), None);
+	}
+
+	#[test]
+	fn clone() {
+		let known = Error::KnownError(ffi::DNSServiceError::BadParam);
+		assert_eq!(known.clone(), known);
+		let io = Error::IoError(io::Error::new(io::ErrorKind::InvalidData, "bad data"));
+		let cloned = io.clone();
+		assert_eq!(cloned, io);
+		assert_eq!(cloned.to_string(), "bad data");
+	}
+
+	#[test]
+	fn io_round_trip() {
+		let e = Error::KnownError(ffi::DNSServiceError::NameConflict);
+		let io_error = io::Error::from(e.clone());
+		assert_eq!(io_error.kind(), io::ErrorKind::AlreadyExists);
+		assert_eq!(Error::from_io(&io_error), Some(&e));
+		assert_eq!(<Error as From<io::Error>>::from(io_error), e);
 	}
 }
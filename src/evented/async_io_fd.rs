@@ -0,0 +1,42 @@
+use async_io::Async;
+use std::io;
+use std::os::raw::{c_int};
+use std::os::unix::io::{AsFd,BorrowedFd};
+use std::task::{Context,Poll};
+
+/// borrowed fd; the DNSService owns (and closes) it
+struct Fd(c_int);
+
+impl AsFd for Fd {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		// the registration is dropped before the service closes the fd
+		unsafe { BorrowedFd::borrow_raw(self.0) }
+	}
+}
+
+pub struct PollReadFd(Async<Fd>);
+
+impl PollReadFd {
+	/// does not take overship of fd
+	///
+	/// Registers with the global `async-io` reactor (as used by
+	/// async-std and smol).
+	pub fn new(fd: c_int) -> io::Result<Self> {
+		// don't switch the fd to non-blocking mode; the C library
+		// expects blocking reads
+		Ok(PollReadFd(Async::new_nonblocking(Fd(fd))?))
+	}
+
+	pub fn poll_read(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+		self.0.poll_readable(cx)
+	}
+
+	/// Called after reading from the fd; registers the task for the
+	/// next read event (readiness is level-triggered, so pending data
+	/// triggers another event).
+	pub fn need_read(&self, cx: &mut Context) {
+		if self.0.poll_readable(cx).is_ready() {
+			cx.waker().wake_by_ref();
+		}
+	}
+}
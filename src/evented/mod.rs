@@ -1,42 +1,121 @@
 #[cfg(unix)]
-use self::unix::*;
+use self::unix::select_read;
 #[cfg(unix)]
 mod unix;
 
 #[cfg(windows)]
-use self::windows::*;
+use self::windows::select_read;
 #[cfg(windows)]
 mod windows;
 
-use futures;
+#[cfg(all(unix, feature = "async-io"))]
+mod async_io_fd;
+mod shared;
+mod thread;
+#[cfg(all(unix, feature = "tokio"))]
+mod tokio_fd;
+
 use std::io;
-use tokio_core::reactor::{Handle,Remote};
+use std::task::{Context,Poll};
+
+use crate::raw::DNSService;
+
+pub use self::shared::{SharedChild,SharedEvented};
+
+/// Read readiness source for the daemon connection
+enum PollReadFd {
+	/// registered with the reactor of a tokio runtime
+	#[cfg(all(unix, feature = "tokio"))]
+	Tokio(tokio_fd::PollReadFd),
+	/// registered with the global async-io reactor
+	#[cfg(all(unix, feature = "async-io"))]
+	AsyncIo(async_io_fd::PollReadFd),
+	/// waiting in a background thread; works with any executor
+	Thread(thread::PollReadFd),
+	/// callbacks run on a dispatch queue and send their results
+	/// directly; nothing to wait for
+	#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+	Dispatch,
+}
+
+impl PollReadFd {
+	fn new(fd: std::os::raw::c_int) -> io::Result<Self> {
+		#[cfg(all(unix, feature = "tokio"))]
+		{
+			if tokio::runtime::Handle::try_current().is_ok() {
+				return Ok(PollReadFd::Tokio(tokio_fd::PollReadFd::new(fd)?));
+			}
+		}
+		#[cfg(all(unix, feature = "async-io"))]
+		{
+			return Ok(PollReadFd::AsyncIo(async_io_fd::PollReadFd::new(fd)?));
+		}
+		#[allow(unreachable_code)]
+		Ok(PollReadFd::Thread(thread::PollReadFd::new(fd)?))
+	}
+
+	fn poll_read(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+		match *self {
+			#[cfg(all(unix, feature = "tokio"))]
+			PollReadFd::Tokio(ref p) => p.poll_read(cx),
+			#[cfg(all(unix, feature = "async-io"))]
+			PollReadFd::AsyncIo(ref p) => p.poll_read(cx),
+			PollReadFd::Thread(ref p) => p.poll_read(cx),
+			#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+			PollReadFd::Dispatch => Poll::Pending,
+		}
+	}
 
-use raw::DNSService;
-use remote::GetRemote;
+	fn need_read(&self, cx: &mut Context) {
+		match *self {
+			#[cfg(all(unix, feature = "tokio"))]
+			PollReadFd::Tokio(ref p) => p.need_read(cx),
+			#[cfg(all(unix, feature = "async-io"))]
+			PollReadFd::AsyncIo(ref p) => p.need_read(cx),
+			PollReadFd::Thread(ref p) => p.need_read(cx),
+			#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+			PollReadFd::Dispatch => (),
+		}
+	}
+}
 
 pub struct EventedDNSService {
-	service: DNSService,
+	// stop watching the fd before it gets closed
 	poll: PollReadFd,
+	service: DNSService,
 }
 
 impl EventedDNSService {
-	pub fn new(service: DNSService, handle: &Handle) -> io::Result<Self> {
+	/// Uses the reactor of the current tokio runtime if there is one,
+	/// then the `async-io` reactor (if the feature is enabled),
+	/// otherwise waits for events in a background thread.
+	///
+	/// With the `dispatch-queue` feature on Apple platforms the daemon
+	/// delivers callbacks on a dispatch queue instead.
+	pub fn new(service: DNSService) -> io::Result<Self> {
+		#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+		{
+			service.set_dispatch_queue()?;
+			return Ok(EventedDNSService{
+				poll: PollReadFd::Dispatch,
+				service,
+			});
+		}
+
+		#[allow(unreachable_code)]
 		let fd = service.fd();
 
 		Ok(EventedDNSService{
-			service: service,
-			poll: PollReadFd::new(fd, handle)?,
+			poll: PollReadFd::new(fd)?,
+			service,
 		})
 	}
 
-	pub fn poll(&self) -> io::Result<()> {
-		match self.poll.poll_read() {
-			futures::Async::Ready(()) => {
-				self.service.process_result()?;
-				self.poll.need_read();
-			},
-			futures::Async::NotReady => (),
+	pub fn poll(&self, cx: &mut Context) -> io::Result<()> {
+		if let Poll::Ready(r) = self.poll.poll_read(cx) {
+			r?;
+			self.service.process_result()?;
+			self.poll.need_read(cx);
 		}
 		Ok(())
 	}
@@ -46,8 +125,27 @@ impl EventedDNSService {
 	}
 }
 
-impl GetRemote for EventedDNSService {
-	fn remote(&self) -> &Remote {
-		self.poll.remote()
+/// Read readiness of other connections to the daemon (e.g. D-Bus) or
+/// sockets of the built-in mDNS engine
+#[cfg(any(all(avahi_compat, feature = "avahi-dbus"), all(unix, feature = "mdns")))]
+pub struct EventedFd(PollReadFd);
+
+#[cfg(any(all(avahi_compat, feature = "avahi-dbus"), all(unix, feature = "mdns")))]
+impl EventedFd {
+	/// does not take overship of fd
+	pub fn new(fd: std::os::raw::c_int) -> io::Result<Self> {
+		Ok(EventedFd(PollReadFd::new(fd)?))
+	}
+
+	/// Calls `read` when the fd is readable
+	pub fn poll<F>(&self, cx: &mut Context, read: F) -> io::Result<()>
+	where F: FnOnce() -> io::Result<()>
+	{
+		if let Poll::Ready(r) = self.0.poll_read(cx) {
+			r?;
+			read()?;
+			self.0.need_read(cx);
+		}
+		Ok(())
 	}
 }
@@ -0,0 +1,89 @@
+//! Connection shared by multiple operations
+//! (`kDNSServiceFlagsShareConnection`): results for all operations are
+//! read from the single socket of the connection, so polling any of the
+//! operations processes them.
+//!
+//! The read readiness only wakes a single task; to not lose results for
+//! operations polled in other tasks all tasks polling one of the
+//! operations get woken.
+
+use futures::task::{self,ArcWake};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::task::{Context,Waker};
+
+use super::EventedDNSService;
+use crate::raw::DNSService;
+
+struct Inner {
+	service: EventedDNSService,
+	// tasks of the operations to wake on read readiness, by operation id
+	wakers: Mutex<HashMap<usize, Waker>>,
+	next_id: AtomicUsize,
+}
+
+impl ArcWake for Inner {
+	fn wake_by_ref(arc_self: &Arc<Self>) {
+		let wakers = arc_self.wakers.lock().unwrap_or_else(|e| e.into_inner());
+		for waker in wakers.values() {
+			waker.wake_by_ref();
+		}
+	}
+}
+
+pub struct SharedEvented(Arc<Inner>);
+
+impl SharedEvented {
+	pub fn new(service: EventedDNSService) -> Self {
+		SharedEvented(Arc::new(Inner {
+			service,
+			wakers: Mutex::default(),
+			next_id: AtomicUsize::new(0),
+		}))
+	}
+
+	pub fn service(&self) -> &DNSService {
+		self.0.service.service()
+	}
+
+	/// Wrap operation created on the shared connection
+	pub fn child(&self, service: DNSService) -> SharedChild {
+		SharedChild {
+			connection: self.0.clone(),
+			id: self.0.next_id.fetch_add(1, Ordering::Relaxed),
+			service,
+		}
+	}
+}
+
+pub struct SharedChild {
+	connection: Arc<Inner>,
+	id: usize,
+	service: DNSService,
+}
+
+impl SharedChild {
+	pub fn poll(&self, cx: &mut Context) -> io::Result<()> {
+		{
+			let mut wakers = self.connection.wakers.lock().unwrap_or_else(|e| e.into_inner());
+			match wakers.get(&self.id) {
+				Some(waker) if waker.will_wake(cx.waker()) => (),
+				_ => { wakers.insert(self.id, cx.waker().clone()); },
+			}
+		}
+		let waker = task::waker_ref(&self.connection);
+		self.connection.service.poll(&mut Context::from_waker(&waker))
+	}
+
+	pub fn service(&self) -> &DNSService {
+		&self.service
+	}
+}
+
+impl Drop for SharedChild {
+	fn drop(&mut self) {
+		self.connection.wakers.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.id);
+	}
+}
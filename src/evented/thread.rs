@@ -0,0 +1,104 @@
+//! Runtime independent read readiness: a background thread waits (with
+//! select()/poll()) for the fd to become readable and wakes the task.
+//!
+//! To cancel the wait we'd need another fd to wait for (and there is no
+//! socketpair() on windows); instead we use a small (1 second) timeout,
+//! it is only used to terminate the thread anyway.
+//!
+//! This of course wastes one thread per fd we want to watch; a bigger
+//! solution would reuse the same backend thread over and over.
+
+use std::io;
+use std::os::raw::{c_int};
+use std::sync::{Arc,Condvar,Mutex};
+use std::task::{Context,Poll,Waker};
+use std::thread;
+use std::time::Duration;
+
+use super::select_read;
+
+#[derive(Default)]
+struct State {
+	/// task to wake on the next read event; also means the thread
+	/// should be waiting for one
+	waker: Option<Waker>,
+	/// set when the watched fd is going away
+	closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+	state: Mutex<State>,
+	request: Condvar,
+}
+
+pub struct PollReadFd {
+	/// file descriptor to watch read events for
+	fd: c_int,
+	shared: Arc<Shared>,
+}
+
+impl PollReadFd {
+	/// does not take overship of fd
+	pub fn new(fd: c_int) -> io::Result<Self> {
+		let shared = Arc::new(Shared::default());
+		let thread_shared = shared.clone();
+
+		thread::Builder::new()
+			.name("dnssd select".to_string())
+			.spawn(move || select_thread(fd, &thread_shared))?;
+
+		Ok(PollReadFd{
+			fd,
+			shared,
+		})
+	}
+
+	pub fn poll_read(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+		if select_read(self.fd, Duration::from_millis(0)) {
+			return Poll::Ready(Ok(()));
+		}
+		let mut state = self.shared.state.lock().unwrap();
+		state.waker = Some(cx.waker().clone());
+		self.shared.request.notify_one();
+		Poll::Pending
+	}
+
+	/// Called after reading from the fd; registers the task for the
+	/// next read event.
+	pub fn need_read(&self, cx: &mut Context) {
+		if self.poll_read(cx).is_ready() {
+			// more data pending: poll again
+			cx.waker().wake_by_ref();
+		}
+	}
+}
+
+impl Drop for PollReadFd {
+	fn drop(&mut self) {
+		self.shared.state.lock().unwrap().closed = true;
+		self.shared.request.notify_one();
+	}
+}
+
+fn select_thread(fd: c_int, shared: &Shared) {
+	loop {
+		{
+			let mut state = shared.state.lock().unwrap();
+			while state.waker.is_none() && !state.closed {
+				state = shared.request.wait(state).unwrap();
+			}
+			if state.closed { return; }
+		}
+
+		while !select_read(fd, Duration::from_millis(1000)) {
+			if shared.state.lock().unwrap().closed { return; }
+		}
+
+		let mut state = shared.state.lock().unwrap();
+		if state.closed { return; }
+		if let Some(waker) = state.waker.take() {
+			waker.wake();
+		}
+	}
+}
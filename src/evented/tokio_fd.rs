@@ -0,0 +1,40 @@
+use std::io;
+use std::os::raw::{c_int};
+use std::task::{Context,Poll};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+use super::select_read;
+
+pub struct PollReadFd(AsyncFd<c_int>);
+
+impl PollReadFd {
+	/// does not take overship of fd
+	///
+	/// Registers with the reactor of the current tokio runtime.
+	pub fn new(fd: c_int) -> io::Result<Self> {
+		Ok(PollReadFd(AsyncFd::with_interest(fd, Interest::READABLE)?))
+	}
+
+	pub fn poll_read(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+		self.0.poll_read_ready(cx).map_ok(|_guard| ())
+	}
+
+	/// Called after reading from the fd; clears readiness if no more
+	/// data is pending and registers the task for the next read event.
+	pub fn need_read(&self, cx: &mut Context) {
+		if select_read(*self.0.get_ref(), Duration::from_millis(0)) {
+			// more data pending: keep readiness and poll again
+			cx.waker().wake_by_ref();
+			return;
+		}
+		if let Poll::Ready(Ok(mut guard)) = self.0.poll_read_ready(cx) {
+			guard.clear_ready();
+		}
+		if self.0.poll_read_ready(cx).is_ready() {
+			// new read event arrived in the meantime
+			cx.waker().wake_by_ref();
+		}
+	}
+}
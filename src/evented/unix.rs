@@ -1,48 +1,14 @@
-use futures::{Async};
-use mio;
-use std::io;
 use std::os::raw::{c_int};
-use tokio_core::reactor::{Handle,PollEvented,Remote};
-
-use remote::GetRemote;
-
-pub struct PollReadFd(PollEvented<EventedFd>);
-
-impl PollReadFd {
-	pub fn new(fd: c_int, handle: &Handle) -> io::Result<Self> {
-		Ok(PollReadFd(PollEvented::new(EventedFd(fd), handle)?))
-	}
-
-	pub fn poll_read(&self) -> Async<()> {
-		self.0.poll_read()
-	}
-
-	pub fn need_read(&self) {
-		self.0.need_read()
-	}
-}
-
-impl GetRemote for PollReadFd {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
-	}
-}
-
-struct EventedFd(c_int);
-
-impl mio::Evented for EventedFd {
-	fn register(&self, poll: &mio::Poll, token: mio::Token, interest: mio::Ready, opts: mio::PollOpt) -> io::Result<()> {
-		let efd = mio::unix::EventedFd(&self.0);
-		mio::Evented::register(&efd, poll, token, interest, opts)
-	}
-
-	fn reregister(&self, poll: &mio::Poll, token: mio::Token, interest: mio::Ready, opts: mio::PollOpt) -> io::Result<()> {
-		let efd = mio::unix::EventedFd(&self.0);
-		mio::Evented::reregister(&efd, poll, token, interest, opts)
-	}
-
-	fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
-		let efd = mio::unix::EventedFd(&self.0);
-		mio::Evented::deregister(&efd, poll)
-	}
+use std::time::Duration;
+
+/// check whether fd is readable, waiting at most `timeout`
+pub fn select_read(fd: c_int, timeout: Duration) -> bool {
+	let mut pfd = libc::pollfd{
+		fd,
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	let timeout = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+	let r = unsafe { libc::poll(&mut pfd, 1, timeout) };
+	r > 0 && 0 != (pfd.revents & libc::POLLIN)
 }
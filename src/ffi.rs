@@ -4,10 +4,12 @@ use std::os::raw::{c_int,c_void,c_char};
 
 // type without an instance
 pub enum DNSServiceT{}
+/// Raw `DNSServiceRef` of the C API
 pub type DNSServiceRef = *mut DNSServiceT;
 
 // type without an instance
 pub enum DNSRecordT{}
+/// Raw `DNSRecordRef` of the C API
 pub type DNSRecordRef = *mut DNSRecordT;
 
 pub type DNSServiceFlags = u32;
@@ -25,10 +27,19 @@ pub const FLAGS_REGISTRATION_DOMAINS : DNSServiceFlags = 0x80;
 pub const FLAGS_LONG_LIVED_QUERY     : DNSServiceFlags = 0x100;
 #[cfg(not(unix))]
 pub const FLAGS_LONG_LIVED_QUERY     : DNSServiceFlags = 0;
-// avahi only?
-//pub const FLAGS_ALLOW_REMOTE_QUERY   : DNSServiceFlags = 0x200;
-//pub const FLAGS_FORCE_MULTICAS       : DNSServiceFlags = 0x400;
-//pub const FLAGS_RETURN_CNAME         : DNSServiceFlags = 0x800;
+pub const FLAGS_ALLOW_REMOTE_QUERY   : DNSServiceFlags = 0x200;
+pub const FLAGS_FORCE_MULTICAST      : DNSServiceFlags = 0x400;
+pub const FLAGS_FORCE                : DNSServiceFlags = 0x800;
+pub const FLAGS_RETURN_INTERMEDIATES : DNSServiceFlags = 0x1000;
+pub const FLAGS_SHARE_CONNECTION     : DNSServiceFlags = 0x4000;
+pub const FLAGS_SUPPRESS_UNUSABLE    : DNSServiceFlags = 0x8000;
+pub const FLAGS_TIMEOUT              : DNSServiceFlags = 0x10000;
+pub const FLAGS_INCLUDE_P2P          : DNSServiceFlags = 0x20000;
+pub const FLAGS_WAKE_ON_RESOLVE      : DNSServiceFlags = 0x40000;
+pub const FLAGS_BACKGROUND_TRAFFIC_CLASS : DNSServiceFlags = 0x80000;
+pub const FLAGS_INCLUDE_AWDL         : DNSServiceFlags = 0x100000;
+pub const FLAGS_THRESHOLD_ONE        : DNSServiceFlags = 0x2000000;
+pub const FLAGS_THRESHOLD_FINDER     : DNSServiceFlags = 0x4000000;
 
 /// Maximum length of full name including trailing dot and terminating NULL
 ///
@@ -39,15 +50,29 @@ pub const INTERFACE_INDEX_ANY : u32 = 0;
 pub const INTERFACE_INDEX_LOCAL_ONLY : u32 = !0;
 pub const INTERFACE_INDEX_UNICAST : u32 = !1;
 pub const INTERFACE_INDEX_P2P : u32 = !2;
+pub const INTERFACE_INDEX_BLE : u32 = !3;
+
+pub type DNSServiceProtocol = u32;
+pub const PROTOCOL_IPV4 : DNSServiceProtocol = 0x01;
+pub const PROTOCOL_IPV6 : DNSServiceProtocol = 0x02;
+pub const PROTOCOL_UDP : DNSServiceProtocol = 0x10;
+pub const PROTOCOL_TCP : DNSServiceProtocol = 0x20;
+
+pub const PROPERTY_DAEMON_VERSION : &[u8] = b"DaemonVersion\0";
+
+// struct sockaddr; only read through raw bytes
+pub enum SockAddr{}
 
 macro_rules! c_api_enum {
-	($name:ident : $ty:tt => $($case:ident = $val:expr,)* ) => (
+	($(#[$attr:meta])* $name:ident : $ty:tt => $($case:ident = $val:expr,)* ) => (
+		$(#[$attr])*
 		#[derive(Clone,Copy,Eq,PartialEq,Ord,PartialOrd,Hash,Debug)]
 		#[repr($ty)]
 		pub enum $name {
 			$($case = $val,)*
 		}
 		impl $name {
+			/// Map raw value to known code
 			pub fn try_from(value: $ty) -> Option<$name> {
 				$(if value == $val {
 					Some($name::$case)
@@ -71,11 +96,12 @@ c_api_enum!{DNSServiceNoError: i32 =>
 	ConfigChanged         = -65791,
 	MemFree               = -65792,
 }
-/// Known error codes
-///
-/// See [`DNSServiceErrorType`](https://developer.apple.com/documentation/dnssd/1823426-anonymous)
-#[allow(missing_docs)]
-c_api_enum!{DNSServiceError: i32 =>
+c_api_enum!{
+	/// Known error codes
+	///
+	/// See [`DNSServiceErrorType`](https://developer.apple.com/documentation/dnssd/1823426-anonymous)
+	#[allow(missing_docs)]
+	DNSServiceError: i32 =>
 	Unknown               = -65537,
 	NoSuchName            = -65538,
 	NoMemory              = -65539,
@@ -89,14 +115,36 @@ c_api_enum!{DNSServiceError: i32 =>
 	AlreadyRegistered     = -65547,
 	NameConflict          = -65548,
 	Invalid               = -65549,
+	Firewall              = -65550,
 	Incompatible          = -65551,
 	BadInterfaceIndex     = -65552,
 	Refused               = -65553,
 	NoSuchRecord          = -65554,
 	NoAuth                = -65555,
 	NoSuchKey             = -65556,
-	NoValue               = -65557,
-	BufferTooSmall        = -65558,
+	NATTraversal          = -65557,
+	DoubleNAT             = -65558,
+	BadTime               = -65559,
+	BadSig                = -65560,
+	BadKey                = -65561,
+	Transient             = -65562,
+	ServiceNotRunning     = -65563,
+	NATPortMappingUnsupported = -65564,
+	NATPortMappingDisabled = -65565,
+	NoRouter              = -65566,
+	PollingMode           = -65567,
+	Timeout               = -65568,
+	DefunctConnection     = -65569,
+}
+
+#[allow(non_upper_case_globals)]
+impl DNSServiceError {
+	/// Old name of [`NATTraversal`](#variant.NATTraversal)
+	#[deprecated(note = "use DNSServiceError::NATTraversal")]
+	pub const NoValue: DNSServiceError = DNSServiceError::NATTraversal;
+	/// Old name of [`DoubleNAT`](#variant.DoubleNAT)
+	#[deprecated(note = "use DNSServiceError::DoubleNAT")]
+	pub const BufferTooSmall: DNSServiceError = DNSServiceError::DoubleNAT;
 }
 
 pub type DNSServiceDomainEnumReply = Option<extern "C" fn(
@@ -158,6 +206,33 @@ pub type DNSServiceQueryRecordReply = Option<extern "C" fn(
 	ttl: u32,
 	context: *mut c_void
 )>;
+pub type DNSServiceGetAddrInfoReply = Option<extern "C" fn(
+	sd_ref: DNSServiceRef,
+	flags: DNSServiceFlags,
+	interface_index: u32,
+	error_code: DNSServiceErrorType,
+	hostname: *const c_char,
+	address: *const SockAddr,
+	ttl: u32,
+	context: *mut c_void
+)>;
+pub type DNSServiceNATPortMappingReply = Option<extern "C" fn(
+	sd_ref: DNSServiceRef,
+	flags: DNSServiceFlags,
+	interface_index: u32,
+	error_code: DNSServiceErrorType,
+	external_address: u32,
+	protocol: DNSServiceProtocol,
+	internal_port: u16,
+	external_port: u16,
+	ttl: u32,
+	context: *mut c_void
+)>;
+pub type DNSServiceSleepKeepaliveReply = Option<extern "C" fn(
+	sd_ref: DNSServiceRef,
+	error_code: DNSServiceErrorType,
+	context: *mut c_void
+)>;
 
 extern "C" {
 	pub fn DNSServiceRefSockFD(sd_ref: DNSServiceRef) -> c_int;
@@ -267,6 +342,125 @@ extern "C" {
 	) -> c_int;
 }
 
+// not available in avahi compat
+#[cfg(not(avahi_compat))]
+extern "C" {
+	pub fn DNSServiceGetAddrInfo(
+		sd_ref: *mut DNSServiceRef,
+		flags: DNSServiceFlags,
+		interface_index: u32,
+		protocol: DNSServiceProtocol,
+		hostname: *const c_char,
+		callback: DNSServiceGetAddrInfoReply,
+		context: *mut c_void
+	) -> DNSServiceErrorType;
+	pub fn DNSServiceNATPortMappingCreate(
+		sd_ref: *mut DNSServiceRef,
+		flags: DNSServiceFlags,
+		interface_index: u32,
+		protocol: DNSServiceProtocol,
+		internal_port: u16,
+		external_port: u16,
+		ttl: u32,
+		callback: DNSServiceNATPortMappingReply,
+		context: *mut c_void
+	) -> DNSServiceErrorType;
+	pub fn DNSServiceGetProperty(
+		property: *const c_char,
+		result: *mut c_void,
+		size: *mut u32
+	) -> DNSServiceErrorType;
+}
+
+#[cfg(avahi_compat)]
+mod ffi_avahi {
+	//! Replacements for functions missing in avahi compat; they fail
+	//! like the stubs avahi provides for other unsupported functions.
+	#![allow(non_snake_case)]
+	use std::os::raw::{c_char,c_int,c_void};
+	use super::*;
+
+	const UNSUPPORTED : DNSServiceErrorType = DNSServiceError::Unsupported as DNSServiceErrorType;
+
+	pub unsafe fn DNSServiceGetAddrInfo(
+		_sd_ref: *mut DNSServiceRef,
+		_flags: DNSServiceFlags,
+		_interface_index: u32,
+		_protocol: DNSServiceProtocol,
+		_hostname: *const c_char,
+		_callback: DNSServiceGetAddrInfoReply,
+		_context: *mut c_void
+	) -> DNSServiceErrorType {
+		UNSUPPORTED
+	}
+
+	pub unsafe fn DNSServiceNATPortMappingCreate(
+		_sd_ref: *mut DNSServiceRef,
+		_flags: DNSServiceFlags,
+		_interface_index: u32,
+		_protocol: DNSServiceProtocol,
+		_internal_port: u16,
+		_external_port: u16,
+		_ttl: u32,
+		_callback: DNSServiceNATPortMappingReply,
+		_context: *mut c_void
+	) -> DNSServiceErrorType {
+		UNSUPPORTED
+	}
+
+	pub unsafe fn DNSServiceGetProperty(
+		_property: *const c_char,
+		_result: *mut c_void,
+		_size: *mut u32
+	) -> DNSServiceErrorType {
+		UNSUPPORTED
+	}
+
+	pub unsafe fn DNSServiceSleepKeepalive(
+		_sd_ref: *mut DNSServiceRef,
+		_flags: DNSServiceFlags,
+		_fd: c_int,
+		_timeout: u32,
+		_callback: DNSServiceSleepKeepaliveReply,
+		_context: *mut c_void
+	) -> DNSServiceErrorType {
+		UNSUPPORTED
+	}
+}
+#[cfg(avahi_compat)]
+pub use self::ffi_avahi::*;
+
+// only provided by mDNSResponder on unix platforms
+#[cfg(all(unix, not(avahi_compat)))]
+extern "C" {
+	pub fn DNSServiceSleepKeepalive(
+		sd_ref: *mut DNSServiceRef,
+		flags: DNSServiceFlags,
+		fd: c_int,
+		timeout: u32,
+		callback: DNSServiceSleepKeepaliveReply,
+		context: *mut c_void
+	) -> DNSServiceErrorType;
+}
+
+// deliver callbacks on a libdispatch queue instead of through the fd
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+pub enum DispatchQueueT{}
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+pub type DispatchQueue = *mut DispatchQueueT;
+
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+extern "C" {
+	pub fn DNSServiceSetDispatchQueue(
+		sd_ref: DNSServiceRef,
+		queue: DispatchQueue
+	) -> DNSServiceErrorType;
+
+	// libdispatch (part of libSystem)
+	pub fn dispatch_queue_create(label: *const c_char, attr: *mut c_void) -> DispatchQueue;
+	pub fn dispatch_sync_f(queue: DispatchQueue, context: *mut c_void, work: extern "C" fn(*mut c_void));
+}
+
 // TXTRecordRef utils not wrapped - should be easy enough to implement
 // in pure rust
 
@@ -1,5 +1,27 @@
 #![macro_use]
 
+use std::fmt;
+
+// `LongLivedQuery` -> `long-lived-query`, `IncludeP2P` -> `include-p2p`,
+// `IPv4` -> `ipv4`
+pub(crate) fn write_flag_name(f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+	let mut prev_lower = false;
+	for c in name.chars() {
+		if c.is_ascii_uppercase() && prev_lower {
+			f.write_str("-")?;
+		}
+		prev_lower = c.is_ascii_lowercase();
+		fmt::Write::write_char(f, c.to_ascii_lowercase())?;
+	}
+	Ok(())
+}
+
+// ignores case, `-` and `_`
+pub(crate) fn flag_name_matches(name: &str, input: &str) -> bool {
+	let mut input = input.chars().filter(|&c| c != '-' && c != '_');
+	name.chars().all(|c| input.next().is_some_and(|i| i.eq_ignore_ascii_case(&c))) && input.next().is_none()
+}
+
 macro_rules! flags_ops {
 	($flagset:ident: $ty:ty: $flags:ident: $($case:ident,)*) => (
 		impl ::std::ops::BitOr<$flags> for $flags {
@@ -14,17 +36,103 @@ macro_rules! flags_ops {
 			pub fn none() -> Self {
 				$flagset(0)
 			}
+
+			/// Whether no flag is set.
+			pub fn is_empty(self) -> bool {
+				0 == self.0
+			}
+
+			/// Whether all flags of `flags` (a flag or a set) are set.
+			pub fn contains<F: Into<$flagset>>(self, flags: F) -> bool {
+				let flags = flags.into();
+				flags.0 == (self.0 & flags.0)
+			}
+
+			/// Iterate over the set flags.
+			pub fn iter(self) -> impl Iterator<Item = $flags> {
+				IntoIterator::into_iter([$($flags::$case,)*]).filter(move |&flag| self & flag)
+			}
 		}
 
+		impl ::std::iter::FromIterator<$flags> for $flagset {
+			fn from_iter<I: IntoIterator<Item = $flags>>(iter: I) -> Self {
+				iter.into_iter().fold($flagset::none(), |flags, flag| flags | flag)
+			}
+		}
+
+		// flag names separated by ` | `, or `none`
 		impl ::std::fmt::Debug for $flagset {
 			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-				write!(f, "[")?;
+				if self.is_empty() {
+					return f.write_str("none");
+				}
+				for (i, flag) in self.iter().enumerate() {
+					if i > 0 {
+						f.write_str(" | ")?;
+					}
+					write!(f, "{:?}", flag)?;
+				}
+				Ok(())
+			}
+		}
+
+		// flag names (like `long-lived-query`) separated by `|`, or `none`
+		impl ::std::fmt::Display for $flagset {
+			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+				if self.is_empty() {
+					return f.write_str("none");
+				}
+				for (i, flag) in self.iter().enumerate() {
+					if i > 0 {
+						f.write_str("|")?;
+					}
+					::std::fmt::Display::fmt(&flag, f)?;
+				}
+				Ok(())
+			}
+		}
+
+		// flag names separated by `|` or `,`; `none` (or nothing) for the
+		// empty set
+		impl ::std::str::FromStr for $flagset {
+			type Err = ::std::io::Error;
+
+			fn from_str(s: &str) -> ::std::io::Result<Self> {
+				let s = s.trim();
+				if s.eq_ignore_ascii_case("none") {
+					return Ok($flagset::none());
+				}
+				s.split(|c| c == '|' || c == ',')
+					.map(str::trim)
+					.filter(|name| !name.is_empty())
+					.map(str::parse::<$flags>)
+					.collect()
+			}
+		}
+
+		// kebab-case name, like `long-lived-query`
+		impl ::std::fmt::Display for $flags {
+			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+				crate::flags_macro::write_flag_name(f, match *self {
+					$($flags::$case => stringify!($case),)*
+				})
+			}
+		}
+
+		// kebab-case or variant name, ignoring case
+		impl ::std::str::FromStr for $flags {
+			type Err = ::std::io::Error;
+
+			fn from_str(s: &str) -> ::std::io::Result<Self> {
 				$(
-					if *self & $flags::$case {
-						write!(f, "{:?},", $flags::$case)?;
+					if crate::flags_macro::flag_name_matches(stringify!($case), s) {
+						return Ok($flags::$case);
 					}
 				)*
-				write!(f, "]")
+				Err(::std::io::Error::new(
+					::std::io::ErrorKind::InvalidInput,
+					format!("unknown {}: {:?}", stringify!($flags), s),
+				))
 			}
 		}
 
@@ -40,9 +148,9 @@ macro_rules! flags_ops {
 			}
 		}
 
-		impl ::std::convert::Into<$ty> for $flagset {
-			fn into(self) -> $ty {
-				self.0
+		impl ::std::convert::From<$flagset> for $ty {
+			fn from(value: $flagset) -> Self {
+				value.0
 			}
 		}
 
@@ -80,6 +188,36 @@ macro_rules! flags_ops {
 				0 != ($flagset::from(self).0 & rhs.0)
 			}
 		}
+
+		// serialized as list of flag names
+		#[cfg(feature = "serde")]
+		impl ::serde::Serialize for $flagset {
+			fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				use ::serde::ser::SerializeSeq;
+				let mut seq = serializer.serialize_seq(None)?;
+				$(
+					if *self & $flags::$case {
+						seq.serialize_element(stringify!($case))?;
+					}
+				)*
+				seq.end()
+			}
+		}
+
+		#[cfg(feature = "serde")]
+		impl<'de> ::serde::Deserialize<'de> for $flagset {
+			fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				let names = <Vec<String> as ::serde::Deserialize>::deserialize(deserializer)?;
+				let mut flags = $flagset::none();
+				for name in names {
+					flags |= match &*name {
+						$(stringify!($case) => $flags::$case,)*
+						_ => return Err(<D::Error as ::serde::de::Error>::unknown_variant(&name, &[$(stringify!($case),)*])),
+					};
+				}
+				Ok(flags)
+			}
+		}
 	);
 }
 
@@ -87,10 +225,10 @@ macro_rules! flag_mapping {
 	($flagset:ident: $flags:ident => $ty:ty:
 		$($case:ident => $value:expr,)*
 	) => (
-		impl Into<$ty> for $flagset {
-			fn into(self) -> $ty {
+		impl From<$flagset> for $ty {
+			fn from(value: $flagset) -> Self {
 				$(
-					(if self & $flags::$case {
+					(if value & $flags::$case {
 						$value
 					} else {
 						0
@@ -114,3 +252,68 @@ macro_rules! flag_mapping {
 		}
 	);
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::service::{
+		GetAddrInfoFlag,
+		GetAddrInfoFlags,
+		Protocol,
+		Protocols,
+		RegisterFlag,
+		RegisterFlags,
+	};
+
+	#[test]
+	fn flag_names() {
+		assert_eq!(GetAddrInfoFlag::LongLivedQuery.to_string(), "long-lived-query");
+		assert_eq!(RegisterFlag::IncludeP2P.to_string(), "include-p2p");
+		assert_eq!(RegisterFlag::IncludeAWDL.to_string(), "include-awdl");
+		assert_eq!(Protocol::IPv4.to_string(), "ipv4");
+
+		for name in &["long-lived-query", "LongLivedQuery", "long_lived_query", "LONG-LIVED-QUERY"] {
+			assert_eq!(name.parse::<GetAddrInfoFlag>().unwrap(), GetAddrInfoFlag::LongLivedQuery);
+		}
+		assert_eq!("include-p2p".parse::<RegisterFlag>().unwrap(), RegisterFlag::IncludeP2P);
+		for name in &["", "long-lived", "long-lived-query-x", "unique"] {
+			let e = name.parse::<GetAddrInfoFlag>().unwrap_err();
+			assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput, "{:?}", name);
+		}
+	}
+
+	#[test]
+	fn flag_set_display() {
+		assert_eq!(RegisterFlags::none().to_string(), "none");
+		assert_eq!(format!("{:?}", RegisterFlags::none()), "none");
+		let flags = RegisterFlag::Unique | RegisterFlag::IncludeP2P;
+		assert_eq!(flags.to_string(), "unique|include-p2p");
+		assert_eq!(format!("{:?}", flags), "Unique | IncludeP2P");
+		assert_eq!((Protocol::IPv6 | Protocol::IPv4).to_string(), "ipv4|ipv6");
+	}
+
+	#[test]
+	fn flag_set_parse() {
+		assert_eq!("none".parse::<RegisterFlags>().unwrap(), RegisterFlags::none());
+		assert_eq!("".parse::<RegisterFlags>().unwrap(), RegisterFlags::none());
+		let flags = RegisterFlag::Unique | RegisterFlag::IncludeP2P;
+		assert_eq!("include-p2p | unique".parse::<RegisterFlags>().unwrap(), flags);
+		assert_eq!("Unique,IncludeP2P,unique".parse::<RegisterFlags>().unwrap(), flags);
+		assert!("unique|bogus".parse::<RegisterFlags>().is_err());
+		assert!("none|unique".parse::<RegisterFlags>().is_err());
+	}
+
+	#[test]
+	fn flag_set_round_trip() {
+		let sets = [
+			GetAddrInfoFlags::none(),
+			GetAddrInfoFlag::LongLivedQuery.into(),
+			GetAddrInfoFlag::LongLivedQuery | GetAddrInfoFlag::BackgroundTrafficClass,
+		];
+		for flags in &sets {
+			assert_eq!(flags.to_string().parse::<GetAddrInfoFlags>().unwrap(), *flags);
+		}
+		let all: Protocols = [Protocol::IPv4, Protocol::IPv6].iter().copied().collect();
+		assert_eq!(all.to_string().parse::<Protocols>().unwrap(), all);
+		assert_eq!(all.iter().collect::<Vec<_>>(), [Protocol::IPv4, Protocol::IPv6]);
+	}
+}
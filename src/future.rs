@@ -1,117 +1,127 @@
-use futures::sync::oneshot;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::future::Future;
 use std::io;
-use std::rc::Rc;
-use tokio_core::reactor::{Remote};
-
-use evented::EventedDNSService;
-use raw::DNSService;
-use raw_box::RawBox;
-use remote::GetRemote;
-
-struct Inner<T> {
-	service: EventedDNSService,
-	_sender: RawBox<oneshot::Sender<io::Result<T>>>,
-	receiver: oneshot::Receiver<io::Result<T>>,
-}
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context,Poll};
 
-pub struct ServiceFuture<T>(Option<Inner<T>>);
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::raw::DNSService;
+use crate::raw_box::RawBox;
+use crate::stop_handle::StopHandle;
+use crate::stream::ServiceStream;
 
-impl<T> ServiceFuture<T> {
+pub struct ServiceFuture<T>(Option<ServiceStream<T>>);
+
+impl<T: Send + 'static> ServiceFuture<T> {
 	pub fn new<F>(f: F) -> io::Result<Self>
-	where F: FnOnce(*mut oneshot::Sender<io::Result<T>>) -> io::Result<EventedDNSService>
+	where F: FnOnce(*mut mpsc::UnboundedSender<Result<T, Error>>) -> io::Result<EventedDNSService>
 	{
-		let (sender, receiver) = oneshot::channel::<io::Result<T>>();
-		let sender = RawBox::new(sender);
+		Ok(ServiceFuture(Some(ServiceStream::new(f)?)))
+	}
+}
 
-		let service = f(sender.get_ptr())?;
+impl<T> ServiceFuture<T> {
+	#[cfg(any(test, feature = "mock", all(avahi_compat, feature = "avahi-dbus"), all(unix, feature = "mdns"), all(windows, feature = "windows-dnsapi")))]
+	pub fn from_stream(stream: ServiceStream<T>) -> Self {
+		ServiceFuture(Some(stream))
+	}
 
-		Ok(ServiceFuture(Some(Inner{
-			service: service,
-			_sender: sender,
-			receiver: receiver,
-		})))
+	pub fn into_stream(mut self) -> ServiceStream<T> {
+		self.0.take().expect("can only get ready once")
 	}
 
-	fn inner(&self) -> &Inner<T> {
+	fn stream(&self) -> &ServiceStream<T> {
 		self.0.as_ref().expect("can only get ready once")
 	}
 
-	fn inner_mut(&mut self) -> &mut Inner<T> {
-		self.0.as_mut().expect("can only get ready once")
+	pub fn service(&self) -> DNSService {
+		self.stream().service()
+	}
+
+	pub fn try_service(&self) -> io::Result<DNSService> {
+		self.stream().try_service()
 	}
 
-	pub fn service(&self) -> &DNSService {
-		&self.inner().service.service()
+	pub fn as_raw(&self) -> Option<crate::ffi::DNSServiceRef> {
+		self.try_service().ok().map(|service| service.as_raw())
+	}
+
+	pub fn stop_handle(&self) -> StopHandle {
+		self.stream().stop_handle()
 	}
 }
 
-impl<T> futures::Future for ServiceFuture<T> {
-	type Item = (EventedDNSService, T);
-	type Error = io::Error;
+impl<T> Future for ServiceFuture<T> {
+	type Output = Result<(ServiceStream<T>, T), Error>;
 
-	fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
-		if self.0.is_none() {
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let stream = match self.0.as_mut() {
 			// can only get ready once.
-			return Ok(Async::NotReady);
-		}
-		self.inner_mut().service.poll()?;
-		match self.inner_mut().receiver.poll() {
-			Ok(Async::Ready(item)) => Ok(Async::Ready((
-				self.0.take().unwrap().service,
-				item?
-			))),
-			Ok(Async::NotReady) => Ok(Async::NotReady),
-			Err(futures::Canceled) => unreachable!(),
+			None => return Poll::Pending,
+			Some(stream) => stream,
+		};
+		match stream.poll_next_unpin(cx) {
+			Poll::Ready(Some(item)) => {
+				let stream = self.0.take().unwrap();
+				Poll::Ready(item.map(|item| (stream, item)))
+			},
+			// only ends when the operation ended (stopped, timed out, ...)
+			Poll::Ready(None) => {
+				let reason = stream.termination_reason();
+				self.0 = None;
+				Poll::Ready(Err(crate::TerminationReason::into_error(reason)))
+			},
+			Poll::Pending => Poll::Pending,
 		}
 	}
 }
 
-impl<T> GetRemote for ServiceFuture<T> {
-	fn remote(&self) -> &Remote {
-		self.inner().service.remote()
-	}
-}
 
 pub struct ServiceFutureSingle<T> {
-	service: Rc<EventedDNSService>,
-	_sender: RawBox<oneshot::Sender<io::Result<T>>>,
-	receiver: oneshot::Receiver<io::Result<T>>,
+	service: Arc<EventedDNSService>,
+	_sender: RawBox<mpsc::UnboundedSender<Result<T, Error>>>,
+	receiver: mpsc::UnboundedReceiver<Result<T, Error>>,
 }
 
 impl<T> ServiceFutureSingle<T> {
-	pub fn new<R, F>(service: Rc<EventedDNSService>, f: F) -> io::Result<(Self, R)>
-	where F: FnOnce(*mut oneshot::Sender<io::Result<T>>) -> io::Result<R>
+	pub fn new<R, F>(service: Arc<EventedDNSService>, f: F) -> io::Result<(Self, R)>
+	where F: FnOnce(*mut mpsc::UnboundedSender<Result<T, Error>>) -> io::Result<R>
 	{
-		let (sender, receiver) = oneshot::channel::<io::Result<T>>();
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
 		let sender = RawBox::new(sender);
 
 		let res = f(sender.get_ptr())?;
 
 		Ok((ServiceFutureSingle{
-			service: service,
+			service,
 			_sender: sender,
-			receiver: receiver,
+			receiver,
 		}, res))
 	}
+
+	/// Drop the future but keep the callback context alive as long as
+	/// the underlying service lives.
+	pub fn keep_alive(self)
+	where T: Send + 'static
+	{
+		self.service.service().keep_alive(Box::new(self._sender));
+	}
 }
 
-impl<T> futures::Future for ServiceFutureSingle<T> {
-	type Item = T;
-	type Error = io::Error;
+impl<T> Future for ServiceFutureSingle<T> {
+	type Output = Result<T, Error>;
 
-	fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
-		self.service.poll()?;
-		match self.receiver.poll() {
-			Ok(Async::Ready(item)) => Ok(Async::Ready(item?)),
-			Ok(Async::NotReady) => Ok(Async::NotReady),
-			Err(futures::Canceled) => unreachable!(),
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		self.service.poll(cx)?;
+		match self.receiver.poll_next_unpin(cx) {
+			Poll::Ready(Some(item)) => Poll::Ready(item),
+			// sender is owned by us and outlives the receiver
+			Poll::Ready(None) => unreachable!(),
+			Poll::Pending => Poll::Pending,
 		}
 	}
 }
 
-impl<T> GetRemote for ServiceFutureSingle<T> {
-	fn remote(&self) -> &Remote {
-		self.service.remote()
-	}
-}
@@ -0,0 +1,112 @@
+//! Conversions to and from [`hickory-proto`](https://docs.rs/hickory-proto) types
+
+use hickory_proto::rr::{DNSClass,Name,RData,Record};
+use hickory_proto::serialize::binary::{BinDecoder,BinEncodable,BinEncoder,Restrict};
+use std::convert::TryFrom;
+use std::error;
+use std::io;
+
+use crate::dns_types::RecordType;
+use crate::record_data::RecordData;
+use crate::service::QueryRecordResult;
+
+fn invalid_data<E: Into<Box<dyn error::Error + Send + Sync>>>(e: E) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// parse full name as returned by the daemon: `\DDD` escapes are
+// decimal (hickory's own parser treats them as octal)
+fn parse_fullname(fullname: &str) -> io::Result<Name> {
+	let invalid = || invalid_data("invalid escape sequence in name");
+	let bytes = fullname.as_bytes();
+	let mut labels = Vec::new();
+	let mut label = Vec::new();
+	let mut pos = 0;
+	while pos < bytes.len() {
+		match bytes[pos] {
+			b'.' => {
+				if label.is_empty() && !(labels.is_empty() && pos + 1 == bytes.len()) {
+					return Err(invalid_data("empty label in name"));
+				}
+				labels.push(std::mem::take(&mut label));
+				pos += 1;
+			},
+			b'\\' => {
+				let digits = bytes.get(pos + 1..pos + 4).filter(|d| d.iter().all(u8::is_ascii_digit));
+				if let Some(digits) = digits {
+					let value = digits.iter().fold(0u32, |v, d| v * 10 + u32::from(d - b'0'));
+					if value > 255 {
+						return Err(invalid());
+					}
+					label.push(value as u8);
+					pos += 4;
+				} else {
+					label.push(*bytes.get(pos + 1).ok_or_else(invalid)?);
+					pos += 2;
+				}
+			},
+			c => {
+				label.push(c);
+				pos += 1;
+			},
+		}
+	}
+	if !label.is_empty() {
+		labels.push(label);
+	}
+	// root name "." results in a single empty label
+	labels.retain(|l| !l.is_empty());
+	Name::from_labels(labels).map_err(invalid_data)
+}
+
+fn rdata_to_bytes(rdata: &RData) -> io::Result<Vec<u8>> {
+	let mut bytes = Vec::new();
+	let mut encoder = BinEncoder::new(&mut bytes);
+	// no name compression: pointers would refer into a message
+	encoder.set_canonical_names(true);
+	rdata.emit(&mut encoder).map_err(invalid_data)?;
+	Ok(bytes)
+}
+
+fn rdata_from_bytes(rr_type: RecordType, rdata: &[u8]) -> io::Result<RData> {
+	if rdata.len() > 0xffff {
+		return Err(invalid_data("record data too long"));
+	}
+	let mut decoder = BinDecoder::new(rdata);
+	RData::read(&mut decoder, u16::from(rr_type).into(), Restrict::new(rdata.len() as u16))
+		.map_err(invalid_data)
+}
+
+/// Convert query result to a hickory record
+///
+/// Fails if the name or the record data can't be parsed.
+impl TryFrom<QueryRecordResult> for Record {
+	type Error = io::Error;
+
+	fn try_from(result: QueryRecordResult) -> io::Result<Self> {
+		let name = parse_fullname(&result.fullname)?;
+		let rdata = rdata_from_bytes(result.rr_type, &result.rdata)?;
+		let mut record = Record::from_rdata(name, result.ttl, rdata);
+		record.set_dns_class(DNSClass::from(u16::from(result.rr_class)));
+		Ok(record)
+	}
+}
+
+/// Convert hickory record data (e.g. to register records)
+impl<'a> TryFrom<&'a RData> for RecordData {
+	type Error = io::Error;
+
+	fn try_from(rdata: &'a RData) -> io::Result<Self> {
+		let rr_type = RecordType::from(u16::from(rdata.record_type()));
+		RecordData::parse(rr_type, &rdata_to_bytes(rdata)?)
+	}
+}
+
+/// Convert record data to hickory record data
+impl<'a> TryFrom<&'a RecordData> for RData {
+	type Error = io::Error;
+
+	fn try_from(data: &'a RecordData) -> io::Result<Self> {
+		rdata_from_bytes(data.rr_type(), &data.to_bytes()?)
+	}
+}
@@ -0,0 +1,58 @@
+use std::io;
+
+fn invalid_input(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Check a service instance name (unescaped, e.g. `"My Printer"`)
+///
+/// Instance names can contain any UTF-8 text
+/// ([RFC 6763 section 4.1.1](https://tools.ietf.org/html/rfc6763#section-4.1.1)),
+/// but must not be empty, must not exceed 63 bytes (a single DNS label)
+/// and must not contain ASCII control characters.
+pub fn validate_instance_name(name: &str) -> io::Result<()> {
+	if name.is_empty() {
+		return Err(invalid_input("empty instance name"));
+	}
+	if name.len() > 63 {
+		return Err(invalid_input("instance name longer than 63 bytes"));
+	}
+	if name.chars().any(|c| c.is_ascii_control()) {
+		return Err(invalid_input("control character in instance name"));
+	}
+	Ok(())
+}
+
+/// Normalize a service instance name to Unicode NFC
+///
+/// Names are compared byte-wise; RFC 6763 requires NFC so the same
+/// name typed on different platforms (e.g. with decomposed umlauts)
+/// matches.  Also see
+/// [`validate_instance_name`](fn.validate_instance_name.html).
+#[cfg(feature = "idna")]
+pub fn normalize_instance_name(name: &str) -> String {
+	use unicode_normalization::UnicodeNormalization;
+
+	name.nfc().collect()
+}
+
+/// Convert a domain to its ASCII (punycode) form
+///
+/// Labels with non-ASCII characters are mapped and encoded following
+/// IDNA (UTS #46), e.g. for use with unicast DNS; a trailing dot is
+/// kept.  Only use this for domains, not for service instance names
+/// (which are plain UTF-8).
+#[cfg(feature = "idna")]
+pub fn domain_to_ascii(domain: &str) -> io::Result<String> {
+	idna::domain_to_ascii(domain).map_err(|_| invalid_input("invalid internationalized domain name"))
+}
+
+/// Convert a domain with punycode labels to Unicode
+///
+/// Reverse of [`domain_to_ascii`](fn.domain_to_ascii.html).
+#[cfg(feature = "idna")]
+pub fn domain_to_unicode(domain: &str) -> io::Result<String> {
+	let (result, status) = idna::domain_to_unicode(domain);
+	status.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid internationalized domain name"))?;
+	Ok(result)
+}
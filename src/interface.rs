@@ -1,6 +1,23 @@
+use std::error;
+use std::ffi::CString;
 use std::fmt;
+use std::io;
+use std::str::FromStr;
 
-use ffi;
+use crate::ffi;
+
+#[cfg(unix)]
+use libc::{if_indextoname,if_nametoindex};
+
+#[cfg(windows)]
+#[link(name = "iphlpapi")]
+extern "system" {
+	fn if_nametoindex(name: *const ::std::os::raw::c_char) -> u32;
+	fn if_indextoname(index: u32, name: *mut ::std::os::raw::c_char) -> *mut ::std::os::raw::c_char;
+}
+
+// larger than IF_NAMESIZE on all supported platforms
+const NAME_BUFFER_SIZE : usize = 256;
 
 /// Network interface index
 ///
@@ -10,13 +27,16 @@ pub struct InterfaceIndex(u32);
 
 impl InterfaceIndex {
 	/// Construct new `InterfaceIndex` from raw index and makes sure
-	/// not to use the special reserved values (`0` and `!0`).
+	/// not to use the special reserved values (`0` for "any", `!0` for
+	/// "local only", and the unicast, P2P and BLE indices; see
+	/// [`Interface`](enum.Interface.html)).
 	pub fn from_raw(ndx: u32) -> Option<Self> {
 		match ndx {
 			ffi::INTERFACE_INDEX_ANY => None,
 			ffi::INTERFACE_INDEX_LOCAL_ONLY => None,
 			ffi::INTERFACE_INDEX_UNICAST => None,
 			ffi::INTERFACE_INDEX_P2P => None,
+			ffi::INTERFACE_INDEX_BLE => None,
 			_ => Some(InterfaceIndex(ndx)),
 		}
 	}
@@ -25,11 +45,45 @@ impl InterfaceIndex {
 	pub fn into_raw(self) -> u32 {
 		self.0
 	}
+
+	/// Name of the interface (e.g. `"en0"` or `"eth0"`)
+	///
+	/// Returns `None` if the interface doesn't exist (anymore).
+	///
+	/// See [`if_indextoname`](http://man7.org/linux/man-pages/man3/if_indextoname.3.html).
+	pub fn name(self) -> Option<String> {
+		let mut buf = [0u8; NAME_BUFFER_SIZE];
+		let res = unsafe { if_indextoname(self.0, buf.as_mut_ptr() as *mut _) };
+		if res.is_null() {
+			return None;
+		}
+		let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+		Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+	}
 }
 
-impl Into<u32> for InterfaceIndex {
-	fn into(self) -> u32 {
-		self.into_raw()
+// serialized as raw index
+#[cfg(feature = "serde")]
+impl serde::Serialize for InterfaceIndex {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_u32(self.0)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for InterfaceIndex {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let ndx = u32::deserialize(deserializer)?;
+		InterfaceIndex::from_raw(ndx).ok_or_else(|| serde::de::Error::invalid_value(
+			serde::de::Unexpected::Unsigned(ndx as u64),
+			&"interface index other than the reserved values for any, local-only, unicast, p2p and ble",
+		))
+	}
+}
+
+impl From<InterfaceIndex> for u32 {
+	fn from(value: InterfaceIndex) -> Self {
+		value.into_raw()
 	}
 }
 
@@ -39,11 +93,25 @@ impl fmt::Debug for InterfaceIndex {
 	}
 }
 
+impl fmt::Display for InterfaceIndex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.name() {
+			Some(name) => f.write_str(&name),
+			None => write!(f, "index:{}", self.0),
+		}
+	}
+}
+
 /// Network interface
 ///
 /// Either identifies a single interface (by index) or the special "Any"
 /// or "LocalOnly" interfaces.
+///
+/// Displays and parses as `any`, `local-only`, `unicast`, `p2p`, `ble`,
+/// the interface name (like `lo0`) or `index:<n>` (for interfaces
+/// without a name).
 #[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Interface {
 	/// Any interface; depending on domain name this means either
 	/// multicast or unicast
@@ -51,11 +119,23 @@ pub enum Interface {
 	/// Single interface
 	Index(InterfaceIndex),
 	/// Local machine only
+	///
+	/// Services registered on this interface are only visible to
+	/// clients on the same machine (e.g. to publish services bound to
+	/// the loopback address); browsing and resolving only find those.
+	///
+	/// See [`kDNSServiceInterfaceIndexLocalOnly`](https://developer.apple.com/documentation/dnssd/kdnsserviceinterfaceindexlocalonly)
 	LocalOnly,
+	/// Unicast DNS only, even for `local.` names (no multicast)
+	///
 	/// See [`kDNSServiceInterfaceIndexUnicast`](https://developer.apple.com/documentation/dnssd/kdnsserviceinterfaceindexunicast)
 	Unicast,
 	/// See [`kDNSServiceInterfaceIndexP2P`](https://developer.apple.com/documentation/dnssd/kdnsserviceinterfaceindexp2p)
 	PeerToPeer,
+	/// Bluetooth Low Energy
+	///
+	/// See [`kDNSServiceInterfaceIndexBLE`](https://developer.apple.com/documentation/dnssd/kdnsserviceinterfaceindexble)
+	Ble,
 }
 
 impl Interface {
@@ -66,10 +146,35 @@ impl Interface {
 			ffi::INTERFACE_INDEX_LOCAL_ONLY => Interface::LocalOnly,
 			ffi::INTERFACE_INDEX_UNICAST => Interface::Unicast,
 			ffi::INTERFACE_INDEX_P2P => Interface::PeerToPeer,
+			ffi::INTERFACE_INDEX_BLE => Interface::Ble,
 			_ => Interface::Index(InterfaceIndex(raw)),
 		}
 	}
 
+	/// Look up interface by name (e.g. `"en0"` or `"eth0"`)
+	///
+	/// See [`if_nametoindex`](http://man7.org/linux/man-pages/man3/if_nametoindex.3.html).
+	pub fn from_name(name: &str) -> Result<Self, NoSuchInterface> {
+		let not_found = || NoSuchInterface { name: name.to_string() };
+		let c_name = CString::new(name).map_err(|_| not_found())?;
+		let ndx = unsafe { if_nametoindex(c_name.as_ptr()) };
+		match InterfaceIndex::from_raw(ndx) {
+			Some(ndx) => Ok(Interface::Index(ndx)),
+			None => Err(not_found()),
+		}
+	}
+
+	/// Name of a single interface
+	///
+	/// Returns `None` for the special interfaces and for interfaces
+	/// which don't exist (anymore).
+	pub fn name(self) -> Option<String> {
+		match self {
+			Interface::Index(ndx) => ndx.name(),
+			_ => None,
+		}
+	}
+
 	/// Convert to raw value
 	pub fn into_raw(self) -> u32 {
 		match self {
@@ -78,12 +183,148 @@ impl Interface {
 			Interface::LocalOnly => ffi::INTERFACE_INDEX_LOCAL_ONLY,
 			Interface::Unicast => ffi::INTERFACE_INDEX_UNICAST,
 			Interface::PeerToPeer => ffi::INTERFACE_INDEX_P2P,
+			Interface::Ble => ffi::INTERFACE_INDEX_BLE,
+		}
+	}
+}
+
+impl fmt::Display for Interface {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Interface::Any => f.write_str("any"),
+			Interface::Index(ndx) => fmt::Display::fmt(&ndx, f),
+			Interface::LocalOnly => f.write_str("local-only"),
+			Interface::Unicast => f.write_str("unicast"),
+			Interface::PeerToPeer => f.write_str("p2p"),
+			Interface::Ble => f.write_str("ble"),
 		}
 	}
 }
 
-impl Into<u32> for Interface {
-	fn into(self) -> u32 {
-		self.into_raw()
+// special interface names, `index:<n>` or an interface name (like `lo0`)
+impl FromStr for Interface {
+	type Err = io::Error;
+
+	fn from_str(s: &str) -> io::Result<Self> {
+		match s {
+			"any" => return Ok(Interface::Any),
+			"local-only" => return Ok(Interface::LocalOnly),
+			"unicast" => return Ok(Interface::Unicast),
+			"p2p" => return Ok(Interface::PeerToPeer),
+			"ble" => return Ok(Interface::Ble),
+			_ => (),
+		}
+		if let Some(ndx) = s.strip_prefix("index:") {
+			return ndx.parse().ok().and_then(InterfaceIndex::from_raw).map(Interface::Index).ok_or_else(|| io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("invalid interface index: {:?}", ndx),
+			));
+		}
+		Ok(Interface::from_name(s)?)
+	}
+}
+
+impl From<Interface> for u32 {
+	fn from(value: Interface) -> Self {
+		value.into_raw()
+	}
+}
+
+/// Error when looking up an interface by a name which doesn't exist
+///
+/// See [`Interface::from_name`](enum.Interface.html#method.from_name).
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct NoSuchInterface {
+	/// Name which wasn't found
+	pub name: String,
+}
+
+impl fmt::Display for NoSuchInterface {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "no such interface: {:?}", self.name)
+	}
+}
+
+impl error::Error for NoSuchInterface {}
+
+impl From<NoSuchInterface> for io::Error {
+	fn from(e: NoSuchInterface) -> Self {
+		io::Error::new(io::ErrorKind::NotFound, e)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reserved_indices() {
+		for raw in &[
+			ffi::INTERFACE_INDEX_ANY,
+			ffi::INTERFACE_INDEX_LOCAL_ONLY,
+			ffi::INTERFACE_INDEX_UNICAST,
+			ffi::INTERFACE_INDEX_P2P,
+			ffi::INTERFACE_INDEX_BLE,
+		] {
+			assert_eq!(InterfaceIndex::from_raw(*raw), None);
+			assert!(!matches!(Interface::from_raw(*raw), Interface::Index(_)));
+			assert_eq!(Interface::from_raw(*raw).into_raw(), *raw);
+		}
+		assert_eq!(InterfaceIndex::from_raw(7).map(InterfaceIndex::into_raw), Some(7));
+		assert_eq!(Interface::from_raw(7).into_raw(), 7);
+	}
+
+	#[test]
+	fn debug() {
+		// no name lookup in `Debug`
+		let ndx = InterfaceIndex::from_raw(42).unwrap();
+		assert_eq!(format!("{:?}", ndx), "42");
+		assert_eq!(format!("{:?}", Interface::Index(ndx)), "Index(42)");
+	}
+
+	#[test]
+	fn special_round_trip() {
+		let special = [
+			(Interface::Any, "any"),
+			(Interface::LocalOnly, "local-only"),
+			(Interface::Unicast, "unicast"),
+			(Interface::PeerToPeer, "p2p"),
+			(Interface::Ble, "ble"),
+		];
+		for &(interface, name) in &special {
+			assert_eq!(interface.to_string(), name);
+			assert_eq!(name.parse::<Interface>().unwrap(), interface);
+			assert_eq!(interface.name(), None);
+		}
+	}
+
+	#[test]
+	fn index_round_trip() {
+		// index without interface
+		let interface = Interface::from_raw(0x7fff_fff0);
+		assert_eq!(interface.name(), None);
+		assert_eq!(interface.to_string(), "index:2147483632");
+		assert_eq!(interface.to_string().parse::<Interface>().unwrap(), interface);
+
+		assert!("index:0".parse::<Interface>().is_err());
+		assert!("index:4294967295".parse::<Interface>().is_err());
+		assert!("index:x".parse::<Interface>().is_err());
+	}
+
+	#[test]
+	fn name_round_trip() {
+		// loopback interface might not be called `lo`
+		if let Ok(interface) = Interface::from_name("lo") {
+			assert_eq!(interface.name().as_deref(), Some("lo"));
+			assert_eq!(interface.to_string(), "lo");
+			assert_eq!("lo".parse::<Interface>().unwrap(), interface);
+		}
+
+		let e = "no-such-interface0".parse::<Interface>().unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::NotFound);
+		assert_eq!(
+			Interface::from_name("a\0b").unwrap_err(),
+			NoSuchInterface { name: "a\0b".to_string() },
+		);
 	}
 }
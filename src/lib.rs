@@ -2,53 +2,130 @@
 //!
 //! Interesting entry points:
 //!
-//! * [Browses for available services](method.browse.html)
-//! * [Create Connection to register records with](method.connect.html)
+//! * [Browses for available services](method.browse.html) (also [in all browse domains](fn.browse_all_domains.html))
+//! * [Discover services with their addresses](method.discover.html) (or [list them once](fn.resolve_all.html))
+//! * [Create Connection to register records with](method.connect.html) (e.g. [addresses of another host](struct.HostRecord.html))
 //! * [Enumerates domains that are recommended for registration or browsing](method.enumerate_domains.html)
-//! * [Query for an arbitrary DNS record](method.query_record.html)
-//! * [Registers a service](method.register.html)
+//! * [Query for an arbitrary DNS record](method.query_record.html) (optionally [following CNAME records](fn.query_record_follow_cnames.html))
+//! * [Registers a service](method.register.html) (also [in all registration domains](fn.register_all_domains.html))
 //! * [Find hostname and port (and more) for a service](method.resolve.html)
+//! * [Look up the addresses of a host](method.get_addr_info.html) (also [merged and deduplicated](fn.resolve_host_addresses.html))
+//! * [Map a port on a NAT gateway](method.nat_port_mapping.html) (and [keep it renewed](struct.NatPortMapping.html#method.auto_renew))
+//! * [Keep TCP connections alive while sleeping](method.sleep_keepalive.html)
 //!
 //! Also the following things might be interesting:
 //!
 //! * [Purge record from cache](method.reconfirm_record.html)
+//! * [Version of the running daemon](method.daemon_version.html)
+//! * [Build and parse TXT records](struct.TxtRecord.html) and [monitor their changes](fn.monitor_txt.html)
+//! * Parse and validate [service types](struct.ServiceType.html), [domain names](struct.DomainName.html) and [host names](struct.Hostname.html)
 //! * [Construct full name](struct.FullName#method.construct)
+//! * [Escape](fn.escape_instance_name.html), [unescape](fn.unescape_instance_name.html) and [validate](fn.validate_instance_name.html) instance names
 //! * [Stream timeouts](struct.TimeoutStream)
+//! * [Batch results while more are coming](struct.Batched)
+//! * [Collect the currently known results once](struct.Snapshot.html)
+//! * [Report metrics of all operations](trait.Metrics.html)
+//! * [Stop all operations of an application on shutdown](struct.DnsSd.html)
+//! * [Blocking iterators for use without an async runtime](blocking/index.html)
+//! * [Restart operations when the daemon restarts](method.reconnect.html) (also [for all operations of a session](struct.Session.html)) or [after transient failures](fn.retrying.html)
+//! * [Watch a record with a durable long-lived query](method.watch_record.html)
+//! * [Cache query answers for their TTL](struct.RecordCache.html)
+//! * [Watch the addresses of this host](method.watch_self_addresses.html)
+//!
+//! The futures and streams don't need a reactor handle: when created
+//! within a tokio runtime they register with its reactor, otherwise a
+//! background thread waits for events, so they can be driven by any
+//! executor.  They are `Send + 'static`, so they can be moved into
+//! spawned tasks.
+//!
+//! Cargo features:
+//!
+//! * `tokio` (default): use the reactor of the current tokio runtime.
+//! * `async-io`: use the [`async-io`](https://docs.rs/async-io) reactor
+//!   (async-std, smol) when not running in a tokio runtime (unix only).
+//! * `avahi-dbus`: talk to avahi through its D-Bus API when available
+//!   (instead of the compat library) to browse, resolve, query records
+//!   and register services; links libdbus-1.
+//! * `dispatch-queue`: let the daemon deliver callbacks on a
+//!   libdispatch queue instead of reading the connection socket (macOS
+//!   and iOS only).
+//! * `mdns`: built-in mDNS engine to browse, resolve and register
+//!   services in `local.` when no daemon is running (unix only).
+//! * `tracing`: emit [`tracing`](https://docs.rs/tracing) spans for
+//!   calls into the DNS-SD library and its callbacks.
+//! * `serde`: implement `Serialize` and `Deserialize` for result
+//!   types, flags, record types and classes, and interfaces.
+//! * `hickory-proto`: convert query results and record data to and
+//!   from [`hickory-proto`](https://docs.rs/hickory-proto) records.
+//! * `idna`: [normalize instance names](fn.normalize_instance_name.html)
+//!   and convert domains [to](fn.domain_to_ascii.html) and
+//!   [from](fn.domain_to_unicode.html) punycode.
+//! * `mock`: in-memory [mock daemon](mock/index.html) for tests.
+//! * `windows-dnsapi`: browse, resolve and register services in
+//!   `local.` through `dnsapi.dll` (Windows 10 and later) instead of
+//!   the Bonjour runtime; links dnsapi.
 
 #![warn(missing_docs)]
+// the wrappers mirror the argument lists of the C API
+#![allow(clippy::too_many_arguments)]
 
-extern crate futures;
-#[cfg(windows)] // only the windows event loop has debug logging for now
-#[macro_use]
-extern crate log;
-extern crate mio;
-extern crate tokio_core;
-
-#[cfg(windows)]
-extern crate libc;
-#[cfg(windows)]
-extern crate ws2_32;
-#[cfg(windows)]
-extern crate winapi;
-
+pub use self::batched::*;
+pub use self::context::*;
+pub use self::dns_types::*;
+pub use self::domain_name::{DomainName,Hostname};
 pub use self::error::*;
-pub use self::ffi::MAX_DOMAIN_NAME;
+pub use self::idn::*;
+pub use self::ffi::{DNSRecordRef,DNSServiceError,DNSServiceRef,MAX_DOMAIN_NAME};
 pub use self::interface::*;
-pub use self::remote::*;
+pub use self::metrics::*;
+pub use self::reconnect::*;
+pub use self::retry::*;
+pub use self::record_data::*;
+pub use self::property::*;
 pub use self::service::*;
+pub use self::session::*;
+pub use self::stop_handle::StopHandle;
+pub use self::stream::{QueuePolicy,TerminationReason};
 pub use self::timeout_stream::*;
+pub use self::timestamp::Timestamp;
+pub use self::txt_record::*;
 
 mod flags_macro;
+mod trace;
 
+#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+mod avahi_dbus;
+mod batched;
+pub mod blocking;
+mod context;
 mod cstr;
+mod dns_types;
+mod domain_name;
 mod error;
 mod evented;
 mod ffi;
 mod future;
+#[cfg(feature = "hickory-proto")]
+mod hickory;
+mod idn;
 mod interface;
+#[cfg(all(unix, feature = "mdns"))]
+mod mdns;
+mod metrics;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod property;
 mod raw;
 mod raw_box;
-mod remote;
+mod reconnect;
+mod retry;
+mod record_data;
 mod service;
+mod session;
+mod stop_handle;
 mod stream;
 mod timeout_stream;
+mod timestamp;
+mod txt_record;
+#[cfg(all(windows, feature = "windows-dnsapi"))]
+mod windows_dnsapi;
@@ -0,0 +1,541 @@
+// minimal DNS message codec for mDNS
+
+use std::io;
+use std::net::{Ipv4Addr,Ipv6Addr};
+
+pub const TYPE_A : u16 = 1;
+pub const TYPE_PTR : u16 = 12;
+pub const TYPE_TXT : u16 = 16;
+pub const TYPE_AAAA : u16 = 28;
+pub const TYPE_SRV : u16 = 33;
+pub const TYPE_ANY : u16 = 255;
+
+pub const CLASS_IN : u16 = 1;
+// top bit of the class: "unicast response" in questions, "cache flush"
+// in records
+const CLASS_FLAG : u16 = 0x8000;
+
+fn invalid_data(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Domain name as list of (raw) labels
+#[derive(Clone,Debug,Default)]
+pub struct Name(pub Vec<Vec<u8>>);
+
+impl Name {
+	/// Parse dotted name; doesn't support escapes (not needed for
+	/// service types and domains)
+	pub fn from_dotted(name: &str) -> Self {
+		Name(name.split('.').filter(|l| !l.is_empty()).map(|l| l.as_bytes().to_vec()).collect())
+	}
+
+	/// Prepend a single (unescaped) label
+	pub fn with_label(&self, label: &str) -> Self {
+		let mut labels = vec![label.as_bytes().to_vec()];
+		labels.extend(self.0.iter().cloned());
+		Name(labels)
+	}
+
+	pub fn first_label(&self) -> Option<String> {
+		self.0.first().map(|l| String::from_utf8_lossy(l).into_owned())
+	}
+
+	/// Presentation format with trailing dot
+	pub fn to_escaped(&self) -> String {
+		let mut name = String::new();
+		for label in &self.0 {
+			for &c in label {
+				match c {
+					b'.' | b'\\' => {
+						name.push('\\');
+						name.push(c as char);
+					},
+					0x21..=0x7e => name.push(c as char),
+					_ => name.push_str(&format!("\\{:03}", c)),
+				}
+			}
+			name.push('.');
+		}
+		if name.is_empty() {
+			name.push('.');
+		}
+		name
+	}
+
+	/// Dotted format without escapes (for types and domains)
+	pub fn to_dotted(&self) -> String {
+		let mut name = String::new();
+		for label in &self.0 {
+			name.push_str(&String::from_utf8_lossy(label));
+			name.push('.');
+		}
+		name
+	}
+}
+
+// names are case-insensitive
+impl PartialEq for Name {
+	fn eq(&self, other: &Name) -> bool {
+		self.0.len() == other.0.len()
+			&& self.0.iter().zip(other.0.iter()).all(|(a, b)| a.eq_ignore_ascii_case(b))
+	}
+}
+
+impl Eq for Name {}
+
+impl std::hash::Hash for Name {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		for label in &self.0 {
+			label.to_ascii_lowercase().hash(state);
+		}
+	}
+}
+
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum Data {
+	A(Ipv4Addr),
+	Aaaa(Ipv6Addr),
+	Ptr(Name),
+	Srv {
+		priority: u16,
+		weight: u16,
+		port: u16,
+		target: Name,
+	},
+	Txt(Vec<u8>),
+	Other(Vec<u8>),
+}
+
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub struct Question {
+	pub name: Name,
+	pub rr_type: u16,
+	pub unicast_response: bool,
+}
+
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub struct Record {
+	pub name: Name,
+	pub rr_type: u16,
+	pub cache_flush: bool,
+	pub ttl: u32,
+	pub data: Data,
+}
+
+impl Record {
+	pub fn matches(&self, question: &Question) -> bool {
+		self.name == question.name
+			&& (question.rr_type == TYPE_ANY || question.rr_type == self.rr_type)
+	}
+}
+
+#[derive(Clone,Default,Debug)]
+pub struct Message {
+	pub response: bool,
+	pub questions: Vec<Question>,
+	pub answers: Vec<Record>,
+	// proposed records while probing
+	pub authorities: Vec<Record>,
+	pub additionals: Vec<Record>,
+}
+
+impl Message {
+	pub fn query(questions: Vec<Question>) -> Self {
+		Message {
+			questions,
+			..Message::default()
+		}
+	}
+
+	pub fn response(answers: Vec<Record>) -> Self {
+		Message {
+			response: true,
+			answers,
+			..Message::default()
+		}
+	}
+
+	/// Answers and additional records of a response
+	pub fn records(&self) -> impl Iterator<Item = &Record> {
+		self.answers.iter().chain(self.additionals.iter())
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+		// id 0 for multicast
+		put_u16(&mut buf, 0);
+		// QR and AA bits for responses
+		put_u16(&mut buf, if self.response { 0x8400 } else { 0 });
+		put_u16(&mut buf, self.questions.len() as u16);
+		put_u16(&mut buf, self.answers.len() as u16);
+		put_u16(&mut buf, self.authorities.len() as u16);
+		put_u16(&mut buf, self.additionals.len() as u16);
+		for question in &self.questions {
+			put_name(&mut buf, &question.name);
+			put_u16(&mut buf, question.rr_type);
+			put_u16(&mut buf, CLASS_IN | if question.unicast_response { CLASS_FLAG } else { 0 });
+		}
+		for record in self.answers.iter().chain(&self.authorities).chain(&self.additionals) {
+			put_record(&mut buf, record);
+		}
+		buf
+	}
+
+	pub fn parse(packet: &[u8]) -> io::Result<Self> {
+		let mut reader = Reader { packet, pos: 0 };
+		let _id = reader.u16()?;
+		let flags = reader.u16()?;
+		let qdcount = reader.u16()?;
+		let ancount = reader.u16()?;
+		let nscount = reader.u16()?;
+		let arcount = reader.u16()?;
+
+		let mut message = Message {
+			response: 0 != flags & 0x8000,
+			..Message::default()
+		};
+		for _ in 0..qdcount {
+			let name = reader.name()?;
+			let rr_type = reader.u16()?;
+			let class = reader.u16()?;
+			message.questions.push(Question {
+				name,
+				rr_type,
+				unicast_response: 0 != class & CLASS_FLAG,
+			});
+		}
+		for _ in 0..ancount {
+			message.answers.push(reader.record()?);
+		}
+		for _ in 0..nscount {
+			message.authorities.push(reader.record()?);
+		}
+		for _ in 0..arcount {
+			message.additionals.push(reader.record()?);
+		}
+		Ok(message)
+	}
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+	buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_name(buf: &mut Vec<u8>, name: &Name) {
+	for label in &name.0 {
+		let len = label.len().min(63);
+		buf.push(len as u8);
+		buf.extend_from_slice(&label[..len]);
+	}
+	buf.push(0);
+}
+
+fn put_record(buf: &mut Vec<u8>, record: &Record) {
+	put_name(buf, &record.name);
+	put_u16(buf, record.rr_type);
+	put_u16(buf, CLASS_IN | if record.cache_flush { CLASS_FLAG } else { 0 });
+	buf.extend_from_slice(&record.ttl.to_be_bytes());
+	let len_pos = buf.len();
+	put_u16(buf, 0);
+	match record.data {
+		Data::A(addr) => buf.extend_from_slice(&addr.octets()),
+		Data::Aaaa(addr) => buf.extend_from_slice(&addr.octets()),
+		Data::Ptr(ref name) => put_name(buf, name),
+		Data::Srv { priority, weight, port, ref target } => {
+			put_u16(buf, priority);
+			put_u16(buf, weight);
+			put_u16(buf, port);
+			put_name(buf, target);
+		},
+		Data::Txt(ref txt) => buf.extend_from_slice(txt),
+		Data::Other(ref rdata) => buf.extend_from_slice(rdata),
+	}
+	let len = (buf.len() - len_pos - 2) as u16;
+	buf[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+}
+
+struct Reader<'a> {
+	packet: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+		if self.packet.len() - self.pos < len {
+			return Err(invalid_data("truncated DNS message"));
+		}
+		let data = &self.packet[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(data)
+	}
+
+	fn u16(&mut self) -> io::Result<u16> {
+		let data = self.bytes(2)?;
+		Ok(u16::from_be_bytes([data[0], data[1]]))
+	}
+
+	fn u32(&mut self) -> io::Result<u32> {
+		let data = self.bytes(4)?;
+		Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+	}
+
+	// names might use compression pointers to earlier data
+	fn name(&mut self) -> io::Result<Name> {
+		let mut labels = Vec::new();
+		let mut pos = self.pos;
+		let mut end = None;
+		// each pointer has to go backwards; limits loops
+		let mut limit = pos;
+		loop {
+			let len = *self.packet.get(pos).ok_or_else(|| invalid_data("truncated domain name"))? as usize;
+			match len {
+				0 => {
+					pos += 1;
+					break;
+				},
+				1..=63 => {
+					let label = self.packet.get(pos + 1..pos + 1 + len).ok_or_else(|| invalid_data("truncated domain name"))?;
+					labels.push(label.to_vec());
+					pos += 1 + len;
+				},
+				0xc0..=0xff => {
+					let low = *self.packet.get(pos + 1).ok_or_else(|| invalid_data("truncated domain name"))? as usize;
+					let target = ((len & 0x3f) << 8) | low;
+					if target >= limit {
+						return Err(invalid_data("invalid compression pointer"));
+					}
+					if end.is_none() {
+						end = Some(pos + 2);
+					}
+					limit = target;
+					pos = target;
+				},
+				_ => return Err(invalid_data("unsupported label type")),
+			}
+		}
+		self.pos = end.unwrap_or(pos);
+		Ok(Name(labels))
+	}
+
+	fn record(&mut self) -> io::Result<Record> {
+		let name = self.name()?;
+		let rr_type = self.u16()?;
+		let class = self.u16()?;
+		let ttl = self.u32()?;
+		let len = self.u16()? as usize;
+		let start = self.pos;
+		let rdata = self.bytes(len)?;
+		let data = match rr_type {
+			TYPE_A if len == 4 => Data::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+			TYPE_AAAA if len == 16 => {
+				let mut octets = [0u8; 16];
+				octets.copy_from_slice(rdata);
+				Data::Aaaa(Ipv6Addr::from(octets))
+			},
+			TYPE_PTR => {
+				let mut reader = Reader { packet: &self.packet[..start + len], pos: start };
+				Data::Ptr(reader.name()?)
+			},
+			TYPE_SRV => {
+				let mut reader = Reader { packet: &self.packet[..start + len], pos: start };
+				Data::Srv {
+					priority: reader.u16()?,
+					weight: reader.u16()?,
+					port: reader.u16()?,
+					target: reader.name()?,
+				}
+			},
+			TYPE_TXT => Data::Txt(rdata.to_vec()),
+			_ => Data::Other(rdata.to_vec()),
+		};
+		Ok(Record {
+			name,
+			rr_type,
+			cache_flush: 0 != class & CLASS_FLAG,
+			ttl,
+			data,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const HEADER_ONE_ANSWER: [u8; 12] = [0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+
+	fn record(name: &str, rr_type: u16, data: Data) -> Record {
+		Record {
+			name: Name::from_dotted(name),
+			rr_type,
+			cache_flush: false,
+			ttl: 120,
+			data,
+		}
+	}
+
+	// message with a single answer of type `rr_type` and the given raw
+	// owner name, rdata length and rdata
+	fn answer(name: &[u8], rr_type: u16, rd_len: u16, rdata: &[u8]) -> Vec<u8> {
+		let mut packet = HEADER_ONE_ANSWER.to_vec();
+		packet.extend_from_slice(name);
+		put_u16(&mut packet, rr_type);
+		put_u16(&mut packet, CLASS_IN);
+		packet.extend_from_slice(&120u32.to_be_bytes());
+		put_u16(&mut packet, rd_len);
+		packet.extend_from_slice(rdata);
+		packet
+	}
+
+	fn parse_error(packet: &[u8]) -> String {
+		let e = Message::parse(packet).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		e.to_string()
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut message = Message::response(vec![
+			record("_http._tcp.local", TYPE_PTR, Data::Ptr(Name::from_dotted("web._http._tcp.local"))),
+			Record {
+				cache_flush: true,
+				..record("web._http._tcp.local", TYPE_SRV, Data::Srv {
+					priority: 1,
+					weight: 2,
+					port: 8080,
+					target: Name::from_dotted("host.local"),
+				})
+			},
+			record("web._http._tcp.local", TYPE_TXT, Data::Txt(b"\x05a=b c\x00".to_vec())),
+		]);
+		message.additionals = vec![
+			record("host.local", TYPE_A, Data::A(Ipv4Addr::new(192, 0, 2, 1))),
+			record("host.local", TYPE_AAAA, Data::Aaaa("2001:db8::1".parse().unwrap())),
+			record("host.local", 99, Data::Other(vec![1, 2, 3])),
+		];
+		message.authorities = vec![record("probe.local", TYPE_A, Data::A(Ipv4Addr::LOCALHOST))];
+		message.questions = vec![Question {
+			name: Name::from_dotted("_http._tcp.local"),
+			rr_type: TYPE_PTR,
+			unicast_response: true,
+		}];
+
+		let parsed = Message::parse(&message.encode()).unwrap();
+		assert!(parsed.response);
+		assert_eq!(parsed.questions, message.questions);
+		assert_eq!(parsed.answers, message.answers);
+		assert_eq!(parsed.authorities, message.authorities);
+		assert_eq!(parsed.additionals, message.additionals);
+
+		let query = Message::parse(&Message::query(message.questions.clone()).encode()).unwrap();
+		assert!(!query.response);
+		assert_eq!(query.questions, message.questions);
+		assert!(query.answers.is_empty());
+	}
+
+	#[test]
+	fn compression() {
+		// PTR "_http._tcp.local" -> "web" + pointer to the owner name
+		let mut packet = answer(b"\x05_http\x04_tcp\x05local\x00", TYPE_PTR, 6, b"\x03web\xc0\x0c");
+		let message = Message::parse(&packet).unwrap();
+		assert_eq!(message.answers[0].data, Data::Ptr(Name::from_dotted("web._http._tcp.local")));
+
+		// owner name of a second record as pointer into the first
+		packet[7] = 2;
+		packet.extend_from_slice(b"\xc0\x12");
+		put_u16(&mut packet, TYPE_A);
+		put_u16(&mut packet, CLASS_IN);
+		packet.extend_from_slice(&120u32.to_be_bytes());
+		put_u16(&mut packet, 4);
+		packet.extend_from_slice(&[192, 0, 2, 1]);
+		let message = Message::parse(&packet).unwrap();
+		assert_eq!(message.answers[1].name, Name::from_dotted("_tcp.local"));
+		assert_eq!(message.answers[1].data, Data::A(Ipv4Addr::new(192, 0, 2, 1)));
+	}
+
+	#[test]
+	fn compression_loops() {
+		// pointer to itself
+		let packet = answer(b"\xc0\x0c", TYPE_A, 4, &[0; 4]);
+		assert_eq!(parse_error(&packet), "invalid compression pointer");
+
+		// two labels pointing at each other: the second pointer has to
+		// go backwards before the first target
+		let packet = answer(b"\x01a\xc0\x10\x01b\xc0\x0c", TYPE_A, 4, &[0; 4]);
+		assert_eq!(parse_error(&packet), "invalid compression pointer");
+
+		// forward pointer
+		let packet = answer(b"\xc0\x20", TYPE_A, 4, &[0; 4]);
+		assert_eq!(parse_error(&packet), "invalid compression pointer");
+	}
+
+	#[test]
+	fn pointer_out_of_range() {
+		let packet = answer(b"\xff\xff", TYPE_A, 4, &[0; 4]);
+		assert_eq!(parse_error(&packet), "invalid compression pointer");
+
+		// PTR rdata pointing behind itself (outside of the message)
+		let packet = answer(b"\x00", TYPE_PTR, 2, b"\xc0\x30");
+		assert_eq!(parse_error(&packet), "invalid compression pointer");
+
+		// pointer cut off
+		let packet = answer(b"\x00", TYPE_PTR, 1, b"\xc0");
+		assert_eq!(parse_error(&packet), "truncated domain name");
+	}
+
+	#[test]
+	fn truncated_header() {
+		for len in 0..12 {
+			assert_eq!(parse_error(&HEADER_ONE_ANSWER[..len]), "truncated DNS message");
+		}
+		// counts announce more than present
+		assert_eq!(parse_error(&HEADER_ONE_ANSWER), "truncated domain name");
+	}
+
+	#[test]
+	fn truncated_rdata() {
+		let packet = answer(b"\x00", TYPE_A, 4, &[192, 0, 2]);
+		assert_eq!(parse_error(&packet), "truncated DNS message");
+
+		// SRV fixed fields longer than the rdata
+		let packet = answer(b"\x00", TYPE_SRV, 4, &[0, 1, 0, 2]);
+		assert_eq!(parse_error(&packet), "truncated DNS message");
+
+		// SRV target runs past the rdata (into the next bytes)
+		let mut packet = answer(b"\x00", TYPE_SRV, 8, b"\x00\x01\x00\x02\x00\x50\x04host");
+		packet.push(0);
+		assert_eq!(parse_error(&packet), "truncated domain name");
+
+		// unexpected A/AAAA rdata length is kept as is
+		let packet = answer(b"\x00", TYPE_A, 3, &[1, 2, 3]);
+		assert_eq!(Message::parse(&packet).unwrap().answers[0].data, Data::Other(vec![1, 2, 3]));
+	}
+
+	#[test]
+	fn oversized_labels() {
+		// label length 64 (and the reserved 0x40/0x80 label types)
+		for &len in &[0x40u8, 0x80, 0xbf] {
+			let mut name = vec![len];
+			name.extend(std::iter::repeat_n(b'a', len as usize));
+			name.push(0);
+			let packet = answer(&name, TYPE_A, 4, &[0; 4]);
+			assert_eq!(parse_error(&packet), "unsupported label type");
+		}
+
+		// encoding cuts labels at 63 bytes
+		let long = "a".repeat(100);
+		let message = Message::response(vec![record(&format!("{}.local", long), TYPE_A, Data::A(Ipv4Addr::LOCALHOST))]);
+		let parsed = Message::parse(&message.encode()).unwrap();
+		assert_eq!(parsed.answers[0].name.0[0], vec![b'a'; 63]);
+	}
+
+	#[test]
+	fn names() {
+		let name = Name(vec![b"a.b\\c".to_vec(), b"x y\x7f".to_vec()]);
+		assert_eq!(name.to_escaped(), "a\\.b\\\\c.x\\032y\\127.");
+		assert_eq!(Name::default().to_escaped(), ".");
+		assert_eq!(Name::from_dotted("_http._tcp.local.").to_dotted(), "_http._tcp.local.");
+		assert_eq!(Name::from_dotted("Local").with_label("x"), Name::from_dotted("X.local"));
+	}
+}
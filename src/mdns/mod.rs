@@ -0,0 +1,927 @@
+//! Built-in mDNS engine used when no DNS-SD daemon is available
+//!
+//! With the `mdns` feature browsing, resolving and registering fall
+//! back to talking multicast DNS directly when the daemon isn't
+//! running (e.g. in containers).  This only covers the basics: IPv4
+//! only, results are reported on `Interface::Any`, and registered
+//! services are announced with the addresses of all local IPv4
+//! interfaces.
+//!
+//! Each operation uses its own socket bound to port 5353 (with
+//! `SO_REUSEADDR`/`SO_REUSEPORT`, so the kernel delivers multicast
+//! packets to all of them).  Unicast packets to port 5353 only reach
+//! one of the sockets, so all queries (including probes) ask for
+//! multicast responses.  Registrations keep answering queries in a
+//! background thread as long as the registration is alive, as nothing
+//! polls them after they completed.
+
+mod dns;
+
+use futures::FutureExt;
+use futures_timer::Delay;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr,SocketAddrV4,UdpSocket};
+use std::os::unix::io::{AsRawFd,FromRawFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::thread;
+use std::task::Context;
+use std::time::{Duration,Instant};
+
+use futures::channel::mpsc;
+
+use self::dns::{Data,Message,Name,Question,Record};
+use crate::error::Error;
+use crate::evented::EventedFd;
+use crate::ffi::DNSServiceError;
+use crate::interface::Interface;
+use crate::service::{
+	host_name,
+	subtype_label,
+	BrowsedFlag,
+	BrowsedFlags,
+	BrowseResult,
+	RegisterResult,
+	ResolveResult,
+};
+use crate::stream::ServiceStream;
+
+const MDNS_ADDR : Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT : u16 = 5353;
+
+// TTLs recommended by RFC 6762, section 10
+const HOST_TTL : u32 = 120;
+const OTHER_TTL : u32 = 4500;
+
+type Sender<T> = mpsc::UnboundedSender<Result<T, Error>>;
+
+fn send<T>(sender: &Sender<T>, item: Result<T, Error>) {
+	// the stream might be gone already
+	let _ = sender.unbounded_send(item);
+}
+
+/// Whether an error starting an operation means the daemon isn't there
+fn daemon_unavailable(e: &io::Error) -> bool {
+	match Error::from_io(e).and_then(Error::known_code) {
+		Some(DNSServiceError::ServiceNotRunning) => true,
+		// avahi compat maps most client failures (including a missing
+		// daemon) to "unknown"
+		#[cfg(avahi_compat)]
+		Some(DNSServiceError::Unknown) => true,
+		_ => false,
+	}
+}
+
+/// Use `fallback` if starting the operation failed because the daemon
+/// isn't running
+pub fn or_fallback<T, F>(result: io::Result<T>, fallback: F) -> io::Result<T>
+where F: FnOnce() -> io::Result<T>
+{
+	match result {
+		Err(ref e) if daemon_unavailable(e) => fallback(),
+		result => result,
+	}
+}
+
+fn local_domain(domain: Option<&str>) -> io::Result<Name> {
+	let domain = Name::from_dotted(domain.unwrap_or("local."));
+	if domain != Name::from_dotted("local.") {
+		return Err(Error::KnownError(DNSServiceError::Unsupported).into());
+	}
+	Ok(domain)
+}
+
+fn check_interface(interface: Interface) -> io::Result<()> {
+	match interface {
+		Interface::Any => Ok(()),
+		_ => Err(Error::KnownError(DNSServiceError::BadInterfaceIndex).into()),
+	}
+}
+
+fn local_ipv4_addresses() -> Vec<Ipv4Addr> {
+	let mut result = Vec::new();
+	let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+	if 0 != unsafe { libc::getifaddrs(&mut addrs) } {
+		return result;
+	}
+	let mut current = addrs;
+	while !current.is_null() {
+		let ifa = unsafe { &*current };
+		if !ifa.ifa_addr.is_null() && libc::AF_INET == unsafe { (*ifa.ifa_addr).sa_family } as libc::c_int {
+			let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+			let addr = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+			if !result.contains(&addr) {
+				result.push(addr);
+			}
+		}
+		current = ifa.ifa_next;
+	}
+	unsafe { libc::freeifaddrs(addrs) };
+	result
+}
+
+fn mdns_socket() -> io::Result<UdpSocket> {
+	let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	// takes ownership of fd
+	let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+
+	let on: libc::c_int = 1;
+	for &option in &[libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+		if 0 != unsafe { libc::setsockopt(
+			socket.as_raw_fd(),
+			libc::SOL_SOCKET,
+			option,
+			&on as *const libc::c_int as *const libc::c_void,
+			mem::size_of::<libc::c_int>() as libc::socklen_t
+		)} {
+			return Err(io::Error::last_os_error());
+		}
+	}
+
+	let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+	addr.sin_family = libc::AF_INET as libc::sa_family_t;
+	addr.sin_port = MDNS_PORT.to_be();
+	addr.sin_addr.s_addr = u32::from(Ipv4Addr::UNSPECIFIED).to_be();
+	if 0 != unsafe { libc::bind(
+		socket.as_raw_fd(),
+		&addr as *const libc::sockaddr_in as *const libc::sockaddr,
+		mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
+	)} {
+		return Err(io::Error::last_os_error());
+	}
+
+	// join on all interfaces; fails for interfaces without multicast
+	let mut joined = false;
+	for addr in local_ipv4_addresses() {
+		joined |= socket.join_multicast_v4(&MDNS_ADDR, &addr).is_ok();
+	}
+	if !joined {
+		socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+	}
+	socket.set_multicast_ttl_v4(255)?;
+	socket.set_multicast_loop_v4(true)?;
+	socket.set_nonblocking(true)?;
+	Ok(socket)
+}
+
+fn send_message(socket: &UdpSocket, message: &Message) {
+	// packets get lost all the time anyway; queries are repeated
+	let _ = socket.send_to(&message.encode(), SocketAddrV4::new(MDNS_ADDR, MDNS_PORT));
+}
+
+/// Handles packets and timeouts for an operation
+trait Handler: Send {
+	/// Handle received message
+	fn handle(&mut self, socket: &UdpSocket, message: &Message, now: Instant);
+
+	/// Next time `timeout` should be called
+	fn deadline(&self) -> Option<Instant>;
+
+	/// Called when the deadline is reached
+	fn timeout(&mut self, socket: &UdpSocket, now: Instant);
+
+	/// Called when the operation is dropped
+	fn close(&mut self, _socket: &UdpSocket) {
+	}
+}
+
+// receive all pending packets
+fn receive(socket: &UdpSocket, handler: &mut dyn Handler) -> io::Result<()> {
+	let mut buf = [0u8; 9000];
+	loop {
+		match socket.recv_from(&mut buf) {
+			Ok((len, _)) => {
+				// ignore garbage
+				if let Ok(message) = Message::parse(&buf[..len]) {
+					handler.handle(socket, &message, Instant::now());
+				}
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+// run the handler until `stop` is set; used for operations which need
+// to keep responding without being polled (registrations)
+fn run_background(socket: UdpSocket, mut handler: Box<dyn Handler>, stop: &AtomicBool) {
+	let mut poll_fd = libc::pollfd {
+		fd: socket.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+	while !stop.load(Ordering::Acquire) {
+		let now = Instant::now();
+		if let Some(deadline) = handler.deadline() {
+			if deadline <= now {
+				handler.timeout(&socket, now);
+				continue;
+			}
+		}
+		// wake up regularly to check `stop`
+		let timeout = handler.deadline().map_or(BACKGROUND_TICK, |deadline| (deadline - now).min(BACKGROUND_TICK));
+		let timeout = timeout.as_millis() as libc::c_int + 1;
+		if unsafe { libc::poll(&mut poll_fd, 1, timeout) } > 0 && receive(&socket, &mut *handler).is_err() {
+			break;
+		}
+	}
+	handler.close(&socket);
+}
+
+const BACKGROUND_TICK : Duration = Duration::from_millis(100);
+
+enum Inner {
+	Polled {
+		// stop watching the fd before the socket gets closed
+		poll: EventedFd,
+		timer: Option<(Instant, Delay)>,
+		handler: Box<dyn Handler>,
+		socket: UdpSocket,
+	},
+	Background {
+		stop: Arc<AtomicBool>,
+		thread: Option<thread::JoinHandle<()>>,
+	},
+}
+
+/// Running mDNS operation
+pub struct Operation(Inner);
+
+impl Operation {
+	// handler only runs while the stream is polled
+	fn new(socket: UdpSocket, handler: Box<dyn Handler>) -> io::Result<Self> {
+		Ok(Operation(Inner::Polled {
+			poll: EventedFd::new(socket.as_raw_fd())?,
+			timer: None,
+			handler,
+			socket,
+		}))
+	}
+
+	// handler runs in a separate thread until the operation is dropped
+	fn spawn(socket: UdpSocket, handler: Box<dyn Handler>) -> io::Result<Self> {
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread_stop = stop.clone();
+		let thread = thread::Builder::new()
+			.name("dnssd mdns".to_string())
+			.spawn(move || run_background(socket, handler, &thread_stop))?;
+		Ok(Operation(Inner::Background {
+			stop,
+			thread: Some(thread),
+		}))
+	}
+
+	pub fn poll(&mut self, cx: &mut Context) -> io::Result<()> {
+		let (poll, timer, handler, socket) = match &mut self.0 {
+			Inner::Polled { poll, timer, handler, socket } => (poll, timer, handler, socket),
+			// results are sent through the channel
+			Inner::Background { .. } => return Ok(()),
+		};
+		poll.poll(cx, || receive(socket, &mut **handler))?;
+
+		while let Some(deadline) = handler.deadline() {
+			let now = Instant::now();
+			if deadline <= now {
+				*timer = None;
+				handler.timeout(socket, now);
+				continue;
+			}
+			let delay = match timer {
+				Some((at, delay)) if *at == deadline => delay,
+				_ => &mut timer.insert((deadline, Delay::new(deadline - now))).1,
+			};
+			if delay.poll_unpin(cx).is_pending() {
+				break;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Drop for Operation {
+	fn drop(&mut self) {
+		match &mut self.0 {
+			Inner::Polled { handler, socket, .. } => handler.close(socket),
+			Inner::Background { stop, thread } => {
+				stop.store(true, Ordering::Release);
+				// wait for goodbye packets
+				if let Some(thread) = thread.take() {
+					let _ = thread.join();
+				}
+			},
+		}
+	}
+}
+
+// repeat queries after 1 second, doubling the interval up to one hour
+// (RFC 6762, section 5.2)
+struct QuerySchedule {
+	next: Instant,
+	interval: Duration,
+}
+
+impl QuerySchedule {
+	fn new(now: Instant) -> Self {
+		QuerySchedule {
+			next: now,
+			interval: Duration::from_secs(1),
+		}
+	}
+
+	fn advance(&mut self, now: Instant) {
+		self.next = now + self.interval;
+		self.interval = (self.interval * 2).min(Duration::from_secs(3600));
+	}
+}
+
+struct BrowseHandler {
+	sender: Sender<BrowseResult>,
+	reg_type: Name,
+	// browsed name (including subtype)
+	query_name: Name,
+	domain: Name,
+	// known instances and when they expire
+	known: HashMap<Name, Instant>,
+	schedule: QuerySchedule,
+}
+
+impl BrowseHandler {
+	fn result(&self, instance: &Name, flags: BrowsedFlags) -> BrowseResult {
+		BrowseResult {
+			flags,
+			interface: Interface::Any,
+			service_name: instance.first_label().unwrap_or_default(),
+			reg_type: self.reg_type.to_dotted(),
+			domain: self.domain.to_dotted(),
+			received_at: crate::Timestamp::now(),
+		}
+	}
+
+	fn send_burst(&self, results: Vec<BrowseResult>) {
+		let len = results.len();
+		for (ndx, mut result) in results.into_iter().enumerate() {
+			if ndx + 1 < len {
+				result.flags |= BrowsedFlag::MoreComing;
+			}
+			send(&self.sender, Ok(result));
+		}
+	}
+}
+
+impl Handler for BrowseHandler {
+	fn handle(&mut self, _socket: &UdpSocket, message: &Message, now: Instant) {
+		if !message.response {
+			return;
+		}
+		let mut results = Vec::new();
+		for record in message.records() {
+			let instance = match record.data {
+				Data::Ptr(ref instance) if record.name == self.query_name => instance,
+				_ => continue,
+			};
+			if record.ttl == 0 {
+				// goodbye packet; RFC 6762 says to wait one second, but
+				// there is no point to report it later
+				if self.known.remove(instance).is_some() {
+					results.push(self.result(instance, BrowsedFlags::none()));
+				}
+			} else {
+				let expires = now + Duration::from_secs(record.ttl as u64);
+				if self.known.insert(instance.clone(), expires).is_none() {
+					results.push(self.result(instance, BrowsedFlag::Add.into()));
+				}
+			}
+		}
+		self.send_burst(results);
+	}
+
+	fn deadline(&self) -> Option<Instant> {
+		self.known.values().cloned().chain(Some(self.schedule.next)).min()
+	}
+
+	fn timeout(&mut self, socket: &UdpSocket, now: Instant) {
+		let expired: Vec<Name> = self.known.iter()
+			.filter(|&(_, &expires)| expires <= now)
+			.map(|(instance, _)| instance.clone())
+			.collect();
+		let mut results = Vec::new();
+		for instance in expired {
+			self.known.remove(&instance);
+			results.push(self.result(&instance, BrowsedFlags::none()));
+		}
+		self.send_burst(results);
+
+		if self.schedule.next <= now {
+			send_message(socket, &Message::query(vec![Question {
+				name: self.query_name.clone(),
+				rr_type: dns::TYPE_PTR,
+				unicast_response: false,
+			}]));
+			self.schedule.advance(now);
+		}
+	}
+}
+
+/// Browse through the built-in mDNS engine
+///
+/// Only supports `Interface::Any` and the `local.` domain.
+pub fn browse(
+	interface: Interface,
+	reg_type: &str,
+	subtype: Option<&str>,
+	domain: Option<&str>
+) -> io::Result<ServiceStream<BrowseResult>> {
+	check_interface(interface)?;
+	let domain = local_domain(domain)?;
+	let reg_type = Name::from_dotted(reg_type);
+	let mut service_type = reg_type.clone();
+	service_type.0.extend(domain.0.iter().cloned());
+	let query_name = match subtype {
+		Some(subtype) => service_type.with_label("_sub").with_label(subtype_label(subtype)),
+		None => service_type,
+	};
+
+	let socket = mdns_socket()?;
+	ServiceStream::new_mdns(move |sender| {
+		Operation::new(socket, Box::new(BrowseHandler {
+			sender,
+			reg_type,
+			query_name,
+			domain,
+			known: HashMap::new(),
+			schedule: QuerySchedule::new(Instant::now()),
+		}))
+	})
+}
+
+struct ResolveHandler {
+	sender: Sender<ResolveResult>,
+	instance: Name,
+	srv: Option<(u16, Name)>,
+	txt: Option<Vec<u8>>,
+	last: Option<ResolveResult>,
+	schedule: QuerySchedule,
+}
+
+impl Handler for ResolveHandler {
+	fn handle(&mut self, _socket: &UdpSocket, message: &Message, _now: Instant) {
+		if !message.response {
+			return;
+		}
+		for record in message.records() {
+			if record.name != self.instance || record.ttl == 0 {
+				continue;
+			}
+			match record.data {
+				Data::Srv { port, ref target, .. } => self.srv = Some((port, target.clone())),
+				Data::Txt(ref txt) => self.txt = Some(txt.clone()),
+				_ => (),
+			}
+		}
+		if let (Some((port, target)), Some(txt)) = (self.srv.as_ref(), self.txt.as_ref()) {
+			let result = ResolveResult {
+				interface: Interface::Any,
+				fullname: self.instance.to_escaped(),
+				host_target: target.to_escaped(),
+				port: *port,
+				txt: if txt.is_empty() { vec![0] } else { txt.clone() },
+				received_at: crate::Timestamp::now(),
+			};
+			if self.last.as_ref() != Some(&result) {
+				self.last = Some(result.clone());
+				send(&self.sender, Ok(result));
+			}
+		}
+	}
+
+	fn deadline(&self) -> Option<Instant> {
+		// stop querying once resolved
+		if self.last.is_some() {
+			None
+		} else {
+			Some(self.schedule.next)
+		}
+	}
+
+	fn timeout(&mut self, socket: &UdpSocket, now: Instant) {
+		let question = |rr_type| Question {
+			name: self.instance.clone(),
+			rr_type,
+			unicast_response: false,
+		};
+		send_message(socket, &Message::query(vec![question(dns::TYPE_SRV), question(dns::TYPE_TXT)]));
+		self.schedule.advance(now);
+	}
+}
+
+/// Resolve through the built-in mDNS engine
+///
+/// Only supports `Interface::Any` and the `local.` domain.
+pub fn resolve(
+	interface: Interface,
+	name: &str,
+	reg_type: &str,
+	domain: &str
+) -> io::Result<ServiceStream<ResolveResult>> {
+	check_interface(interface)?;
+	let domain = local_domain(Some(domain))?;
+	let mut instance = Name::from_dotted(reg_type).with_label(name);
+	instance.0.extend(domain.0.iter().cloned());
+
+	let socket = mdns_socket()?;
+	ServiceStream::new_mdns(move |sender| {
+		Operation::new(socket, Box::new(ResolveHandler {
+			sender,
+			instance,
+			srv: None,
+			txt: None,
+			last: None,
+			schedule: QuerySchedule::new(Instant::now()),
+		}))
+	})
+}
+
+enum RegisterState {
+	// number of probes sent
+	Probing(u32),
+	// number of announcements sent
+	Announcing(u32),
+	Established,
+	Failed,
+}
+
+struct RegisterHandler {
+	sender: Sender<RegisterResult>,
+	no_auto_rename: bool,
+	base_name: String,
+	rename_count: u32,
+	name: String,
+	reg_type: Name,
+	subtypes: Vec<String>,
+	domain: Name,
+	host: Name,
+	// only announce addresses for our own host name
+	addresses: Vec<Ipv4Addr>,
+	port: u16,
+	txt: Vec<u8>,
+	state: RegisterState,
+	next: Instant,
+}
+
+impl RegisterHandler {
+	fn service_type(&self) -> Name {
+		let mut name = self.reg_type.clone();
+		name.0.extend(self.domain.0.iter().cloned());
+		name
+	}
+
+	fn instance(&self) -> Name {
+		self.service_type().with_label(&self.name)
+	}
+
+	// records for the instance name (checked for conflicts)
+	fn unique_records(&self, ttl_scale: u32) -> Vec<Record> {
+		let instance = self.instance();
+		let mut records = vec![
+			Record {
+				name: instance.clone(),
+				rr_type: dns::TYPE_SRV,
+				cache_flush: true,
+				ttl: HOST_TTL * ttl_scale,
+				data: Data::Srv {
+					priority: 0,
+					weight: 0,
+					port: self.port,
+					target: self.host.clone(),
+				},
+			},
+			Record {
+				name: instance,
+				rr_type: dns::TYPE_TXT,
+				cache_flush: true,
+				ttl: OTHER_TTL * ttl_scale,
+				data: Data::Txt(if self.txt.is_empty() { vec![0] } else { self.txt.clone() }),
+			},
+		];
+		for &addr in &self.addresses {
+			records.push(Record {
+				name: self.host.clone(),
+				rr_type: dns::TYPE_A,
+				cache_flush: true,
+				ttl: HOST_TTL * ttl_scale,
+				data: Data::A(addr),
+			});
+		}
+		records
+	}
+
+	// shared PTR records; `ttl_scale` 0 for goodbye packets
+	fn shared_records(&self, ttl_scale: u32) -> Vec<Record> {
+		let service_type = self.service_type();
+		let instance = self.instance();
+		let ptr = |name: Name, target: Name| Record {
+			name,
+			rr_type: dns::TYPE_PTR,
+			cache_flush: false,
+			ttl: OTHER_TTL * ttl_scale,
+			data: Data::Ptr(target),
+		};
+		let mut services = Name::from_dotted("_services._dns-sd._udp");
+		services.0.extend(self.domain.0.iter().cloned());
+		let mut records = vec![
+			ptr(service_type.clone(), instance.clone()),
+			ptr(services, service_type.clone()),
+		];
+		for subtype in &self.subtypes {
+			records.push(ptr(service_type.with_label("_sub").with_label(subtype), instance.clone()));
+		}
+		records
+	}
+
+	fn all_records(&self) -> Vec<Record> {
+		let mut records = self.shared_records(1);
+		records.extend(self.unique_records(1));
+		records
+	}
+
+	// probe for the instance name with the proposed records.  Asks for
+	// multicast answers (QM): a unicast answer to port 5353 would only
+	// reach one of the sockets sharing the port, maybe not ours
+	fn probe(&self) -> Message {
+		let mut query = Message::query(vec![Question {
+			name: self.instance(),
+			rr_type: dns::TYPE_ANY,
+			unicast_response: false,
+		}]);
+		query.authorities = self.unique_records(1);
+		query
+	}
+
+	fn start_probing(&mut self, now: Instant) {
+		self.state = RegisterState::Probing(0);
+		self.next = now;
+	}
+
+	fn conflict(&mut self, now: Instant) {
+		if self.no_auto_rename {
+			self.state = RegisterState::Failed;
+			send(&self.sender, Err(Error::KnownError(DNSServiceError::NameConflict)));
+			return;
+		}
+		self.rename_count += 1;
+		self.name = format!("{} ({})", self.base_name, self.rename_count + 1);
+		self.start_probing(now);
+	}
+}
+
+impl Handler for RegisterHandler {
+	fn handle(&mut self, socket: &UdpSocket, message: &Message, now: Instant) {
+		let instance = self.instance();
+		if message.response {
+			// while probing any answer for our name is a conflict
+			let probing = matches!(self.state, RegisterState::Probing(_));
+			if probing && message.records().any(|r| r.name == instance) {
+				self.conflict(now);
+			}
+			return;
+		}
+
+		match self.state {
+			RegisterState::Probing(_) => {
+				// simultaneous probes: tie-breaking would compare the
+				// proposed records; just pick another name if the other
+				// side proposes different data
+				let ours = self.unique_records(1);
+				let conflict = message.authorities.iter()
+					.filter(|r| r.name == instance)
+					.any(|r| !ours.iter().any(|o| o.rr_type == r.rr_type && o.data == r.data));
+				if conflict {
+					self.conflict(now);
+				}
+			},
+			RegisterState::Announcing(_) | RegisterState::Established => {
+				let records = self.all_records();
+				let answers: Vec<Record> = records.iter()
+					.filter(|r| message.questions.iter().any(|q| r.matches(q)))
+					.cloned()
+					.collect();
+				if !answers.is_empty() {
+					send_message(socket, &Message::response(answers));
+				}
+			},
+			RegisterState::Failed => (),
+		}
+	}
+
+	fn deadline(&self) -> Option<Instant> {
+		match self.state {
+			RegisterState::Probing(_) | RegisterState::Announcing(_) => Some(self.next),
+			RegisterState::Established | RegisterState::Failed => None,
+		}
+	}
+
+	fn timeout(&mut self, socket: &UdpSocket, now: Instant) {
+		match self.state {
+			RegisterState::Probing(probes) if probes < 3 => {
+				// RFC 6762, section 8.1: three probes 250ms apart
+				send_message(socket, &self.probe());
+				self.state = RegisterState::Probing(probes + 1);
+				self.next = now + Duration::from_millis(250);
+			},
+			RegisterState::Probing(_) => {
+				self.state = RegisterState::Announcing(0);
+				self.next = now;
+			},
+			RegisterState::Announcing(count) => {
+				send_message(socket, &Message::response(self.all_records()));
+				if count == 0 {
+					send(&self.sender, Ok(RegisterResult {
+						name: self.name.clone(),
+						reg_type: self.reg_type.to_dotted(),
+						domain: self.domain.to_dotted(),
+						received_at: crate::Timestamp::now(),
+					}));
+				}
+				// RFC 6762, section 8.3: at least two announcements
+				// one second apart
+				self.state = if count + 1 < 2 {
+					RegisterState::Announcing(count + 1)
+				} else {
+					RegisterState::Established
+				};
+				self.next = now + Duration::from_secs(1);
+			},
+			RegisterState::Established | RegisterState::Failed => (),
+		}
+	}
+
+	fn close(&mut self, socket: &UdpSocket) {
+		if let RegisterState::Announcing(_) | RegisterState::Established = self.state {
+			let mut goodbye = self.shared_records(0);
+			goodbye.extend(self.unique_records(0));
+			send_message(socket, &Message::response(goodbye));
+		}
+	}
+}
+
+/// Register service through the built-in mDNS engine
+///
+/// Only supports `Interface::Any` and the `local.` domain; without a
+/// name the host name is used.
+pub fn register(
+	no_auto_rename: bool,
+	interface: Interface,
+	name: Option<&str>,
+	reg_type: &str,
+	subtypes: &[&str],
+	domain: Option<&str>,
+	host: Option<&str>,
+	port: u16,
+	txt: &[u8]
+) -> io::Result<ServiceStream<RegisterResult>> {
+	check_interface(interface)?;
+	let domain = local_domain(domain)?;
+	let (host, addresses) = match host {
+		Some(host) => (Name::from_dotted(host), Vec::new()),
+		None => {
+			let mut host = Name::from_dotted(&host_name());
+			host.0.extend(domain.0.iter().cloned());
+			let addresses = local_ipv4_addresses().into_iter().filter(|a| !a.is_loopback()).collect();
+			(host, addresses)
+		},
+	};
+	let name = name.map(str::to_string).unwrap_or_else(host_name);
+
+	let socket = mdns_socket()?;
+	let now = Instant::now();
+	ServiceStream::new_mdns(move |sender| {
+		Operation::spawn(socket, Box::new(RegisterHandler {
+			sender,
+			no_auto_rename,
+			base_name: name.clone(),
+			rename_count: 0,
+			name,
+			reg_type: Name::from_dotted(reg_type),
+			subtypes: subtypes.iter().map(|s| subtype_label(s).to_string()).collect(),
+			domain,
+			host,
+			addresses,
+			port,
+			txt: txt.to_vec(),
+			state: RegisterState::Probing(0),
+			next: now,
+		}))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::StreamExt;
+
+	fn socket() -> UdpSocket {
+		UdpSocket::bind("127.0.0.1:0").unwrap()
+	}
+
+	fn register_handler(sender: Sender<RegisterResult>, no_auto_rename: bool) -> RegisterHandler {
+		RegisterHandler {
+			sender,
+			no_auto_rename,
+			base_name: "web".to_string(),
+			rename_count: 0,
+			name: "web".to_string(),
+			reg_type: Name::from_dotted("_http._tcp"),
+			subtypes: Vec::new(),
+			domain: Name::from_dotted("local."),
+			host: Name::from_dotted("host.local."),
+			addresses: vec![Ipv4Addr::new(192, 168, 1, 2)],
+			port: 80,
+			txt: Vec::new(),
+			state: RegisterState::Probing(0),
+			next: Instant::now(),
+		}
+	}
+
+	fn browse_handler(sender: Sender<BrowseResult>) -> BrowseHandler {
+		BrowseHandler {
+			sender,
+			reg_type: Name::from_dotted("_http._tcp"),
+			query_name: Name::from_dotted("_http._tcp.local."),
+			domain: Name::from_dotted("local."),
+			known: HashMap::new(),
+			schedule: QuerySchedule::new(Instant::now()),
+		}
+	}
+
+	// answer of another host already using the name
+	fn conflicting_response() -> Message {
+		Message::response(vec![Record {
+			name: Name::from_dotted("web._http._tcp.local."),
+			rr_type: dns::TYPE_SRV,
+			cache_flush: true,
+			ttl: HOST_TTL,
+			data: Data::Srv {
+				priority: 0,
+				weight: 0,
+				port: 8080,
+				target: Name::from_dotted("other.local."),
+			},
+		}])
+	}
+
+	#[test]
+	fn probes_ask_for_multicast_responses() {
+		let (sender, _receiver) = mpsc::unbounded();
+		let handler = register_handler(sender, false);
+		let probe = Message::parse(&handler.probe().encode()).unwrap();
+		assert!(!probe.response);
+		assert!(!probe.questions.is_empty());
+		assert!(probe.questions.iter().all(|q| !q.unicast_response));
+		assert_eq!(probe.authorities.len(), 3);
+	}
+
+	#[test]
+	fn probe_conflict_with_other_operation() {
+		let socket = socket();
+		let (browse_sender, mut browse_results) = mpsc::unbounded();
+		let mut browse = browse_handler(browse_sender);
+		let (sender, mut results) = mpsc::unbounded();
+		let mut register = register_handler(sender, false);
+		let now = Instant::now();
+		browse.timeout(&socket, now);
+		register.timeout(&socket, now);
+		assert!(matches!(register.state, RegisterState::Probing(1)));
+
+		// only the register handler gets the (multicast) answer
+		register.handle(&socket, &conflicting_response(), now);
+		assert_eq!(register.name, "web (2)");
+		assert!(matches!(register.state, RegisterState::Probing(0)));
+		assert!(results.next().now_or_never().is_none());
+		assert!(browse_results.next().now_or_never().is_none());
+
+		// the renamed service doesn't conflict
+		register.timeout(&socket, now);
+		register.handle(&socket, &conflicting_response(), now);
+		assert_eq!(register.name, "web (2)");
+		assert!(matches!(register.state, RegisterState::Probing(1)));
+	}
+
+	#[test]
+	fn probe_conflict_no_auto_rename() {
+		let socket = socket();
+		let (sender, mut results) = mpsc::unbounded();
+		let mut register = register_handler(sender, true);
+		let now = Instant::now();
+		register.timeout(&socket, now);
+		register.handle(&socket, &conflicting_response(), now);
+		assert!(matches!(register.state, RegisterState::Failed));
+		let e = results.next().now_or_never().unwrap().unwrap().unwrap_err();
+		assert!(e.is_name_conflict());
+		assert_eq!(register.deadline(), None);
+	}
+}
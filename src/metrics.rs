@@ -0,0 +1,60 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Receive statistics about running operations (e.g. to feed them into
+/// Prometheus or statsd)
+///
+/// All operations report through the same hooks; `operation` names the
+/// type of results (e.g. `"BrowseResult"`).  All methods default to
+/// doing nothing.
+///
+/// The hooks are called while the operations are polled and should
+/// return quickly.
+///
+/// Install with [`set_metrics`](fn.set_metrics.html).
+pub trait Metrics: Send + Sync {
+	/// A result was received
+	fn result_received(&self, operation: &'static str) {
+		let _ = operation;
+	}
+
+	/// An operation failed (or reported an error)
+	fn error(&self, operation: &'static str, error: &Error) {
+		let _ = (operation, error);
+	}
+
+	/// Time spent processing replies from the daemon (running the
+	/// callbacks) in a poll that received results
+	fn callback_latency(&self, operation: &'static str, latency: Duration) {
+		let _ = (operation, latency);
+	}
+
+	/// Number of results waiting to be consumed after receiving new
+	/// results
+	fn queue_depth(&self, operation: &'static str, depth: usize) {
+		let _ = (operation, depth);
+	}
+}
+
+static METRICS: OnceLock<Box<dyn Metrics>> = OnceLock::new();
+
+/// Install global [`Metrics`](trait.Metrics.html) hooks
+///
+/// Can only be done once; returns `false` (and drops `metrics`) if
+/// hooks were already installed.
+pub fn set_metrics<M: Metrics + 'static>(metrics: M) -> bool {
+	METRICS.set(Box::new(metrics)).is_ok()
+}
+
+pub(crate) fn metrics() -> Option<&'static dyn Metrics> {
+	METRICS.get().map(|m| &**m)
+}
+
+// last path segment of the result type name
+pub(crate) fn operation_name<T>() -> &'static str {
+	let name = std::any::type_name::<T>();
+	let name = name.split('<').next().unwrap_or(name);
+	name.rsplit("::").next().unwrap_or(name)
+}
@@ -0,0 +1,786 @@
+//! In-memory mock of the DNS-SD daemon
+//!
+//! A [`MockDaemon`](struct.MockDaemon.html) provides
+//! [`browse`](struct.MockDaemon.html#method.browse),
+//! [`resolve`](struct.MockDaemon.html#method.resolve),
+//! [`query_record`](struct.MockDaemon.html#method.query_record) and
+//! [`register`](struct.MockDaemon.html#method.register) with the same
+//! signatures and result types as the real functions, answering from
+//! services and records added as fixtures.  This allows testing
+//! discovery logic without a running daemon.
+//!
+//! Results follow the daemon conventions: results known when an
+//! operation starts (or changed together) are delivered as one burst
+//! with `MoreComing` set on all but the last result, and removals are
+//! reported without the `Add` flag.
+//!
+//! Record operations on mock registrations (like
+//! [`Registration::add_raw_record`](../struct.Registration.html#method.add_raw_record))
+//! are not supported and panic.
+//!
+//! Requires the `mock` feature.
+
+use futures::channel::mpsc;
+use std::any::Any;
+use std::io;
+use std::sync::{Arc,Mutex,MutexGuard};
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::ffi;
+use crate::future::ServiceFuture;
+use crate::interface::Interface;
+use crate::service::{
+	subtype_label,
+	Browse,
+	BrowsedFlag,
+	BrowsedFlags,
+	BrowseFlags,
+	QueriedRecordFlag,
+	QueriedRecordFlags,
+	QueryRecord,
+	QueryRecordFlags,
+	Register,
+	RegisterFlag,
+	RegisterFlags,
+	RegisterResult,
+	Resolve,
+	ResolveResult,
+	BrowseResult,
+	QueryRecordResult,
+};
+use crate::stream::ServiceStream;
+
+/// Service known to a [`MockDaemon`](struct.MockDaemon.html)
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct MockService {
+	/// Interface the service is found on
+	pub interface: Interface,
+	/// Name of the service
+	pub name: String,
+	/// Type of the service, e.g. `"_http._tcp"`
+	pub reg_type: String,
+	/// Subtypes (single labels like `"_printer"`) of the service
+	pub subtypes: Vec<String>,
+	/// Domain of the service
+	pub domain: String,
+	/// Name of the host the service is running on
+	pub host_target: String,
+	/// Port the service is listening on
+	pub port: u16,
+	/// Raw TXT record data
+	pub txt: Vec<u8>,
+}
+
+impl MockService {
+	/// Service in domain `local.` on `Interface::LocalOnly` without
+	/// subtypes and with an empty TXT record
+	pub fn new(name: &str, reg_type: &str, host_target: &str, port: u16) -> Self {
+		MockService {
+			interface: Interface::LocalOnly,
+			name: name.to_string(),
+			reg_type: reg_type.to_string(),
+			subtypes: Vec::new(),
+			domain: "local.".to_string(),
+			host_target: host_target.to_string(),
+			port,
+			txt: vec![0],
+		}
+	}
+
+	fn is(&self, name: &str, reg_type: &str, domain: &str) -> bool {
+		self.name == name
+			&& same_name(&self.reg_type, reg_type)
+			&& same_name(&self.domain, domain)
+	}
+
+	fn browse_result(&self, flags: BrowsedFlags) -> BrowseResult {
+		BrowseResult {
+			flags,
+			interface: self.interface,
+			service_name: self.name.clone(),
+			reg_type: absolute(&self.reg_type),
+			domain: absolute(&self.domain),
+			received_at: crate::Timestamp::now(),
+		}
+	}
+
+	fn resolve_result(&self) -> ResolveResult {
+		ResolveResult {
+			interface: self.interface,
+			fullname: format!("{}.{}{}", escape_label(&self.name), absolute(&self.reg_type), absolute(&self.domain)),
+			host_target: absolute(&self.host_target),
+			port: self.port,
+			txt: self.txt.clone(),
+			received_at: crate::Timestamp::now(),
+		}
+	}
+}
+
+/// Record known to a [`MockDaemon`](struct.MockDaemon.html)
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct MockRecord {
+	/// Interface the record is found on
+	pub interface: Interface,
+	/// Full name of the record
+	pub fullname: String,
+	/// Type of the record
+	pub rr_type: RecordType,
+	/// Class of the record
+	pub rr_class: RecordClass,
+	/// Raw record data (wire format)
+	pub rdata: Vec<u8>,
+	/// Time to live in seconds
+	pub ttl: u32,
+}
+
+impl MockRecord {
+	fn query_result(&self, flags: QueriedRecordFlags) -> QueryRecordResult {
+		QueryRecordResult {
+			flags,
+			interface: self.interface,
+			fullname: absolute(&self.fullname),
+			rr_type: self.rr_type,
+			rr_class: self.rr_class,
+			rdata: self.rdata.clone(),
+			ttl: self.ttl,
+			received_at: crate::Timestamp::now(),
+		}
+	}
+}
+
+// names are compared case-insensitive and with optional trailing dot
+fn same_name(a: &str, b: &str) -> bool {
+	a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn absolute(name: &str) -> String {
+	format!("{}.", name.trim_end_matches('.'))
+}
+
+fn escape_label(label: &str) -> String {
+	let mut result = String::new();
+	for c in label.chars() {
+		if c == '.' || c == '\\' {
+			result.push('\\');
+		}
+		result.push(c);
+	}
+	result
+}
+
+fn matches_interface(filter: Interface, interface: Interface) -> bool {
+	filter == Interface::Any || filter == interface
+}
+
+struct BrowseWatch {
+	interface: Interface,
+	reg_type: String,
+	subtype: Option<String>,
+	domain: String,
+	sender: mpsc::UnboundedSender<Result<BrowseResult, Error>>,
+}
+
+impl BrowseWatch {
+	fn matches(&self, service: &MockService) -> bool {
+		matches_interface(self.interface, service.interface)
+			&& same_name(&self.reg_type, &service.reg_type)
+			&& same_name(&self.domain, &service.domain)
+			&& self.subtype.as_ref().is_none_or(|subtype| service.subtypes.iter().any(|s| s == subtype))
+	}
+}
+
+struct ResolveWatch {
+	interface: Interface,
+	name: String,
+	reg_type: String,
+	domain: String,
+	sender: mpsc::UnboundedSender<Result<ResolveResult, Error>>,
+}
+
+impl ResolveWatch {
+	fn matches(&self, service: &MockService) -> bool {
+		matches_interface(self.interface, service.interface)
+			&& service.is(&self.name, &self.reg_type, &self.domain)
+	}
+}
+
+struct QueryWatch {
+	interface: Interface,
+	fullname: String,
+	rr_type: RecordType,
+	rr_class: RecordClass,
+	sender: mpsc::UnboundedSender<Result<QueryRecordResult, Error>>,
+}
+
+impl QueryWatch {
+	fn matches(&self, record: &MockRecord) -> bool {
+		matches_interface(self.interface, record.interface)
+			&& same_name(&self.fullname, &record.fullname)
+			&& (self.rr_type == RecordType::ANY || self.rr_type == record.rr_type)
+			&& (self.rr_class == RecordClass::ANY || self.rr_class == record.rr_class)
+	}
+}
+
+#[derive(Default)]
+struct State {
+	// services with the id of the registration they belong to
+	services: Vec<(Option<u64>, MockService)>,
+	records: Vec<MockRecord>,
+	browses: Vec<BrowseWatch>,
+	resolves: Vec<ResolveWatch>,
+	queries: Vec<QueryWatch>,
+	registrations: Vec<(u64, mpsc::UnboundedSender<Result<RegisterResult, Error>>)>,
+	next_registration: u64,
+}
+
+// send a burst of results, setting `MoreComing` on all but the last
+fn send_burst<T, F>(sender: &mpsc::UnboundedSender<Result<T, Error>>, items: Vec<F>, build: impl Fn(F, bool) -> T) {
+	let len = items.len();
+	for (ndx, item) in items.into_iter().enumerate() {
+		// receiver might be gone; the watch is removed later
+		let _ = sender.unbounded_send(Ok(build(item, ndx + 1 < len)));
+	}
+}
+
+impl State {
+	fn prune(&mut self) {
+		self.browses.retain(|w| !w.sender.is_closed());
+		self.resolves.retain(|w| !w.sender.is_closed());
+		self.queries.retain(|w| !w.sender.is_closed());
+		self.registrations.retain(|(_, sender)| !sender.is_closed());
+	}
+
+	// `added` is `false` for removed services
+	fn notify_services(&mut self, added: bool, changed: &[MockService]) {
+		self.prune();
+		for watch in &self.browses {
+			let matching: Vec<&MockService> = changed.iter().filter(|s| watch.matches(s)).collect();
+			send_burst(&watch.sender, matching, |service, more_coming| {
+				let mut flags = BrowsedFlags::none();
+				if added {
+					flags |= BrowsedFlag::Add;
+				}
+				if more_coming {
+					flags |= BrowsedFlag::MoreComing;
+				}
+				service.browse_result(flags)
+			});
+		}
+		if added {
+			for watch in &self.resolves {
+				for service in changed.iter().filter(|s| watch.matches(s)) {
+					let _ = watch.sender.unbounded_send(Ok(service.resolve_result()));
+				}
+			}
+		}
+	}
+
+	fn notify_records(&mut self, added: bool, changed: &[MockRecord]) {
+		self.prune();
+		for watch in &self.queries {
+			let matching: Vec<&MockRecord> = changed.iter().filter(|r| watch.matches(r)).collect();
+			send_burst(&watch.sender, matching, |record, more_coming| {
+				let mut flags = QueriedRecordFlags::none();
+				if added {
+					flags |= QueriedRecordFlag::Add;
+				}
+				if more_coming {
+					flags |= QueriedRecordFlag::MoreComing;
+				}
+				record.query_result(flags)
+			});
+		}
+	}
+
+	fn remove_services<F>(&mut self, mut f: F) -> Vec<MockService>
+	where F: FnMut(&(Option<u64>, MockService)) -> bool
+	{
+		let mut removed = Vec::new();
+		self.services.retain(|entry| {
+			if f(entry) {
+				removed.push(entry.1.clone());
+				false
+			} else {
+				true
+			}
+		});
+		removed
+	}
+}
+
+// a panic while notifying operations doesn't leave the state
+// inconsistent
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+	state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// removes the registered service when the registration is dropped
+struct RegistrationGuard {
+	state: Arc<Mutex<State>>,
+	id: u64,
+}
+
+impl Drop for RegistrationGuard {
+	fn drop(&mut self) {
+		let mut state = lock(&self.state);
+		let id = self.id;
+		let removed = state.remove_services(|(reg, _)| *reg == Some(id));
+		state.registrations.retain(|(reg, _)| *reg != id);
+		state.notify_services(false, &removed);
+	}
+}
+
+/// In-memory DNS-SD daemon
+///
+/// Clones share the same state.  Operations started from the daemon
+/// are updated when fixtures change; dropping all handles of the daemon
+/// (and all registrations) ends the streams.
+#[derive(Clone,Default)]
+pub struct MockDaemon(Arc<Mutex<State>>);
+
+impl MockDaemon {
+	/// Create daemon without any services or records
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a service
+	pub fn add_service(&self, service: MockService) {
+		self.add_services(vec![service]);
+	}
+
+	/// Add services; browse results are delivered as a single burst
+	pub fn add_services<I>(&self, services: I)
+	where I: IntoIterator<Item = MockService>
+	{
+		let services: Vec<MockService> = services.into_iter().collect();
+		let mut state = lock(&self.0);
+		state.services.extend(services.iter().cloned().map(|s| (None, s)));
+		state.notify_services(true, &services);
+	}
+
+	/// Remove a service (including registered ones)
+	///
+	/// Returns whether the service was found.
+	pub fn remove_service(&self, name: &str, reg_type: &str, domain: &str) -> bool {
+		let mut state = lock(&self.0);
+		let removed = state.remove_services(|(_, s)| s.is(name, reg_type, domain));
+		state.notify_services(false, &removed);
+		!removed.is_empty()
+	}
+
+	/// Currently known services (including registered ones)
+	pub fn services(&self) -> Vec<MockService> {
+		lock(&self.0).services.iter().map(|(_, s)| s.clone()).collect()
+	}
+
+	/// Add a record
+	pub fn add_record(&self, record: MockRecord) {
+		let mut state = lock(&self.0);
+		state.records.push(record.clone());
+		state.notify_records(true, &[record]);
+	}
+
+	/// Remove a record
+	///
+	/// Returns whether the record was found.
+	pub fn remove_record(&self, record: &MockRecord) -> bool {
+		let mut state = lock(&self.0);
+		let len = state.records.len();
+		state.records.retain(|r| r != record);
+		if state.records.len() == len {
+			return false;
+		}
+		state.notify_records(false, std::slice::from_ref(record));
+		true
+	}
+
+	/// Report an error to all running operations
+	///
+	/// E.g. use [`ServiceNotRunning`](../enum.DNSServiceError.html#variant.ServiceNotRunning)
+	/// to simulate a daemon restart.
+	pub fn fail(&self, error: ffi::DNSServiceError) {
+		let mut state = lock(&self.0);
+		state.prune();
+		for w in &state.browses {
+			let _ = w.sender.unbounded_send(Err(Error::KnownError(error)));
+		}
+		for w in &state.resolves {
+			let _ = w.sender.unbounded_send(Err(Error::KnownError(error)));
+		}
+		for w in &state.queries {
+			let _ = w.sender.unbounded_send(Err(Error::KnownError(error)));
+		}
+		for (_, sender) in &state.registrations {
+			let _ = sender.unbounded_send(Err(Error::KnownError(error)));
+		}
+	}
+
+	/// Browse for services
+	///
+	/// See [`browse`](../fn.browse.html).
+	pub fn browse(
+		&self,
+		_flags: BrowseFlags,
+		interface: Interface,
+		reg_type: &str,
+		subtype: Option<&str>,
+		domain: Option<&str>
+	) -> io::Result<Browse> {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let watch = BrowseWatch {
+			interface,
+			reg_type: reg_type.to_string(),
+			subtype: subtype.map(|s| subtype_label(s).to_string()),
+			domain: domain.unwrap_or("local.").to_string(),
+			sender,
+		};
+		let mut state = lock(&self.0);
+		let known: Vec<&MockService> = state.services.iter().map(|(_, s)| s).filter(|s| watch.matches(s)).collect();
+		send_burst(&watch.sender, known, |service, more_coming| {
+			let mut flags = BrowsedFlags::from(BrowsedFlag::Add);
+			if more_coming {
+				flags |= BrowsedFlag::MoreComing;
+			}
+			service.browse_result(flags)
+		});
+		state.browses.push(watch);
+		Ok(Browse(stream))
+	}
+
+	/// Resolve a service
+	///
+	/// See [`resolve`](../fn.resolve.html).
+	pub fn resolve(
+		&self,
+		interface: Interface,
+		name: &str,
+		reg_type: &str,
+		domain: &str
+	) -> io::Result<Resolve> {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let watch = ResolveWatch {
+			interface,
+			name: name.to_string(),
+			reg_type: reg_type.to_string(),
+			domain: domain.to_string(),
+			sender,
+		};
+		let mut state = lock(&self.0);
+		for (_, service) in state.services.iter().filter(|(_, s)| watch.matches(s)) {
+			let _ = watch.sender.unbounded_send(Ok(service.resolve_result()));
+		}
+		state.resolves.push(watch);
+		Ok(Resolve(stream))
+	}
+
+	/// Query records
+	///
+	/// See [`query_record`](../fn.query_record.html).
+	pub fn query_record(
+		&self,
+		_flags: QueryRecordFlags,
+		interface: Interface,
+		fullname: &str,
+		rr_type: RecordType,
+		rr_class: RecordClass
+	) -> io::Result<QueryRecord> {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let watch = QueryWatch {
+			interface,
+			fullname: fullname.to_string(),
+			rr_type,
+			rr_class,
+			sender,
+		};
+		let mut state = lock(&self.0);
+		let known: Vec<&MockRecord> = state.records.iter().filter(|r| watch.matches(r)).collect();
+		send_burst(&watch.sender, known, |record, more_coming| {
+			let mut flags = QueriedRecordFlags::from(QueriedRecordFlag::Add);
+			if more_coming {
+				flags |= QueriedRecordFlag::MoreComing;
+			}
+			record.query_result(flags)
+		});
+		state.queries.push(watch);
+		Ok(QueryRecord(stream))
+	}
+
+	/// Register a service
+	///
+	/// The service is known to the daemon until the registration is
+	/// dropped.  Without a name the service is called `"mock"`; the host
+	/// defaults to `"mock.local."`.
+	///
+	/// Conflicting names are renamed to `"name (2)"` etc. unless
+	/// [`NoAutoRename`](../enum.RegisterFlag.html#variant.NoAutoRename)
+	/// is given, which fails with a
+	/// [`NameConflict`](../enum.DNSServiceError.html#variant.NameConflict).
+	///
+	/// See [`register`](../fn.register.html).
+	pub fn register(
+		&self,
+		flags: RegisterFlags,
+		interface: Interface,
+		name: Option<&str>,
+		reg_type: &str,
+		subtypes: &[&str],
+		domain: Option<&str>,
+		host: Option<&str>,
+		port: u16,
+		txt: &[u8]
+	) -> io::Result<Register> {
+		let restart = {
+			let daemon = self.clone();
+			let reg_type = reg_type.to_string();
+			let subtypes: Vec<String> = subtypes.iter().map(|s| s.to_string()).collect();
+			let domain = domain.map(str::to_string);
+			let host = host.map(str::to_string);
+			let txt = txt.to_vec();
+			let restart: Arc<crate::service::Restart> = Arc::new(move |name: &str, port: u16, changed_txt: Option<&[u8]>| {
+				let subtypes: Vec<&str> = subtypes.iter().map(String::as_str).collect();
+				daemon.register(flags, interface, Some(name), &reg_type, &subtypes, domain.as_deref(), host.as_deref(), port, changed_txt.unwrap_or(&txt))
+			});
+			Some(restart)
+		};
+		let mut state = lock(&self.0);
+		let id = state.next_registration;
+		state.next_registration += 1;
+
+		let guard: Box<dyn Any + Send> = Box::new(RegistrationGuard {
+			state: self.0.clone(),
+			id,
+		});
+		let (sender, stream) = ServiceStream::new_mock(guard);
+
+		let base_name = name.unwrap_or("mock");
+		let domain = domain.unwrap_or("local.");
+		let mut service_name = base_name.to_string();
+		let mut counter = 1;
+		while state.services.iter().any(|(_, s)| s.is(&service_name, reg_type, domain)) {
+			if flags & RegisterFlag::NoAutoRename {
+				let _ = sender.unbounded_send(Err(Error::KnownError(ffi::DNSServiceError::NameConflict)));
+				state.registrations.push((id, sender));
+				return Ok(Register::new(ServiceFuture::from_stream(stream), restart));
+			}
+			counter += 1;
+			service_name = format!("{} ({})", base_name, counter);
+		}
+
+		let service = MockService {
+			interface: if interface == Interface::Any { Interface::LocalOnly } else { interface },
+			name: service_name,
+			reg_type: reg_type.to_string(),
+			subtypes: subtypes.iter().map(|s| subtype_label(s).to_string()).collect(),
+			domain: domain.to_string(),
+			host_target: host.unwrap_or("mock.local.").to_string(),
+			port,
+			txt: if txt.is_empty() { vec![0] } else { txt.to_vec() },
+		};
+		let _ = sender.unbounded_send(Ok(RegisterResult {
+			name: service.name.clone(),
+			reg_type: absolute(reg_type),
+			domain: absolute(domain),
+			received_at: crate::Timestamp::now(),
+		}));
+		state.registrations.push((id, sender));
+		state.services.push((Some(id), service.clone()));
+		state.notify_services(true, &[service]);
+
+		Ok(Register::new(ServiceFuture::from_stream(stream), restart))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::executor::block_on;
+	use futures::{FutureExt,StreamExt};
+
+	use super::*;
+	use crate::batched::BatchedTrait;
+	use crate::{QueuePolicy,TerminationReason};
+
+	fn service(name: &str) -> MockService {
+		MockService::new(name, "_http._tcp", "host.local", 80)
+	}
+
+	fn browse(daemon: &MockDaemon) -> Browse {
+		daemon.browse(BrowseFlags::none(), Interface::Any, "_http._tcp", None, None).unwrap()
+	}
+
+	fn names(results: &[BrowseResult]) -> Vec<&str> {
+		results.iter().map(|r| r.service_name.as_str()).collect()
+	}
+
+	fn register(daemon: &MockDaemon, flags: RegisterFlags, name: &str) -> Register {
+		daemon.register(flags, Interface::Any, Some(name), "_http._tcp", &[], None, None, 80, &[]).unwrap()
+	}
+
+	#[test]
+	fn browse_bursts() {
+		let daemon = MockDaemon::new();
+		daemon.add_services(vec![service("a"), service("b")]);
+		let mut batches = browse(&daemon).batched();
+		let batch = block_on(batches.next()).unwrap().unwrap();
+		assert_eq!(names(&batch), ["a", "b"]);
+		assert!(batch.iter().all(|r| r.flags & BrowsedFlag::Add));
+		assert!(batch[0].flags & BrowsedFlag::MoreComing);
+		assert!(!(batch[1].flags & BrowsedFlag::MoreComing));
+
+		daemon.add_service(service("c"));
+		assert!(daemon.remove_service("a", "_http._tcp", "local"));
+		let batch = block_on(batches.next()).unwrap().unwrap();
+		assert_eq!(names(&batch), ["c"]);
+		let batch = block_on(batches.next()).unwrap().unwrap();
+		assert_eq!(names(&batch), ["a"]);
+		assert!(!(batch[0].flags & BrowsedFlag::Add));
+		assert!(batches.next().now_or_never().is_none());
+	}
+
+	#[test]
+	fn browse_stop() {
+		let daemon = MockDaemon::new();
+		daemon.add_service(service("a"));
+		let mut browse = browse(&daemon);
+		browse.stop_handle().stop();
+		assert!(block_on(browse.next()).is_none());
+		assert_eq!(browse.termination_reason(), Some(TerminationReason::Stopped));
+		// stays ended
+		daemon.add_service(service("b"));
+		assert!(block_on(browse.next()).is_none());
+	}
+
+	#[test]
+	fn browse_finished() {
+		let daemon = MockDaemon::new();
+		daemon.add_service(service("a"));
+		let mut browse = browse(&daemon);
+		drop(daemon);
+		// queued results are still delivered
+		assert_eq!(block_on(browse.next()).unwrap().unwrap().service_name, "a");
+		assert!(block_on(browse.next()).is_none());
+		assert_eq!(browse.termination_reason(), Some(TerminationReason::Finished));
+	}
+
+	#[test]
+	fn daemon_exited() {
+		let daemon = MockDaemon::new();
+		let mut browse = browse(&daemon);
+		daemon.fail(ffi::DNSServiceError::ServiceNotRunning);
+		let e = block_on(browse.next()).unwrap().unwrap_err();
+		assert!(matches!(e, Error::KnownError(ffi::DNSServiceError::ServiceNotRunning)));
+		assert_eq!(browse.termination_reason(), Some(TerminationReason::DaemonExited));
+		assert!(block_on(browse.next()).is_none());
+	}
+
+	#[test]
+	fn query_timeout() {
+		let daemon = MockDaemon::new();
+		let mut query = daemon.query_record(QueryRecordFlags::none(), Interface::Any, "host.local", RecordType::A, RecordClass::IN).unwrap();
+		daemon.fail(ffi::DNSServiceError::Timeout);
+		// not an error
+		assert!(block_on(query.next()).is_none());
+		assert_eq!(query.termination_reason(), Some(TerminationReason::TimedOut));
+	}
+
+	#[test]
+	fn queue_overflow() {
+		let services = || (0..5).map(|i| service(&format!("s{}", i)));
+
+		let daemon = MockDaemon::new();
+		daemon.add_services(services());
+		let mut results = browse(&daemon).queue_capacity(2).queue_policy(QueuePolicy::Error);
+		assert!(block_on(results.next()).unwrap().is_err());
+		assert_eq!(block_on(results.next()).unwrap().unwrap().service_name, "s0");
+		assert_eq!(block_on(results.next()).unwrap().unwrap().service_name, "s1");
+		assert!(results.next().now_or_never().is_none());
+
+		let daemon = MockDaemon::new();
+		daemon.add_services(services());
+		let mut results = browse(&daemon).queue_capacity(2).queue_policy(QueuePolicy::DropOldest);
+		assert_eq!(block_on(results.next()).unwrap().unwrap().service_name, "s3");
+		assert_eq!(block_on(results.next()).unwrap().unwrap().service_name, "s4");
+		assert!(results.next().now_or_never().is_none());
+
+		// blocking keeps everything
+		let daemon = MockDaemon::new();
+		daemon.add_services(services());
+		let results = browse(&daemon).queue_capacity(2);
+		let batch = block_on(results.batched().into_future()).0.unwrap().unwrap();
+		assert_eq!(names(&batch), ["s0", "s1", "s2", "s3", "s4"]);
+	}
+
+	#[test]
+	fn filtered_snapshot() {
+		let daemon = MockDaemon::new();
+		daemon.add_services(vec![service("a1"), service("b"), service("a2"), service("c")]);
+		let snapshot = block_on(browse(&daemon).filter_name("A*").initial_snapshot()).unwrap();
+		assert_eq!(names(&snapshot), ["a1", "a2"]);
+
+		// the burst ends with a dropped result
+		let daemon = MockDaemon::new();
+		daemon.add_services(vec![service("a1"), service("b")]);
+		let snapshot = block_on(browse(&daemon).filter_name("a*").initial_snapshot()).unwrap();
+		assert_eq!(names(&snapshot), ["a1"]);
+	}
+
+	#[test]
+	fn resolve() {
+		let daemon = MockDaemon::new();
+		daemon.add_service(MockService::new("web.site", "_http._tcp", "host.local", 8080));
+		let mut resolve = daemon.resolve(Interface::Any, "web.site", "_http._tcp.", "local.").unwrap();
+		let result = block_on(resolve.next()).unwrap().unwrap();
+		assert_eq!(result.fullname, "web\\.site._http._tcp.local.");
+		assert_eq!(result.host_target, "host.local.");
+		assert_eq!(result.port, 8080);
+
+		// nothing else (yet)
+		assert!(resolve.next().now_or_never().is_none());
+		let mut other = daemon.resolve(Interface::Any, "other", "_http._tcp", "local").unwrap();
+		assert!(other.next().now_or_never().is_none());
+	}
+
+	#[test]
+	fn register_and_drop() {
+		let daemon = MockDaemon::new();
+		let mut batches = browse(&daemon).batched();
+		let (registration, result) = block_on(register(&daemon, RegisterFlags::none(), "web")).unwrap();
+		assert_eq!(result.name, "web");
+		assert_eq!(result.reg_type, "_http._tcp.");
+		assert_eq!(names(&block_on(batches.next()).unwrap().unwrap()), ["web"]);
+
+		drop(registration);
+		assert!(daemon.services().is_empty());
+		let batch = block_on(batches.next()).unwrap().unwrap();
+		assert!(!(batch[0].flags & BrowsedFlag::Add));
+	}
+
+	#[test]
+	fn register_conflicts() {
+		let daemon = MockDaemon::new();
+		daemon.add_service(service("web"));
+		let (_registration, result) = block_on(register(&daemon, RegisterFlags::none(), "web")).unwrap();
+		assert_eq!(result.name, "web (2)");
+
+		let e = block_on(register(&daemon, RegisterFlag::NoAutoRename.into(), "web")).err().unwrap();
+		assert!(matches!(e, Error::KnownError(ffi::DNSServiceError::NameConflict)));
+		assert_eq!(daemon.services().len(), 2);
+	}
+
+	#[test]
+	fn name_conflict() {
+		let daemon = MockDaemon::new();
+		let (mut registration, _) = block_on(register(&daemon, RegisterFlag::NoAutoRename.into(), "web")).unwrap();
+		assert!(registration.name_conflict().now_or_never().is_none());
+		daemon.fail(ffi::DNSServiceError::NameConflict);
+		assert!(block_on(registration.name_conflict()).is_ok());
+
+		// ends when the registration is stopped
+		let (mut registration, _) = block_on(register(&daemon, RegisterFlags::none(), "other")).unwrap();
+		registration.stop_handle().stop();
+		let e = block_on(registration.name_conflict()).unwrap_err();
+		assert_eq!(io::Error::from(e).kind(), io::ErrorKind::Interrupted);
+		assert_eq!(registration.termination_reason(), Some(TerminationReason::Stopped));
+	}
+}
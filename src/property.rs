@@ -0,0 +1,69 @@
+use std::fmt;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char,c_void};
+
+use crate::error::Error;
+use crate::ffi;
+
+/// Version of the running daemon
+///
+/// Orders like the version numbers of mDNSResponder releases, e.g.
+/// `878.1.1` is older than `1310.1`.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct DaemonVersion {
+	/// major version
+	pub major: u32,
+	/// minor version
+	pub minor: u32,
+	/// patch level
+	pub patch: u32,
+}
+
+impl DaemonVersion {
+	/// Decode version as returned by the daemon
+	///
+	/// The daemon encodes the version as `major * 10000 + minor * 100 +
+	/// patch`.
+	pub fn from_raw(version: u32) -> Self {
+		DaemonVersion {
+			major: version / 10000,
+			minor: (version / 100) % 100,
+			patch: version % 100,
+		}
+	}
+
+	/// Encode version like the daemon does
+	pub fn into_raw(self) -> u32 {
+		self.major * 10000 + self.minor * 100 + self.patch
+	}
+}
+
+impl fmt::Display for DaemonVersion {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+	}
+}
+
+/// Query version of the running daemon
+///
+/// Fails with [`DNSServiceError::Unsupported`] when running against
+/// avahi (or other implementations without this property), and with
+/// [`DNSServiceError::ServiceNotRunning`] if the daemon isn't running.
+///
+/// [`DNSServiceError::Unsupported`]: enum.DNSServiceError.html#variant.Unsupported
+/// [`DNSServiceError::ServiceNotRunning`]: enum.DNSServiceError.html#variant.ServiceNotRunning
+///
+/// See [`DNSServiceGetProperty`](https://developer.apple.com/documentation/dnssd/1804732-dnsservicegetproperty).
+pub fn daemon_version() -> io::Result<DaemonVersion> {
+	let mut version : u32 = 0;
+	let mut size = mem::size_of::<u32>() as u32;
+	Error::from(trace_ffi!("DNSServiceGetProperty"; unsafe {
+		ffi::DNSServiceGetProperty(
+			ffi::PROPERTY_DAEMON_VERSION.as_ptr() as *const c_char,
+			&mut version as *mut u32 as *mut c_void,
+			&mut size
+		)
+	}))?;
+	Ok(DaemonVersion::from_raw(version))
+}
@@ -1,33 +1,135 @@
+use std::any::Any;
 use std::os::raw::{c_int,c_void};
-use std::cell::UnsafeCell;
 use std::ptr::null_mut;
-use std::rc::Rc;
+use std::sync::{Arc,Mutex,MutexGuard};
 
-use cstr;
-use error::Error;
-use ffi;
+use crate::cstr;
+use crate::error::Error;
+use crate::ffi;
 
 type FFIResult<R> = Result<R, Error>;
 
-struct InnerDNSService(ffi::DNSServiceRef);
+struct InnerDNSService {
+	sd_ref: ffi::DNSServiceRef,
+	// a `DNSServiceRef` (and its records) must not be used concurrently
+	lock: Mutex<()>,
+	keep_alive: Mutex<Vec<Box<dyn Any + Send>>>,
+	// connection shared with (through `kDNSServiceFlagsShareConnection`);
+	// must outlive this reference
+	parent: Option<DNSService>,
+	// callbacks are delivered on the dispatch queue
+	#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+	dispatched: std::sync::atomic::AtomicBool,
+}
+
+// all calls using the `DNSServiceRef` hold `lock` (apart from `Drop`,
+// which has exclusive access)
+unsafe impl Send for InnerDNSService {}
+unsafe impl Sync for InnerDNSService {}
 
 impl Drop for InnerDNSService {
 	fn drop(&mut self) {
+		// the parent might be in use by other operations
+		let parent = self.parent.take();
+		let _lock = parent.as_ref().map(|p| p.get().lock());
+		#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+		{
+			if *self.dispatched.get_mut() {
+				// deallocate on the queue so no callback is running
+				// concurrently
+				extern "C" fn deallocate(sd_ref: *mut c_void) {
+					unsafe { ffi::DNSServiceRefDeallocate(sd_ref as ffi::DNSServiceRef) };
+				}
+				unsafe {
+					ffi::dispatch_sync_f(dispatch_queue().0, self.sd_ref as *mut c_void, deallocate);
+				}
+				return;
+			}
+		}
 		unsafe {
-			ffi::DNSServiceRefDeallocate(self.0);
+			ffi::DNSServiceRefDeallocate(self.sd_ref);
 		}
 	}
 }
 
+// single serial queue shared by all operations
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+struct Queue(ffi::DispatchQueue);
+
+// dispatch queues are thread-safe
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+unsafe impl Send for Queue {}
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+unsafe impl Sync for Queue {}
+
+#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+fn dispatch_queue() -> &'static Queue {
+	static QUEUE: std::sync::OnceLock<Queue> = std::sync::OnceLock::new();
+	QUEUE.get_or_init(|| {
+		let label = b"async-dnssd\0";
+		// serial queue (null attributes); never released
+		Queue(unsafe { ffi::dispatch_queue_create(label.as_ptr() as *const _, null_mut()) })
+	})
+}
+
+// start value of the `DNSServiceRef` and flags to create an operation
+// (optionally sharing the connection of `parent`)
+fn share_connection(parent: Option<&DNSService>, flags: ffi::DNSServiceFlags) -> (ffi::DNSServiceRef, ffi::DNSServiceFlags) {
+	match parent {
+		Some(parent) => (parent.get().sd_ref, flags | ffi::FLAGS_SHARE_CONNECTION),
+		None => (null_mut(), flags),
+	}
+}
+
 impl InnerDNSService {
-	fn fd(&mut self) -> c_int {
-		unsafe { ffi::DNSServiceRefSockFD(self.0) }
+	fn new(sd_ref: ffi::DNSServiceRef) -> Self {
+		InnerDNSService::new_child(sd_ref, None)
+	}
+
+	fn new_child(sd_ref: ffi::DNSServiceRef, parent: Option<&DNSService>) -> Self {
+		InnerDNSService {
+			sd_ref,
+			lock: Mutex::default(),
+			keep_alive: Mutex::default(),
+			parent: parent.cloned(),
+			#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+			dispatched: Default::default(),
+		}
 	}
 
-	fn process_result(&mut self) -> FFIResult<()> {
-		Error::from(unsafe {
-			ffi::DNSServiceProcessResult(self.0)
-		})
+	fn lock(&self) -> MutexGuard<'_, ()> {
+		// shared references use the connection of the parent
+		if let Some(parent) = &self.parent {
+			return parent.get().lock();
+		}
+		// nothing to poison: the guarded data is the `DNSServiceRef`
+		self.lock.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn fd(&self) -> c_int {
+		unsafe { ffi::DNSServiceRefSockFD(self.sd_ref) }
+	}
+
+	#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+	fn set_dispatch_queue(&self) -> FFIResult<()> {
+		let _lock = self.lock();
+		Error::from(trace_ffi!("DNSServiceSetDispatchQueue"; unsafe {
+			ffi::DNSServiceSetDispatchQueue(self.sd_ref, dispatch_queue().0)
+		}))?;
+		self.dispatched.store(true, std::sync::atomic::Ordering::Relaxed);
+		Ok(())
+	}
+
+	fn process_result(&self) -> FFIResult<()> {
+		let _lock = self.lock();
+		Error::from(trace_ffi!("DNSServiceProcessResult"; unsafe {
+			ffi::DNSServiceProcessResult(self.sd_ref)
+		}))
+	}
+
+	// contexts are dropped after the service was deallocated
+	fn keep_alive(&self, data: Box<dyn Any + Send>) {
+		self.keep_alive.lock().unwrap_or_else(|e| e.into_inner()).push(data);
 	}
 
 	fn enumerate_domains(
@@ -37,10 +139,10 @@ impl InnerDNSService {
 		context: *mut c_void
 	) -> FFIResult<InnerDNSService> {
 		let mut sd_ref : ffi::DNSServiceRef = null_mut();
-		Error::from(unsafe {
+		Error::from(trace_ffi!("DNSServiceEnumerateDomains", flags, interface_index; unsafe {
 			ffi::DNSServiceEnumerateDomains(&mut sd_ref, flags, interface_index, callback, context)
-		})?;
-		Ok(InnerDNSService(sd_ref))
+		}))?;
+		Ok(InnerDNSService::new(sd_ref))
 	}
 
 	fn register(
@@ -61,7 +163,7 @@ impl InnerDNSService {
 		let txt_record = txt.as_ptr();
 
 		let mut sd_ref : ffi::DNSServiceRef = null_mut();
-		Error::from(unsafe {
+		Error::from(trace_ffi!("DNSServiceRegister", flags, interface_index, port; unsafe {
 			ffi::DNSServiceRegister(
 				&mut sd_ref,
 				flags,
@@ -76,11 +178,12 @@ impl InnerDNSService {
 				callback,
 				context
 			)
-		})?;
-		Ok(InnerDNSService(sd_ref))
+		}))?;
+		Ok(InnerDNSService::new(sd_ref))
 	}
 
 	fn browse(
+		parent: Option<&DNSService>,
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
 		reg_type: &cstr::CStr,
@@ -88,8 +191,9 @@ impl InnerDNSService {
 		callback: ffi::DNSServiceBrowseReply,
 		context: *mut c_void
 	) -> FFIResult<InnerDNSService> {
-		let mut sd_ref : ffi::DNSServiceRef = null_mut();
-		Error::from(unsafe {
+		let (mut sd_ref, flags) = share_connection(parent, flags);
+		let _lock = parent.map(|p| p.get().lock());
+		Error::from(trace_ffi!("DNSServiceBrowse", flags, interface_index; unsafe {
 			ffi::DNSServiceBrowse(
 				&mut sd_ref,
 				flags,
@@ -99,11 +203,12 @@ impl InnerDNSService {
 				callback,
 				context
 			)
-		})?;
-		Ok(InnerDNSService(sd_ref))
+		}))?;
+		Ok(InnerDNSService::new_child(sd_ref, parent))
 	}
 
 	fn resolve(
+		parent: Option<&DNSService>,
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
 		name: &cstr::CStr,
@@ -112,8 +217,9 @@ impl InnerDNSService {
 		callback: ffi::DNSServiceResolveReply,
 		context: *mut c_void
 	) -> FFIResult<InnerDNSService> {
-		let mut sd_ref : ffi::DNSServiceRef = null_mut();
-		Error::from(unsafe {
+		let (mut sd_ref, flags) = share_connection(parent, flags);
+		let _lock = parent.map(|p| p.get().lock());
+		Error::from(trace_ffi!("DNSServiceResolve", flags, interface_index; unsafe {
 			ffi::DNSServiceResolve(
 				&mut sd_ref,
 				flags,
@@ -124,19 +230,92 @@ impl InnerDNSService {
 				callback,
 				context
 			)
-		})?;
-		Ok(InnerDNSService(sd_ref))
+		}))?;
+		Ok(InnerDNSService::new_child(sd_ref, parent))
 	}
 
 	fn create_connection() -> FFIResult<InnerDNSService> {
 		let mut sd_ref : ffi::DNSServiceRef = null_mut();
-		Error::from(unsafe {
+		Error::from(trace_ffi!("DNSServiceCreateConnection"; unsafe {
 			ffi::DNSServiceCreateConnection(&mut sd_ref)
-		})?;
-		Ok(InnerDNSService(sd_ref))
+		}))?;
+		Ok(InnerDNSService::new(sd_ref))
+	}
+
+	fn get_addr_info(
+		flags: ffi::DNSServiceFlags,
+		interface_index: u32,
+		protocol: ffi::DNSServiceProtocol,
+		hostname: &cstr::CStr,
+		callback: ffi::DNSServiceGetAddrInfoReply,
+		context: *mut c_void
+	) -> FFIResult<InnerDNSService> {
+		let mut sd_ref : ffi::DNSServiceRef = null_mut();
+		Error::from(trace_ffi!("DNSServiceGetAddrInfo", flags, interface_index, protocol; unsafe {
+			ffi::DNSServiceGetAddrInfo(
+				&mut sd_ref,
+				flags,
+				interface_index,
+				protocol,
+				hostname.as_ptr(),
+				callback,
+				context
+			)
+		}))?;
+		Ok(InnerDNSService::new(sd_ref))
+	}
+
+	fn nat_port_mapping_create(
+		flags: ffi::DNSServiceFlags,
+		interface_index: u32,
+		protocol: ffi::DNSServiceProtocol,
+		internal_port: u16,
+		external_port: u16,
+		ttl: u32,
+		callback: ffi::DNSServiceNATPortMappingReply,
+		context: *mut c_void
+	) -> FFIResult<InnerDNSService> {
+		let mut sd_ref : ffi::DNSServiceRef = null_mut();
+		Error::from(trace_ffi!("DNSServiceNATPortMappingCreate", flags, interface_index, protocol, internal_port, external_port, ttl; unsafe {
+			ffi::DNSServiceNATPortMappingCreate(
+				&mut sd_ref,
+				flags,
+				interface_index,
+				protocol,
+				internal_port,
+				external_port,
+				ttl,
+				callback,
+				context
+			)
+		}))?;
+		Ok(InnerDNSService::new(sd_ref))
+	}
+
+	#[cfg(unix)]
+	fn sleep_keepalive(
+		flags: ffi::DNSServiceFlags,
+		fd: c_int,
+		timeout: u32,
+		callback: ffi::DNSServiceSleepKeepaliveReply,
+		context: *mut c_void
+	) -> FFIResult<InnerDNSService> {
+		let mut sd_ref : ffi::DNSServiceRef = null_mut();
+		Error::from(trace_ffi!("DNSServiceSleepKeepalive", flags, fd, timeout; unsafe {
+			ffi::DNSServiceSleepKeepalive(
+				&mut sd_ref,
+				flags,
+				fd,
+				timeout,
+				callback,
+				context
+			)
+		}))?;
+		Ok(InnerDNSService::new(sd_ref))
 	}
 
 	fn query_record(
+		parent: Option<&DNSService>,
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
 		fullname: &cstr::CStr,
@@ -145,8 +324,9 @@ impl InnerDNSService {
 		callback: ffi::DNSServiceQueryRecordReply,
 		context: *mut c_void
 	) -> FFIResult<InnerDNSService> {
-		let mut sd_ref : ffi::DNSServiceRef = null_mut();
-		Error::from(unsafe {
+		let (mut sd_ref, flags) = share_connection(parent, flags);
+		let _lock = parent.map(|p| p.get().lock());
+		Error::from(trace_ffi!("DNSServiceQueryRecord", flags, interface_index, rr_type, rr_class; unsafe {
 			ffi::DNSServiceQueryRecord(
 				&mut sd_ref,
 				flags,
@@ -157,36 +337,51 @@ impl InnerDNSService {
 				callback,
 				context
 			)
-		})?;
-		Ok(InnerDNSService(sd_ref))
+		}))?;
+		Ok(InnerDNSService::new_child(sd_ref, parent))
 	}
 }
 
 #[derive(Clone)]
-pub struct DNSService(Rc<UnsafeCell<InnerDNSService>>);
+pub struct DNSService(Arc<InnerDNSService>);
 
 impl DNSService {
-	fn get(&self) -> &mut InnerDNSService {
-		use std::mem::transmute;
-		// Rc means it cannot be shared across threads.
-		// we need to make sure our callbacks don't ever come back
-		// here, otherwise there is no way for "re-entrance".
-		let r = self.0.get();
-		unsafe { transmute::<*mut InnerDNSService, &mut InnerDNSService>(r) }
+	fn get(&self) -> &InnerDNSService {
+		&self.0
 	}
 
 	fn new(s: FFIResult<InnerDNSService>) -> FFIResult<DNSService> {
-		s.map(|s| DNSService(Rc::new(UnsafeCell::new(s))))
+		s.map(|s| DNSService(Arc::new(s)))
 	}
 
 	pub fn fd(&self) -> c_int {
 		self.get().fd()
 	}
 
+	pub fn as_raw(&self) -> ffi::DNSServiceRef {
+		self.get().sd_ref
+	}
+
+	// takes ownership of `sd_ref`
+	pub unsafe fn from_raw(sd_ref: ffi::DNSServiceRef) -> DNSService {
+		DNSService(Arc::new(InnerDNSService::new(sd_ref)))
+	}
+
 	pub fn process_result(&self) -> FFIResult<()> {
 		self.get().process_result()
 	}
 
+	/// Deliver callbacks on the shared dispatch queue
+	#[cfg(all(target_vendor = "apple", feature = "dispatch-queue"))]
+	pub fn set_dispatch_queue(&self) -> FFIResult<()> {
+		self.get().set_dispatch_queue()
+	}
+
+	/// Keep (callback context) data alive as long as the service lives
+	pub fn keep_alive(&self, data: Box<dyn Any + Send>) {
+		self.get().keep_alive(data)
+	}
+
 	pub fn enumerate_domains(
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
@@ -235,6 +430,7 @@ impl DNSService {
 	}
 
 	pub fn browse(
+		parent: Option<&DNSService>,
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
 		reg_type: &cstr::CStr,
@@ -243,11 +439,12 @@ impl DNSService {
 		context: *mut c_void
 	) -> FFIResult<DNSService> {
 		Self::new(
-			InnerDNSService::browse(flags, interface_index, reg_type, domain, callback, context)
+			InnerDNSService::browse(parent, flags, interface_index, reg_type, domain, callback, context)
 		)
 	}
 
 	pub fn resolve(
+		parent: Option<&DNSService>,
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
 		name: &cstr::CStr,
@@ -257,7 +454,7 @@ impl DNSService {
 		context: *mut c_void
 	) -> FFIResult<DNSService> {
 		Self::new(
-			InnerDNSService::resolve(flags, interface_index, name, reg_type, domain, callback, context)
+			InnerDNSService::resolve(parent, flags, interface_index, name, reg_type, domain, callback, context)
 		)
 	}
 
@@ -294,7 +491,49 @@ impl DNSService {
 		)
 	}
 
+	pub fn get_addr_info(
+		flags: ffi::DNSServiceFlags,
+		interface_index: u32,
+		protocol: ffi::DNSServiceProtocol,
+		hostname: &cstr::CStr,
+		callback: ffi::DNSServiceGetAddrInfoReply,
+		context: *mut c_void
+	) -> FFIResult<DNSService> {
+		Self::new(
+			InnerDNSService::get_addr_info(flags, interface_index, protocol, hostname, callback, context)
+		)
+	}
+
+	pub fn nat_port_mapping_create(
+		flags: ffi::DNSServiceFlags,
+		interface_index: u32,
+		protocol: ffi::DNSServiceProtocol,
+		internal_port: u16,
+		external_port: u16,
+		ttl: u32,
+		callback: ffi::DNSServiceNATPortMappingReply,
+		context: *mut c_void
+	) -> FFIResult<DNSService> {
+		Self::new(
+			InnerDNSService::nat_port_mapping_create(flags, interface_index, protocol, internal_port, external_port, ttl, callback, context)
+		)
+	}
+
+	#[cfg(unix)]
+	pub fn sleep_keepalive(
+		flags: ffi::DNSServiceFlags,
+		fd: c_int,
+		timeout: u32,
+		callback: ffi::DNSServiceSleepKeepaliveReply,
+		context: *mut c_void
+	) -> FFIResult<DNSService> {
+		Self::new(
+			InnerDNSService::sleep_keepalive(flags, fd, timeout, callback, context)
+		)
+	}
+
 	pub fn query_record(
+		parent: Option<&DNSService>,
 		flags: ffi::DNSServiceFlags,
 		interface_index: u32,
 		fullname: &cstr::CStr,
@@ -304,29 +543,34 @@ impl DNSService {
 		context: *mut c_void
 	) -> FFIResult<DNSService> {
 		Self::new(
-			InnerDNSService::query_record(flags, interface_index, fullname, rr_type, rr_class, callback, context)
+			InnerDNSService::query_record(parent, flags, interface_index, fullname, rr_type, rr_class, callback, context)
 		)
 	}
 }
 
 struct InnerDNSRecord(DNSService, ffi::DNSRecordRef, u16);
 
+// the `DNSRecordRef` is only used while holding the lock of the service
+unsafe impl Send for InnerDNSRecord {}
+unsafe impl Sync for InnerDNSRecord {}
+
 impl Drop for InnerDNSRecord {
 	fn drop(&mut self) {
-		if null_mut() != self.1 {
+		if !self.1.is_null() {
+			let _lock = self.get_service().lock();
 			unsafe {
-				ffi::DNSServiceRemoveRecord(
-					self.get_service().0,
+				trace_ffi!("DNSServiceRemoveRecord"; ffi::DNSServiceRemoveRecord(
+					self.get_service().sd_ref,
 					self.1,
 					0 /* no flags */
-				);
+				));
 			}
 		}
 	}
 }
 
 impl InnerDNSRecord {
-	fn get_service(&self) -> &mut InnerDNSService {
+	fn get_service(&self) -> &InnerDNSService {
 		self.0.get()
 	}
 
@@ -348,9 +592,10 @@ impl InnerDNSRecord {
 		let rdata = rdata.as_ptr();
 
 		let mut record_ref: ffi::DNSRecordRef = null_mut();
-		Error::from(unsafe {
+		let _lock = service.get().lock();
+		Error::from(trace_ffi!("DNSServiceAddRecord", flags, rr_type, ttl; unsafe {
 			ffi::DNSServiceAddRecord(
-				service.get().0,
+				service.get().sd_ref,
 				&mut record_ref,
 				flags,
 				rr_type,
@@ -358,7 +603,7 @@ impl InnerDNSRecord {
 				rdata,
 				ttl
 			)
-		})?;
+		}))?;
 		Ok(InnerDNSRecord(service.clone(), record_ref, rr_type))
 	}
 
@@ -381,9 +626,10 @@ impl InnerDNSRecord {
 		let rdata = rdata.as_ptr();
 
 		let mut record_ref: ffi::DNSRecordRef = null_mut();
-		Error::from(unsafe {
+		let _lock = service.get().lock();
+		Error::from(trace_ffi!("DNSServiceRegisterRecord", flags, interface_index, rr_type, rr_class, ttl; unsafe {
 			ffi::DNSServiceRegisterRecord(
-				service.get().0,
+				service.get().sd_ref,
 				&mut record_ref,
 				flags,
 				interface_index,
@@ -396,7 +642,7 @@ impl InnerDNSRecord {
 				callback,
 				context
 			)
-		})?;
+		}))?;
 		Ok(InnerDNSRecord(service.clone(), record_ref, rr_type))
 	}
 
@@ -411,16 +657,34 @@ impl InnerDNSRecord {
 		let rd_len = rd_len as u16;
 		let rdata = rdata.as_ptr();
 
-		Error::from(unsafe {
+		let _lock = self.get_service().lock();
+		Error::from(trace_ffi!("DNSServiceUpdateRecord", flags, ttl; unsafe {
 			ffi::DNSServiceUpdateRecord(
-				self.get_service().0,
+				self.get_service().sd_ref,
 				self.1,
 				flags,
 				rd_len,
 				rdata,
 				ttl
 			)
-		})
+		}))
+	}
+
+	fn remove(mut self) -> FFIResult<()> {
+		// don't remove again when dropped
+		let record_ref = std::mem::replace(&mut self.1, null_mut());
+		if record_ref.is_null() {
+			// default TXT record
+			return Err(Error::KnownError(ffi::DNSServiceError::BadReference));
+		}
+		let _lock = self.get_service().lock();
+		Error::from(trace_ffi!("DNSServiceRemoveRecord"; unsafe {
+			ffi::DNSServiceRemoveRecord(
+				self.get_service().sd_ref,
+				record_ref,
+				0 /* no flags */
+			)
+		}))
 	}
 
 	fn keep(mut self) {
@@ -435,6 +699,14 @@ impl DNSRecord {
 		self.0.rr_type()
 	}
 
+	pub fn as_raw(&self) -> ffi::DNSRecordRef {
+		self.0.1
+	}
+
+	pub fn service_as_raw(&self) -> ffi::DNSServiceRef {
+		self.0.0.as_raw()
+	}
+
 	pub fn update_record(
 		&self,
 		flags: ffi::DNSServiceFlags,
@@ -444,6 +716,10 @@ impl DNSRecord {
 		self.0.update_record(flags, rdata, ttl)
 	}
 
+	pub fn remove(self) -> FFIResult<()> {
+		self.0.remove()
+	}
+
 	// keep "forever" (until service is dropped)
 	pub fn keep(self) {
 		self.0.keep()
@@ -457,13 +733,13 @@ pub fn reconfirm_record(
 	rr_type: u16,
 	rr_class: u16,
 	rdata: &[u8]
-) {
+) -> FFIResult<()> {
 	let rd_len = rdata.len();
 	assert!(rd_len < (1 << 16));
 	let rd_len = rd_len as u16;
 	let rdata = rdata.as_ptr();
 
-	unsafe {
+	let _result = trace_ffi!("DNSServiceReconfirmRecord", flags, interface_index, rr_type, rr_class; unsafe {
 		ffi::DNSServiceReconfirmRecord(
 			flags,
 			interface_index,
@@ -472,6 +748,12 @@ pub fn reconfirm_record(
 			rr_class,
 			rd_len,
 			rdata
-		);
-	}
+		)
+	});
+
+	// older implementations (including avahi) return `void`
+	#[cfg(not(avahi_compat))]
+	Error::from(_result)?;
+
+	Ok(())
 }
@@ -1,9 +1,12 @@
 pub struct RawBox<T>(*mut T);
 
+// owns the data like a `Box`
+unsafe impl<T: Send> Send for RawBox<T> {}
+
 impl<T> Drop for RawBox<T> {
 	fn drop(&mut self) {
 		// reconstruct Box data and let it drop
-		unsafe { Box::from_raw(self.0) };
+		drop(unsafe { Box::from_raw(self.0) });
 	}
 }
 
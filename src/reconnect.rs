@@ -0,0 +1,132 @@
+use futures::{self,FutureExt,Stream,StreamExt};
+use futures_timer::Delay;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::ffi;
+
+/// Item of a [`Reconnect`](struct.Reconnect.html) stream
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub enum ReconnectEvent<T> {
+	/// Result from the underlying operation
+	Result(T),
+	/// The daemon restarted and the operation was started again
+	///
+	/// Results received before might be outdated (e.g. browse results
+	/// will be reported again, without removal of the old ones).
+	Reconnected,
+}
+
+/// Restart an operation when the daemon restarts
+///
+/// See [`reconnect`](fn.reconnect.html).
+pub struct Reconnect<S, F> {
+	factory: F,
+	stream: Option<S>,
+	retry: Option<Delay>,
+	retry_interval: Duration,
+	// notified when the daemon connection broke
+	on_disconnect: Option<Box<dyn FnMut() + Send>>,
+}
+
+// errors indicating the connection to the daemon broke
+pub(crate) fn is_disconnect(e: &Error) -> bool {
+	matches!(e.known_code(), Some(
+		ffi::DNSServiceError::ServiceNotRunning
+		| ffi::DNSServiceError::DefunctConnection
+	))
+}
+
+impl<S, F> Reconnect<S, F> {
+	/// Set interval to retry starting the operation while the daemon
+	/// isn't running (default 1 second)
+	pub fn retry_interval(mut self, interval: Duration) -> Self {
+		self.retry_interval = interval;
+		self
+	}
+
+	pub(crate) fn on_disconnect(mut self, observer: Box<dyn FnMut() + Send>) -> Self {
+		self.on_disconnect = Some(observer);
+		self
+	}
+}
+
+impl<T, S, F> Stream for Reconnect<S, F>
+where
+	S: Stream<Item = Result<T, Error>> + Unpin,
+	F: FnMut() -> io::Result<S> + Unpin,
+{
+	type Item = Result<ReconnectEvent<T>, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			if let Some(stream) = this.stream.as_mut() {
+				match stream.poll_next_unpin(cx) {
+					Poll::Ready(Some(Err(ref e))) if is_disconnect(e) => {
+						// start again right away; the daemon might
+						// already be back
+						this.stream = None;
+						this.retry = None;
+						if let Some(observer) = this.on_disconnect.as_mut() {
+							observer();
+						}
+					},
+					Poll::Ready(Some(item)) => return Poll::Ready(Some(item.map(ReconnectEvent::Result))),
+					Poll::Ready(None) => return Poll::Ready(None),
+					Poll::Pending => return Poll::Pending,
+				}
+			}
+
+			if let Some(retry) = this.retry.as_mut() {
+				match retry.poll_unpin(cx) {
+					Poll::Pending => return Poll::Pending,
+					Poll::Ready(()) => this.retry = None,
+				}
+			}
+
+			match (this.factory)() {
+				Ok(stream) => {
+					this.stream = Some(stream);
+					return Poll::Ready(Some(Ok(ReconnectEvent::Reconnected)));
+				},
+				Err(e) => {
+					this.retry = Some(Delay::new(this.retry_interval));
+					let e : Error = e.into();
+					if !is_disconnect(&e) {
+						return Poll::Ready(Some(Err(e)));
+					}
+				},
+			}
+		}
+	}
+}
+
+/// Restart an operation when the daemon restarts
+///
+/// `factory` starts the operation (e.g. by calling
+/// [`browse`](fn.browse.html) with the original parameters).  When the
+/// stream reports the daemon isn't running anymore the operation is
+/// started again (retrying periodically while the daemon is down), and
+/// a [`Reconnected`](enum.ReconnectEvent.html#variant.Reconnected) event
+/// is yielded.
+///
+/// Other errors starting the operation are yielded (and the start is
+/// retried later); other errors from the operation are passed through.
+pub fn reconnect<T, S, F>(mut factory: F) -> io::Result<Reconnect<S, F>>
+where
+	S: Stream<Item = Result<T, Error>> + Unpin,
+	F: FnMut() -> io::Result<S> + Unpin,
+{
+	let stream = factory()?;
+	Ok(Reconnect {
+		factory,
+		stream: Some(stream),
+		retry: None,
+		retry_interval: Duration::from_secs(1),
+		on_disconnect: None,
+	})
+}
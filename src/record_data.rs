@@ -0,0 +1,471 @@
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr,Ipv6Addr};
+
+use crate::dns_types::RecordType;
+use crate::txt_record::TxtRecord;
+
+fn invalid_data(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn invalid_input(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Decoded record data
+///
+/// Record types without a dedicated variant are kept as `Unknown`.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub enum RecordData {
+	/// IPv4 address (`A`)
+	A(Ipv4Addr),
+	/// IPv6 address (`AAAA`)
+	AAAA(Ipv6Addr),
+	/// Domain name pointer (`PTR`), e.g. a service instance name when
+	/// browsing
+	PTR(String),
+	/// Service location (`SRV`)
+	SRV {
+		/// priority of the target host (lower is preferred)
+		priority: u16,
+		/// relative weight for entries with the same priority
+		weight: u16,
+		/// port of the service
+		port: u16,
+		/// host providing the service
+		target: String,
+	},
+	/// Text strings (`TXT`)
+	TXT(TxtRecord),
+	/// Canonical name (`CNAME`)
+	CNAME(String),
+	/// Next secure record (`NSEC`), used by mDNS to assert
+	/// non-existence of other record types
+	NSEC {
+		/// next domain name
+		next_domain: String,
+		/// record types existing for the name
+		types: Vec<RecordType>,
+	},
+	/// Any other record type
+	Unknown {
+		/// Type of the record
+		rr_type: RecordType,
+		/// Raw record data (wire format)
+		rdata: Vec<u8>,
+	},
+}
+
+impl RecordData {
+	/// Decode record data (wire format) of the given type
+	///
+	/// Domain names are returned in the escaped presentation format
+	/// (`\.` for dots and `\\` for backslashes within labels, `\DDD` for
+	/// non-printable bytes) with a trailing dot.
+	pub fn parse(rr_type: RecordType, rdata: &[u8]) -> io::Result<Self> {
+		Ok(match rr_type {
+			RecordType::A => {
+				if rdata.len() != 4 {
+					return Err(invalid_data("invalid A record length"));
+				}
+				let mut octets = [0u8; 4];
+				octets.copy_from_slice(rdata);
+				RecordData::A(Ipv4Addr::from(octets))
+			},
+			RecordType::AAAA => {
+				if rdata.len() != 16 {
+					return Err(invalid_data("invalid AAAA record length"));
+				}
+				let mut octets = [0u8; 16];
+				octets.copy_from_slice(rdata);
+				RecordData::AAAA(Ipv6Addr::from(octets))
+			},
+			RecordType::PTR => RecordData::PTR(parse_full_name(rdata)?),
+			RecordType::CNAME => RecordData::CNAME(parse_full_name(rdata)?),
+			RecordType::SRV => {
+				if rdata.len() < 7 {
+					return Err(invalid_data("SRV record too short"));
+				}
+				RecordData::SRV {
+					priority: u16::from_be_bytes([rdata[0], rdata[1]]),
+					weight: u16::from_be_bytes([rdata[2], rdata[3]]),
+					port: u16::from_be_bytes([rdata[4], rdata[5]]),
+					target: parse_full_name(&rdata[6..])?,
+				}
+			},
+			RecordType::TXT => RecordData::TXT(TxtRecord::parse(rdata)?),
+			RecordType::NSEC => {
+				let (next_domain, rest) = parse_name(rdata)?;
+				RecordData::NSEC {
+					next_domain,
+					types: parse_type_bitmaps(rest)?,
+				}
+			},
+			_ => RecordData::Unknown {
+				rr_type,
+				rdata: rdata.into(),
+			},
+		})
+	}
+
+	/// Type of the record
+	pub fn rr_type(&self) -> RecordType {
+		match *self {
+			RecordData::A(_) => RecordType::A,
+			RecordData::AAAA(_) => RecordType::AAAA,
+			RecordData::PTR(_) => RecordType::PTR,
+			RecordData::SRV { .. } => RecordType::SRV,
+			RecordData::TXT(_) => RecordType::TXT,
+			RecordData::CNAME(_) => RecordType::CNAME,
+			RecordData::NSEC { .. } => RecordType::NSEC,
+			RecordData::Unknown { rr_type, .. } => rr_type,
+		}
+	}
+
+	/// Encode record data (wire format)
+	///
+	/// Domain names are expected in the escaped presentation format (see
+	/// [`parse`](#method.parse)); a trailing dot is optional.
+	pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+		let mut rdata = Vec::new();
+		match *self {
+			RecordData::A(addr) => rdata.extend_from_slice(&addr.octets()),
+			RecordData::AAAA(addr) => rdata.extend_from_slice(&addr.octets()),
+			RecordData::PTR(ref name) | RecordData::CNAME(ref name) => write_name(&mut rdata, name)?,
+			RecordData::SRV { priority, weight, port, ref target } => {
+				rdata.extend_from_slice(&priority.to_be_bytes());
+				rdata.extend_from_slice(&weight.to_be_bytes());
+				rdata.extend_from_slice(&port.to_be_bytes());
+				write_name(&mut rdata, target)?;
+			},
+			RecordData::TXT(ref txt) => rdata = txt.to_bytes(),
+			RecordData::NSEC { ref next_domain, ref types } => {
+				write_name(&mut rdata, next_domain)?;
+				write_type_bitmaps(&mut rdata, types);
+			},
+			RecordData::Unknown { ref rdata, .. } => return Ok(rdata.clone()),
+		}
+		if rdata.len() > 0xffff {
+			return Err(invalid_input("record data too long"));
+		}
+		Ok(rdata)
+	}
+}
+
+// presentation (zone file) format of the data
+impl fmt::Display for RecordData {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RecordData::A(addr) => write!(f, "{}", addr),
+			RecordData::AAAA(addr) => write!(f, "{}", addr),
+			RecordData::PTR(ref name) | RecordData::CNAME(ref name) => f.write_str(name),
+			RecordData::SRV { priority, weight, port, ref target } => {
+				write!(f, "{} {} {} {}", priority, weight, port, target)
+			},
+			RecordData::TXT(ref txt) => {
+				for (ndx, (key, value)) in txt.iter().enumerate() {
+					if ndx > 0 {
+						f.write_str(" ")?;
+					}
+					f.write_str("\"")?;
+					write_escaped(f, key.as_bytes())?;
+					if let Some(value) = value {
+						f.write_str("=")?;
+						write_escaped(f, value)?;
+					}
+					f.write_str("\"")?;
+				}
+				Ok(())
+			},
+			RecordData::NSEC { ref next_domain, ref types } => {
+				f.write_str(next_domain)?;
+				for rr_type in types {
+					write!(f, " {}", rr_type)?;
+				}
+				Ok(())
+			},
+			// generic notation from RFC 3597
+			RecordData::Unknown { ref rdata, .. } => {
+				write!(f, "\\# {}", rdata.len())?;
+				if !rdata.is_empty() {
+					f.write_str(" ")?;
+				}
+				for b in rdata {
+					write!(f, "{:02x}", b)?;
+				}
+				Ok(())
+			},
+		}
+	}
+}
+
+// escape bytes within a quoted character string
+fn write_escaped(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+	for &c in data {
+		match c {
+			b'"' | b'\\' => write!(f, "\\{}", c as char)?,
+			0x20..=0x7e => write!(f, "{}", c as char)?,
+			_ => write!(f, "\\{:03}", c)?,
+		}
+	}
+	Ok(())
+}
+
+// encode name in presentation format (uncompressed)
+fn write_name(rdata: &mut Vec<u8>, name: &str) -> io::Result<()> {
+	let start = rdata.len();
+	let bytes = name.as_bytes();
+	let mut label = Vec::new();
+	let mut pos = 0;
+	// the root domain ("." or "") has no labels
+	let mut pending = !(bytes.is_empty() || bytes == b".");
+	while pending {
+		let c = bytes.get(pos).cloned();
+		match c {
+			Some(b'\\') => {
+				let digits = bytes.get(pos + 1..pos + 4).filter(|d| d.iter().all(u8::is_ascii_digit));
+				if let Some(digits) = digits {
+					let value = digits.iter().fold(0u32, |v, d| v * 10 + u32::from(d - b'0'));
+					if value > 255 {
+						return Err(invalid_input("invalid escape sequence in domain name"));
+					}
+					label.push(value as u8);
+					pos += 4;
+				} else {
+					label.push(*bytes.get(pos + 1).ok_or_else(|| invalid_input("invalid escape sequence in domain name"))?);
+					pos += 2;
+				}
+			},
+			Some(b'.') | None => {
+				if label.is_empty() {
+					return Err(invalid_input("empty label in domain name"));
+				}
+				if label.len() > 63 {
+					return Err(invalid_input("label in domain name longer than 63 bytes"));
+				}
+				rdata.push(label.len() as u8);
+				rdata.append(&mut label);
+				pos += 1;
+				pending = pos < bytes.len();
+			},
+			Some(c) => {
+				label.push(c);
+				pos += 1;
+			},
+		}
+	}
+	rdata.push(0);
+	if rdata.len() - start > 255 {
+		return Err(invalid_input("domain name longer than 255 bytes"));
+	}
+	Ok(())
+}
+
+fn write_type_bitmaps(rdata: &mut Vec<u8>, types: &[RecordType]) {
+	let mut types: Vec<u16> = types.iter().map(|&t| u16::from(t)).collect();
+	types.sort_unstable();
+	types.dedup();
+	let mut ndx = 0;
+	while ndx < types.len() {
+		let window = types[ndx] >> 8;
+		let mut bitmap = [0u8; 32];
+		let mut len = 0;
+		while ndx < types.len() && types[ndx] >> 8 == window {
+			let low = (types[ndx] & 0xff) as usize;
+			bitmap[low / 8] |= 0x80 >> (low % 8);
+			len = low / 8 + 1;
+			ndx += 1;
+		}
+		rdata.push(window as u8);
+		rdata.push(len as u8);
+		rdata.extend_from_slice(&bitmap[..len]);
+	}
+}
+
+// parse name which must span the complete data
+fn parse_full_name(data: &[u8]) -> io::Result<String> {
+	let (name, rest) = parse_name(data)?;
+	if !rest.is_empty() {
+		return Err(invalid_data("trailing data after domain name"));
+	}
+	Ok(name)
+}
+
+// parse uncompressed name in wire format; returns presentation format
+// and remaining data
+fn parse_name(data: &[u8]) -> io::Result<(String, &[u8])> {
+	let mut name = String::new();
+	let mut rest = data;
+	loop {
+		let (&len, tail) = rest.split_first().ok_or_else(|| invalid_data("truncated domain name"))?;
+		let len = len as usize;
+		if len == 0 {
+			if name.is_empty() {
+				name.push('.');
+			}
+			return Ok((name, tail));
+		}
+		if len > 63 {
+			// compression pointers (and extended label types) are not
+			// used in record data returned by the daemon
+			return Err(invalid_data("unsupported label type in domain name"));
+		}
+		if tail.len() < len {
+			return Err(invalid_data("truncated domain name"));
+		}
+		let (label, tail) = tail.split_at(len);
+		rest = tail;
+		for &c in label {
+			match c {
+				b'.' | b'\\' => {
+					name.push('\\');
+					name.push(c as char);
+				},
+				0x21..=0x7e => name.push(c as char),
+				_ => name.push_str(&format!("\\{:03}", c)),
+			}
+		}
+		name.push('.');
+	}
+}
+
+fn parse_type_bitmaps(data: &[u8]) -> io::Result<Vec<RecordType>> {
+	let mut types = Vec::new();
+	let mut rest = data;
+	while !rest.is_empty() {
+		if rest.len() < 2 {
+			return Err(invalid_data("truncated NSEC type bitmap"));
+		}
+		let window = u16::from(rest[0]);
+		let len = rest[1] as usize;
+		if len == 0 || len > 32 || rest.len() < 2 + len {
+			return Err(invalid_data("invalid NSEC type bitmap"));
+		}
+		for (ndx, &byte) in rest[2..2 + len].iter().enumerate() {
+			for bit in 0..8 {
+				if 0 != byte & (0x80 >> bit) {
+					types.push(RecordType::from((window << 8) | (ndx as u16 * 8 + bit)));
+				}
+			}
+		}
+		rest = &rest[2 + len..];
+	}
+	Ok(types)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(data: RecordData, rdata: &[u8]) {
+		assert_eq!(data.to_bytes().unwrap(), rdata);
+		assert_eq!(RecordData::parse(data.rr_type(), rdata).unwrap(), data);
+	}
+
+	fn parse_error(rr_type: RecordType, rdata: &[u8]) -> io::ErrorKind {
+		RecordData::parse(rr_type, rdata).unwrap_err().kind()
+	}
+
+	fn encode_error(name: &str) -> String {
+		let e = RecordData::PTR(name.to_string()).to_bytes().unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+		e.to_string()
+	}
+
+	#[test]
+	fn addresses() {
+		round_trip(RecordData::A(Ipv4Addr::new(192, 0, 2, 1)), &[192, 0, 2, 1]);
+		round_trip(RecordData::AAAA("2001:db8::1".parse().unwrap()), &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+		assert_eq!(parse_error(RecordType::A, &[1, 2, 3]), io::ErrorKind::InvalidData);
+		assert_eq!(parse_error(RecordType::AAAA, &[0; 4]), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn names() {
+		round_trip(RecordData::PTR("web._http._tcp.local.".to_string()), b"\x03web\x05_http\x04_tcp\x05local\x00");
+		round_trip(RecordData::CNAME(".".to_string()), b"\x00");
+		// escapes
+		round_trip(RecordData::PTR("a\\.b\\\\c\\032d\\127.local.".to_string()), b"\x08a.b\\c d\x7f\x05local\x00");
+		// trailing dot and decimal escapes of printable characters are optional
+		assert_eq!(RecordData::PTR("\\065b.local".to_string()).to_bytes().unwrap(), b"\x02Ab\x05local\x00");
+		assert_eq!(RecordData::PTR(String::new()).to_bytes().unwrap(), b"\x00");
+
+		assert_eq!(parse_error(RecordType::PTR, b"\x03web"), io::ErrorKind::InvalidData);
+		assert_eq!(parse_error(RecordType::PTR, b"\x03web\x00x"), io::ErrorKind::InvalidData);
+		// compression pointer
+		assert_eq!(parse_error(RecordType::PTR, b"\x03web\xc0\x0c"), io::ErrorKind::InvalidData);
+		assert_eq!(parse_error(RecordType::PTR, b""), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn invalid_names() {
+		assert_eq!(encode_error("a..b"), "empty label in domain name");
+		assert_eq!(encode_error(".a"), "empty label in domain name");
+		assert_eq!(encode_error("a\\256"), "invalid escape sequence in domain name");
+		assert_eq!(encode_error("a\\"), "invalid escape sequence in domain name");
+		assert_eq!(encode_error(&"a".repeat(64)), "label in domain name longer than 63 bytes");
+		assert!(RecordData::PTR("a".repeat(63)).to_bytes().is_ok());
+		let long = vec!["a".repeat(63); 4].join(".");
+		assert_eq!(encode_error(&long), "domain name longer than 255 bytes");
+		assert!(RecordData::PTR(long[2..].to_string()).to_bytes().is_ok());
+	}
+
+	#[test]
+	fn srv() {
+		let data = RecordData::SRV {
+			priority: 1,
+			weight: 2,
+			port: 8080,
+			target: "host.local.".to_string(),
+		};
+		round_trip(data.clone(), b"\x00\x01\x00\x02\x1f\x90\x04host\x05local\x00");
+		assert_eq!(data.to_string(), "1 2 8080 host.local.");
+		assert_eq!(parse_error(RecordType::SRV, b"\x00\x01\x00\x02\x1f\x90"), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn txt() {
+		let mut txt = TxtRecord::new();
+		txt.insert("path", Some(b"/\"x\"\n")).unwrap();
+		txt.insert("flag", None).unwrap();
+		let data = RecordData::TXT(txt.clone());
+		round_trip(data.clone(), &txt.to_bytes());
+		assert_eq!(data.to_string(), "\"path=/\\\"x\\\"\\010\" \"flag\"");
+	}
+
+	#[test]
+	fn nsec() {
+		let data = RecordData::NSEC {
+			next_domain: "host.local.".to_string(),
+			types: vec![RecordType::A, RecordType::AAAA, RecordType::Other(1234)],
+		};
+		// window 0: A (1) and AAAA (28); window 4: 1234 = 4 * 256 + 210
+		let mut rdata = b"\x04host\x05local\x00\x00\x04\x40\x00\x00\x08\x04\x1b".to_vec();
+		rdata.extend_from_slice(&[0; 26]);
+		rdata.push(0x20);
+		round_trip(data.clone(), &rdata);
+		assert_eq!(data.to_string(), "host.local. A AAAA TYPE1234");
+
+		// types are sorted and deduplicated when encoding
+		let unsorted = RecordData::NSEC {
+			next_domain: "host.local.".to_string(),
+			types: vec![RecordType::AAAA, RecordType::A, RecordType::A],
+		};
+		assert_eq!(unsorted.to_bytes().unwrap(), &rdata[..18]);
+
+		assert_eq!(parse_error(RecordType::NSEC, b"\x00\x00"), io::ErrorKind::InvalidData);
+		assert_eq!(parse_error(RecordType::NSEC, b"\x00\x00\x00"), io::ErrorKind::InvalidData);
+		assert_eq!(parse_error(RecordType::NSEC, b"\x00\x00\x21"), io::ErrorKind::InvalidData);
+		assert_eq!(parse_error(RecordType::NSEC, b"\x00\x00\x02\x40"), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn unknown() {
+		let data = RecordData::parse(RecordType::Other(99), &[0xab, 0x01]).unwrap();
+		assert_eq!(data, RecordData::Unknown { rr_type: RecordType::Other(99), rdata: vec![0xab, 0x01] });
+		assert_eq!(data.to_bytes().unwrap(), [0xab, 0x01]);
+		assert_eq!(data.to_string(), "\\# 2 ab01");
+		assert_eq!(RecordData::Unknown { rr_type: RecordType::Other(99), rdata: Vec::new() }.to_string(), "\\# 0");
+	}
+}
@@ -0,0 +1,244 @@
+use futures::{self,FutureExt,Stream,StreamExt};
+use futures_timer::Delay;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::{Duration,Instant};
+
+use crate::error::Error;
+use crate::ffi;
+
+/// Observer notified before each retry with the number of the attempt
+/// (starting at 1), the delay before it and the error causing it
+pub type RetryObserver = Box<dyn FnMut(u32, Duration, &Error) + Send>;
+
+/// Recreate an operation after transient failures
+///
+/// See [`retrying`](fn.retrying.html).
+pub struct Retrying<S, F> {
+	factory: F,
+	stream: Option<S>,
+	retry: Option<Delay>,
+	// failed attempts since the last result
+	attempts: u32,
+	max_attempts: Option<u32>,
+	initial_delay: Duration,
+	max_delay: Duration,
+	observer: Option<RetryObserver>,
+	random: RandomState,
+	finished: bool,
+}
+
+fn should_retry(e: &Error) -> bool {
+	e.is_transient() || e.known_code() == Some(ffi::DNSServiceError::Refused)
+}
+
+impl<S, F> Retrying<S, F> {
+	/// Set delay before the first retry (default 100 milliseconds);
+	/// doubled for each further attempt
+	pub fn initial_delay(mut self, delay: Duration) -> Self {
+		self.initial_delay = delay;
+		self
+	}
+
+	/// Set upper limit for the delay between attempts (default 30
+	/// seconds)
+	pub fn max_delay(mut self, delay: Duration) -> Self {
+		self.max_delay = delay;
+		self
+	}
+
+	/// Give up after `attempts` failed attempts in a row (default: retry
+	/// forever); the last error is yielded and the stream ends
+	pub fn max_attempts(mut self, attempts: u32) -> Self {
+		self.max_attempts = Some(attempts);
+		self
+	}
+
+	/// Call `observer` before each retry
+	pub fn on_retry<O>(mut self, observer: O) -> Self
+	where O: FnMut(u32, Duration, &Error) + Send + 'static
+	{
+		self.observer = Some(Box::new(observer));
+		self
+	}
+
+	// exponential backoff with "equal jitter": half of the delay is
+	// fixed, the other half random
+	fn next_delay(&mut self) -> Duration {
+		let exp = self.attempts.saturating_sub(1).min(31);
+		let delay = self.initial_delay.saturating_mul(1 << exp).min(self.max_delay);
+		let random = (self.random.hash_one((self.attempts, Instant::now())) % 1024) as u32;
+		delay / 2 + (delay / 2) * random / 1024
+	}
+
+	// returns the error if giving up
+	fn schedule_retry(&mut self, e: Error) -> Option<Error> {
+		self.stream = None;
+		self.attempts += 1;
+		if self.max_attempts.is_some_and(|max| self.attempts > max) {
+			self.finished = true;
+			return Some(e);
+		}
+		let delay = self.next_delay();
+		if let Some(observer) = self.observer.as_mut() {
+			observer(self.attempts, delay, &e);
+		}
+		self.retry = Some(Delay::new(delay));
+		None
+	}
+}
+
+impl<T, S, F> Stream for Retrying<S, F>
+where
+	S: Stream<Item = Result<T, Error>> + Unpin,
+	F: FnMut() -> io::Result<S> + Unpin,
+{
+	type Item = Result<T, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			if this.finished {
+				return Poll::Ready(None);
+			}
+
+			if let Some(stream) = this.stream.as_mut() {
+				match stream.poll_next_unpin(cx) {
+					Poll::Ready(Some(Err(e))) => {
+						if !should_retry(&e) {
+							return Poll::Ready(Some(Err(e)));
+						}
+						if let Some(e) = this.schedule_retry(e) {
+							return Poll::Ready(Some(Err(e)));
+						}
+					},
+					Poll::Ready(Some(Ok(item))) => {
+						this.attempts = 0;
+						return Poll::Ready(Some(Ok(item)));
+					},
+					Poll::Ready(None) => {
+						this.finished = true;
+						return Poll::Ready(None);
+					},
+					Poll::Pending => return Poll::Pending,
+				}
+			}
+
+			if let Some(retry) = this.retry.as_mut() {
+				match retry.poll_unpin(cx) {
+					Poll::Pending => return Poll::Pending,
+					Poll::Ready(()) => this.retry = None,
+				}
+			}
+
+			match (this.factory)() {
+				Ok(stream) => this.stream = Some(stream),
+				Err(e) => {
+					let e : Error = e.into();
+					if !should_retry(&e) {
+						// e.g. invalid parameters; won't get better
+						this.finished = true;
+						return Poll::Ready(Some(Err(e)));
+					}
+					if let Some(e) = this.schedule_retry(e) {
+						return Poll::Ready(Some(Err(e)));
+					}
+				},
+			}
+		}
+	}
+}
+
+/// Recreate an operation after transient failures
+///
+/// `factory` starts the operation (e.g. by calling
+/// [`browse`](fn.browse.html) with the original parameters).  If
+/// starting it fails or the stream yields an error which is
+/// [transient](enum.Error.html#method.is_transient) (or
+/// [`Refused`](enum.DNSServiceError.html#variant.Refused)), the
+/// operation is started again after a delay growing exponentially with
+/// the number of failed attempts in a row (with random jitter to avoid
+/// many clients retrying at the same time).
+///
+/// Other errors are passed through; if starting the operation fails
+/// with such an error it is yielded and the stream ends.  Unlike
+/// [`reconnect`](fn.reconnect.html) restarts are not reported in the
+/// stream; use [`on_retry`](struct.Retrying.html#method.on_retry) to
+/// observe them.
+pub fn retrying<T, S, F>(factory: F) -> Retrying<S, F>
+where
+	S: Stream<Item = Result<T, Error>> + Unpin,
+	F: FnMut() -> io::Result<S> + Unpin,
+{
+	Retrying {
+		factory,
+		stream: None,
+		retry: None,
+		attempts: 0,
+		max_attempts: None,
+		initial_delay: Duration::from_millis(100),
+		max_delay: Duration::from_secs(30),
+		observer: None,
+		random: RandomState::new(),
+		finished: false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::executor::block_on;
+	use futures::stream;
+	use futures::StreamExt;
+	use std::sync::{Arc,Mutex};
+	use std::time::Duration;
+
+	use super::*;
+
+	#[test]
+	fn start_failure_not_transient() {
+		let mut calls = 0;
+		let mut retry = retrying(|| {
+			calls += 1;
+			Err::<stream::Pending<Result<(), Error>>, _>(Error::KnownError(ffi::DNSServiceError::BadParam).into())
+		});
+		let e = block_on(retry.next()).unwrap().unwrap_err();
+		assert!(matches!(e, Error::KnownError(ffi::DNSServiceError::BadParam)));
+		assert!(block_on(retry.next()).is_none());
+		drop(retry);
+		assert_eq!(calls, 1);
+	}
+
+	#[test]
+	fn start_failure_transient() {
+		let mut calls = 0;
+		let retried = Arc::new(Mutex::new(Vec::new()));
+		let observed = retried.clone();
+		let retry = retrying(|| {
+			calls += 1;
+			if calls < 3 {
+				return Err(Error::KnownError(ffi::DNSServiceError::ServiceNotRunning).into());
+			}
+			Ok(stream::iter(vec![Ok(calls)]))
+		})
+			.initial_delay(Duration::from_millis(1))
+			.on_retry(move |attempt, _, _| observed.lock().unwrap().push(attempt));
+		let results: Vec<_> = block_on(retry.collect::<Vec<_>>()).into_iter().map(Result::unwrap).collect();
+		assert_eq!(results, [3]);
+		assert_eq!(*retried.lock().unwrap(), [1, 2]);
+	}
+
+	#[test]
+	fn max_attempts() {
+		let mut retry = retrying(|| {
+			Err::<stream::Pending<Result<(), Error>>, _>(Error::KnownError(ffi::DNSServiceError::ServiceNotRunning).into())
+		})
+			.initial_delay(Duration::from_millis(1))
+			.max_attempts(2);
+		let e = block_on(retry.next()).unwrap().unwrap_err();
+		assert!(e.is_transient());
+		assert!(block_on(retry.next()).is_none());
+	}
+}
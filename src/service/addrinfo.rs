@@ -0,0 +1,281 @@
+use futures::channel::mpsc;
+use futures::{self,StreamExt};
+use std::net::{IpAddr,Ipv4Addr,Ipv6Addr};
+use std::os::raw::{c_void,c_char};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::cstr;
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::stream::ServiceStream;
+
+/// Set of [`Protocol`](enum.Protocol.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+///
+/// An empty set lets the daemon decide which address families to look
+/// up.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct Protocols(u8);
+
+/// Address families to look up
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum Protocol {
+	/// Look up IPv4 (`A`) addresses
+	///
+	/// See [`kDNSServiceProtocol_IPv4`](https://developer.apple.com/documentation/dnssd/kdnsserviceprotocol_ipv4).
+	IPv4 = 0,
+
+	/// Look up IPv6 (`AAAA`) addresses
+	///
+	/// See [`kDNSServiceProtocol_IPv6`](https://developer.apple.com/documentation/dnssd/kdnsserviceprotocol_ipv6).
+	IPv6,
+}
+
+flags_ops!{Protocols: u8: Protocol:
+	IPv4,
+	IPv6,
+}
+
+flag_mapping!{Protocols: Protocol => ffi::DNSServiceProtocol:
+	IPv4 => ffi::PROTOCOL_IPV4,
+	IPv6 => ffi::PROTOCOL_IPV6,
+}
+
+/// Set of [`GetAddrInfoFlag`](enum.GetAddrInfoFlag.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct GetAddrInfoFlags(u8);
+
+/// Flags used to look up addresses
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum GetAddrInfoFlag {
+	/// long-lived unicast query
+	///
+	/// See [`kDNSServiceFlagsLongLivedQuery`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagslonglivedquery).
+	LongLivedQuery = 0,
+
+	/// Mark traffic of the operation as background (low priority) traffic
+	///
+	/// See [`kDNSServiceFlagsBackgroundTrafficClass`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsbackgroundtrafficclass).
+	BackgroundTrafficClass,
+}
+
+flags_ops!{GetAddrInfoFlags: u8: GetAddrInfoFlag:
+	LongLivedQuery,
+	BackgroundTrafficClass,
+}
+
+flag_mapping!{GetAddrInfoFlags: GetAddrInfoFlag => ffi::DNSServiceFlags:
+	LongLivedQuery => ffi::FLAGS_LONG_LIVED_QUERY,
+	BackgroundTrafficClass => ffi::FLAGS_BACKGROUND_TRAFFIC_CLASS,
+}
+
+/// Set of [`AddrInfoFlag`](enum.AddrInfoFlag.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct AddrInfoFlags(u8);
+
+/// Flags for [`AddrInfoResult`](struct.AddrInfoResult.html)
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum AddrInfoFlag {
+	/// Indicates at least one more result is pending in the queue.  If
+	/// not set there still might be more results coming in the future.
+	///
+	/// See [`kDNSServiceFlagsMoreComing`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsmorecoming).
+	MoreComing = 0,
+
+	/// Indicates the result is new.  If not set indicates the result
+	/// was removed.
+	///
+	/// See [`kDNSServiceFlagsAdd`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsadd).
+	Add,
+}
+
+flags_ops!{AddrInfoFlags: u8: AddrInfoFlag:
+	MoreComing,
+	Add,
+}
+
+flag_mapping!{AddrInfoFlags: AddrInfoFlag => ffi::DNSServiceFlags:
+	MoreComing => ffi::FLAGS_MORE_COMING,
+	Add => ffi::FLAGS_ADD,
+}
+
+/// Pending address lookup
+pub struct GetAddrInfo(ServiceStream<AddrInfoResult>);
+
+impl GetAddrInfo {
+	/// Set number of results to queue (default 64)
+	///
+	/// While the queue is full (with the default
+	/// [`QueuePolicy`](enum.QueuePolicy.html)) no further results are
+	/// processed, which leaves them buffered in the daemon connection
+	/// until the stream is polled again.
+	pub fn queue_capacity(mut self, capacity: usize) -> Self {
+		self.0.set_capacity(capacity);
+		self
+	}
+
+	/// Set what happens when the result queue is full (default
+	/// [`QueuePolicy::Block`](enum.QueuePolicy.html#variant.Block))
+	pub fn queue_policy(mut self, policy: crate::QueuePolicy) -> Self {
+		self.0.set_policy(policy);
+		self
+	}
+
+	/// Why the operation ended (`None` while it is running)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.0.termination_reason()
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.0.stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.0.as_raw()
+	}
+}
+
+impl futures::Stream for GetAddrInfo {
+	type Item = Result<AddrInfoResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.0.poll_next_unpin(cx)
+	}
+}
+
+impl futures::stream::FusedStream for GetAddrInfo {
+	fn is_terminated(&self) -> bool {
+		self.0.is_terminated()
+	}
+}
+
+/// Address lookup result
+///
+/// See [`DNSServiceGetAddrInfoReply`](https://developer.apple.com/documentation/dnssd/dnsservicegetaddrinforeply).
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct AddrInfoResult{
+	/// Flags indicating whether the address was added or removed and
+	/// whether there are more pending results.
+	pub flags: AddrInfoFlags,
+	/// Interface the address was found on.
+	pub interface: Interface,
+	/// Name of the host
+	pub hostname: String,
+	/// Address of the host
+	pub address: IpAddr,
+	/// Time to live in seconds
+	pub ttl: u32,
+	/// When the result was received
+	pub received_at: crate::Timestamp,
+}
+
+#[cfg(windows)]
+const AF_INET6: u16 = 23;
+#[cfg(unix)]
+const AF_INET6: u16 = libc::AF_INET6 as u16;
+
+// the layouts of `sockaddr_in` and `sockaddr_in6` only differ in the
+// family header; BSD-derived systems have a length byte before a
+// single byte family.
+unsafe fn from_sockaddr(address: *const ffi::SockAddr) -> io::Result<IpAddr> {
+	let raw = address as *const u8;
+	let family = if cfg!(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")) {
+		u16::from(*raw.offset(1))
+	} else {
+		u16::from_ne_bytes([*raw, *raw.offset(1)])
+	};
+	if 2 == family /* AF_INET everywhere */ {
+		let mut octets = [0u8; 4];
+		octets.copy_from_slice(::std::slice::from_raw_parts(raw.offset(4), 4));
+		Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+	} else if AF_INET6 == family {
+		let mut octets = [0u8; 16];
+		octets.copy_from_slice(::std::slice::from_raw_parts(raw.offset(8), 16));
+		Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+	} else {
+		Err(io::Error::new(io::ErrorKind::InvalidData, "unknown address family"))
+	}
+}
+
+extern "C" fn get_addr_info_callback(
+	_sd_ref: ffi::DNSServiceRef,
+	flags: ffi::DNSServiceFlags,
+	interface_index: u32,
+	error_code: ffi::DNSServiceErrorType,
+	hostname: *const c_char,
+	address: *const ffi::SockAddr,
+	ttl: u32,
+	context: *mut c_void
+) {
+	trace_callback!("get_addr_info", flags, interface_index, error_code, ttl);
+	let sender = context as *mut mpsc::UnboundedSender<Result<AddrInfoResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<AddrInfoResult, Error>> = unsafe { &*sender };
+
+	let data = Error::from(error_code).and_then(|_| {
+		let hostname = unsafe { cstr::from_cstr(hostname) }?;
+		let address = unsafe { from_sockaddr(address) }?;
+
+		Ok(AddrInfoResult{
+			flags: AddrInfoFlags::from(flags),
+			interface: Interface::from_raw(interface_index),
+			hostname: hostname.to_string(),
+			address,
+			ttl,
+			received_at: crate::Timestamp::now(),
+		})
+	});
+
+	sender.unbounded_send(data).unwrap();
+}
+
+/// Look up the IPv4 and/or IPv6 addresses of a host
+///
+/// An empty `protocol` set lets the daemon decide which address
+/// families to look up.
+///
+/// Not supported by avahi.
+///
+/// See [`DNSServiceGetAddrInfo`](https://developer.apple.com/documentation/dnssd/1804700-dnsservicegetaddrinfo).
+pub fn get_addr_info(
+	flags: GetAddrInfoFlags,
+	interface: Interface,
+	protocol: Protocols,
+	hostname: &str
+) -> io::Result<GetAddrInfo> {
+	let hostname = cstr::CStr::from(&hostname)?;
+
+	Ok(GetAddrInfo(ServiceStream::new(move |sender|
+		EventedDNSService::new(
+			raw::DNSService::get_addr_info(
+				flags.into(),
+				interface.into_raw(),
+				protocol.into(),
+				&hostname,
+				Some(get_addr_info_callback),
+				sender as *mut c_void,
+			)?
+		)
+	)?))
+}
@@ -1,17 +1,80 @@
-use futures::sync::mpsc;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::{self,StreamExt};
+use std::collections::{HashMap,HashSet};
+use std::fmt;
 use std::os::raw::{c_void,c_char};
 use std::io;
-use tokio_core::reactor::{Handle,Remote};
+use std::pin::Pin;
+use std::task::{Context,Poll};
 
-use cstr;
-use error::Error;
-use evented::EventedDNSService;
-use ffi;
-use interface::Interface;
-use raw;
-use remote::GetRemote;
-use stream::ServiceStream;
+use crate::cstr;
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::stream::ServiceStream;
+
+/// Set of [`BrowseFlag`](enum.BrowseFlag.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct BrowseFlags(u8);
+
+/// Flags used to browse for services
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum BrowseFlag {
+	/// Include peer-to-peer interfaces (like AWDL) when using
+	/// `Interface::Any`
+	///
+	/// See [`kDNSServiceFlagsIncludeP2P`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsincludep2p).
+	IncludeP2P = 0,
+
+	/// Include AWDL (Apple Wireless Direct Link) when using
+	/// `Interface::Any`
+	///
+	/// See [`kDNSServiceFlagsIncludeAWDL`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsincludeawdl).
+	IncludeAWDL,
+
+	/// Mark traffic of the operation as background (low priority) traffic
+	///
+	/// See [`kDNSServiceFlagsBackgroundTrafficClass`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsbackgroundtrafficclass).
+	BackgroundTrafficClass,
+
+	/// Ask the daemon to stop sending multicast queries once a single
+	/// answer arrived (the browse keeps reporting changes)
+	///
+	/// Meant for battery-sensitive clients which only need any one
+	/// instance of a service.  Ignored by daemons without support.
+	///
+	/// See `kDNSServiceFlagsThresholdOne` in `dns_sd.h`.
+	ThresholdOne,
+
+	/// Like [`ThresholdOne`](#variant.ThresholdOne), but wait for a
+	/// number of answers suitable to show a list of services to choose
+	/// from (e.g. in a printer "finder" dialog)
+	///
+	/// See `kDNSServiceFlagsThresholdFinder` in `dns_sd.h`.
+	ThresholdFinder,
+}
+
+flags_ops!{BrowseFlags: u8: BrowseFlag:
+	IncludeP2P,
+	IncludeAWDL,
+	BackgroundTrafficClass,
+	ThresholdOne,
+	ThresholdFinder,
+}
+
+flag_mapping!{BrowseFlags: BrowseFlag => ffi::DNSServiceFlags:
+	IncludeP2P => ffi::FLAGS_INCLUDE_P2P,
+	IncludeAWDL => ffi::FLAGS_INCLUDE_AWDL,
+	BackgroundTrafficClass => ffi::FLAGS_BACKGROUND_TRAFFIC_CLASS,
+	ThresholdOne => ffi::FLAGS_THRESHOLD_ONE,
+	ThresholdFinder => ffi::FLAGS_THRESHOLD_FINDER,
+}
 
 /// Set of [`BrowsedFlag`](enum.BrowsedFlag.html)s
 ///
@@ -50,27 +113,268 @@ flag_mapping!{BrowsedFlags: BrowsedFlag => ffi::DNSServiceFlags:
 /// Pending browse request
 ///
 /// Results are delivered through `futures::Stream`.
-pub struct Browse(ServiceStream<BrowseResult>);
+///
+/// Use [`TimeoutTrait::timeout`](trait.TimeoutTrait.html#method.timeout)
+/// to end the stream when no result arrives in time.
+pub struct Browse(pub(crate) ServiceStream<BrowseResult>);
+
+impl Browse {
+	/// Set number of results to queue (default 64)
+	///
+	/// While the queue is full (with the default
+	/// [`QueuePolicy`](enum.QueuePolicy.html)) no further results are
+	/// processed, which leaves them buffered in the daemon connection
+	/// until the stream is polled again.
+	pub fn queue_capacity(mut self, capacity: usize) -> Self {
+		self.0.set_capacity(capacity);
+		self
+	}
+
+	/// Set what happens when the result queue is full (default
+	/// [`QueuePolicy::Block`](enum.QueuePolicy.html#variant.Block))
+	pub fn queue_policy(mut self, policy: crate::QueuePolicy) -> Self {
+		self.0.set_policy(policy);
+		self
+	}
+
+	/// Why the operation ended (`None` while it is running)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.0.termination_reason()
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.0.stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.0.as_raw()
+	}
+
+	/// Only yield services whose instance name matches `pattern`
+	///
+	/// In the pattern `*` matches any sequence of characters and `?` a
+	/// single character; matching ignores ASCII case (like DNS).  E.g.
+	/// `OfficePrinter-*` skips all other printers, which saves resolving
+	/// them.
+	pub fn filter_name(mut self, pattern: &str) -> Self {
+		let pattern: Vec<char> = pattern.chars().collect();
+		self.0.add_filter(Box::new(move |result: &BrowseResult, last: Option<&mut BrowseResult>| {
+			glob_match(&pattern, &result.service_name) || skip(result, last)
+		}));
+		self
+	}
+
+	/// Report each service only once, not once per interface it is
+	/// seen on
+	///
+	/// Services are identified by name, type and domain; the first
+	/// result carries the interface the service was seen on first, and
+	/// the removal is reported when it is gone from all interfaces.
+	pub fn dedup_interfaces(mut self) -> Self {
+		let mut seen: HashMap<(String, String, String), HashSet<Interface>> = HashMap::new();
+		self.0.add_filter(Box::new(move |result: &BrowseResult, last: Option<&mut BrowseResult>| {
+			let key = (
+				result.service_name.to_ascii_lowercase(),
+				result.reg_type.to_ascii_lowercase(),
+				result.domain.to_ascii_lowercase(),
+			);
+			let keep = if result.flags & BrowsedFlag::Add {
+				let interfaces = seen.entry(key).or_default();
+				interfaces.insert(result.interface);
+				interfaces.len() == 1
+			} else {
+				match seen.get_mut(&key) {
+					Some(interfaces) => {
+						interfaces.remove(&result.interface);
+						if interfaces.is_empty() {
+							seen.remove(&key);
+							true
+						} else {
+							false
+						}
+					},
+					// not reported as added
+					None => false,
+				}
+			};
+			keep || skip(result, last)
+		}));
+		self
+	}
+
+	/// Yield [`BrowseEvent`](enum.BrowseEvent.html)s instead of raw
+	/// results
+	pub fn events(self) -> BrowseEvents {
+		BrowseEvents(self)
+	}
+}
+
+// drop `result`; returns `false`
+fn skip(result: &BrowseResult, last: Option<&mut BrowseResult>) -> bool {
+	if !(result.flags & BrowsedFlag::MoreComing) {
+		// the last kept result ends the batch now
+		if let Some(last) = last {
+			last.flags = if last.flags & BrowsedFlag::Add {
+				BrowsedFlag::Add.into()
+			} else {
+				BrowsedFlags::none()
+			};
+		}
+	}
+	false
+}
+
+// `*` matches any sequence, `?` any character; ASCII case-insensitive
+fn glob_match(pattern: &[char], name: &str) -> bool {
+	let name: Vec<char> = name.chars().collect();
+	let (mut p, mut n) = (0, 0);
+	// position after the last `*` and the name position it resumes at
+	let mut backtrack = None;
+	while n < name.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				p += 1;
+				backtrack = Some((p, n));
+			},
+			Some(&c) if c == '?' || c.eq_ignore_ascii_case(&name[n]) => {
+				p += 1;
+				n += 1;
+			},
+			_ => match backtrack {
+				Some((star_p, star_n)) => {
+					p = star_p;
+					n = star_n + 1;
+					backtrack = Some((star_p, star_n + 1));
+				},
+				None => return false,
+			},
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
 
 impl futures::Stream for Browse {
-	type Item = BrowseResult;
-	type Error = io::Error;
+	type Item = Result<BrowseResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.0.poll_next_unpin(cx)
+	}
+}
+
+impl futures::stream::FusedStream for Browse {
+	fn is_terminated(&self) -> bool {
+		self.0.is_terminated()
+	}
+}
+
+/// Stream of [`BrowseEvent`](enum.BrowseEvent.html)s
+///
+/// See [`Browse::events`](struct.Browse.html#method.events).
+pub struct BrowseEvents(Browse);
+
+impl futures::Stream for BrowseEvents {
+	type Item = Result<BrowseEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let item = futures::ready!(self.0.poll_next_unpin(cx));
+		Poll::Ready(item.map(|item| item.map(BrowseEvent::from)))
+	}
+}
+
+/// Service instance found while browsing
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceRef {
+	/// Interface the service was found on.
+	pub interface: Interface,
+	/// Name of the service.
+	pub service_name: String,
+	/// Type of the service
+	pub reg_type: String,
+	/// Domain the service was found in
+	pub domain: String,
+}
+
+impl ServiceRef {
+	/// Resolve the service
+	pub fn resolve(&self) -> io::Result<crate::Resolve> {
+		crate::resolve(
+			crate::ResolveFlags::none(),
+			self.interface,
+			&self.service_name,
+			&self.reg_type,
+			&self.domain
+		)
+	}
+}
 
-	fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-		self.0.poll()
+/// Typed browse result
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BrowseEvent {
+	/// A service appeared
+	Added {
+		/// The service
+		service: ServiceRef,
+		/// More results are pending in the queue (see
+		/// [`BrowsedFlag::MoreComing`](enum.BrowsedFlag.html#variant.MoreComing))
+		more_coming: bool,
+	},
+	/// A service went away
+	Removed {
+		/// The service
+		service: ServiceRef,
+		/// More results are pending in the queue (see
+		/// [`BrowsedFlag::MoreComing`](enum.BrowsedFlag.html#variant.MoreComing))
+		more_coming: bool,
+	},
+}
+
+impl BrowseEvent {
+	/// The service which was added or removed
+	pub fn service(&self) -> &ServiceRef {
+		match *self {
+			BrowseEvent::Added { ref service, .. } | BrowseEvent::Removed { ref service, .. } => service,
+		}
+	}
+
+	/// Whether more results are pending in the queue
+	pub fn more_coming(&self) -> bool {
+		match *self {
+			BrowseEvent::Added { more_coming, .. } | BrowseEvent::Removed { more_coming, .. } => more_coming,
+		}
 	}
 }
 
-impl GetRemote for Browse {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
+impl From<BrowseResult> for BrowseEvent {
+	fn from(result: BrowseResult) -> Self {
+		let more_coming = result.flags & BrowsedFlag::MoreComing;
+		let service = ServiceRef {
+			interface: result.interface,
+			service_name: result.service_name,
+			reg_type: result.reg_type,
+			domain: result.domain,
+		};
+		if result.flags & BrowsedFlag::Add {
+			BrowseEvent::Added { service, more_coming }
+		} else {
+			BrowseEvent::Removed { service, more_coming }
+		}
 	}
 }
 
+
 /// Browse result
 ///
 /// See [DNSServiceBrowseReply](https://developer.apple.com/documentation/dnssd/dnsservicebrowsereply).
 #[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BrowseResult{
 	/// Flags indicating whether the service was added or removed and
 	/// whether there are more pending results.
@@ -83,6 +387,9 @@ pub struct BrowseResult{
 	pub reg_type: String,
 	/// Domain the service was found in
 	pub domain: String,
+	/// When the result was received
+	#[cfg_attr(feature = "serde", serde(skip))]
+	pub received_at: crate::Timestamp,
 }
 
 impl BrowseResult {
@@ -90,13 +397,56 @@ impl BrowseResult {
 	///
 	/// Should check before whether result has the `Add` flag, as
 	/// otherwise it probably won't find anything.
-	pub fn resolve(&self, handle: &Handle) -> io::Result<::Resolve> {
-		::resolve(
+	pub fn resolve(&self) -> io::Result<crate::Resolve> {
+		crate::resolve(
+			crate::ResolveFlags::none(),
 			self.interface,
 			&self.service_name,
 			&self.reg_type,
-			&self.domain,
-			handle
+			&self.domain
+		)
+	}
+
+	/// Ask the daemon to verify the service is still there (and purge
+	/// it from the cache otherwise)
+	///
+	/// Reconfirms the `PTR` record the result was built from, e.g.
+	/// after connecting to the service failed; if the record doesn't
+	/// get confirmed the daemon removes it and browsing reports the
+	/// removal.
+	///
+	/// See [`reconfirm_record`](fn.reconfirm_record.html).
+	pub fn reconfirm(&self, flags: crate::ReconfirmFlags) -> io::Result<()> {
+		let instance = super::FullName {
+			service: Some(&self.service_name),
+			reg_type: &self.reg_type,
+			domain: &self.domain,
+		}.construct()?;
+		let service_type = super::FullName {
+			service: None,
+			reg_type: &self.reg_type,
+			domain: &self.domain,
+		}.construct()?;
+		crate::reconfirm_record(
+			flags,
+			self.interface,
+			&service_type,
+			crate::RecordType::PTR,
+			crate::RecordClass::IN,
+			&crate::RecordData::PTR(instance).to_bytes()?
+		)
+	}
+}
+
+// e.g. `added "My Printer" _ipp._tcp. local. on eth0`
+impl fmt::Display for BrowseResult {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} {:?} {} {} on {}",
+			if self.flags & BrowsedFlag::Add { "added" } else { "removed" },
+			self.service_name,
+			self.reg_type,
+			self.domain,
+			self.interface
 		)
 	}
 }
@@ -111,10 +461,11 @@ extern "C" fn browse_callback(
 	reply_domain: *const c_char,
 	context: *mut c_void
 ) {
-	let sender = context as *mut mpsc::UnboundedSender<io::Result<BrowseResult>>;
-	let sender : &mpsc::UnboundedSender<io::Result<BrowseResult>> = unsafe { &*sender };
+	trace_callback!("browse", flags, interface_index, error_code);
+	let sender = context as *mut mpsc::UnboundedSender<Result<BrowseResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<BrowseResult, Error>> = unsafe { &*sender };
 
-	let data = Error::from(error_code).map_err(io::Error::from).and_then(|_| {
+	let data = Error::from(error_code).and_then(|_| {
 		let service_name = unsafe { cstr::from_cstr(service_name) }?;
 		let reg_type = unsafe { cstr::from_cstr(reg_type) }?;
 		let reply_domain = unsafe { cstr::from_cstr(reply_domain) }?;
@@ -125,37 +476,224 @@ extern "C" fn browse_callback(
 			service_name: service_name.to_string(),
 			reg_type: reg_type.to_string(),
 			domain: reply_domain.to_string(),
+			received_at: crate::Timestamp::now(),
 		})
 	});
 
-	sender.send(data).unwrap();
+	sender.unbounded_send(data).unwrap();
 }
 
 /// Browses for available services
 ///
-/// `reg_type` specifies the service type to search, e.g. `"_ssh._tcp"`.
+/// `reg_type` specifies the service type to search, e.g. `"_ssh._tcp"`
+/// (to validate it first use
+/// [`ServiceType::browse`](struct.ServiceType.html#method.browse)).
+///
+/// If `subtype` is given only services registered with that subtype
+/// are found; it can be given either as single label (`"_universal"`)
+/// or in the full form (`"_universal._sub._ipp._tcp"`).
 ///
 /// See [`DNSServiceBrowse`](https://developer.apple.com/documentation/dnssd/1804742-dnsservicebrowse).
 pub fn browse(
+	flags: BrowseFlags,
 	interface: Interface,
 	reg_type: &str,
-	domain: Option<&str>,
-	handle: &Handle
+	subtype: Option<&str>,
+	domain: Option<&str>
 ) -> io::Result<Browse> {
+	#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+	{
+		if let Some(stream) = crate::avahi_dbus::browse(interface, reg_type, subtype, domain)? {
+			return Ok(Browse(stream));
+		}
+	}
+	#[cfg(all(windows, feature = "windows-dnsapi"))]
+	{
+		if let Some(stream) = crate::windows_dnsapi::browse(interface, reg_type, subtype, domain)? {
+			return Ok(Browse(stream));
+		}
+	}
+
+	#[cfg(all(unix, feature = "mdns"))]
+	let fallback = move || crate::mdns::browse(interface, reg_type, subtype, domain);
+
+	let reg_type = super::reg_type_with_subtypes(reg_type, subtype.as_slice())?;
 	let reg_type = cstr::CStr::from(&reg_type)?;
 	let domain = cstr::NullableCStr::from(&domain)?;
 
-	Ok(Browse(ServiceStream::new(move |sender|
+	let stream = ServiceStream::new(move |sender|
 		EventedDNSService::new(
 			raw::DNSService::browse(
-				0, /* no flags */
+				None, /* own connection */
+				flags.into(),
 				interface.into_raw(),
 				&reg_type,
 				&domain,
 				Some(browse_callback),
 				sender as *mut c_void,
-			)?,
-			handle
+			)?
 		)
-	)?))
+	);
+	#[cfg(all(unix, feature = "mdns"))]
+	let stream = crate::mdns::or_fallback(stream, fallback);
+
+	Ok(Browse(stream?))
+}
+
+impl crate::SharedConnection {
+	/// Browse for services on the shared connection
+	///
+	/// See [`browse`](fn.browse.html).
+	pub fn browse(
+		&self,
+		flags: BrowseFlags,
+		interface: Interface,
+		reg_type: &str,
+		subtype: Option<&str>,
+		domain: Option<&str>
+	) -> io::Result<Browse> {
+		let reg_type = super::reg_type_with_subtypes(reg_type, subtype.as_slice())?;
+		let reg_type = cstr::CStr::from(&reg_type)?;
+		let domain = cstr::NullableCStr::from(&domain)?;
+
+		Ok(Browse(ServiceStream::new_shared(self.evented(), move |sender|
+			Ok(raw::DNSService::browse(
+				Some(self.evented().service()),
+				flags.into(),
+				interface.into_raw(),
+				&reg_type,
+				&domain,
+				Some(browse_callback),
+				sender as *mut c_void,
+			)?)
+		)?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::{FutureExt,StreamExt};
+
+	use super::*;
+	use crate::batched::{BatchedTrait,MoreComing};
+
+	fn result(name: &str, interface: Interface, flags: BrowsedFlags) -> Result<BrowseResult, Error> {
+		Ok(BrowseResult {
+			flags,
+			interface,
+			service_name: name.to_string(),
+			reg_type: "_http._tcp.".to_string(),
+			domain: "local.".to_string(),
+			received_at: crate::Timestamp::now(),
+		})
+	}
+
+	fn more() -> BrowsedFlags {
+		BrowsedFlag::Add | BrowsedFlag::MoreComing
+	}
+
+	fn last() -> BrowsedFlags {
+		BrowsedFlag::Add.into()
+	}
+
+	fn names(batch: Vec<BrowseResult>) -> Vec<String> {
+		batch.into_iter().map(|r| r.service_name).collect()
+	}
+
+	#[test]
+	fn glob() {
+		let matches = |pattern: &str, name: &str| glob_match(&pattern.chars().collect::<Vec<_>>(), name);
+		assert!(matches("Office*", "officePrinter"));
+		assert!(matches("*printer", "Office Printer"));
+		assert!(matches("a?c", "abc"));
+		assert!(matches("*", ""));
+		assert!(matches("a*b*c", "axxbyyc"));
+		assert!(!matches("a*b*c", "axxbyy"));
+		assert!(!matches("a?c", "ac"));
+		assert!(!matches("abc", "abcd"));
+	}
+
+	#[test]
+	fn filter_name() {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let mut browse = Browse(stream).filter_name("a*");
+		sender.unbounded_send(result("a1", Interface::Any, more())).unwrap();
+		sender.unbounded_send(result("b1", Interface::Any, more())).unwrap();
+		sender.unbounded_send(result("a2", Interface::Any, last())).unwrap();
+		let first = browse.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(first.service_name, "a1");
+		assert!(first.more_coming());
+		let second = browse.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(second.service_name, "a2");
+		assert!(!second.more_coming());
+		assert!(browse.next().now_or_never().is_none());
+	}
+
+	// the dropped result ends the burst after the previous one was
+	// already received (the daemon delivers one reply per poll)
+	#[test]
+	fn filter_name_ends_batch() {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let mut batches = Browse(stream).filter_name("a*").batched();
+		sender.unbounded_send(result("a1", Interface::Any, more())).unwrap();
+		assert!(batches.next().now_or_never().is_none());
+		sender.unbounded_send(result("b1", Interface::Any, last())).unwrap();
+		let batch = batches.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(names(batch), ["a1"]);
+
+		// next burst is unaffected
+		sender.unbounded_send(result("a2", Interface::Any, more())).unwrap();
+		sender.unbounded_send(result("a3", Interface::Any, last())).unwrap();
+		let batch = batches.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(names(batch), ["a2", "a3"]);
+	}
+
+	#[test]
+	fn dedup_interfaces() {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let mut batches = Browse(stream).dedup_interfaces().batched();
+		let (eth, wlan) = (Interface::from_raw(2), Interface::from_raw(3));
+		sender.unbounded_send(result("a", eth, more())).unwrap();
+		sender.unbounded_send(result("B", eth, more())).unwrap();
+		sender.unbounded_send(result("A", wlan, last())).unwrap();
+		let batch = batches.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(names(batch), ["a", "B"]);
+
+		// only reported as removed when gone from all interfaces
+		sender.unbounded_send(result("a", eth, BrowsedFlag::MoreComing.into())).unwrap();
+		sender.unbounded_send(result("b", eth, BrowsedFlags::none())).unwrap();
+		let batch = batches.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(names(batch), ["b"]);
+		sender.unbounded_send(result("a", wlan, BrowsedFlags::none())).unwrap();
+		let batch = batches.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(batch.len(), 1);
+		assert!(!(batch[0].flags & BrowsedFlag::Add));
+	}
+
+	// a duplicate on a second interface ends the burst after the first
+	// result was already received
+	#[test]
+	fn dedup_interfaces_ends_batch() {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let mut batches = Browse(stream).dedup_interfaces().batched();
+		let (eth, wlan) = (Interface::from_raw(2), Interface::from_raw(3));
+		sender.unbounded_send(result("a", eth, more())).unwrap();
+		assert!(batches.next().now_or_never().is_none());
+		sender.unbounded_send(result("a", wlan, last())).unwrap();
+		let batch = batches.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(names(batch), ["a"]);
+	}
+
+	#[test]
+	fn filter_name_held_result_ends_with_stream() {
+		let (sender, stream) = ServiceStream::new_mock(Box::new(()));
+		let mut browse = Browse(stream).filter_name("a*");
+		sender.unbounded_send(result("a1", Interface::Any, more())).unwrap();
+		assert!(browse.next().now_or_never().is_none());
+		drop(sender);
+		let held = browse.next().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(held.service_name, "a1");
+		assert!(browse.next().now_or_never().unwrap().is_none());
+		assert_eq!(browse.termination_reason(), Some(crate::TerminationReason::Finished));
+	}
 }
@@ -0,0 +1,117 @@
+use futures::{self,StreamExt};
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::domain_name::DomainName;
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	browse,
+	enumerate_domains,
+	Browse,
+	BrowseFlags,
+	BrowseResult,
+	Enumerate,
+	EnumeratedFlag,
+	EnumerateDomains,
+};
+
+/// Browse for services in all enumerated browse domains
+///
+/// See [`browse_all_domains`](fn.browse_all_domains.html).
+pub struct BrowseAllDomains {
+	reg_type: String,
+	enumerate: Option<EnumerateDomains>,
+	// a domain can be enumerated on multiple interfaces; browse it as
+	// long as it is announced on at least one
+	browses: HashMap<DomainName, (usize, Browse)>,
+}
+
+impl BrowseAllDomains {
+	// returns errors from enumerating and starting browse requests;
+	// `None` if enumerating is pending or finished.
+	fn poll_enumerate(&mut self, cx: &mut Context) -> Option<Error> {
+		while let Some(enumerate) = self.enumerate.as_mut() {
+			let result = match enumerate.poll_next_unpin(cx) {
+				Poll::Pending => return None,
+				Poll::Ready(None) => {
+					self.enumerate = None;
+					return None;
+				},
+				Poll::Ready(Some(Err(e))) => return Some(e),
+				Poll::Ready(Some(Ok(result))) => result,
+			};
+
+			let domain = result.domain_name();
+			if result.flags & EnumeratedFlag::Add {
+				if let Some((count, _)) = self.browses.get_mut(&domain) {
+					*count += 1;
+					continue;
+				}
+				match browse(BrowseFlags::none(), Interface::Any, &self.reg_type, None, Some(&domain)) {
+					Ok(browse) => { self.browses.insert(domain, (1, browse)); },
+					Err(e) => return Some(e.into()),
+				}
+			} else if let Some((count, _)) = self.browses.get_mut(&domain) {
+				*count -= 1;
+				if *count == 0 {
+					self.browses.remove(&domain);
+				}
+			}
+		}
+		None
+	}
+}
+
+impl futures::Stream for BrowseAllDomains {
+	type Item = Result<BrowseResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		if let Some(e) = this.poll_enumerate(cx) {
+			return Poll::Ready(Some(Err(e)));
+		}
+
+		let mut finished = Vec::new();
+		let mut item = None;
+		for (domain, (_, browse)) in this.browses.iter_mut() {
+			match browse.poll_next_unpin(cx) {
+				Poll::Pending => (),
+				Poll::Ready(None) => finished.push(domain.clone()),
+				Poll::Ready(Some(result)) => {
+					item = Some(result);
+					break;
+				},
+			}
+		}
+		for domain in finished {
+			this.browses.remove(&domain);
+		}
+
+		match item {
+			Some(item) => Poll::Ready(Some(item)),
+			None if this.enumerate.is_none() && this.browses.is_empty() => Poll::Ready(None),
+			None => Poll::Pending,
+		}
+	}
+}
+
+/// Browse for services of the given type in all browse domains
+///
+/// Enumerates the [browse domains](enum.Enumerate.html#variant.BrowseDomains)
+/// and browses for `reg_type` (e.g. `"_http._tcp"`) in each of them on
+/// all interfaces; browsing a domain starts when it is added and stops
+/// when it is removed (services found in a removed domain are not
+/// reported as removed).  The results of all domains are merged into a
+/// single stream.
+///
+/// Errors are passed through without ending the stream.
+pub fn browse_all_domains(reg_type: &str) -> io::Result<BrowseAllDomains> {
+	Ok(BrowseAllDomains {
+		reg_type: reg_type.to_string(),
+		enumerate: Some(enumerate_domains(Enumerate::BrowseDomains, Interface::Any)?),
+		browses: HashMap::new(),
+	})
+}
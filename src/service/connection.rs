@@ -1,37 +1,36 @@
-use futures::sync::mpsc;
-use futures::{self,Async,Future};
+use futures::channel::mpsc;
+use futures::FutureExt;
+use std::future::Future;
 use std::os::raw::{c_void};
 use std::io;
-use std::rc::Rc;
-use tokio_core::reactor::{Handle,Remote};
-
-use cstr;
-use error::Error;
-use evented::EventedDNSService;
-use ffi;
-use interface::Interface;
-use raw;
-use remote::GetRemote;
-use future::ServiceFutureSingle;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context,Poll};
 
-/// Connection to register records with
-pub struct Connection(Rc<EventedDNSService>);
+use crate::cstr;
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::future::ServiceFutureSingle;
 
-impl GetRemote for Connection {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
-	}
-}
+/// Connection to register records with
+///
+/// A single connection to the daemon can be used to register many
+/// standalone records; see
+/// [`register_record`](#method.register_record).
+pub struct Connection(Arc<EventedDNSService>);
 
 /// Create [`Connection`](struct.Connection.html) to register records
 /// with
 ///
+/// Same as [`Connection::new`](struct.Connection.html#method.new).
+///
 /// See [`DNSServiceCreateConnection`](https://developer.apple.com/documentation/dnssd/1804724-dnsservicecreateconnection).
-pub fn connect(handle: &Handle) -> io::Result<Connection> {
-	let con = raw::DNSService::create_connection()?;
-	Ok(Connection(Rc::new(
-		EventedDNSService::new(con, handle)?
-	)))
+pub fn connect() -> io::Result<Connection> {
+	Connection::new()
 }
 
 /// Set of [`RegisterRecordFlag`](enum.RegisterRecordFlag.html)s
@@ -42,59 +41,87 @@ pub fn connect(handle: &Handle) -> io::Result<Connection> {
 pub struct RegisterRecordFlags(u8);
 
 /// Flags used to register a record
+///
+/// Whether the record is shared or unique is passed separately as
+/// [`RecordSharing`](enum.RecordSharing.html).
 #[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
 #[repr(u8)]
 pub enum RegisterRecordFlag {
-	/// Indicates there might me multiple records with the given name, type and class.
+	/// Answer queries for the records from outside the local network
+	/// (by default the daemon only answers queriers on a directly
+	/// connected network)
 	///
-	/// See [`kDNSServiceFlagsShared`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsshared).
-	Shared = 0,
-
-	/// Indicates the records with the given name, type and class is unique.
-	///
-	/// See [`kDNSServiceFlagsUnique`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsunique).
-	Unique,
+	/// See [`kDNSServiceFlagsAllowRemoteQuery`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsallowremotequery).
+	AllowRemoteQuery = 0,
 }
 
 flags_ops!{RegisterRecordFlags: u8: RegisterRecordFlag:
+	AllowRemoteQuery,
+}
+
+flag_mapping!{RegisterRecordFlags: RegisterRecordFlag => ffi::DNSServiceFlags:
+	AllowRemoteQuery => ffi::FLAGS_ALLOW_REMOTE_QUERY,
+}
+
+/// Whether a record is [`Shared`](#variant.Shared) or
+/// [`Unique`](#variant.Unique)
+///
+/// See [`Connection::register_record`](struct.Connection.html#method.register_record).
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub enum RecordSharing {
+	/// There might be multiple records with the given name, type and
+	/// class (e.g. PTR records)
+	///
+	/// See [`kDNSServiceFlagsShared`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsshared).
 	Shared,
+	/// The record is the only one with the given name, type and class
+	/// (e.g. A records of a host); the daemon probes for conflicts
+	///
+	/// See [`kDNSServiceFlagsUnique`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsunique).
 	Unique,
 }
 
-flag_mapping!{RegisterRecordFlags: RegisterRecordFlag => ffi::DNSServiceFlags:
-	Shared => ffi::FLAGS_SHARED,
-	Unique => ffi::FLAGS_UNIQUE,
+impl From<RecordSharing> for ffi::DNSServiceFlags {
+	fn from(sharing: RecordSharing) -> Self {
+		match sharing {
+			RecordSharing::Shared => ffi::FLAGS_SHARED,
+			RecordSharing::Unique => ffi::FLAGS_UNIQUE,
+		}
+	}
 }
 
 /// Pending record registration
 ///
 /// Becomes invalid when the future completes; use the returned
 /// [`Record`](struct.Record.html) instead.
-// the future gets canceled by dropping the record; must
-// not drop the future without dropping the record.
-pub struct RegisterRecord(ServiceFutureSingle<RegisterRecordResult>, Option<raw::DNSRecord>);
-
-impl futures::Future for RegisterRecord {
-	type Item = ::Record;
-	type Error = io::Error;
-
-	fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
-		match self.0.poll() {
-			Ok(Async::Ready(RegisterRecordResult)) => Ok(Async::Ready(
-				super::new_record(self.1.take().unwrap())
-			)),
-			Ok(Async::NotReady) => Ok(Async::NotReady),
-			Err(e) => Err(e),
-		}
+// the future gets canceled by dropping the record; the record must be
+// removed before the callback context (owned by the future) is freed,
+// as the connection might be polled concurrently (see `Drop`).  Once
+// registered, the context is kept alive with the connection, as the
+// daemon can still report errors for the record.
+pub struct RegisterRecord(Option<ServiceFutureSingle<RegisterRecordResult>>, Option<raw::DNSRecord>);
+
+impl Future for RegisterRecord {
+	type Output = Result<crate::Record, Error>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let future = self.0.as_mut().expect("RegisterRecord future is done");
+		let RegisterRecordResult = futures::ready!(future.poll_unpin(cx))?;
+		self.0.take().expect("RegisterRecord future is done").keep_alive();
+		Poll::Ready(Ok(super::new_record(self.1.take().expect("RegisterRecord future is done"))))
 	}
 }
 
-impl GetRemote for RegisterRecord {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
+impl Drop for RegisterRecord {
+	fn drop(&mut self) {
+		// remove the record (holding the connection lock) before the
+		// callback context in the future is freed
+		drop(self.1.take());
+		drop(self.0.take());
 	}
 }
 
+
 #[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
 struct RegisterRecordResult;
 
@@ -105,40 +132,89 @@ extern "C" fn register_record_callback(
 	error_code: ffi::DNSServiceErrorType,
 	context: *mut c_void
 ) {
-	let sender = context as *mut mpsc::UnboundedSender<io::Result<RegisterRecordResult>>;
-	let sender : &mpsc::UnboundedSender<io::Result<RegisterRecordResult>> = unsafe { &*sender };
+	trace_callback!("register_record", error_code);
+	let sender = context as *mut mpsc::UnboundedSender<Result<RegisterRecordResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<RegisterRecordResult, Error>> = unsafe { &*sender };
 
-	let data = Error::from(error_code).map_err(io::Error::from).and_then(|_| {
-		Ok(RegisterRecordResult)
+	let data = Error::from(error_code).map(|_| {
+		RegisterRecordResult
 	});
 
-	sender.send(data).unwrap();
+	// the receiver is gone if the registration was kept before it
+	// completed
+	let _ = sender.unbounded_send(data);
 }
 
 impl Connection {
+	/// Create connection to register records with
+	///
+	/// See [`DNSServiceCreateConnection`](https://developer.apple.com/documentation/dnssd/1804724-dnsservicecreateconnection).
+	pub fn new() -> io::Result<Connection> {
+		let con = raw::DNSService::create_connection()?;
+		Ok(Connection(Arc::new(
+			EventedDNSService::new(con)?
+		)))
+	}
+
+	/// Take ownership of a `DNSServiceRef` created by
+	/// `DNSServiceCreateConnection` elsewhere (e.g. by other C code)
+	///
+	/// # Safety
+	///
+	/// `sd_ref` must be a valid connection reference which isn't used
+	/// or deallocated by anyone else afterwards; it is deallocated when
+	/// the last handle (including records) is dropped.
+	pub unsafe fn from_raw(sd_ref: ffi::DNSServiceRef) -> io::Result<Connection> {
+		let con = raw::DNSService::from_raw(sd_ref);
+		Ok(Connection(Arc::new(
+			EventedDNSService::new(con)?
+		)))
+	}
+
+	/// Underlying `DNSServiceRef`
+	///
+	/// Can be used to call functions of the C API which aren't wrapped
+	/// (the same applies to `as_raw` of the other handles).
+	///
+	/// The reference stays owned by the handle: it must not be
+	/// deallocated and must not be used after the handle was dropped.
+	/// The C library isn't thread-safe, so calls with the reference
+	/// must not happen concurrently with polling the handle (or other
+	/// handles sharing the connection).  Callbacks registered through
+	/// the reference are run while the handle is polled.
+	pub fn as_raw(&self) -> ffi::DNSServiceRef {
+		self.0.service().as_raw()
+	}
+
 	/// Register record on interface with given name, type, class, rdata
-	/// and ttl
+	/// (wire format) and ttl
 	///
-	// See [`DNSServiceRegisterRecord`](https://developer.apple.com/documentation/dnssd/1804727-dnsserviceregisterrecord).
-	pub fn register_raw_record(
+	/// The connection needs to be polled (through the returned future
+	/// or other registrations on the same connection) for the
+	/// registration to complete.
+	///
+	/// See [`DNSServiceRegisterRecord`](https://developer.apple.com/documentation/dnssd/1804727-dnsserviceregisterrecord).
+	pub fn register_record(
 		&self,
+		sharing: RecordSharing,
 		flags: RegisterRecordFlags,
 		interface: Interface,
 		fullname: &str,
-		rr_type: u16,
-		rr_class: u16,
+		rr_type: RecordType,
+		rr_class: RecordClass,
 		rdata: &[u8],
 		ttl: u32
 	) -> io::Result<RegisterRecord> {
+		let flags = ffi::DNSServiceFlags::from(sharing) | ffi::DNSServiceFlags::from(flags);
 		let fullname = cstr::CStr::from(&fullname)?;
 
 		let (serv, record) = ServiceFutureSingle::new(self.0.clone(), move |sender|
 			Ok(self.0.service().register_record(
-				flags.into(),
+				flags,
 				interface.into_raw(),
 				&fullname,
-				rr_type,
-				rr_class,
+				rr_type.into(),
+				rr_class.into(),
 				rdata,
 				ttl,
 				Some(register_record_callback),
@@ -146,7 +222,7 @@ impl Connection {
 			)?)
 		)?;
 
-		Ok(RegisterRecord(serv, Some(record)))
+		Ok(RegisterRecord(Some(serv), Some(record)))
 	}
 }
 
@@ -161,8 +237,8 @@ impl RegisterRecord {
 	///
 	/// Panics after the future completed.  Use the returned
 	/// [`Record`](struct.Record.html) instead.
-	pub fn rr_type(&self) -> u16 {
-		self.record().rr_type()
+	pub fn rr_type(&self) -> RecordType {
+		RecordType::from(self.record().rr_type())
 	}
 
 	/// Update record
@@ -194,22 +270,16 @@ impl RegisterRecord {
 	/// [`Connection`](struct.Connection.html) or some other record from
 	/// the same `Connection`) alive.
 	///
-	/// Due to some implementation detail the underlying connection
-	/// might live until this future successfully completes.
-	///
 	/// # Panics
 	///
 	/// Panics after the future completed.  Use the returned
 	/// [`Record`](struct.Record.html) instead.
-	// - implementation detail: this drives the future to continuation,
-	//   it is not possible to drop the (shared) underlying service
-	//   before. instead we could store the callback context with the
-	//   underyling service, and drop it either when dropping the
-	//   service or the callback was called.
-	pub fn keep(self, handle: &Handle) {
-		let (fut, rec) = (self.0, self.1.expect("RegisterRecord future is done"));
-		// drive future to continuation, ignore errors
-		handle.spawn(fut.then(|_| Ok(())));
+	// - implementation detail: the callback context is stored with the
+	//   underlying service and released when the service is dropped.
+	pub fn keep(mut self) {
+		let rec = self.1.take().expect("RegisterRecord future is done");
+		let fut = self.0.take().expect("RegisterRecord future is done");
+		fut.keep_alive();
 		rec.keep();
 	}
 }
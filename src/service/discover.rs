@@ -0,0 +1,156 @@
+use futures::{self,StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	browse,
+	BrowseFlags,
+	AddrInfoFlag,
+	BrowseResult,
+	GetAddrInfo,
+	ResolveEach,
+	ResolveResult,
+};
+use crate::txt_record::TxtRecord;
+
+/// Service found by [`discover`](fn.discover.html)
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct DiscoveredService {
+	/// Interface the service was found on.
+	pub interface: Interface,
+	/// Name of the service instance
+	pub service_name: String,
+	/// Type of the service
+	pub reg_type: String,
+	/// Domain the service was found in
+	pub domain: String,
+	/// Name of the host the service is running on
+	pub host_target: String,
+	/// Addresses (including port) the service is reachable on
+	pub addresses: Vec<SocketAddr>,
+	/// TXT record of the service
+	pub txt: TxtRecord,
+}
+
+struct PendingLookup {
+	service: DiscoveredService,
+	port: u16,
+	lookup: GetAddrInfo,
+}
+
+/// Pending service discovery
+///
+/// See [`discover`](fn.discover.html).
+pub struct Discover {
+	resolve: Option<ResolveEach>,
+	pending: Vec<PendingLookup>,
+}
+
+impl Discover {
+	fn start_lookup(&mut self, browsed: BrowseResult, resolved: ResolveResult) -> Result<(), Error> {
+		let lookup = resolved.get_addr_info()?;
+		let txt = TxtRecord::parse(&resolved.txt)?;
+		self.pending.push(PendingLookup {
+			service: DiscoveredService {
+				interface: resolved.interface,
+				service_name: browsed.service_name,
+				reg_type: browsed.reg_type,
+				domain: browsed.domain,
+				host_target: resolved.host_target,
+				addresses: Vec::new(),
+				txt,
+			},
+			port: resolved.port,
+			lookup,
+		});
+		Ok(())
+	}
+
+	// returns errors from browsing/resolving or starting lookups; `None`
+	// if resolving is pending or finished.
+	fn poll_resolve(&mut self, cx: &mut Context) -> Option<Error> {
+		while let Some(resolve) = self.resolve.as_mut() {
+			match resolve.poll_next_unpin(cx) {
+				Poll::Pending => return None,
+				Poll::Ready(None) => self.resolve = None,
+				Poll::Ready(Some(Err(e))) => return Some(e),
+				Poll::Ready(Some(Ok((browsed, resolved)))) => {
+					if let Err(e) = self.start_lookup(browsed, resolved) {
+						return Some(e);
+					}
+				},
+			}
+		}
+		None
+	}
+}
+
+impl futures::Stream for Discover {
+	type Item = Result<DiscoveredService, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		if let Some(e) = this.poll_resolve(cx) {
+			return Poll::Ready(Some(Err(e)));
+		}
+
+		let mut ndx = 0;
+		while ndx < this.pending.len() {
+			let pending = &mut this.pending[ndx];
+			match pending.lookup.poll_next_unpin(cx) {
+				Poll::Pending => ndx += 1,
+				Poll::Ready(None) => {
+					let pending = this.pending.swap_remove(ndx);
+					if !pending.service.addresses.is_empty() {
+						return Poll::Ready(Some(Ok(pending.service)));
+					}
+				},
+				Poll::Ready(Some(Err(e))) => {
+					this.pending.swap_remove(ndx);
+					return Poll::Ready(Some(Err(e)));
+				},
+				Poll::Ready(Some(Ok(addr_info))) => {
+					if addr_info.flags & AddrInfoFlag::Add {
+						let addr = SocketAddr::new(addr_info.address, pending.port);
+						if !pending.service.addresses.contains(&addr) {
+							pending.service.addresses.push(addr);
+						}
+					}
+					if !(addr_info.flags & AddrInfoFlag::MoreComing) && !pending.service.addresses.is_empty() {
+						// first batch of addresses complete
+						let pending = this.pending.swap_remove(ndx);
+						return Poll::Ready(Some(Ok(pending.service)));
+					}
+				},
+			}
+		}
+
+		if this.resolve.is_none() && this.pending.is_empty() {
+			Poll::Ready(None)
+		} else {
+			Poll::Pending
+		}
+	}
+}
+
+/// Discover services of the given type together with their addresses
+///
+/// Browses for `reg_type` (e.g. `"_http._tcp"`) in the default domains
+/// on all interfaces, resolves each found service and looks up the
+/// addresses of its host.  Each service is yielded once with the first
+/// batch of addresses.
+///
+/// Errors for single services are passed through without ending the
+/// stream.
+///
+/// Looking up addresses is not supported by avahi.
+pub fn discover(reg_type: &str) -> io::Result<Discover> {
+	Ok(Discover {
+		resolve: Some(browse(BrowseFlags::none(), Interface::Any, reg_type, None, None)?.resolve_each()),
+		pending: Vec::new(),
+	})
+}
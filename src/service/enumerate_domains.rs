@@ -1,17 +1,18 @@
-use futures::sync::mpsc;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::{self,StreamExt};
 use std::os::raw::{c_void,c_char};
 use std::io;
-use tokio_core::reactor::{Handle,Remote};
-
-use cstr;
-use error::Error;
-use evented::EventedDNSService;
-use ffi;
-use interface::Interface;
-use raw;
-use remote::GetRemote;
-use stream::ServiceStream;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::cstr;
+use crate::domain_name::DomainName;
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::stream::ServiceStream;
 
 /// Whether to enumerate domains which are browsed or domains for which
 /// registrations can be made.
@@ -23,9 +24,9 @@ pub enum Enumerate {
 	RegistrationDomains,
 }
 
-impl Into<ffi::DNSServiceFlags> for Enumerate {
-	fn into(self) -> ffi::DNSServiceFlags {
-		match self {
+impl From<Enumerate> for ffi::DNSServiceFlags {
+	fn from(value: Enumerate) -> Self {
+		match value {
 			Enumerate::BrowseDomains => ffi::FLAGS_BROWSE_DOMAINS,
 			Enumerate::RegistrationDomains => ffi::FLAGS_REGISTRATION_DOMAINS,
 		}
@@ -74,34 +75,122 @@ flag_mapping!{EnumeratedFlags: EnumeratedFlag => ffi::DNSServiceFlags:
 }
 
 /// Pending domain enumeration
-pub struct EnumerateDomains(ServiceStream<EnumerateResult>);
+pub struct EnumerateDomains(ServiceStream<EnumerateResult>, Enumerate);
+
+impl EnumerateDomains {
+	/// Set number of results to queue (default 64)
+	///
+	/// While the queue is full (with the default
+	/// [`QueuePolicy`](enum.QueuePolicy.html)) no further results are
+	/// processed, which leaves them buffered in the daemon connection
+	/// until the stream is polled again.
+	pub fn queue_capacity(mut self, capacity: usize) -> Self {
+		self.0.set_capacity(capacity);
+		self
+	}
+
+	/// Set what happens when the result queue is full (default
+	/// [`QueuePolicy::Block`](enum.QueuePolicy.html#variant.Block))
+	pub fn queue_policy(mut self, policy: crate::QueuePolicy) -> Self {
+		self.0.set_policy(policy);
+		self
+	}
+
+	/// Why the operation ended (`None` while it is running)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.0.termination_reason()
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.0.stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.0.as_raw()
+	}
+
+	/// Yield typed [`DomainEvent`](struct.DomainEvent.html)s instead
+	/// of raw results
+	pub fn events(self) -> DomainEvents {
+		DomainEvents(self)
+	}
+}
 
 impl futures::Stream for EnumerateDomains {
-	type Item = EnumerateResult;
-	type Error = io::Error;
+	type Item = Result<EnumerateResult, Error>;
 
-	fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-		self.0.poll()
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.0.poll_next_unpin(cx)
 	}
 }
 
-impl GetRemote for EnumerateDomains {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
+impl futures::stream::FusedStream for EnumerateDomains {
+	fn is_terminated(&self) -> bool {
+		self.0.is_terminated()
 	}
 }
 
+/// Typed domain enumeration result
+///
+/// The default registration domain is the one with `kind:
+/// Enumerate::RegistrationDomains, default: true, added: true`.
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct DomainEvent {
+	/// Name of the domain
+	pub domain: DomainName,
+	/// Whether the domain is for browsing or registrations
+	pub kind: Enumerate,
+	/// Whether this is the default domain
+	pub default: bool,
+	/// Whether the domain was added (or removed)
+	pub added: bool,
+	/// Interface the domain was found on
+	pub interface: Interface,
+}
+
+/// Stream of [`DomainEvent`](struct.DomainEvent.html)s
+///
+/// See [`EnumerateDomains::events`](struct.EnumerateDomains.html#method.events).
+pub struct DomainEvents(EnumerateDomains);
+
+impl futures::Stream for DomainEvents {
+	type Item = Result<DomainEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let kind = self.0 .1;
+		let item = futures::ready!(self.0.poll_next_unpin(cx));
+		Poll::Ready(item.map(|item| item.map(|result| DomainEvent {
+			domain: result.domain_name(),
+			kind,
+			default: result.flags & EnumeratedFlag::Default,
+			added: result.flags & EnumeratedFlag::Add,
+			interface: result.interface,
+		})))
+	}
+}
+
+
 /// Domain enumeration result
 ///
 /// See [DNSServiceDomainEnumReply](https://developer.apple.com/documentation/dnssd/dnsservicedomainenumreply).
 #[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
 pub struct EnumerateResult{
-	///
+	/// Flags indicating whether the domain was added or removed,
+	/// whether it is the default domain and whether there are more
+	/// pending results.
 	pub flags: EnumeratedFlags,
-	///
+	/// Interface the domain was found on.
 	pub interface: Interface,
-	///
+	/// Name of the domain
 	pub domain: String,
+	/// When the result was received
+	pub received_at: crate::Timestamp,
 }
 
 extern "C" fn enumerate_callback(
@@ -112,26 +201,28 @@ extern "C" fn enumerate_callback(
 	reply_domain: *const c_char,
 	context: *mut c_void
 ) {
-	let sender = context as *mut mpsc::UnboundedSender<io::Result<EnumerateResult>>;
-	let sender : &mpsc::UnboundedSender<io::Result<EnumerateResult>> = unsafe { &*sender };
+	trace_callback!("enumerate_domains", flags, interface_index, error_code);
+	let sender = context as *mut mpsc::UnboundedSender<Result<EnumerateResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<EnumerateResult, Error>> = unsafe { &*sender };
 
-	let data = Error::from(error_code).map_err(io::Error::from).and_then(|_| {
+	let data = Error::from(error_code).and_then(|_| {
 		let reply_domain = unsafe { cstr::from_cstr(reply_domain) }?;
 
 		Ok(EnumerateResult{
 			flags: EnumeratedFlags::from(flags),
 			interface: Interface::from_raw(interface_index),
 			domain: reply_domain.to_string(),
+			received_at: crate::Timestamp::now(),
 		})
 	});
 
-	sender.send(data).unwrap();
+	sender.unbounded_send(data).unwrap();
 }
 
 /// Enumerates domains that are recommended for registration or browsing
 ///
 /// See [`DNSServiceEnumerateDomains`](https://developer.apple.com/documentation/dnssd/1804754-dnsserviceenumeratedomains).
-pub fn enumerate_domains(enumerate: Enumerate, interface: Interface, handle: &Handle) -> io::Result<EnumerateDomains> {
+pub fn enumerate_domains(enumerate: Enumerate, interface: Interface) -> io::Result<EnumerateDomains> {
 	Ok(EnumerateDomains(ServiceStream::new(move |sender|
 		EventedDNSService::new(
 			raw::DNSService::enumerate_domains(
@@ -139,8 +230,7 @@ pub fn enumerate_domains(enumerate: Enumerate, interface: Interface, handle: &Ha
 				interface.into_raw(),
 				Some(enumerate_callback),
 				sender as *mut c_void,
-			)?,
-			handle
+			)?
 		)
-	)?))
+	)?, enumerate))
 }
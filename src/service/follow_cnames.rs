@@ -0,0 +1,183 @@
+use futures::{self,StreamExt};
+use std::collections::HashSet;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::domain_name::DomainName;
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::record_data::RecordData;
+use crate::service::{
+	query_record,
+	QueriedRecordFlag,
+	QueryRecord,
+	QueryRecordFlag,
+	QueryRecordFlags,
+	QueryRecordResult,
+};
+
+// CNAMEs to follow at most (per chain)
+const MAX_DEPTH: usize = 8;
+
+/// Final record found by [`query_record_follow_cnames`](fn.query_record_follow_cnames.html)
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct ChasedRecord {
+	/// The record
+	pub result: QueryRecordResult,
+	/// Names of the CNAME records which were followed to find the
+	/// record, starting with the queried name (empty if the record was
+	/// found directly)
+	pub chain: Vec<String>,
+}
+
+impl ChasedRecord {
+	/// Number of CNAME records followed
+	pub fn depth(&self) -> usize {
+		self.chain.len()
+	}
+}
+
+struct Chase {
+	// name queried in this step
+	name: DomainName,
+	chain: Vec<String>,
+	query: QueryRecord,
+}
+
+// identifies a record to report it only once (the daemon might follow
+// the CNAMEs itself as well)
+type RecordKey = (Interface, DomainName, RecordType, Vec<u8>);
+
+fn record_key(result: &QueryRecordResult) -> RecordKey {
+	(result.interface, DomainName::from_daemon(&result.fullname), result.rr_type, result.rdata.clone())
+}
+
+/// Query following CNAME records
+///
+/// See [`query_record_follow_cnames`](fn.query_record_follow_cnames.html).
+pub struct FollowCnames {
+	flags: QueryRecordFlags,
+	interface: Interface,
+	rr_type: RecordType,
+	rr_class: RecordClass,
+	chases: Vec<Chase>,
+	reported: HashSet<RecordKey>,
+}
+
+impl FollowCnames {
+	// returns an item to yield for a result of the query `ndx`, if any
+	fn handle(&mut self, ndx: usize, result: QueryRecordResult) -> Option<Result<ChasedRecord, Error>> {
+		let added = result.flags & QueriedRecordFlag::Add;
+		if result.rr_type == RecordType::CNAME && self.rr_type != RecordType::CNAME {
+			let target = match RecordData::parse(RecordType::CNAME, &result.rdata) {
+				Ok(RecordData::CNAME(target)) => DomainName::from_daemon(&target),
+				Ok(_) => return None,
+				Err(e) => return Some(Err(e.into())),
+			};
+			let mut chain = self.chases[ndx].chain.clone();
+			chain.push(result.fullname);
+			if !added {
+				self.chases.retain(|c| !(c.name == target && c.chain == chain));
+				return None;
+			}
+			if chain.len() > MAX_DEPTH {
+				return Some(Err(io::Error::new(io::ErrorKind::InvalidData, "too many CNAME records").into()));
+			}
+			if chain.iter().any(|name| DomainName::from_daemon(name) == target) {
+				return Some(Err(io::Error::new(io::ErrorKind::InvalidData, "CNAME loop").into()));
+			}
+			if self.chases.iter().any(|c| c.name == target && c.chain == chain) {
+				return None;
+			}
+			return match query_record(self.flags, self.interface, &target, self.rr_type, self.rr_class) {
+				Ok(query) => {
+					self.chases.push(Chase { name: target, chain, query });
+					None
+				},
+				Err(e) => Some(Err(e.into())),
+			};
+		}
+
+		let key = record_key(&result);
+		let new = if added { self.reported.insert(key) } else { self.reported.remove(&key) };
+		if !new {
+			return None;
+		}
+		Some(Ok(ChasedRecord {
+			result,
+			chain: self.chases[ndx].chain.clone(),
+		}))
+	}
+}
+
+impl futures::Stream for FollowCnames {
+	type Item = Result<ChasedRecord, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		let mut ndx = 0;
+		while ndx < this.chases.len() {
+			match this.chases[ndx].query.poll_next_unpin(cx) {
+				Poll::Pending => ndx += 1,
+				Poll::Ready(None) => {
+					if ndx == 0 {
+						// the original query ended
+						this.chases.clear();
+						return Poll::Ready(None);
+					}
+					this.chases.remove(ndx);
+				},
+				Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+				Poll::Ready(Some(Ok(result))) => {
+					if let Some(item) = this.handle(ndx, result) {
+						return Poll::Ready(Some(item));
+					}
+					// the result might have removed queries; start
+					// over (polling ready queries again is fine)
+					ndx = 0;
+				},
+			}
+		}
+		if this.chases.is_empty() {
+			Poll::Ready(None)
+		} else {
+			Poll::Pending
+		}
+	}
+}
+
+/// Query for a record, following CNAME records
+///
+/// Queries with [`ReturnIntermediates`](enum.QueryRecordFlag.html#variant.ReturnIntermediates)
+/// (added to `flags`); for CNAME answers (unless `rr_type` is `CNAME`)
+/// a follow-up query for the target is started, up to a depth of 8.
+/// Only the final records are yielded, together with the chain of names
+/// leading to them; a record found through multiple paths (e.g. because
+/// the daemon followed the CNAME as well) is only reported once.
+///
+/// Removing a CNAME stops the follow-up query; records found through it
+/// are not reported as removed.
+pub fn query_record_follow_cnames(
+	flags: QueryRecordFlags,
+	interface: Interface,
+	fullname: &str,
+	rr_type: RecordType,
+	rr_class: RecordClass
+) -> io::Result<FollowCnames> {
+	let flags = flags | QueryRecordFlag::ReturnIntermediates;
+	let query = query_record(flags, interface, fullname, rr_type, rr_class)?;
+	Ok(FollowCnames {
+		flags,
+		interface,
+		rr_type,
+		rr_class,
+		chases: vec![Chase {
+			name: DomainName::from_daemon(fullname),
+			chain: Vec::new(),
+			query,
+		}],
+		reported: HashSet::new(),
+	})
+}
@@ -0,0 +1,96 @@
+use std::io;
+use std::net::IpAddr;
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::interface::Interface;
+use crate::service::{
+	Connection,
+	RecordSharing,
+	RegisterRecord,
+	RegisterRecordFlags,
+};
+
+/// Address record of a host
+///
+/// Used to publish the addresses of another device which can't do so
+/// itself (acting as mDNS proxy); services of that device can then be
+/// registered with the host name (see
+/// [`RegisterBuilder::host`](struct.RegisterBuilder.html#method.host)).
+///
+/// See [`Connection::register_host_record`](struct.Connection.html#method.register_host_record).
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct HostRecord {
+	/// Full name of the host, e.g. `"printer.local."`
+	pub hostname: String,
+	/// Address of the host
+	pub address: IpAddr,
+	/// Interface to publish the address on
+	pub interface: Interface,
+	/// Time to live of the record in seconds
+	pub ttl: u32,
+}
+
+impl HostRecord {
+	/// Create record for `address` on all interfaces with a TTL of
+	/// 120 seconds (the mDNS default for address records)
+	pub fn new(hostname: &str, address: IpAddr) -> Self {
+		HostRecord {
+			hostname: hostname.to_string(),
+			address,
+			interface: Interface::Any,
+			ttl: 120,
+		}
+	}
+
+	/// Set interface to publish the address on
+	pub fn interface(mut self, interface: Interface) -> Self {
+		self.interface = interface;
+		self
+	}
+
+	/// Set time to live of the record in seconds
+	pub fn ttl(mut self, ttl: u32) -> Self {
+		self.ttl = ttl;
+		self
+	}
+
+	/// `A` or `AAAA`, depending on the address
+	pub fn rr_type(&self) -> RecordType {
+		match self.address {
+			IpAddr::V4(_) => RecordType::A,
+			IpAddr::V6(_) => RecordType::AAAA,
+		}
+	}
+
+	/// Record data in wire format
+	pub fn rdata(&self) -> Vec<u8> {
+		match self.address {
+			IpAddr::V4(a) => a.octets().to_vec(),
+			IpAddr::V6(a) => a.octets().to_vec(),
+		}
+	}
+}
+
+impl Connection {
+	/// Publish address record of a host
+	///
+	/// The record is registered as
+	/// [`Unique`](enum.RecordSharing.html#variant.Unique): the
+	/// daemon probes for conflicts and announces it with the cache-flush
+	/// bit set, so stale addresses in other caches get replaced.
+	///
+	/// Register one record per address; all records of a host need to
+	/// be registered on the same connection.
+	pub fn register_host_record(&self, record: &HostRecord) -> io::Result<RegisterRecord> {
+		self.register_record(
+			RecordSharing::Unique,
+			RegisterRecordFlags::none(),
+			record.interface,
+			&record.hostname,
+			record.rr_type(),
+			RecordClass::IN,
+			&record.rdata(),
+			record.ttl,
+		)
+	}
+}
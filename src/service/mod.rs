@@ -1,40 +1,98 @@
+pub use self::addrinfo::*;
 pub use self::browse::*;
+pub use self::browse_all_domains::*;
 pub use self::connection::*;
+pub use self::discover::*;
 pub use self::enumerate_domains::*;
+pub use self::follow_cnames::*;
+pub use self::host_record::*;
+pub use self::monitor_txt::*;
+pub use self::nat_port_mapping::*;
+pub use self::nat_renewal::*;
+pub use self::proxy::*;
+pub use self::query_expiry::*;
 pub use self::query_record::*;
-pub use self::records::Record;
+pub use self::reconfirm_record::*;
+pub use self::record_cache::*;
+pub use self::records::{Record,UpdateRecordFlags};
 pub use self::register::*;
+pub use self::register_all_domains::*;
 pub use self::resolve::*;
-use self::records::new_record;
+pub use self::resolve_all::*;
+pub use self::resolve_host_addresses::*;
+pub use self::resolve_each::*;
+pub use self::resolve_pool::*;
+pub use self::service_info::*;
+pub use self::service_type::*;
+pub use self::shared_connection::*;
+#[cfg(unix)]
+pub use self::sleep_keepalive::*;
+pub use self::tracker::*;
+pub use self::watch_record::*;
+#[cfg(unix)]
+pub use self::watch_self_addresses::*;
+#[cfg(all(unix, feature = "mdns"))]
+pub(crate) use self::watch_self_addresses::host_name;
+use self::records::{new_record,RecordTracker};
 
+mod addrinfo;
 mod browse;
+mod browse_all_domains;
 mod connection;
+mod discover;
 mod enumerate_domains;
+mod follow_cnames;
+mod host_record;
+mod monitor_txt;
+mod nat_port_mapping;
+mod nat_renewal;
+mod proxy;
+mod query_expiry;
 mod query_record;
+mod reconfirm_record;
+mod record_cache;
 mod records;
 mod register;
+mod register_all_domains;
 mod resolve;
+mod resolve_all;
+mod resolve_host_addresses;
+mod resolve_each;
+mod resolve_pool;
+mod service_info;
+mod service_type;
+mod shared_connection;
+#[cfg(unix)]
+mod sleep_keepalive;
+mod tracker;
+mod watch_record;
+#[cfg(unix)]
+mod watch_self_addresses;
 
-/// Purge record from cache
-///
-/// See [`DNSServiceReconfirmRecord`](https://developer.apple.com/documentation/dnssd/1804726-dnsservicereconfirmrecord).
-pub fn reconfirm_record(
-	interface: ::interface::Interface,
-	fullname: &str,
-	rr_type: u16,
-	rr_class: u16,
-	rdata: &[u8]
-) -> ::std::io::Result<()> {
-	let fullname = ::cstr::CStr::from(&fullname)?;
-	::raw::reconfirm_record(
-		0, /* no flags */
-		interface.into_raw(),
-		&fullname,
-		rr_type,
-		rr_class,
-		rdata);
-
-	Ok(())
+// DNS-SD subtypes are passed to the C API as comma separated suffixes
+// of the registration type.  Subtypes can be given either as plain
+// label ("_printer") or in the full "_printer._sub._http._tcp" form.
+fn reg_type_with_subtypes(reg_type: &str, subtypes: &[&str]) -> ::std::io::Result<String> {
+	use std::io;
+
+	let mut result = reg_type.to_string();
+	for subtype in subtypes {
+		let label = subtype_label(subtype);
+		if label.is_empty() || label.contains(',') {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid subtype"));
+		}
+		result.push(',');
+		result.push_str(label);
+	}
+	Ok(result)
+}
+
+// first label of a subtype given in either form
+pub(crate) fn subtype_label(subtype: &str) -> &str {
+	match subtype.find("._sub.") {
+		Some(pos) => &subtype[..pos],
+		None => subtype,
+	}
 }
 
 /// Full name consiting of (up to) three parts
@@ -53,27 +111,158 @@ impl<'a> FullName<'a> {
 	/// See [`DNSServiceConstructFullName`](https://developer.apple.com/documentation/dnssd/1804753-dnsserviceconstructfullname)
 	pub fn construct(&self) -> ::std::io::Result<String> {
 		use std::io;
+		use std::os::raw::c_char;
 
-		let service = ::cstr::NullableCStr::from(&self.service)?;
-		let reg_type = ::cstr::CStr::from(&self.reg_type)?;
-		let domain = ::cstr::CStr::from(&self.domain)?;
+		let service = crate::cstr::NullableCStr::from(&self.service)?;
+		let reg_type = crate::cstr::CStr::from(&self.reg_type)?;
+		let domain = crate::cstr::CStr::from(&self.domain)?;
 
-		const SIZE : usize = ::ffi::MAX_DOMAIN_NAME + 200;
-		let mut buf : Vec<u8> = Vec::new();
-		buf.reserve(SIZE);
-		let len = unsafe { ::ffi::DNSServiceConstructFullName(
-			buf.as_mut_ptr() as *mut i8,
+		// the C function assumes a buffer of kDNSServiceMaxDomainName
+		// bytes; add some room just in case
+		const SIZE : usize = crate::ffi::MAX_DOMAIN_NAME + 200;
+		let mut buf = vec![0u8; SIZE];
+		// returns 0 on success and -1 on error (not the length)
+		let result = trace_ffi!("DNSServiceConstructFullName"; unsafe { crate::ffi::DNSServiceConstructFullName(
+			buf.as_mut_ptr() as *mut c_char,
 			service.as_ptr(),
 			reg_type.as_ptr(),
 			domain.as_ptr()
-		)};
+		)});
 
-		if len < 0 {
+		if result != 0 {
 			return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid input"))
 		}
 
-		unsafe { buf.set_len(len as usize); }
+		let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+		buf.truncate(len);
 
 		String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
 	}
 }
+
+/// Escape a service instance name for use as single label in a
+/// full name
+///
+/// Dots and backslashes are escaped with a backslash, control
+/// characters and spaces as `\DDD` (decimal byte value), the same way
+/// [`DNSServiceConstructFullName`](https://developer.apple.com/documentation/dnssd/1804753-dnsserviceconstructfullname)
+/// does.  Unlike mDNSResponder this also escapes DEL (`\127`), like
+/// other names in presentation format; both forms unescape to the same
+/// label.
+pub fn escape_instance_name(name: &str) -> String {
+	let mut result = String::with_capacity(name.len());
+	for c in name.chars() {
+		match c {
+			'.' | '\\' => {
+				result.push('\\');
+				result.push(c);
+			},
+			'\0'..=' ' | '\x7f' => result.push_str(&format!("\\{:03}", c as u32)),
+			_ => result.push(c),
+		}
+	}
+	result
+}
+
+/// Reverse [`escape_instance_name`](fn.escape_instance_name.html)
+///
+/// Decodes the first label of `name` (e.g. the `fullname` of a
+/// [`ResolveResult`](struct.ResolveResult.html)) and returns it
+/// together with the remaining (still escaped) name.
+pub fn unescape_instance_name(name: &str) -> ::std::io::Result<(String, &str)> {
+	use std::io;
+
+	let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid escape sequence in name");
+	let bytes = name.as_bytes();
+	let mut label = Vec::new();
+	let mut pos = 0;
+	while pos < bytes.len() {
+		match bytes[pos] {
+			b'.' => {
+				pos += 1;
+				break;
+			},
+			b'\\' => {
+				let digits = bytes.get(pos + 1..pos + 4).filter(|d| d.iter().all(u8::is_ascii_digit));
+				if let Some(digits) = digits {
+					let value = digits.iter().fold(0u32, |v, d| v * 10 + u32::from(d - b'0'));
+					if value > 255 {
+						return Err(invalid());
+					}
+					label.push(value as u8);
+					pos += 4;
+				} else {
+					label.push(*bytes.get(pos + 1).ok_or_else(invalid)?);
+					pos += 2;
+				}
+			},
+			c => {
+				label.push(c);
+				pos += 1;
+			},
+		}
+	}
+	let label = String::from_utf8(label).map_err(|_| invalid())?;
+	// `pos` is always at a character boundary: after ASCII or a
+	// complete multi-byte sequence
+	Ok((label, &name[pos..]))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn escape() {
+		assert_eq!(escape_instance_name("Web Server"), "Web\\032Server");
+		assert_eq!(escape_instance_name("a.b\\c"), "a\\.b\\\\c");
+		assert_eq!(escape_instance_name("tab\t\0"), "tab\\009\\000");
+		assert_eq!(escape_instance_name("del\x7f"), "del\\127");
+		// non-ASCII is kept as is
+		assert_eq!(escape_instance_name("Müller's (2)"), "Müller's\\032(2)");
+	}
+
+	#[test]
+	fn unescape() {
+		let (label, rest) = unescape_instance_name("Web\\032Server._http._tcp.local.").unwrap();
+		assert_eq!(label, "Web Server");
+		assert_eq!(rest, "_http._tcp.local.");
+
+		let (label, rest) = unescape_instance_name("a\\.b\\\\c\\x.rest").unwrap();
+		assert_eq!(label, "a.b\\cx");
+		assert_eq!(rest, "rest");
+
+		// mDNSResponder doesn't escape DEL; both forms are accepted
+		assert_eq!(unescape_instance_name("del\\127").unwrap().0, "del\x7f");
+		assert_eq!(unescape_instance_name("del\x7f").unwrap().0, "del\x7f");
+
+		// `\DDD` escapes bytes of UTF-8 sequences
+		assert_eq!(unescape_instance_name("M\\195\\188ller").unwrap().0, "Müller");
+		assert_eq!(unescape_instance_name("Müller.local.").unwrap(), ("Müller".to_string(), "local."));
+
+		// no dot: everything is the label
+		assert_eq!(unescape_instance_name("single").unwrap(), ("single".to_string(), ""));
+		assert_eq!(unescape_instance_name("").unwrap(), (String::new(), ""));
+	}
+
+	#[test]
+	fn unescape_invalid() {
+		for name in &["trailing\\", "big\\256", "utf8\\195", "utf8\\255x"] {
+			let e = unescape_instance_name(name).unwrap_err();
+			assert_eq!(e.kind(), std::io::ErrorKind::InvalidData, "{}", name);
+		}
+		// fewer than three digits escape the next character only
+		assert_eq!(unescape_instance_name("\\12").unwrap().0, "12");
+	}
+
+	#[test]
+	fn escape_round_trip() {
+		for name in &["plain", "Web Server", "a.b\\c", "ctl\x01\x1f\x7f", "Müller (2)", ".", "\\"] {
+			let escaped = escape_instance_name(name);
+			let full = format!("{}._http._tcp.local.", escaped);
+			let (label, rest) = unescape_instance_name(&full).unwrap();
+			assert_eq!(&label, name);
+			assert_eq!(rest, "_http._tcp.local.");
+		}
+	}
+}
@@ -0,0 +1,140 @@
+use futures::{self,StreamExt};
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::dns_types::RecordType;
+use crate::error::Error;
+use crate::service::{
+	watch_record,
+	QueriedRecordFlag,
+	WatchRecord,
+};
+use crate::txt_record::TxtRecord;
+
+/// Difference between two versions of a TXT record
+///
+/// See [`monitor_txt`](fn.monitor_txt.html).
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct TxtChanged {
+	/// New entries (key and value)
+	pub added: Vec<(String, Option<Vec<u8>>)>,
+	/// Keys of removed entries
+	pub removed: Vec<String>,
+	/// Entries with a new value (key and new value)
+	pub changed: Vec<(String, Option<Vec<u8>>)>,
+	/// The complete new TXT record
+	pub txt: TxtRecord,
+}
+
+impl TxtChanged {
+	fn diff(old: &TxtRecord, new: &TxtRecord) -> Self {
+		let mut added = Vec::new();
+		let mut changed = Vec::new();
+		for (key, value) in new.iter() {
+			match old.get(key) {
+				None => added.push((key.to_string(), value.map(<[u8]>::to_vec))),
+				Some(old_value) if old_value != value => changed.push((key.to_string(), value.map(<[u8]>::to_vec))),
+				Some(_) => (),
+			}
+		}
+		let removed = old.iter()
+			.filter(|&(key, _)| !new.contains_key(key))
+			.map(|(key, _)| key.to_string())
+			.collect();
+		TxtChanged {
+			added,
+			removed,
+			changed,
+			txt: new.clone(),
+		}
+	}
+
+	/// Whether nothing changed
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+	}
+}
+
+/// TXT record monitor
+///
+/// See [`monitor_txt`](fn.monitor_txt.html).
+pub struct MonitorTxt {
+	watch: WatchRecord,
+	// known rdata (with the number of interfaces it was seen on)
+	known: HashMap<Vec<u8>, usize>,
+	// most recently added rdata
+	current: Option<Vec<u8>>,
+	// last reported TXT record
+	reported: TxtRecord,
+}
+
+impl MonitorTxt {
+	/// Last reported TXT record
+	pub fn txt(&self) -> &TxtRecord {
+		&self.reported
+	}
+}
+
+impl futures::Stream for MonitorTxt {
+	type Item = Result<TxtChanged, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			let result = match futures::ready!(this.watch.poll_next_unpin(cx)) {
+				None => return Poll::Ready(None),
+				Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+				Some(Ok(result)) => result,
+			};
+
+			if result.flags & QueriedRecordFlag::Add {
+				*this.known.entry(result.rdata.clone()).or_default() += 1;
+				this.current = Some(result.rdata);
+			} else if let Some(count) = this.known.get_mut(&result.rdata) {
+				*count -= 1;
+				if *count == 0 {
+					this.known.remove(&result.rdata);
+					if this.current.as_ref() == Some(&result.rdata) {
+						this.current = this.known.keys().next().cloned();
+					}
+				}
+			}
+
+			// a change usually arrives as removal and addition; wait
+			// for both
+			if result.flags & QueriedRecordFlag::MoreComing {
+				continue;
+			}
+
+			let txt = match this.current {
+				Some(ref rdata) => match TxtRecord::parse(rdata) {
+					Ok(txt) => txt,
+					Err(e) => return Poll::Ready(Some(Err(e.into()))),
+				},
+				None => TxtRecord::new(),
+			};
+			let diff = TxtChanged::diff(&this.reported, &txt);
+			if !diff.is_empty() {
+				this.reported = txt;
+				return Poll::Ready(Some(Ok(diff)));
+			}
+		}
+	}
+}
+
+/// Monitor the TXT record of a service
+///
+/// Watches the TXT record of `fullname` (e.g. `ResolveResult::fullname`)
+/// with a long-lived query (see [`watch_record`](fn.watch_record.html))
+/// and yields the differences between versions of the record.  The
+/// first change reports all entries as added.
+pub fn monitor_txt(fullname: &str) -> io::Result<MonitorTxt> {
+	Ok(MonitorTxt {
+		watch: watch_record(fullname, RecordType::TXT)?,
+		known: HashMap::new(),
+		current: None,
+		reported: TxtRecord::new(),
+	})
+}
@@ -0,0 +1,223 @@
+use futures::channel::mpsc;
+use futures::{self,StreamExt};
+use std::net::Ipv4Addr;
+use std::os::raw::c_void;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::stream::ServiceStream;
+
+/// Set of [`NatProtocol`](enum.NatProtocol.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+///
+/// An empty set (together with port `0`) only requests the external
+/// address of the NAT gateway.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct NatProtocols(u8);
+
+/// Transport protocols to map a port for
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum NatProtocol {
+	/// Map a UDP port
+	///
+	/// See [`kDNSServiceProtocol_UDP`](https://developer.apple.com/documentation/dnssd/kdnsserviceprotocol_udp).
+	Udp = 0,
+
+	/// Map a TCP port
+	///
+	/// See [`kDNSServiceProtocol_TCP`](https://developer.apple.com/documentation/dnssd/kdnsserviceprotocol_tcp).
+	Tcp,
+}
+
+flags_ops!{NatProtocols: u8: NatProtocol:
+	Udp,
+	Tcp,
+}
+
+flag_mapping!{NatProtocols: NatProtocol => ffi::DNSServiceProtocol:
+	Udp => ffi::PROTOCOL_UDP,
+	Tcp => ffi::PROTOCOL_TCP,
+}
+
+/// Pending NAT port mapping
+///
+/// Yields a new result whenever the external address or port of the
+/// mapping changes.  The mapping is removed when this is dropped.
+///
+/// If the gateway can't provide a mapping the stream yields an error
+/// wrapping [`DNSServiceError::NATTraversal`] or
+/// [`DNSServiceError::DoubleNAT`].
+///
+/// [`DNSServiceError::NATTraversal`]: enum.DNSServiceError.html#variant.NATTraversal
+/// [`DNSServiceError::DoubleNAT`]: enum.DNSServiceError.html#variant.DoubleNAT
+pub struct NatPortMapping {
+	stream: ServiceStream<NatMapResult>,
+	pub(crate) request: NatRequest,
+}
+
+// parameters to create the mapping again
+#[derive(Clone,Copy,Debug)]
+pub(crate) struct NatRequest {
+	pub(crate) interface: Interface,
+	pub(crate) protocol: NatProtocols,
+	pub(crate) internal_port: u16,
+	pub(crate) external_port: u16,
+	pub(crate) ttl: u32,
+}
+
+impl NatPortMapping {
+	/// Set number of results to queue (default 64)
+	///
+	/// While the queue is full (with the default
+	/// [`QueuePolicy`](enum.QueuePolicy.html)) no further results are
+	/// processed, which leaves them buffered in the daemon connection
+	/// until the stream is polled again.
+	pub fn queue_capacity(mut self, capacity: usize) -> Self {
+		self.stream.set_capacity(capacity);
+		self
+	}
+
+	/// Set what happens when the result queue is full (default
+	/// [`QueuePolicy::Block`](enum.QueuePolicy.html#variant.Block))
+	pub fn queue_policy(mut self, policy: crate::QueuePolicy) -> Self {
+		self.stream.set_policy(policy);
+		self
+	}
+
+	/// Why the operation ended (`None` while it is running)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.stream.termination_reason()
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.stream.stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.stream.as_raw()
+	}
+}
+
+impl futures::Stream for NatPortMapping {
+	type Item = Result<NatMapResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.stream.poll_next_unpin(cx)
+	}
+}
+
+impl futures::stream::FusedStream for NatPortMapping {
+	fn is_terminated(&self) -> bool {
+		self.stream.is_terminated()
+	}
+}
+
+/// NAT port mapping result
+///
+/// See [`DNSServiceNATPortMappingReply`](https://developer.apple.com/documentation/dnssd/dnsservicenatportmappingreply).
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct NatMapResult{
+	/// Interface the mapping was created on.
+	pub interface: Interface,
+	/// External address of the NAT gateway
+	pub external_address: Ipv4Addr,
+	/// Protocols the port was mapped for
+	pub protocol: NatProtocols,
+	/// Port on the local host
+	pub internal_port: u16,
+	/// Port on the NAT gateway
+	pub external_port: u16,
+	/// Time to live of the mapping in seconds
+	pub ttl: u32,
+	/// When the result was received
+	pub received_at: crate::Timestamp,
+}
+
+extern "C" fn nat_port_mapping_callback(
+	_sd_ref: ffi::DNSServiceRef,
+	_flags: ffi::DNSServiceFlags,
+	interface_index: u32,
+	error_code: ffi::DNSServiceErrorType,
+	external_address: u32,
+	protocol: ffi::DNSServiceProtocol,
+	internal_port: u16,
+	external_port: u16,
+	ttl: u32,
+	context: *mut c_void
+) {
+	trace_callback!("nat_port_mapping", interface_index, error_code, ttl);
+	let sender = context as *mut mpsc::UnboundedSender<Result<NatMapResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<NatMapResult, Error>> = unsafe { &*sender };
+
+	let data = Error::from(error_code).map(|_| {
+		NatMapResult{
+			interface: Interface::from_raw(interface_index),
+			// all values are in network byte order
+			external_address: Ipv4Addr::from(external_address.to_ne_bytes()),
+			protocol: NatProtocols::from(protocol),
+			internal_port: u16::from_be(internal_port),
+			external_port: u16::from_be(external_port),
+			ttl,
+			received_at: crate::Timestamp::now(),
+		}
+	});
+
+	sender.unbounded_send(data).unwrap();
+}
+
+/// Request a port mapping from a NAT gateway via NAT-PMP or UPnP
+///
+/// `external_port` is only a suggestion; the actually mapped port is
+/// reported in the results.  A `ttl` of `0` uses the daemon default.
+///
+/// Pass an empty `protocol` set and `0` for both ports to only monitor
+/// the external address of the gateway.
+///
+/// Not supported by avahi.
+///
+/// See [`DNSServiceNATPortMappingCreate`](https://developer.apple.com/documentation/dnssd/1804741-dnsservicenatportmappingcreate).
+pub fn nat_port_mapping(
+	interface: Interface,
+	protocol: NatProtocols,
+	internal_port: u16,
+	external_port: u16,
+	ttl: u32
+) -> io::Result<NatPortMapping> {
+	NatRequest{interface, protocol, internal_port, external_port, ttl}.start()
+}
+
+impl NatRequest {
+	pub(crate) fn start(self) -> io::Result<NatPortMapping> {
+		let NatRequest{interface, protocol, internal_port, external_port, ttl} = self;
+		let stream = ServiceStream::new(move |sender|
+			EventedDNSService::new(
+				raw::DNSService::nat_port_mapping_create(
+					0, /* no flags */
+					interface.into_raw(),
+					protocol.into(),
+					internal_port.to_be(),
+					external_port.to_be(),
+					ttl,
+					Some(nat_port_mapping_callback),
+					sender as *mut c_void,
+				)?
+			)
+		)?;
+		Ok(NatPortMapping{stream, request: self})
+	}
+}
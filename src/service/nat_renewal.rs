@@ -0,0 +1,143 @@
+use futures::{self,FutureExt,StreamExt};
+use futures_timer::Delay;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::service::{
+	NatMapResult,
+	NatPortMapping,
+	NatRequest,
+};
+
+/// Event produced by [`NatRenewal`](struct.NatRenewal.html)
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub enum NatMappingEvent {
+	/// The first mapping (or the first one after an error)
+	Mapped(NatMapResult),
+	/// The mapping was renewed with the same external address and port
+	Renewed(NatMapResult),
+	/// The external address or port changed
+	Changed(NatMapResult),
+}
+
+/// NAT port mapping which gets renewed before its lifetime ends
+///
+/// See [`NatPortMapping::auto_renew`](struct.NatPortMapping.html#method.auto_renew).
+pub struct NatRenewal {
+	request: NatRequest,
+	mapping: Option<NatPortMapping>,
+	current: Option<NatMapResult>,
+	renew: Option<Delay>,
+	retry: Option<Delay>,
+	retry_interval: Duration,
+}
+
+impl NatPortMapping {
+	/// Renew the mapping automatically and report changes
+	///
+	/// Requests the mapping again when half of the granted lifetime
+	/// passed without the daemon reporting a renewal (asking for the
+	/// external port granted before).  After errors the mapping is
+	/// requested again after a delay (see
+	/// [`NatRenewal::retry_interval`](struct.NatRenewal.html#method.retry_interval));
+	/// the errors are still yielded.
+	pub fn auto_renew(self) -> NatRenewal {
+		NatRenewal {
+			request: self.request,
+			mapping: Some(self),
+			current: None,
+			renew: None,
+			retry: None,
+			retry_interval: Duration::from_secs(5),
+		}
+	}
+}
+
+impl NatRenewal {
+	/// Set interval to wait before requesting the mapping again after
+	/// an error (default 5 seconds)
+	pub fn retry_interval(mut self, interval: Duration) -> Self {
+		self.retry_interval = interval;
+		self
+	}
+
+	/// Current mapping (if any)
+	pub fn current(&self) -> Option<&NatMapResult> {
+		self.current.as_ref()
+	}
+
+	fn start(&mut self) -> Result<(), Error> {
+		let mut request = self.request;
+		if let Some(current) = &self.current {
+			request.external_port = current.external_port;
+		}
+		// create the new mapping before dropping the old one so the
+		// gateway doesn't release the port in between
+		self.mapping = Some(request.start()?);
+		Ok(())
+	}
+
+	fn event(&mut self, result: NatMapResult) -> NatMappingEvent {
+		let event = match &self.current {
+			None => NatMappingEvent::Mapped(result.clone()),
+			Some(old) if old.external_address == result.external_address && old.external_port == result.external_port => {
+				NatMappingEvent::Renewed(result.clone())
+			},
+			Some(_) => NatMappingEvent::Changed(result.clone()),
+		};
+		self.renew = if result.ttl > 0 {
+			Some(Delay::new(Duration::from_secs(u64::from(result.ttl) / 2)))
+		} else {
+			None
+		};
+		self.current = Some(result);
+		event
+	}
+
+	fn failed(&mut self, e: Error) -> Poll<Option<Result<NatMappingEvent, Error>>> {
+		self.mapping = None;
+		self.current = None;
+		self.renew = None;
+		self.retry = Some(Delay::new(self.retry_interval));
+		Poll::Ready(Some(Err(e)))
+	}
+}
+
+impl futures::Stream for NatRenewal {
+	type Item = Result<NatMappingEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			if let Some(mapping) = this.mapping.as_mut() {
+				match mapping.poll_next_unpin(cx) {
+					Poll::Ready(Some(Ok(result))) => return Poll::Ready(Some(Ok(this.event(result)))),
+					Poll::Ready(Some(Err(e))) => return this.failed(e),
+					// mapping was stopped
+					Poll::Ready(None) => return Poll::Ready(None),
+					Poll::Pending => (),
+				}
+
+				let renew = this.renew.as_mut().is_some_and(|renew| renew.poll_unpin(cx).is_ready());
+				if !renew {
+					return Poll::Pending;
+				}
+				this.renew = None;
+				if let Err(e) = this.start() {
+					return this.failed(e);
+				}
+				continue;
+			}
+
+			if let Some(retry) = this.retry.as_mut() {
+				futures::ready!(retry.poll_unpin(cx));
+				this.retry = None;
+			}
+			if let Err(e) = this.start() {
+				return this.failed(e);
+			}
+		}
+	}
+}
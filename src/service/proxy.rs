@@ -0,0 +1,106 @@
+use futures::{self,FutureExt};
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::error::Error;
+use crate::service::{
+	Connection,
+	HostRecord,
+	Register,
+	RegisterBuilder,
+	RegisterRecord,
+	RegisterResult,
+	Registration,
+};
+
+/// Service registered on behalf of another host
+///
+/// Dropping it removes both the service and the address records of the
+/// host.
+///
+/// See [`RegisterBuilder::start_proxy`](struct.RegisterBuilder.html#method.start_proxy).
+pub struct ProxyRegistration {
+	/// Registration of the service
+	pub registration: Registration,
+	/// Address records of the host
+	pub host_records: Vec<crate::Record>,
+}
+
+/// Pending proxy registration
+///
+/// See [`RegisterBuilder::start_proxy`](struct.RegisterBuilder.html#method.start_proxy).
+pub struct RegisterProxy {
+	pending_records: Vec<RegisterRecord>,
+	host_records: Vec<crate::Record>,
+	register: Option<Register>,
+	registered: Option<(Registration, RegisterResult)>,
+}
+
+impl Future for RegisterProxy {
+	type Output = Result<(ProxyRegistration, RegisterResult), Error>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = &mut *self;
+		let mut ndx = 0;
+		while ndx < this.pending_records.len() {
+			match this.pending_records[ndx].poll_unpin(cx) {
+				Poll::Ready(Ok(record)) => {
+					this.pending_records.swap_remove(ndx);
+					this.host_records.push(record);
+				},
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+				Poll::Pending => ndx += 1,
+			}
+		}
+		if let Some(register) = this.register.as_mut() {
+			this.registered = Some(futures::ready!(register.poll_unpin(cx))?);
+			this.register = None;
+		}
+		if !this.pending_records.is_empty() {
+			return Poll::Pending;
+		}
+		let (registration, result) = this.registered.take().expect("RegisterProxy future is done");
+		Poll::Ready(Ok((
+			ProxyRegistration {
+				registration,
+				host_records: mem::take(&mut this.host_records),
+			},
+			result,
+		)))
+	}
+}
+
+impl RegisterBuilder {
+	/// Register the service for another host
+	///
+	/// Publishes the `addresses` of `host` through `connection` (see
+	/// [`Connection::register_host_record`](struct.Connection.html#method.register_host_record))
+	/// on the interface of the registration and registers the service
+	/// with `host` as target.
+	///
+	/// The connection needs to be kept alive (or polled) for the address
+	/// records to stay registered.
+	pub fn start_proxy(
+		&self,
+		connection: &Connection,
+		host: &str,
+		addresses: &[IpAddr]
+	) -> io::Result<RegisterProxy> {
+		let pending_records = addresses.iter()
+			.map(|&address| connection.register_host_record(
+				&HostRecord::new(host, address).interface(self.interface)
+			))
+			.collect::<io::Result<Vec<_>>>()?;
+		let register = self.clone().host(host).start()?;
+		Ok(RegisterProxy {
+			pending_records,
+			host_records: Vec::new(),
+			register: Some(register),
+			registered: None,
+		})
+	}
+}
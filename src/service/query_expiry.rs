@@ -0,0 +1,158 @@
+use futures::{self,FutureExt,StreamExt};
+use futures_timer::Delay;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::{Duration,Instant};
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{QueryRecord,QueryRecordResult,QueriedRecordFlag};
+
+/// Event produced by [`QueryRecordExpiry`](struct.QueryRecordExpiry.html)
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub enum RecordEvent {
+	/// Record was added (or refreshed after it expired)
+	Added(QueryRecordResult),
+	/// Record was removed by the daemon (e.g. after a goodbye packet)
+	Removed(QueryRecordResult),
+	/// The TTL of the record ran out without the daemon removing it
+	Expired(QueryRecordResult),
+}
+
+// identifies a single record
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+struct RecordKey {
+	interface: Interface,
+	fullname: String,
+	rr_type: RecordType,
+	rr_class: RecordClass,
+	rdata: Vec<u8>,
+}
+
+impl<'a> From<&'a QueryRecordResult> for RecordKey {
+	fn from(result: &'a QueryRecordResult) -> Self {
+		RecordKey {
+			interface: result.interface,
+			fullname: result.fullname.clone(),
+			rr_type: result.rr_type,
+			rr_class: result.rr_class,
+			rdata: result.rdata.clone(),
+		}
+	}
+}
+
+/// Query which tracks the TTL of the answers
+///
+/// Reports [`Expired`](enum.RecordEvent.html#variant.Expired) events
+/// for records whose TTL ran out, even if the daemon didn't remove
+/// them (e.g. because a device disappeared without sending a goodbye
+/// packet).
+///
+/// See [`QueryRecord::with_expiry`](struct.QueryRecord.html#method.with_expiry).
+pub struct QueryRecordExpiry {
+	query: QueryRecord,
+	records: HashMap<RecordKey, (Instant, QueryRecordResult)>,
+	timer: Option<(Instant, Delay)>,
+	reconfirm: bool,
+}
+
+impl QueryRecord {
+	/// Track TTL of answers and report expired records
+	///
+	/// See [`QueryRecordExpiry`](struct.QueryRecordExpiry.html).
+	pub fn with_expiry(self) -> QueryRecordExpiry {
+		QueryRecordExpiry {
+			query: self,
+			records: HashMap::new(),
+			timer: None,
+			reconfirm: false,
+		}
+	}
+}
+
+impl QueryRecordExpiry {
+	/// Ask the daemon to verify records when they expire (default off)
+	///
+	/// The daemon then queries for the record again and removes it from
+	/// its cache if nobody answers; if the record is still valid it is
+	/// reported as added again.
+	///
+	/// See [`reconfirm_record`](fn.reconfirm_record.html).
+	pub fn reconfirm_expired(mut self, reconfirm: bool) -> Self {
+		self.reconfirm = reconfirm;
+		self
+	}
+
+	/// Records which are currently valid
+	pub fn records(&self) -> impl Iterator<Item = &QueryRecordResult> {
+		self.records.values().map(|(_, result)| result)
+	}
+
+	fn apply(&mut self, result: QueryRecordResult) -> Option<RecordEvent> {
+		let key = RecordKey::from(&result);
+		if result.flags & QueriedRecordFlag::Add {
+			let expires = Instant::now() + Duration::from_secs(result.ttl.into());
+			let known = self.records.insert(key, (expires, result.clone())).is_some();
+			if known {
+				// only refreshed the TTL
+				None
+			} else {
+				Some(RecordEvent::Added(result))
+			}
+		} else {
+			self.records.remove(&key);
+			Some(RecordEvent::Removed(result))
+		}
+	}
+
+	// remove next expired record, or register timer for the next expiry
+	fn poll_expired(&mut self, cx: &mut Context) -> Option<RecordEvent> {
+		loop {
+			let (key, expires) = self.records.iter()
+				.min_by_key(|(_, (expires, _))| *expires)
+				.map(|(key, (expires, _))| (key.clone(), *expires))?;
+			if expires <= Instant::now() {
+				self.timer = None;
+				let (_, result) = self.records.remove(&key).unwrap();
+				if self.reconfirm {
+					// the daemon reports the result of the verification
+					// as regular results; nothing to do on errors
+					let _ = result.reconfirm();
+				}
+				return Some(RecordEvent::Expired(result));
+			}
+			let timer = match self.timer {
+				Some((at, ref mut timer)) if at == expires => timer,
+				_ => &mut self.timer.insert((expires, Delay::new(expires - Instant::now()))).1,
+			};
+			if timer.poll_unpin(cx).is_pending() {
+				return None;
+			}
+		}
+	}
+}
+
+impl futures::Stream for QueryRecordExpiry {
+	type Item = Result<RecordEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		loop {
+			match self.query.poll_next_unpin(cx) {
+				Poll::Ready(Some(Ok(result))) => {
+					if let Some(event) = self.apply(result) {
+						return Poll::Ready(Some(Ok(event)));
+					}
+				},
+				Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+				Poll::Ready(None) => return Poll::Ready(None),
+				Poll::Pending => break,
+			}
+		}
+		match self.poll_expired(cx) {
+			Some(event) => Poll::Ready(Some(Ok(event))),
+			None => Poll::Pending,
+		}
+	}
+}
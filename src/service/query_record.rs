@@ -1,17 +1,21 @@
-use futures::sync::mpsc;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::{self,StreamExt};
+use std::fmt;
 use std::os::raw::{c_void,c_char};
 use std::io;
-use tokio_core::reactor::{Handle,Remote};
+use std::pin::Pin;
+use std::task::{Context,Poll};
 
-use cstr;
-use error::Error;
-use evented::EventedDNSService;
-use ffi;
-use interface::Interface;
-use raw;
-use remote::GetRemote;
-use stream::ServiceStream;
+use crate::cstr;
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::record_data::RecordData;
+use crate::service::{reconfirm_record,ReconfirmFlags};
+use crate::stream::ServiceStream;
 
 /// Set of [`QueryRecordFlag`](enum.QueryRecordFlag.html)s
 ///
@@ -28,14 +32,76 @@ pub enum QueryRecordFlag {
 	///
 	/// See [`kDNSServiceFlagsLongLivedQuery`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagslonglivedquery).
 	LongLivedQuery = 0,
+
+	/// Query via multicast even if the name doesn't end in `.local.`
+	///
+	/// See [`kDNSServiceFlagsForceMulticast`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsforcemulticast).
+	ForceMulticast,
+
+	/// Also return intermediate results (like CNAME records) and
+	/// negative answers
+	///
+	/// Negative answers are reported as errors wrapping
+	/// [`DNSServiceError::NoSuchRecord`](enum.DNSServiceError.html#variant.NoSuchRecord);
+	/// the query continues after them.
+	///
+	/// See [`kDNSServiceFlagsReturnIntermediates`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsreturnintermediates).
+	ReturnIntermediates,
+
+	/// Don't return addresses which aren't reachable with the current
+	/// network configuration (e.g. `AAAA` records without IPv6
+	/// connectivity)
+	///
+	/// See [`kDNSServiceFlagsSuppressUnusable`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagssuppressunusable).
+	SuppressUnusable,
+
+	/// Let the daemon stop the query after a timeout
+	///
+	/// The stream then ends without an error;
+	/// [`QueryRecord::termination_reason`](struct.QueryRecord.html#method.termination_reason)
+	/// reports [`TimedOut`](enum.TerminationReason.html#variant.TimedOut).
+	///
+	/// See [`kDNSServiceFlagsTimeout`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagstimeout).
+	Timeout,
+
+	/// Include peer-to-peer interfaces (like AWDL) when using
+	/// `Interface::Any`
+	///
+	/// See [`kDNSServiceFlagsIncludeP2P`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsincludep2p).
+	IncludeP2P,
+
+	/// Include AWDL (Apple Wireless Direct Link) when using
+	/// `Interface::Any`
+	///
+	/// See [`kDNSServiceFlagsIncludeAWDL`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsincludeawdl).
+	IncludeAWDL,
+
+	/// Mark traffic of the operation as background (low priority) traffic
+	///
+	/// See [`kDNSServiceFlagsBackgroundTrafficClass`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsbackgroundtrafficclass).
+	BackgroundTrafficClass,
 }
 
 flags_ops!{QueryRecordFlags: u8: QueryRecordFlag:
 	LongLivedQuery,
+	ForceMulticast,
+	ReturnIntermediates,
+	SuppressUnusable,
+	Timeout,
+	IncludeP2P,
+	IncludeAWDL,
+	BackgroundTrafficClass,
 }
 
 flag_mapping!{QueryRecordFlags: QueryRecordFlag => ffi::DNSServiceFlags:
 	LongLivedQuery => ffi::FLAGS_LONG_LIVED_QUERY,
+	ForceMulticast => ffi::FLAGS_FORCE_MULTICAST,
+	ReturnIntermediates => ffi::FLAGS_RETURN_INTERMEDIATES,
+	SuppressUnusable => ffi::FLAGS_SUPPRESS_UNUSABLE,
+	Timeout => ffi::FLAGS_TIMEOUT,
+	IncludeP2P => ffi::FLAGS_INCLUDE_P2P,
+	IncludeAWDL => ffi::FLAGS_INCLUDE_AWDL,
+	BackgroundTrafficClass => ffi::FLAGS_BACKGROUND_TRAFFIC_CLASS,
 }
 
 /// Set of [`QueriedRecordFlag`](enum.QueriedRecordFlag.html)s
@@ -73,42 +139,133 @@ flag_mapping!{QueriedRecordFlags: QueriedRecordFlag => ffi::DNSServiceFlags:
 }
 
 /// Pending query
-pub struct QueryRecord(ServiceStream<QueryRecordResult>);
+///
+/// Use [`TimeoutTrait::timeout`](trait.TimeoutTrait.html#method.timeout)
+/// to end the stream when no result arrives in time.
+///
+/// Alternatively the daemon can time out the query, see
+/// [`QueryRecordFlag::Timeout`](enum.QueryRecordFlag.html#variant.Timeout).
+pub struct QueryRecord(pub(crate) ServiceStream<QueryRecordResult>);
+
+impl QueryRecord {
+	/// Set number of results to queue (default 64)
+	///
+	/// While the queue is full (with the default
+	/// [`QueuePolicy`](enum.QueuePolicy.html)) no further results are
+	/// processed, which leaves them buffered in the daemon connection
+	/// until the stream is polled again.
+	pub fn queue_capacity(mut self, capacity: usize) -> Self {
+		self.0.set_capacity(capacity);
+		self
+	}
+
+	/// Set what happens when the result queue is full (default
+	/// [`QueuePolicy::Block`](enum.QueuePolicy.html#variant.Block))
+	pub fn queue_policy(mut self, policy: crate::QueuePolicy) -> Self {
+		self.0.set_policy(policy);
+		self
+	}
+
+	/// Why the operation ended (`None` while it is running)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.0.termination_reason()
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.0.stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.0.as_raw()
+	}
+}
 
 impl futures::Stream for QueryRecord {
-	type Item = QueryRecordResult;
-	type Error = io::Error;
+	type Item = Result<QueryRecordResult, Error>;
 
-	fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-		self.0.poll()
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.0.poll_next_unpin(cx)
 	}
 }
 
-impl GetRemote for QueryRecord {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
+impl futures::stream::FusedStream for QueryRecord {
+	fn is_terminated(&self) -> bool {
+		self.0.is_terminated()
 	}
 }
 
+
 /// Query result
 ///
 /// See [`DNSServiceQueryRecordReply`](https://developer.apple.com/documentation/dnssd/dnsservicequeryrecordreply).
 #[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QueryRecordResult{
-	///
+	/// Flags indicating whether the record was added or removed and
+	/// whether there are more pending results.
 	pub flags: QueriedRecordFlags,
-	///
+	/// Interface the record was found on.
 	pub interface: Interface,
-	///
+	/// Full name of the record
 	pub fullname: String,
-	///
-	pub rr_type: u16,
-	///
-	pub rr_class: u16,
-	///
+	/// Type of the record
+	pub rr_type: RecordType,
+	/// Class of the record
+	pub rr_class: RecordClass,
+	/// Raw record data (wire format)
 	pub rdata: Vec<u8>,
-	///
+	/// Time to live in seconds
 	pub ttl: u32,
+	/// When the result was received
+	#[cfg_attr(feature = "serde", serde(skip))]
+	pub received_at: crate::Timestamp,
+}
+
+impl QueryRecordResult {
+	/// Decode record data
+	pub fn parsed(&self) -> io::Result<RecordData> {
+		RecordData::parse(self.rr_type, &self.rdata)
+	}
+
+	/// Ask the daemon to verify this record is still valid (and purge
+	/// it from the cache otherwise)
+	///
+	/// See [`reconfirm_record`](fn.reconfirm_record.html).
+	pub fn reconfirm(&self) -> io::Result<()> {
+		reconfirm_record(
+			ReconfirmFlags::default(),
+			self.interface,
+			&self.fullname,
+			self.rr_type,
+			self.rr_class,
+			&self.rdata
+		)
+	}
+}
+
+// e.g. `added printer.local. 120 IN A 192.168.1.2 on eth0`
+impl fmt::Display for QueryRecordResult {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} {} {} {} {} ",
+			if self.flags & QueriedRecordFlag::Add { "added" } else { "removed" },
+			self.fullname,
+			self.ttl,
+			self.rr_class,
+			self.rr_type
+		)?;
+		match self.parsed() {
+			Ok(data) => write!(f, "{}", data)?,
+			// show raw data
+			Err(_) => write!(f, "{}", RecordData::Unknown { rr_type: self.rr_type, rdata: self.rdata.clone() })?,
+		}
+		write!(f, " on {}", self.interface)
+	}
 }
 
 extern "C" fn query_record_callback(
@@ -124,10 +281,11 @@ extern "C" fn query_record_callback(
 	ttl: u32,
 	context: *mut c_void
 ) {
-	let sender = context as *mut mpsc::UnboundedSender<io::Result<QueryRecordResult>>;
-	let sender : &mpsc::UnboundedSender<io::Result<QueryRecordResult>> = unsafe { &*sender };
+	trace_callback!("query_record", flags, interface_index, error_code, rr_type, ttl);
+	let sender = context as *mut mpsc::UnboundedSender<Result<QueryRecordResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<QueryRecordResult, Error>> = unsafe { &*sender };
 
-	let data = Error::from(error_code).map_err(io::Error::from).and_then(|_| {
+	let data = Error::from(error_code).and_then(|_| {
 		let fullname = unsafe { cstr::from_cstr(fullname) }?;
 		let rdata = unsafe { ::std::slice::from_raw_parts(rdata, rd_len as usize) };
 
@@ -135,41 +293,79 @@ extern "C" fn query_record_callback(
 			flags: QueriedRecordFlags::from(flags),
 			interface: Interface::from_raw(interface_index),
 			fullname: fullname.to_string(),
-			rr_type: rr_type,
-			rr_class: rr_class,
+			rr_type: RecordType::from(rr_type),
+			rr_class: RecordClass::from(rr_class),
 			rdata: rdata.into(),
-			ttl: ttl,
+			ttl,
+			received_at: crate::Timestamp::now(),
 		})
 	});
 
-	sender.send(data).unwrap();
+	sender.unbounded_send(data).unwrap();
 }
 
 /// Query for an arbitrary DNS record
 ///
+/// Only `LongLivedQuery` is supported by avahi.
+///
 /// See [`DNSServiceQueryRecord`](https://developer.apple.com/documentation/dnssd/1804747-dnsservicequeryrecordc).
 pub fn query_record(
 	flags: QueryRecordFlags,
 	interface: Interface,
 	fullname: &str,
-	rr_type: u16,
-	rr_class: u16,
-	handle: &Handle
+	rr_type: RecordType,
+	rr_class: RecordClass
 ) -> io::Result<QueryRecord> {
+	#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+	{
+		if let Some(stream) = crate::avahi_dbus::query_record(interface, fullname, rr_type, rr_class)? {
+			return Ok(QueryRecord(stream));
+		}
+	}
+
 	let fullname = cstr::CStr::from(&fullname)?;
 
 	Ok(QueryRecord(ServiceStream::new(move |sender|
 		EventedDNSService::new(
 			raw::DNSService::query_record(
+				None, /* own connection */
 				flags.into(),
 				interface.into_raw(),
 				&fullname,
-				rr_type,
-				rr_class,
+				rr_type.into(),
+				rr_class.into(),
 				Some(query_record_callback),
 				sender as *mut c_void,
-			)?,
-			handle
+			)?
 		)
 	)?))
 }
+
+impl crate::SharedConnection {
+	/// Query for a record on the shared connection
+	///
+	/// See [`query_record`](fn.query_record.html).
+	pub fn query_record(
+		&self,
+		flags: QueryRecordFlags,
+		interface: Interface,
+		fullname: &str,
+		rr_type: RecordType,
+		rr_class: RecordClass
+	) -> io::Result<QueryRecord> {
+		let fullname = cstr::CStr::from(&fullname)?;
+
+		Ok(QueryRecord(ServiceStream::new_shared(self.evented(), move |sender|
+			Ok(raw::DNSService::query_record(
+				Some(self.evented().service()),
+				flags.into(),
+				interface.into_raw(),
+				&fullname,
+				rr_type.into(),
+				rr_class.into(),
+				Some(query_record_callback),
+				sender as *mut c_void,
+			)?)
+		)?))
+	}
+}
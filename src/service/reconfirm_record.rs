@@ -0,0 +1,60 @@
+use std::io;
+
+use crate::cstr;
+use crate::dns_types::{RecordClass,RecordType};
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+
+/// Set of [`ReconfirmFlag`](enum.ReconfirmFlag.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct ReconfirmFlags(u8);
+
+/// Flags used to reconfirm a record
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum ReconfirmFlag {
+	/// Remove the record from the cache immediately instead of
+	/// verifying it first
+	///
+	/// See [`kDNSServiceFlagsForce`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsforce).
+	Force = 0,
+}
+
+flags_ops!{ReconfirmFlags: u8: ReconfirmFlag:
+	Force,
+}
+
+flag_mapping!{ReconfirmFlags: ReconfirmFlag => ffi::DNSServiceFlags:
+	Force => ffi::FLAGS_FORCE,
+}
+
+/// Purge record from cache
+///
+/// Tells the daemon a cached record might be stale (e.g. because the
+/// device went away without sending a goodbye packet); the daemon
+/// verifies the record and removes it if it doesn't get an answer.
+///
+/// See [`DNSServiceReconfirmRecord`](https://developer.apple.com/documentation/dnssd/1804726-dnsservicereconfirmrecord).
+pub fn reconfirm_record(
+	flags: ReconfirmFlags,
+	interface: Interface,
+	fullname: &str,
+	rr_type: RecordType,
+	rr_class: RecordClass,
+	rdata: &[u8]
+) -> io::Result<()> {
+	let fullname = cstr::CStr::from(&fullname)?;
+	raw::reconfirm_record(
+		flags.into(),
+		interface.into_raw(),
+		&fullname,
+		rr_type.into(),
+		rr_class.into(),
+		rdata)?;
+
+	Ok(())
+}
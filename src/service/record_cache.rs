@@ -0,0 +1,197 @@
+use futures::{self,StreamExt};
+use std::collections::{HashMap,VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc,Mutex,MutexGuard};
+use std::task::{Context,Poll};
+use std::time::{Duration,Instant};
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	query_record,
+	QueriedRecordFlag,
+	QueriedRecordFlags,
+	QueryRecord,
+	QueryRecordFlags,
+	QueryRecordResult,
+};
+
+// names are case-insensitive
+type CacheKey = (String, RecordType, RecordClass);
+
+fn cache_key(fullname: &str, rr_type: RecordType, rr_class: RecordClass) -> CacheKey {
+	(fullname.to_ascii_lowercase(), rr_type, rr_class)
+}
+
+// answers with the time their TTL runs out
+type Answers = Vec<(Instant, QueryRecordResult)>;
+
+/// Cache for [`query_record`](fn.query_record.html) answers
+///
+/// Answers are cached by full name, type and class until their TTL runs
+/// out; repeated queries are answered from the cache while at least one
+/// answer is still valid and only start a query otherwise.  Cloning is
+/// cheap; clones share the cached answers.
+#[derive(Clone,Default)]
+pub struct RecordCache(Arc<Mutex<HashMap<CacheKey, Answers>>>);
+
+impl RecordCache {
+	/// Create empty cache
+	pub fn new() -> Self {
+		RecordCache::default()
+	}
+
+	fn lock(&self) -> MutexGuard<'_, HashMap<CacheKey, Answers>> {
+		self.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Look up records, from the cache if possible
+	///
+	/// Yields the currently valid answers (with the remaining TTL) and
+	/// ends once the last answer of a batch was delivered.  On a cache
+	/// miss this starts a query (with the given `flags` and `interface`;
+	/// the cache doesn't distinguish those) and caches its answers; as
+	/// the query might not find anything, use a
+	/// [`timeout`](trait.TimeoutTrait.html#method.timeout) (or
+	/// [`QueryRecordFlag::Timeout`](enum.QueryRecordFlag.html#variant.Timeout)).
+	pub fn query_record(
+		&self,
+		flags: QueryRecordFlags,
+		interface: Interface,
+		fullname: &str,
+		rr_type: RecordType,
+		rr_class: RecordClass
+	) -> io::Result<CachedQueryRecord> {
+		let key = cache_key(fullname, rr_type, rr_class);
+		if let Some(answers) = self.valid_answers(&key) {
+			return Ok(CachedQueryRecord(State::Cached(answers)));
+		}
+		let query = query_record(flags, interface, fullname, rr_type, rr_class)?;
+		Ok(CachedQueryRecord(State::Querying {
+			query,
+			cache: self.clone(),
+			key,
+		}))
+	}
+
+	// drops expired answers
+	fn valid_answers(&self, key: &CacheKey) -> Option<VecDeque<QueryRecordResult>> {
+		let mut entries = self.lock();
+		let now = Instant::now();
+		let answers = entries.get_mut(key)?;
+		answers.retain(|&(expires, _)| expires > now);
+		if answers.is_empty() {
+			entries.remove(key);
+			return None;
+		}
+		let count = answers.len();
+		Some(answers.iter().enumerate().map(|(i, &(expires, ref answer))| {
+			let mut flags: QueriedRecordFlags = QueriedRecordFlag::Add.into();
+			if i + 1 < count {
+				flags |= QueriedRecordFlag::MoreComing;
+			}
+			QueryRecordResult {
+				flags,
+				// round up, so valid answers don't report a zero TTL
+				ttl: (expires - now).as_secs() as u32 + 1,
+				..answer.clone()
+			}
+		}).collect())
+	}
+
+	fn update(&self, key: &CacheKey, result: &QueryRecordResult) {
+		let mut entries = self.lock();
+		let answers = entries.entry(key.clone()).or_default();
+		answers.retain(|(_, answer)| answer.rdata != result.rdata || answer.interface != result.interface);
+		if result.flags & QueriedRecordFlag::Add && result.ttl > 0 {
+			let expires = result.received_at.instant() + Duration::from_secs(result.ttl.into());
+			answers.push((expires, result.clone()));
+		}
+		if answers.is_empty() {
+			entries.remove(key);
+		}
+	}
+
+	/// Drop cached answers for a record
+	pub fn invalidate(&self, fullname: &str, rr_type: RecordType, rr_class: RecordClass) {
+		self.lock().remove(&cache_key(fullname, rr_type, rr_class));
+	}
+
+	/// Drop all cached answers
+	pub fn invalidate_all(&self) {
+		self.lock().clear();
+	}
+
+	/// Drop expired answers (they are never served, but take up memory
+	/// until the record is queried again)
+	pub fn purge_expired(&self) {
+		let now = Instant::now();
+		self.lock().retain(|_, answers| {
+			answers.retain(|&(expires, _)| expires > now);
+			!answers.is_empty()
+		});
+	}
+
+	/// Number of records with cached answers (including expired ones)
+	pub fn len(&self) -> usize {
+		self.lock().len()
+	}
+
+	/// Whether no answers are cached
+	pub fn is_empty(&self) -> bool {
+		self.lock().is_empty()
+	}
+}
+
+enum State {
+	Cached(VecDeque<QueryRecordResult>),
+	Querying {
+		query: QueryRecord,
+		cache: RecordCache,
+		key: CacheKey,
+	},
+	Done,
+}
+
+/// Pending lookup through a [`RecordCache`](struct.RecordCache.html)
+///
+/// See [`RecordCache::query_record`](struct.RecordCache.html#method.query_record).
+pub struct CachedQueryRecord(State);
+
+impl CachedQueryRecord {
+	/// Whether the answers come from the cache (no query was started)
+	pub fn is_cached(&self) -> bool {
+		matches!(self.0, State::Cached(_))
+	}
+}
+
+impl futures::Stream for CachedQueryRecord {
+	type Item = Result<QueryRecordResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		match self.0 {
+			State::Cached(ref mut answers) => Poll::Ready(answers.pop_front().map(Ok)),
+			State::Querying { ref mut query, ref cache, ref key } => {
+				let item = futures::ready!(query.poll_next_unpin(cx));
+				match item {
+					Some(Ok(result)) => {
+						cache.update(key, &result);
+						if !(result.flags & QueriedRecordFlag::MoreComing) {
+							// batch complete; stop the query
+							self.0 = State::Done;
+						}
+						Poll::Ready(Some(Ok(result)))
+					},
+					Some(Err(e)) => Poll::Ready(Some(Err(e))),
+					None => {
+						self.0 = State::Done;
+						Poll::Ready(None)
+					},
+				}
+			},
+			State::Done => Poll::Ready(None),
+		}
+	}
+}
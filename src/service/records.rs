@@ -1,6 +1,69 @@
+use std::fmt;
 use std::io;
+use std::sync::{Arc,Mutex,MutexGuard,Weak};
 
-use raw;
+use crate::dns_types::RecordType;
+use crate::ffi;
+use crate::raw;
+use crate::record_data::RecordData;
+
+/// Flags for [`Record::update_record`](struct.Record.html#method.update_record)
+///
+/// The C API doesn't define any flags for updates yet (they are
+/// "reserved for future use"); use [`none`](#method.none) unless a
+/// newer daemon documents some, which can be passed through
+/// [`from_raw`](#method.from_raw).
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Default)]
+pub struct UpdateRecordFlags(ffi::DNSServiceFlags);
+
+impl UpdateRecordFlags {
+	/// Construct empty set of flags.
+	pub fn none() -> Self {
+		UpdateRecordFlags(0)
+	}
+
+	/// Construct from raw `DNSServiceFlags`
+	pub fn from_raw(flags: u32) -> Self {
+		UpdateRecordFlags(flags)
+	}
+
+	/// Raw `DNSServiceFlags`
+	pub fn into_raw(self) -> u32 {
+		self.0
+	}
+
+	/// Whether no flag is set.
+	pub fn is_empty(self) -> bool {
+		0 == self.0
+	}
+
+	/// Whether all flags of `flags` are set.
+	pub fn contains(self, flags: UpdateRecordFlags) -> bool {
+		flags.0 == (self.0 & flags.0)
+	}
+
+	/// Iterate over the set flags (one bit each, as there are no named
+	/// flags).
+	pub fn iter(self) -> impl Iterator<Item = UpdateRecordFlags> {
+		(0..32).map(|bit| 1 << bit).filter(move |bit| 0 != self.0 & bit).map(UpdateRecordFlags)
+	}
+}
+
+// set bits in hex separated by ` | `, or `none`
+impl fmt::Debug for UpdateRecordFlags {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.is_empty() {
+			return f.write_str("none");
+		}
+		for (i, flag) in self.iter().enumerate() {
+			if i > 0 {
+				f.write_str(" | ")?;
+			}
+			write!(f, "{:#x}", flag.0)?;
+		}
+		Ok(())
+	}
+}
 
 /// A successful record registration
 ///
@@ -12,15 +75,145 @@ use raw;
 ///
 /// Also keeps the underlying [`Registration`](struct.Registration.html)
 /// or [`Connection`](struct.Connection.html) alive.
-pub struct Record(raw::DNSRecord);
+pub struct Record(Arc<Mutex<RecordSlot>>);
+
+// record state shared with the registration the record belongs to, so
+// it can be moved to a new registration (see `Registration::update_port`)
+pub(crate) struct RecordSlot {
+	// `None` while moving and after moving failed
+	record: Option<raw::DNSRecord>,
+	rr_type: u16,
+	kind: SlotKind,
+}
+
+enum SlotKind {
+	// record registered on a connection; can't be moved
+	Fixed,
+	// record added to a registration with its current data and ttl
+	Added(RecordTracker, Vec<u8>, u32),
+	// default TXT record of a registration
+	DefaultTxt(RecordTracker),
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn gone() -> io::Error {
+	io::Error::new(io::ErrorKind::NotConnected, "registration of the record is gone")
+}
+
+#[derive(Default)]
+struct TrackerInner {
+	slots: Vec<Weak<Mutex<RecordSlot>>>,
+	// records passed to `Record::keep`: type, data and ttl
+	kept: Vec<(u16, Vec<u8>, u32)>,
+	// TXT data set after the registration
+	txt: Option<Vec<u8>>,
+}
+
+/// Records of a registration, which get moved to the new `DNSServiceRef`
+/// when the service is registered again
+#[derive(Clone,Default)]
+pub(crate) struct RecordTracker(Arc<Mutex<TrackerInner>>);
+
+impl RecordTracker {
+	fn track(&self, record: raw::DNSRecord, kind: SlotKind) -> Record {
+		let slot = Arc::new(Mutex::new(RecordSlot {
+			rr_type: record.rr_type(),
+			record: Some(record),
+			kind,
+		}));
+		let mut inner = lock(&self.0);
+		inner.slots.retain(|slot| slot.strong_count() > 0);
+		inner.slots.push(Arc::downgrade(&slot));
+		Record(slot)
+	}
+
+	/// Track record added with `rdata` and `ttl`
+	pub fn added(&self, record: raw::DNSRecord, rdata: &[u8], ttl: u32) -> Record {
+		self.track(record, SlotKind::Added(self.clone(), rdata.to_vec(), ttl))
+	}
+
+	/// Track handle for the default TXT record
+	pub fn default_txt(&self, record: raw::DNSRecord) -> Record {
+		self.track(record, SlotKind::DefaultTxt(self.clone()))
+	}
+
+	/// TXT data set after the registration (if any)
+	pub fn txt(&self) -> Option<Vec<u8>> {
+		lock(&self.0).txt.clone()
+	}
+
+	pub fn set_txt(&self, txt: &[u8]) {
+		lock(&self.0).txt = Some(txt.to_vec());
+	}
+
+	/// Remove all records from the registration (the handles stay
+	/// valid), so it can be released
+	pub fn detach(&self) {
+		for slot in self.live_slots() {
+			lock(&slot).record = None;
+		}
+	}
+
+	/// Add the records to `service`, a new registration
+	pub fn attach(&self, service: &raw::DNSService) -> io::Result<()> {
+		for slot in self.live_slots() {
+			let mut slot = lock(&slot);
+			let record = match slot.kind {
+				SlotKind::Fixed => continue,
+				SlotKind::Added(_, ref rdata, ttl) => service.add_record(0, slot.rr_type, rdata, ttl)?,
+				SlotKind::DefaultTxt(_) => service.get_default_txt_record(),
+			};
+			slot.record = Some(record);
+		}
+		let kept = lock(&self.0).kept.clone();
+		for (rr_type, rdata, ttl) in kept {
+			service.add_record(0, rr_type, &rdata, ttl)?.keep();
+		}
+		Ok(())
+	}
+
+	fn live_slots(&self) -> Vec<Arc<Mutex<RecordSlot>>> {
+		lock(&self.0).slots.iter().filter_map(Weak::upgrade).collect()
+	}
+}
 
 impl Record {
+	fn with_record<F, R>(&self, f: F) -> io::Result<R>
+	where F: FnOnce(&raw::DNSRecord) -> io::Result<R>
+	{
+		match lock(&self.0).record {
+			Some(ref record) => f(record),
+			None => Err(gone()),
+		}
+	}
+
 	/// Type of the record
-	pub fn rr_type(&self) -> u16 {
-		self.0.rr_type()
+	pub fn rr_type(&self) -> RecordType {
+		RecordType::from(lock(&self.0).rr_type)
 	}
 
-	/// Update recor
+	/// Update record with typed data
+	///
+	/// `data` must have the type of the record.  Cannot change type or
+	/// class of record.
+	///
+	/// See [`DNSServiceUpdateRecord`](https://developer.apple.com/documentation/dnssd/1804739-dnsserviceupdaterecord).
+	pub fn update_record(
+		&self,
+		flags: UpdateRecordFlags,
+		data: &RecordData,
+		ttl: u32
+	) -> io::Result<()> {
+		if data.rr_type() != self.rr_type() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "record data doesn't match record type"));
+		}
+		self.update(flags.into_raw(), &data.to_bytes()?, ttl)
+	}
+
+	/// Update record
 	///
 	/// Cannot change type or class of record.
 	///
@@ -30,22 +223,73 @@ impl Record {
 		rdata: &[u8],
 		ttl: u32
 	) -> io::Result<()> {
-		self.0.update_record(
-			0, /* no flags */
-			rdata,
-			ttl
-		)?;
+		self.update(0 /* no flags */, rdata, ttl)
+	}
+
+	fn update(&self, flags: ffi::DNSServiceFlags, rdata: &[u8], ttl: u32) -> io::Result<()> {
+		let mut slot = lock(&self.0);
+		match slot.record {
+			Some(ref record) => record.update_record(flags, rdata, ttl)?,
+			None => return Err(gone()),
+		}
+		// remember the data to add the record again
+		match slot.kind {
+			SlotKind::Fixed => (),
+			SlotKind::Added(_, ref mut data, ref mut data_ttl) => {
+				*data = rdata.to_vec();
+				*data_ttl = ttl;
+			},
+			SlotKind::DefaultTxt(ref tracker) => tracker.set_txt(rdata),
+		}
 		Ok(())
 	}
 
+	/// Remove record now and report errors (dropping the record removes
+	/// it too, but ignores errors)
+	///
+	/// Fails for the default TXT record of a registration.
+	///
+	/// See [`DNSServiceRemoveRecord`](https://developer.apple.com/documentation/dnssd/1804736-dnsserviceremoverecord).
+	pub fn remove(self) -> io::Result<()> {
+		let record = lock(&self.0).record.take().ok_or_else(gone)?;
+		record.remove()?;
+		Ok(())
+	}
+
+	/// Underlying `DNSRecordRef`
+	///
+	/// Null for the default TXT record of a registration (the C API uses
+	/// a null reference for it too).  See
+	/// [`Connection::as_raw`](struct.Connection.html#method.as_raw) for
+	/// the rules when using it.
+	pub fn as_raw(&self) -> ffi::DNSRecordRef {
+		self.with_record(|record| Ok(record.as_raw())).unwrap_or(std::ptr::null_mut())
+	}
+
+	/// `DNSServiceRef` of the registration or connection the record
+	/// belongs to
+	pub fn service_as_raw(&self) -> ffi::DNSServiceRef {
+		self.with_record(|record| Ok(record.service_as_raw())).unwrap_or(std::ptr::null_mut())
+	}
+
 	/// Keep record alive for as long as the underlying
 	/// [`Registration`](struct.Registration.html) or
 	/// [`Connection`](struct.Connection.html) lives
 	pub fn keep(self) {
-		self.0.keep()
+		let mut slot = lock(&self.0);
+		if let SlotKind::Added(ref tracker, ref rdata, ttl) = slot.kind {
+			lock(&tracker.0).kept.push((slot.rr_type, rdata.clone(), ttl));
+		}
+		if let Some(record) = slot.record.take() {
+			record.keep();
+		}
 	}
 }
 
 pub fn new_record(r: raw::DNSRecord) -> Record {
-	Record(r)
+	Record(Arc::new(Mutex::new(RecordSlot {
+		rr_type: r.rr_type(),
+		record: Some(r),
+		kind: SlotKind::Fixed,
+	})))
 }
@@ -1,17 +1,26 @@
-use futures::sync::mpsc;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::{FutureExt,StreamExt};
+use std::future::Future;
 use std::os::raw::{c_void,c_char};
 use std::io;
-use tokio_core::reactor::{Handle,Remote};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context,Poll};
 
-use cstr;
-use error::Error;
-use evented::EventedDNSService;
-use ffi;
-use interface::Interface;
-use raw;
-use remote::GetRemote;
-use future::ServiceFuture;
+use crate::cstr;
+use crate::dns_types::RecordType;
+use crate::record_data::RecordData;
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::future::ServiceFuture;
+use crate::stream::ServiceStream;
+use super::RecordTracker;
+#[cfg(feature = "tracing")]
+use crate::txt_record::RECOMMENDED_TXT_LEN;
+use crate::txt_record::TxtRecord;
 
 /// Set of [`RegisterFlag`](enum.RegisterFlag.html)s
 ///
@@ -38,48 +47,102 @@ pub enum RegisterFlag {
 	///
 	/// See [`kDNSServiceFlagsUnique`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsunique).
 	Unique,
+
+	/// Include peer-to-peer interfaces (like AWDL) when using
+	/// `Interface::Any`
+	///
+	/// See [`kDNSServiceFlagsIncludeP2P`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsincludep2p).
+	IncludeP2P,
+
+	/// Include AWDL (Apple Wireless Direct Link) when using
+	/// `Interface::Any`
+	///
+	/// See [`kDNSServiceFlagsIncludeAWDL`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsincludeawdl).
+	IncludeAWDL,
+
+	/// Answer queries for the records from outside the local network
+	/// (by default the daemon only answers queriers on a directly
+	/// connected network)
+	///
+	/// See [`kDNSServiceFlagsAllowRemoteQuery`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsallowremotequery).
+	AllowRemoteQuery,
 }
 
 flags_ops!{RegisterFlags: u8: RegisterFlag:
 	NoAutoRename,
 	Shared,
 	Unique,
+	IncludeP2P,
+	IncludeAWDL,
+	AllowRemoteQuery,
 }
 
 flag_mapping!{RegisterFlags: RegisterFlag => ffi::DNSServiceFlags:
 	NoAutoRename => ffi::FLAGS_NO_AUTO_RENAME,
 	Shared => ffi::FLAGS_SHARED,
 	Unique => ffi::FLAGS_UNIQUE,
+	IncludeP2P => ffi::FLAGS_INCLUDE_P2P,
+	IncludeAWDL => ffi::FLAGS_INCLUDE_AWDL,
+	AllowRemoteQuery => ffi::FLAGS_ALLOW_REMOTE_QUERY,
 }
 
+// encode data after checking it matches the type
+fn typed_rdata(rr_type: RecordType, data: &RecordData) -> io::Result<Vec<u8>> {
+	if data.rr_type() != rr_type {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "record data doesn't match record type"));
+	}
+	data.to_bytes()
+}
+
+// registers the service again with the given name, port and (if
+// changed) TXT data
+pub(crate) type Restart = dyn Fn(&str, u16, Option<&[u8]>) -> io::Result<Register> + Send + Sync;
+
 /// Pending registration
 ///
 /// Becomes invalid when the future completes; use the returned
 /// [`Registration`](struct.Registration.html) instead.
-pub struct Register(ServiceFuture<RegisterResult>);
-
-impl futures::Future for Register {
-	type Item = (Registration, RegisterResult);
-	type Error = io::Error;
-
-	fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
-		match self.0.poll() {
-			Ok(Async::Ready((service, item))) => Ok(Async::Ready((
-				Registration(service),
-				item
-			))),
-			Ok(Async::NotReady) => Ok(Async::NotReady),
-			Err(e) => Err(e),
+pub struct Register {
+	future: Option<ServiceFuture<RegisterResult>>,
+	records: RecordTracker,
+	restart: Option<Arc<Restart>>,
+}
+
+impl Register {
+	pub(crate) fn new(future: ServiceFuture<RegisterResult>, restart: Option<Arc<Restart>>) -> Self {
+		Register {
+			future: Some(future),
+			records: RecordTracker::default(),
+			restart,
 		}
 	}
+
+	fn future(&self) -> &ServiceFuture<RegisterResult> {
+		self.future.as_ref().expect("can only get ready once")
+	}
 }
 
-impl GetRemote for Register {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
+impl Future for Register {
+	type Output = Result<(Registration, RegisterResult), Error>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let future = match self.future.as_mut() {
+			// can only get ready once.
+			None => return Poll::Pending,
+			Some(future) => future,
+		};
+		let (service, item) = futures::ready!(future.poll_unpin(cx))?;
+		self.future = None;
+		Poll::Ready(Ok((Registration{
+			stream: service,
+			result: item.clone(),
+			records: self.records.clone(),
+			restart: self.restart.clone(),
+		}, item)))
 	}
 }
 
+
 /// Service registration result
 ///
 /// See [`DNSServiceRegisterReply`](https://developer.apple.com/documentation/dnssd/dnsserviceregisterreply).
@@ -89,10 +152,12 @@ pub struct RegisterResult{
 	/// was set this is the original name, otherwise it might be
 	/// different.
 	pub name: String,
-	///
+	/// Type of the service
 	pub reg_type: String,
-	///
+	/// Domain the service was registered in
 	pub domain: String,
+	/// When the result was received
+	pub received_at: crate::Timestamp,
 }
 
 extern "C" fn register_callback(
@@ -104,10 +169,11 @@ extern "C" fn register_callback(
 	domain: *const c_char,
 	context: *mut c_void
 ) {
-	let sender = context as *mut mpsc::UnboundedSender<io::Result<RegisterResult>>;
-	let sender : &mpsc::UnboundedSender<io::Result<RegisterResult>> = unsafe { &*sender };
+	trace_callback!("register", error_code);
+	let sender = context as *mut mpsc::UnboundedSender<Result<RegisterResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<RegisterResult, Error>> = unsafe { &*sender };
 
-	let data = Error::from(error_code).map_err(io::Error::from).and_then(|_| {
+	let data = Error::from(error_code).and_then(|_| {
 		let name = unsafe { cstr::from_cstr(name) }?;
 		let reg_type = unsafe { cstr::from_cstr(reg_type) }?;
 		let domain = unsafe { cstr::from_cstr(domain) }?;
@@ -116,40 +182,254 @@ extern "C" fn register_callback(
 			name: name.to_string(),
 			reg_type: reg_type.to_string(),
 			domain: domain.to_string(),
+			received_at: crate::Timestamp::now(),
 		})
 	});
 
-	sender.send(data).unwrap();
+	sender.unbounded_send(data).unwrap();
 }
 
 /// Successful registration
 ///
 /// On dropping the registration the service will be unregistered.
+/// Dropping is synchronous: the removal is handed to the daemon before
+/// `drop` returns; with the built-in mDNS engine `drop` blocks until the
+/// goodbye packets were sent (up to about 100ms).  Use
+/// [`DnsSd::shutdown`](struct.DnsSd.html#method.shutdown) to tear
+/// registrations down without blocking the executor.
+///
 /// Registered [`Record`](struct.Record.html)s from this `Registration`
 /// or the originating [`Register`](struct.Register.html) future will
 /// keep the `Registration` alive.
-pub struct Registration(EventedDNSService);
+pub struct Registration {
+	stream: ServiceStream<RegisterResult>,
+	result: RegisterResult,
+	records: RecordTracker,
+	restart: Option<Arc<Restart>>,
+}
+
+impl Registration {
+	/// Name the service is currently published under
+	///
+	/// Might differ from the requested name after an automatic rename;
+	/// updated when [`events`](#method.events) reports a rename.
+	pub fn name(&self) -> &str {
+		&self.result.name
+	}
+
+	/// Type of the service
+	pub fn reg_type(&self) -> &str {
+		&self.result.reg_type
+	}
+
+	/// Domain the service was registered in
+	pub fn domain(&self) -> &str {
+		&self.result.domain
+	}
+
+	/// Stream of events after the initial registration
+	///
+	/// Yields a new [`RegisterResult`](struct.RegisterResult.html) when
+	/// the daemon renames the service (e.g. after a conflict or when
+	/// the computer name changes), and errors (like a name conflict with
+	/// [`NoAutoRename`](enum.RegisterFlag.html#variant.NoAutoRename)).
+	pub fn events(&mut self) -> RegistrationEvents<'_> {
+		RegistrationEvents(self)
+	}
+
+	fn poll_event(&mut self, cx: &mut Context) -> Poll<Option<Result<RegisterResult, Error>>> {
+		let item = futures::ready!(self.stream.poll_next_unpin(cx));
+		if let Some(Ok(ref result)) = item {
+			self.result = result.clone();
+		}
+		Poll::Ready(item)
+	}
+
+	/// Wait for a name conflict
+	///
+	/// Only useful if the service was registered with
+	/// [`NoAutoRename`](enum.RegisterFlag.html#variant.NoAutoRename);
+	/// otherwise the daemon resolves conflicts by renaming the service.
+	///
+	/// The service is no longer published after a conflict; the
+	/// application can register it again with a different name.
+	pub fn name_conflict(&mut self) -> NameConflict<'_> {
+		NameConflict(self)
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.stream.stop_handle()
+	}
+
+	/// Why the registration ended (`None` while it is active)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.stream.termination_reason()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.stream.as_raw()
+	}
+}
+
+/// Events of a registration
+///
+/// See [`Registration::events`](struct.Registration.html#method.events).
+pub struct RegistrationEvents<'a>(&'a mut Registration);
+
+impl<'a> futures::Stream for RegistrationEvents<'a> {
+	type Item = Result<RegisterResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.0.poll_event(cx)
+	}
+}
+
+/// Pending name conflict detection
+///
+/// Completes with `Ok(())` once the daemon reports a name conflict for
+/// the registered service, and with an error on other failures or when
+/// the registration ended (e.g. `Interrupted` when it was stopped).
+///
+/// See [`Registration::name_conflict`](struct.Registration.html#method.name_conflict).
+pub struct NameConflict<'a>(&'a mut Registration);
+
+impl<'a> Future for NameConflict<'a> {
+	type Output = Result<(), Error>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		loop {
+			match self.0.poll_event(cx) {
+				Poll::Ready(Some(Ok(_))) => (), // not a conflict
+				Poll::Ready(Some(Err(ref e))) if e.is_name_conflict() => {
+					return Poll::Ready(Ok(()));
+				},
+				Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+				// the registration ended (stopped, daemon exited, ...)
+				Poll::Ready(None) => {
+					return Poll::Ready(Err(crate::TerminationReason::into_error(self.0.termination_reason())));
+				},
+				Poll::Pending => return Poll::Pending,
+			}
+		}
+	}
+}
 
 /// Registers a service
 ///
+/// `subtypes` lists additional subtypes the service is registered
+/// under, either as single label (`"_printer"`) or in the full form
+/// (`"_printer._sub._http._tcp"`).
+///
+/// `host` can name another device the service is running on, which
+/// needs address records published by someone (see
+/// [`RegisterBuilder::start_proxy`](struct.RegisterBuilder.html#method.start_proxy)).
+///
+/// `txt` is checked with [`TxtRecord::validate`](struct.TxtRecord.html#method.validate)
+/// (the error wraps a [`TxtError`](enum.TxtError.html)).
+///
+/// If the name is already in use and
+/// [`NoAutoRename`](enum.RegisterFlag.html#variant.NoAutoRename) was
+/// given the registration fails with an error of kind
+/// [`AlreadyExists`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.AlreadyExists)
+/// (wrapping [`DNSServiceError::NameConflict`](enum.DNSServiceError.html#variant.NameConflict)).
+///
+/// Port 0 is rejected with an error of kind
+/// [`InvalidInput`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidInput):
+/// it only reserves the name without advertising the service, which
+/// needs to be requested explicitly (see
+/// [`Register::placeholder`](struct.Register.html#method.placeholder)).
+///
 /// See [`DNSServiceRegister`](https://developer.apple.com/documentation/dnssd/1804733-dnsserviceregister).
 pub fn register(
 	flags: RegisterFlags,
 	interface: Interface,
 	name: Option<&str>,
 	reg_type: &str,
+	subtypes: &[&str],
+	domain: Option<&str>,
+	host: Option<&str>,
+	port: u16,
+	txt: &[u8]
+) -> io::Result<Register> {
+	start_register(flags, interface, name, reg_type, subtypes, domain, host, port, txt, false)
+}
+
+fn check_port(port: u16, allow_placeholder: bool) -> io::Result<()> {
+	if port == 0 && !allow_placeholder {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"port 0 only reserves the name (use a placeholder registration)",
+		));
+	}
+	Ok(())
+}
+
+fn start_register(
+	flags: RegisterFlags,
+	interface: Interface,
+	name: Option<&str>,
+	reg_type: &str,
+	subtypes: &[&str],
 	domain: Option<&str>,
 	host: Option<&str>,
 	port: u16,
 	txt: &[u8],
-	handle: &Handle
+	allow_placeholder: bool
 ) -> io::Result<Register> {
+	check_port(port, allow_placeholder)?;
+	TxtRecord::validate(txt)?;
+	let restart = {
+		let reg_type = reg_type.to_string();
+		let subtypes: Vec<String> = subtypes.iter().map(|s| s.to_string()).collect();
+		let domain = domain.map(str::to_string);
+		let host = host.map(str::to_string);
+		let txt = txt.to_vec();
+		let restart: Arc<Restart> = Arc::new(move |name: &str, port: u16, changed_txt: Option<&[u8]>| {
+			let subtypes: Vec<&str> = subtypes.iter().map(String::as_str).collect();
+			start_register(flags, interface, Some(name), &reg_type, &subtypes, domain.as_deref(), host.as_deref(), port, changed_txt.unwrap_or(&txt), allow_placeholder)
+		});
+		Some(restart)
+	};
+	#[cfg(feature = "tracing")]
+	{
+		if txt.len() > RECOMMENDED_TXT_LEN {
+			::tracing::warn!(len = txt.len(), "TXT record might not fit into a single multicast packet");
+		}
+	}
+
+	#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+	{
+		let no_auto_rename = flags & RegisterFlag::NoAutoRename;
+		if let Some(stream) = crate::avahi_dbus::register(no_auto_rename, interface, name, reg_type, subtypes, domain, host, port, txt)? {
+			return Ok(Register::new(ServiceFuture::from_stream(stream), restart));
+		}
+	}
+	#[cfg(all(windows, feature = "windows-dnsapi"))]
+	{
+		let no_auto_rename = flags & RegisterFlag::NoAutoRename;
+		if let Some(stream) = crate::windows_dnsapi::register(no_auto_rename, interface, name, reg_type, subtypes, domain, host, port, txt)? {
+			return Ok(Register::new(ServiceFuture::from_stream(stream), restart));
+		}
+	}
+
+	#[cfg(all(unix, feature = "mdns"))]
+	let fallback = move || crate::mdns::register(
+		flags & RegisterFlag::NoAutoRename, interface, name, reg_type, subtypes, domain, host, port, txt
+	).map(ServiceFuture::from_stream);
+
 	let name = cstr::NullableCStr::from(&name)?;
+	let reg_type = super::reg_type_with_subtypes(reg_type, subtypes)?;
 	let reg_type = cstr::CStr::from(&reg_type)?;
 	let domain = cstr::NullableCStr::from(&domain)?;
 	let host = cstr::NullableCStr::from(&host)?;
 
-	Ok(Register(ServiceFuture::new(move |sender|
+	let future = ServiceFuture::new(move |sender|
 		EventedDNSService::new(
 			raw::DNSService::register(
 				flags.into(),
@@ -162,26 +442,44 @@ pub fn register(
 				txt,
 				Some(register_callback),
 				sender as *mut c_void,
-			)?,
-			handle
+			)?
 		)
-	)?))
+	);
+	#[cfg(all(unix, feature = "mdns"))]
+	let future = crate::mdns::or_fallback(future, fallback);
+
+	Ok(Register::new(future?, restart))
 }
 
 impl Register {
 	/// See [`DNSServiceAddRecord`](https://developer.apple.com/documentation/dnssd/1804730-dnsserviceaddrecord)
 	pub fn add_raw_record(
 		&self,
-		rr_type: u16,
+		rr_type: RecordType,
 		rdata: &[u8],
 		ttl: u32
-	) -> io::Result<::Record> {
-		Ok(super::new_record(self.0.service().add_record(
+	) -> io::Result<crate::Record> {
+		let record = self.future().try_service()?.add_record(
 			0, /* no flags */
-			rr_type,
+			rr_type.into(),
 			rdata,
 			ttl
-		)?))
+		)?;
+		Ok(self.records.added(record, rdata, ttl))
+	}
+
+	/// Add record with typed data to the registered service
+	///
+	/// `data` must be of type `rr_type`.
+	///
+	/// See [`DNSServiceAddRecord`](https://developer.apple.com/documentation/dnssd/1804730-dnsserviceaddrecord)
+	pub fn add_record(
+		&self,
+		rr_type: RecordType,
+		data: &RecordData,
+		ttl: u32
+	) -> io::Result<crate::Record> {
+		self.add_raw_record(rr_type, &typed_rdata(rr_type, data)?, ttl)
 	}
 
 	/// Get [`Record`](struct.Record.html) handle for default TXT record
@@ -189,8 +487,152 @@ impl Register {
 	///
 	/// [`Record::keep`](struct.Record.html#method.keep) doesn't do
 	/// anything useful on that handle.
-	pub fn get_default_txt_record(&self) -> ::Record {
-		super::new_record(self.0.service().get_default_txt_record())
+	pub fn get_default_txt_record(&self) -> crate::Record {
+		self.records.default_txt(self.future().service().get_default_txt_record())
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.future().stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.future().as_raw()
+	}
+}
+
+impl Register {
+	/// Start building a registration for a service of type `reg_type`
+	/// listening on `port`
+	///
+	/// All other parameters of [`register`](fn.register.html) are
+	/// optional and default to the daemon's choice.
+	///
+	/// Also see [`ServiceType::register`](struct.ServiceType.html#method.register).
+	pub fn builder(reg_type: &str, port: u16) -> RegisterBuilder {
+		RegisterBuilder {
+			flags: RegisterFlags::none(),
+			interface: Interface::Any,
+			name: None,
+			reg_type: reg_type.to_string(),
+			subtypes: Vec::new(),
+			domain: None,
+			host: None,
+			port,
+			txt: Vec::new(),
+			allow_placeholder: false,
+		}
+	}
+
+	/// Reserve the name `name` for a service of type `reg_type` without
+	/// advertising the service
+	///
+	/// Registers with port 0: browsing doesn't find the service, but
+	/// others can't register the name while the registration is alive.
+	/// [`Registration::update_port`](struct.Registration.html#method.update_port)
+	/// publishes the service later under the reserved name.
+	///
+	/// See [`DNSServiceRegister`](https://developer.apple.com/documentation/dnssd/1804733-dnsserviceregister).
+	pub fn placeholder(name: &str, reg_type: &str) -> io::Result<Register> {
+		Register::builder(reg_type, 0).name(name).allow_placeholder().start()
+	}
+}
+
+/// Builder for service registrations
+///
+/// See [`Register::builder`](struct.Register.html#method.builder).
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub struct RegisterBuilder {
+	flags: RegisterFlags,
+	pub(crate) interface: Interface,
+	name: Option<String>,
+	reg_type: String,
+	subtypes: Vec<String>,
+	domain: Option<String>,
+	host: Option<String>,
+	port: u16,
+	txt: Vec<u8>,
+	allow_placeholder: bool,
+}
+
+impl RegisterBuilder {
+	/// Set registration flags (default none)
+	pub fn flags(mut self, flags: RegisterFlags) -> Self {
+		self.flags = flags;
+		self
+	}
+
+	/// Set interface to register on (default `Interface::Any`)
+	pub fn interface(mut self, interface: Interface) -> Self {
+		self.interface = interface;
+		self
+	}
+
+	/// Set service name (default: computer name)
+	pub fn name(mut self, name: &str) -> Self {
+		self.name = Some(name.to_string());
+		self
+	}
+
+	/// Add subtype to register the service under
+	pub fn subtype(mut self, subtype: &str) -> Self {
+		self.subtypes.push(subtype.to_string());
+		self
+	}
+
+	/// Set domain to register in (default: all default domains)
+	pub fn domain(mut self, domain: &str) -> Self {
+		self.domain = Some(domain.to_string());
+		self
+	}
+
+	/// Set host the service is running on (default: this machine)
+	///
+	/// The host name must already have address records (e.g. through
+	/// [`Connection::register_host_record`](struct.Connection.html#method.register_host_record));
+	/// also see [`start_proxy`](#method.start_proxy).
+	pub fn host(mut self, host: &str) -> Self {
+		self.host = Some(host.to_string());
+		self
+	}
+
+	/// Set raw TXT record data (default empty)
+	pub fn txt(mut self, txt: &[u8]) -> Self {
+		self.txt = txt.to_vec();
+		self
+	}
+
+	/// Allow port 0, which only reserves the name without advertising
+	/// the service
+	///
+	/// See [`Register::placeholder`](struct.Register.html#method.placeholder).
+	pub fn allow_placeholder(mut self) -> Self {
+		self.allow_placeholder = true;
+		self
+	}
+
+	/// Start the registration
+	///
+	/// See [`register`](fn.register.html).
+	pub fn start(&self) -> io::Result<Register> {
+		let subtypes: Vec<&str> = self.subtypes.iter().map(String::as_str).collect();
+		start_register(
+			self.flags,
+			self.interface,
+			self.name.as_deref(),
+			&self.reg_type,
+			&subtypes,
+			self.domain.as_deref(),
+			self.host.as_deref(),
+			self.port,
+			&self.txt,
+			self.allow_placeholder
+		)
 	}
 }
 
@@ -198,16 +640,31 @@ impl Registration {
 	/// See [`DNSServiceAddRecord`](https://developer.apple.com/documentation/dnssd/1804730-dnsserviceaddrecord)
 	pub fn add_raw_record(
 		&self,
-		rr_type: u16,
+		rr_type: RecordType,
 		rdata: &[u8],
 		ttl: u32
-	) -> io::Result<::Record> {
-		Ok(super::new_record(self.0.service().add_record(
+	) -> io::Result<crate::Record> {
+		let record = self.stream.try_service()?.add_record(
 			0, /* no flags */
-			rr_type,
+			rr_type.into(),
 			rdata,
 			ttl
-		)?))
+		)?;
+		Ok(self.records.added(record, rdata, ttl))
+	}
+
+	/// Add record with typed data to the registered service
+	///
+	/// `data` must be of type `rr_type`.
+	///
+	/// See [`DNSServiceAddRecord`](https://developer.apple.com/documentation/dnssd/1804730-dnsserviceaddrecord)
+	pub fn add_record(
+		&self,
+		rr_type: RecordType,
+		data: &RecordData,
+		ttl: u32
+	) -> io::Result<crate::Record> {
+		self.add_raw_record(rr_type, &typed_rdata(rr_type, data)?, ttl)
 	}
 
 	/// Get [`Record`](struct.Record.html) handle for default TXT record
@@ -215,7 +672,55 @@ impl Registration {
 	///
 	/// [`Record::keep`](struct.Record.html#method.keep) doesn't do
 	/// anything useful on that handle.
-	pub fn get_default_txt_record(&self) -> ::Record {
-		super::new_record(self.0.service().get_default_txt_record())
+	pub fn get_default_txt_record(&self) -> crate::Record {
+		self.records.default_txt(self.stream.service().get_default_txt_record())
+	}
+
+	/// Replace the TXT record of the service (e.g. to publish changed
+	/// state)
+	///
+	/// Fails for backends without a `DNSServiceRef`.
+	///
+	/// See [`DNSServiceUpdateRecord`](https://developer.apple.com/documentation/dnssd/1804739-dnsserviceupdaterecord).
+	pub fn set_txt(&self, txt: &crate::TxtRecord) -> io::Result<()> {
+		let record = self.records.default_txt(self.stream.try_service()?.get_default_txt_record());
+		record.update_raw_record(&txt.to_bytes(), 0 /* default ttl */)
+	}
+
+	/// Register the service again listening on another port
+	///
+	/// The service keeps its current name and TXT record; added records
+	/// (including [`kept`](struct.Record.html#method.keep) ones) move to
+	/// the new registration, and existing [`Record`](struct.Record.html)
+	/// and [`StopHandle`](struct.StopHandle.html) handles stay valid.
+	/// The result of the new registration is reported through
+	/// [`events`](#method.events).
+	///
+	/// Also advertises a [`placeholder`](struct.Register.html#method.placeholder)
+	/// registration under its reserved name; port 0 is rejected.
+	///
+	/// The old registration is removed first; if the new one fails the
+	/// service isn't published anymore.
+	pub fn update_port(&mut self, port: u16) -> io::Result<()> {
+		let restart = self.restart.clone().ok_or_else(|| {
+			io::Error::new(io::ErrorKind::Unsupported, "registration can't be restarted")
+		})?;
+		// checked before removing the old registration
+		check_port(port, false)?;
+		if self.stream.stop_handle().is_stopped() || self.termination_reason().is_some() {
+			return Err(io::Error::new(io::ErrorKind::NotConnected, "registration ended"));
+		}
+		// the records hold the old service, and the name is only free
+		// once it is released
+		self.records.detach();
+		self.stream.release();
+		let txt = self.records.txt();
+		let register = restart(&self.result.name, port, txt.as_deref())?;
+		let stream = register.future.expect("new registration").into_stream();
+		if let Ok(service) = stream.try_service() {
+			self.records.attach(&service)?;
+		}
+		self.stream.replace_source(stream);
+		Ok(())
 	}
 }
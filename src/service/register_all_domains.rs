@@ -0,0 +1,197 @@
+use futures::{self,FutureExt,StreamExt};
+use std::collections::{HashMap,VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::domain_name::DomainName;
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	enumerate_domains,
+	Enumerate,
+	EnumeratedFlag,
+	EnumerateDomains,
+	Register,
+	RegisterBuilder,
+	RegisterResult,
+	Registration,
+};
+
+/// Status of the registration in a single domain
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub enum DomainStatus {
+	/// Waiting for the daemon to confirm the registration
+	Pending,
+	/// The service is registered
+	Registered,
+	/// The registration failed (or was stopped); the domain is not
+	/// retried until it is removed and added again
+	Failed,
+}
+
+/// Event of [`RegisterAllDomains`](struct.RegisterAllDomains.html)
+#[derive(Debug)]
+pub enum DomainRegistrationEvent {
+	/// The service was registered in a domain (or renamed there)
+	Registered(RegisterResult),
+	/// Registering the service in the domain failed
+	Failed(DomainName, Error),
+	/// The domain was removed and the registration in it dropped
+	Removed(DomainName),
+}
+
+enum State {
+	Pending(Register),
+	Registered(Registration),
+	Failed,
+}
+
+struct Domain {
+	// number of interfaces the domain was enumerated on
+	count: usize,
+	state: State,
+}
+
+impl Domain {
+	fn status(&self) -> DomainStatus {
+		match self.state {
+			State::Pending(_) => DomainStatus::Pending,
+			State::Registered(_) => DomainStatus::Registered,
+			State::Failed => DomainStatus::Failed,
+		}
+	}
+
+	fn poll(&mut self, domain: &DomainName, cx: &mut Context, events: &mut VecDeque<DomainRegistrationEvent>) {
+		loop {
+			match self.state {
+				State::Pending(ref mut register) => match register.poll_unpin(cx) {
+					Poll::Pending => return,
+					Poll::Ready(Ok((registration, result))) => {
+						self.state = State::Registered(registration);
+						events.push_back(DomainRegistrationEvent::Registered(result));
+					},
+					Poll::Ready(Err(e)) => {
+						self.state = State::Failed;
+						events.push_back(DomainRegistrationEvent::Failed(domain.clone(), e));
+					},
+				},
+				State::Registered(ref mut registration) => match registration.events().poll_next_unpin(cx) {
+					Poll::Pending => return,
+					Poll::Ready(Some(Ok(result))) => {
+						events.push_back(DomainRegistrationEvent::Registered(result));
+					},
+					Poll::Ready(Some(Err(e))) => {
+						self.state = State::Failed;
+						events.push_back(DomainRegistrationEvent::Failed(domain.clone(), e));
+					},
+					Poll::Ready(None) => self.state = State::Failed,
+				},
+				State::Failed => return,
+			}
+		}
+	}
+}
+
+/// Registration of a service in all registration domains
+///
+/// Stream of [`DomainRegistrationEvent`](enum.DomainRegistrationEvent.html)s;
+/// must be polled to follow domain changes and to complete the
+/// registrations.  Errors enumerating the domains are passed through
+/// without ending the stream.
+///
+/// Dropping it removes all registrations.
+///
+/// See [`register_all_domains`](fn.register_all_domains.html).
+pub struct RegisterAllDomains {
+	builder: RegisterBuilder,
+	enumerate: EnumerateDomains,
+	domains: HashMap<DomainName, Domain>,
+	events: VecDeque<DomainRegistrationEvent>,
+}
+
+impl RegisterAllDomains {
+	/// Current status per domain
+	pub fn status(&self) -> impl Iterator<Item = (&DomainName, DomainStatus)> + '_ {
+		self.domains.iter().map(|(name, domain)| (name, domain.status()))
+	}
+
+	/// Registration in the given domain (if registered)
+	pub fn registration(&self, domain: &str) -> Option<&Registration> {
+		let domain = DomainName::new(domain).ok()?;
+		match self.domains.get(&domain)?.state {
+			State::Registered(ref registration) => Some(registration),
+			_ => None,
+		}
+	}
+
+	// returns errors from enumerating
+	fn poll_enumerate(&mut self, cx: &mut Context) -> Option<Error> {
+		loop {
+			let result = match self.enumerate.poll_next_unpin(cx) {
+				// sender is kept alive by the service; only ends when
+				// stopped
+				Poll::Pending | Poll::Ready(None) => return None,
+				Poll::Ready(Some(Err(e))) => return Some(e),
+				Poll::Ready(Some(Ok(result))) => result,
+			};
+
+			let name = result.domain_name();
+			if result.flags & EnumeratedFlag::Add {
+				if let Some(domain) = self.domains.get_mut(&name) {
+					domain.count += 1;
+					continue;
+				}
+				let state = match self.builder.clone().domain(&name).start() {
+					Ok(register) => State::Pending(register),
+					Err(e) => {
+						self.events.push_back(DomainRegistrationEvent::Failed(name.clone(), e.into()));
+						State::Failed
+					},
+				};
+				self.domains.insert(name, Domain { count: 1, state });
+			} else if let Some(domain) = self.domains.get_mut(&name) {
+				domain.count -= 1;
+				if domain.count == 0 {
+					self.domains.remove(&name);
+					self.events.push_back(DomainRegistrationEvent::Removed(name));
+				}
+			}
+		}
+	}
+}
+
+impl futures::Stream for RegisterAllDomains {
+	type Item = Result<DomainRegistrationEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		if let Some(e) = this.poll_enumerate(cx) {
+			return Poll::Ready(Some(Err(e)));
+		}
+
+		for (name, domain) in this.domains.iter_mut() {
+			domain.poll(name, cx, &mut this.events);
+		}
+
+		match this.events.pop_front() {
+			Some(event) => Poll::Ready(Some(Ok(event))),
+			None => Poll::Pending,
+		}
+	}
+}
+
+/// Register a service in all registration domains
+///
+/// Enumerates the [registration domains](enum.Enumerate.html#variant.RegistrationDomains)
+/// and registers the service described by `builder` in each of them
+/// (the domain of `builder` is ignored); registrations are added and
+/// removed as domains come and go.
+pub fn register_all_domains(builder: &RegisterBuilder) -> io::Result<RegisterAllDomains> {
+	Ok(RegisterAllDomains {
+		builder: builder.clone(),
+		enumerate: enumerate_domains(Enumerate::RegistrationDomains, Interface::Any)?,
+		domains: HashMap::new(),
+		events: VecDeque::new(),
+	})
+}
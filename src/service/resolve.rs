@@ -1,51 +1,174 @@
-use futures::sync::mpsc;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::{self,StreamExt};
+use std::fmt;
 use std::os::raw::{c_void,c_char};
 use std::io;
-use tokio_core::reactor::{Handle,Remote};
+use std::pin::Pin;
+use std::task::{Context,Poll};
 
-use cstr;
-use error::Error;
-use evented::EventedDNSService;
-use ffi;
-use interface::Interface;
-use raw;
-use remote::GetRemote;
-use stream::ServiceStream;
+use crate::cstr;
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::interface::Interface;
+use crate::raw;
+use crate::stream::ServiceStream;
+
+/// Set of [`ResolveFlag`](enum.ResolveFlag.html)s
+///
+/// Flags and sets can be combined with bitor (`|`), and bitand (`&`)
+/// can be used to test whether a flag is part of a set.
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct ResolveFlags(u8);
+
+/// Flags used to resolve a service
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[repr(u8)]
+pub enum ResolveFlag {
+	/// Wake up the host of the service (through a sleep proxy) if it is
+	/// sleeping
+	///
+	/// See [`kDNSServiceFlagsWakeOnResolve`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagswakeonresolve).
+	WakeOnResolve = 0,
+
+	/// Mark traffic of the operation as background (low priority) traffic
+	///
+	/// See [`kDNSServiceFlagsBackgroundTrafficClass`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsbackgroundtrafficclass).
+	BackgroundTrafficClass,
+
+	/// Resolve through multicast even if the name looks like a unicast
+	/// name (e.g. dotted instance names or domains other than
+	/// `local.`); useful if the service is known to be link-local
+	///
+	/// See [`kDNSServiceFlagsForceMulticast`](https://developer.apple.com/documentation/dnssd/1823436-anonymous/kdnsserviceflagsforcemulticast).
+	ForceMulticast,
+}
+
+flags_ops!{ResolveFlags: u8: ResolveFlag:
+	WakeOnResolve,
+	BackgroundTrafficClass,
+	ForceMulticast,
+}
+
+flag_mapping!{ResolveFlags: ResolveFlag => ffi::DNSServiceFlags:
+	WakeOnResolve => ffi::FLAGS_WAKE_ON_RESOLVE,
+	BackgroundTrafficClass => ffi::FLAGS_BACKGROUND_TRAFFIC_CLASS,
+	ForceMulticast => ffi::FLAGS_FORCE_MULTICAST,
+}
 
 /// Pending resolve request
-pub struct Resolve(ServiceStream<ResolveResult>);
+///
+/// Use [`TimeoutTrait::timeout`](trait.TimeoutTrait.html#method.timeout)
+/// to end the stream when no result arrives in time.
+pub struct Resolve(pub(crate) ServiceStream<ResolveResult>);
+
+impl Resolve {
+	/// Set number of results to queue (default 64)
+	///
+	/// While the queue is full (with the default
+	/// [`QueuePolicy`](enum.QueuePolicy.html)) no further results are
+	/// processed, which leaves them buffered in the daemon connection
+	/// until the stream is polled again.
+	pub fn queue_capacity(mut self, capacity: usize) -> Self {
+		self.0.set_capacity(capacity);
+		self
+	}
+
+	/// Set what happens when the result queue is full (default
+	/// [`QueuePolicy::Block`](enum.QueuePolicy.html#variant.Block))
+	pub fn queue_policy(mut self, policy: crate::QueuePolicy) -> Self {
+		self.0.set_policy(policy);
+		self
+	}
+
+	/// Why the operation ended (`None` while it is running)
+	pub fn termination_reason(&self) -> Option<crate::TerminationReason> {
+		self.0.termination_reason()
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.0.stop_handle()
+	}
+
+	/// Underlying `DNSServiceRef` (`None` for other backends)
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw).
+	pub fn as_raw(&self) -> Option<crate::DNSServiceRef> {
+		self.0.as_raw()
+	}
+}
 
 impl futures::Stream for Resolve {
-	type Item = ResolveResult;
-	type Error = io::Error;
+	type Item = Result<ResolveResult, Error>;
 
-	fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-		self.0.poll()
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		self.0.poll_next_unpin(cx)
 	}
 }
 
-impl GetRemote for Resolve {
-	fn remote(&self) -> &Remote {
-		self.0.remote()
+impl futures::stream::FusedStream for Resolve {
+	fn is_terminated(&self) -> bool {
+		self.0.is_terminated()
 	}
 }
 
+
 /// Resolve result
 ///
 /// See [`DNSServiceResolveReply`](https://developer.apple.com/documentation/dnssd/dnsserviceresolvereply).
 #[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResolveResult{
-	///
+	/// Interface the service was resolved on.
 	pub interface: Interface,
-	///
+	/// Full name of the service
 	pub fullname: String,
-	///
+	/// Name of the host the service is running on
 	pub host_target: String,
-	///
+	/// Port the service is listening on
 	pub port: u16,
-	///
+	/// Raw TXT record data
 	pub txt: Vec<u8>,
+	/// When the result was received
+	#[cfg_attr(feature = "serde", serde(skip))]
+	pub received_at: crate::Timestamp,
+}
+
+impl ResolveResult {
+	/// Iterate over the entries of the TXT record
+	pub fn txt_entries(&self) -> crate::TxtEntries<'_> {
+		crate::TxtEntries::new(&self.txt)
+	}
+
+	/// Value for the key in the TXT record
+	///
+	/// Returns `None` if the key isn't present, and `Some(None)` if the
+	/// key is present without a value.
+	pub fn txt_get(&self, key: &str) -> Option<Option<&[u8]>> {
+		self.txt_entries().get(key)
+	}
+
+	/// Look up the addresses of the host the service is running on.
+	///
+	/// Not supported by avahi.
+	pub fn get_addr_info(&self) -> io::Result<crate::GetAddrInfo> {
+		crate::get_addr_info(
+			crate::GetAddrInfoFlags::none(),
+			self.interface,
+			crate::Protocols::none(),
+			&self.host_target
+		)
+	}
+}
+
+// e.g. `My\032Printer._ipp._tcp.local. at printer.local.:631 on eth0`
+impl fmt::Display for ResolveResult {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} at {}:{} on {}", self.fullname, self.host_target, self.port, self.interface)
+	}
 }
 
 extern "C" fn resolve_callback(
@@ -60,10 +183,11 @@ extern "C" fn resolve_callback(
 	txt_record: *const u8,
 	context: *mut c_void
 ) {
-	let sender = context as *mut mpsc::UnboundedSender<io::Result<ResolveResult>>;
-	let sender : &mpsc::UnboundedSender<io::Result<ResolveResult>> = unsafe { &*sender };
+	trace_callback!("resolve", interface_index, error_code, port);
+	let sender = context as *mut mpsc::UnboundedSender<Result<ResolveResult, Error>>;
+	let sender : &mpsc::UnboundedSender<Result<ResolveResult, Error>> = unsafe { &*sender };
 
-	let data = Error::from(error_code).map_err(io::Error::from).and_then(|_| {
+	let data = Error::from(error_code).and_then(|_| {
 		let fullname = unsafe { cstr::from_cstr(fullname) }?;
 		let host_target = unsafe { cstr::from_cstr(host_target) }?;
 		let txt = unsafe { ::std::slice::from_raw_parts(txt_record, txt_len as usize) };
@@ -74,38 +198,92 @@ extern "C" fn resolve_callback(
 			host_target: host_target.to_string(),
 			port: u16::from_be(port),
 			txt: txt.into(),
+			received_at: crate::Timestamp::now(),
 		})
 	});
 
-	sender.send(data).unwrap();
+	sender.unbounded_send(data).unwrap();
 }
 
 /// Find hostname and port (and more) for a service
 ///
+/// Flags are ignored by avahi.
+///
 /// See [`DNSServiceResolve`](https://developer.apple.com/documentation/dnssd/1804744-dnsserviceresolve).
 pub fn resolve(
+	flags: ResolveFlags,
 	interface: Interface,
 	name: &str,
 	reg_type: &str,
-	domain: &str,
-	handle: &Handle
+	domain: &str
 ) -> io::Result<Resolve> {
+	#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+	{
+		if let Some(stream) = crate::avahi_dbus::resolve(interface, name, reg_type, domain)? {
+			return Ok(Resolve(stream));
+		}
+	}
+	#[cfg(all(windows, feature = "windows-dnsapi"))]
+	{
+		if let Some(stream) = crate::windows_dnsapi::resolve(interface, name, reg_type, domain)? {
+			return Ok(Resolve(stream));
+		}
+	}
+
+	#[cfg(all(unix, feature = "mdns"))]
+	let fallback = move || crate::mdns::resolve(interface, name, reg_type, domain);
+
 	let name = cstr::CStr::from(&name)?;
 	let reg_type = cstr::CStr::from(&reg_type)?;
 	let domain = cstr::CStr::from(&domain)?;
 
-	Ok(Resolve(ServiceStream::new(move |sender|
+	let stream = ServiceStream::new(move |sender|
 		EventedDNSService::new(
 			raw::DNSService::resolve(
-				0, /* no flags */
+				None, /* own connection */
+				flags.into(),
 				interface.into_raw(),
 				&name,
 				&reg_type,
 				&domain,
 				Some(resolve_callback),
 				sender as *mut c_void,
-			)?,
-			handle
+			)?
 		)
-	)?))
+	);
+	#[cfg(all(unix, feature = "mdns"))]
+	let stream = crate::mdns::or_fallback(stream, fallback);
+
+	Ok(Resolve(stream?))
+}
+
+impl crate::SharedConnection {
+	/// Resolve a service on the shared connection
+	///
+	/// See [`resolve`](fn.resolve.html).
+	pub fn resolve(
+		&self,
+		flags: ResolveFlags,
+		interface: Interface,
+		name: &str,
+		reg_type: &str,
+		domain: &str
+	) -> io::Result<Resolve> {
+		let name = cstr::CStr::from(&name)?;
+		let reg_type = cstr::CStr::from(&reg_type)?;
+		let domain = cstr::CStr::from(&domain)?;
+
+		Ok(Resolve(ServiceStream::new_shared(self.evented(), move |sender|
+			Ok(raw::DNSService::resolve(
+				Some(self.evented().service()),
+				flags.into(),
+				interface.into_raw(),
+				&name,
+				&reg_type,
+				&domain,
+				Some(resolve_callback),
+				sender as *mut c_void,
+			)?)
+		)?))
+	}
 }
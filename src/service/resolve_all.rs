@@ -0,0 +1,66 @@
+use futures::{self,FutureExt,StreamExt};
+use futures_timer::Delay;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use crate::service::{discover,Discover,DiscoveredService,ServiceInfo};
+
+/// Pending listing of services
+///
+/// See [`resolve_all`](fn.resolve_all.html).
+pub struct ResolveAll {
+	discover: Option<Discover>,
+	timer: Delay,
+	services: Vec<ServiceInfo>,
+}
+
+impl ResolveAll {
+	fn add(&mut self, found: DiscoveredService) {
+		let found = ServiceInfo::from(found);
+		match self.services.iter_mut().find(|info| info.is_same_service(&found)) {
+			Some(info) => info.merge(found),
+			None => self.services.push(found),
+		}
+	}
+}
+
+impl Future for ResolveAll {
+	type Output = Vec<ServiceInfo>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = &mut *self;
+		if this.timer.poll_unpin(cx).is_ready() {
+			this.discover = None;
+		}
+		while let Some(discover) = this.discover.as_mut() {
+			match discover.poll_next_unpin(cx) {
+				Poll::Pending => return Poll::Pending,
+				Poll::Ready(None) => this.discover = None,
+				Poll::Ready(Some(Ok(found))) => this.add(found),
+				// services which couldn't be resolved are skipped
+				Poll::Ready(Some(Err(_))) => (),
+			}
+		}
+		Poll::Ready(std::mem::take(&mut this.services))
+	}
+}
+
+/// List the services of a type visible within `timeout`
+///
+/// Browses for `reg_type` (e.g. `"_http._tcp"`), resolves the found
+/// services and looks up the addresses of their hosts (see
+/// [`discover`](fn.discover.html)); completes after `timeout` with all
+/// services that were completely resolved by then.  Services found on
+/// multiple interfaces are listed once.
+///
+/// Looking up addresses is not supported by avahi.
+pub fn resolve_all(reg_type: &str, timeout: Duration) -> io::Result<ResolveAll> {
+	Ok(ResolveAll {
+		discover: Some(discover(reg_type)?),
+		timer: Delay::new(timeout),
+		services: Vec::new(),
+	})
+}
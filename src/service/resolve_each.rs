@@ -0,0 +1,101 @@
+use futures::{self,StreamExt};
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::error::Error;
+use crate::service::{Browse,BrowseResult,BrowsedFlag,Resolve,ResolveResult};
+
+/// Resolve every service found by a [`Browse`](struct.Browse.html)
+///
+/// Yields the browse result together with the first resolve result for
+/// each added service.  Resolving a service stops after the first
+/// result or when the service is removed before it was resolved.
+///
+/// Errors from resolving single services are passed through; the
+/// stream only ends when the underlying browse request ends and all
+/// pending resolve requests completed.
+pub struct ResolveEach {
+	browse: Option<Browse>,
+	pending: Vec<(BrowseResult, Resolve)>,
+}
+
+impl Browse {
+	/// Resolve all services found while browsing
+	///
+	/// See [`ResolveEach`](struct.ResolveEach.html).
+	pub fn resolve_each(self) -> ResolveEach {
+		ResolveEach {
+			browse: Some(self),
+			pending: Vec::new(),
+		}
+	}
+}
+
+fn same_service(a: &BrowseResult, b: &BrowseResult) -> bool {
+	a.interface == b.interface
+		&& a.service_name == b.service_name
+		&& a.reg_type == b.reg_type
+		&& a.domain == b.domain
+}
+
+impl ResolveEach {
+	// returns errors (browse results and failed resolve requests); `None`
+	// if browsing is pending or finished.
+	fn poll_browse(&mut self, cx: &mut Context) -> Option<Error> {
+		while let Some(browse) = self.browse.as_mut() {
+			let result = match browse.poll_next_unpin(cx) {
+				Poll::Pending => return None,
+				Poll::Ready(None) => {
+					self.browse = None;
+					return None;
+				},
+				Poll::Ready(Some(Err(e))) => return Some(e),
+				Poll::Ready(Some(Ok(result))) => result,
+			};
+
+			self.pending.retain(|(r, _)| !same_service(r, &result));
+			if result.flags & BrowsedFlag::Add {
+				match result.resolve() {
+					Ok(resolve) => self.pending.push((result, resolve)),
+					Err(e) => return Some(e.into()),
+				}
+			}
+		}
+		None
+	}
+}
+
+impl futures::Stream for ResolveEach {
+	type Item = Result<(BrowseResult, ResolveResult), Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		if let Some(e) = this.poll_browse(cx) {
+			return Poll::Ready(Some(Err(e)));
+		}
+
+		let mut ndx = 0;
+		while ndx < this.pending.len() {
+			match this.pending[ndx].1.poll_next_unpin(cx) {
+				Poll::Pending => ndx += 1,
+				Poll::Ready(None) => {
+					this.pending.swap_remove(ndx);
+				},
+				Poll::Ready(Some(Err(e))) => {
+					this.pending.swap_remove(ndx);
+					return Poll::Ready(Some(Err(e)));
+				},
+				Poll::Ready(Some(Ok(resolved))) => {
+					let (browsed, _) = this.pending.swap_remove(ndx);
+					return Poll::Ready(Some(Ok((browsed, resolved))));
+				},
+			}
+		}
+
+		if this.browse.is_none() && this.pending.is_empty() {
+			Poll::Ready(None)
+		} else {
+			Poll::Pending
+		}
+	}
+}
@@ -0,0 +1,127 @@
+use futures::{self,StreamExt};
+use std::collections::{HashMap,HashSet};
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::record_data::RecordData;
+use crate::service::{
+	query_record,
+	Protocol,
+	Protocols,
+	QueriedRecordFlag,
+	QueryRecord,
+	QueryRecordFlags,
+	QueryRecordResult,
+};
+
+/// Change of the addresses of a host
+///
+/// See [`resolve_host_addresses`](fn.resolve_host_addresses.html).
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub enum HostAddressEvent {
+	/// The host has a new address
+	Added(IpAddr),
+	/// The address is gone
+	Removed(IpAddr),
+}
+
+/// Addresses of a host
+///
+/// See [`resolve_host_addresses`](fn.resolve_host_addresses.html).
+pub struct HostAddresses {
+	// A and AAAA queries; `None` once ended
+	queries: [Option<QueryRecord>; 2],
+	// interfaces each address is currently known on
+	addresses: HashMap<IpAddr, HashSet<Interface>>,
+}
+
+impl HostAddresses {
+	/// Currently known addresses
+	pub fn addresses(&self) -> impl Iterator<Item = IpAddr> + '_ {
+		self.addresses.keys().cloned()
+	}
+
+	fn handle(&mut self, result: QueryRecordResult) -> Option<Result<HostAddressEvent, Error>> {
+		let address = match result.parsed() {
+			Ok(RecordData::A(addr)) => IpAddr::V4(addr),
+			Ok(RecordData::AAAA(addr)) => IpAddr::V6(addr),
+			Ok(_) => return None,
+			Err(e) => return Some(Err(e.into())),
+		};
+		if result.flags & QueriedRecordFlag::Add {
+			let interfaces = self.addresses.entry(address).or_default();
+			let first = interfaces.is_empty();
+			interfaces.insert(result.interface);
+			if first {
+				return Some(Ok(HostAddressEvent::Added(address)));
+			}
+		} else if let Some(interfaces) = self.addresses.get_mut(&address) {
+			interfaces.remove(&result.interface);
+			if interfaces.is_empty() {
+				self.addresses.remove(&address);
+				return Some(Ok(HostAddressEvent::Removed(address)));
+			}
+		}
+		None
+	}
+}
+
+impl futures::Stream for HostAddresses {
+	type Item = Result<HostAddressEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			let mut progress = false;
+			for ndx in 0..this.queries.len() {
+				let query = match this.queries[ndx].as_mut() {
+					Some(query) => query,
+					None => continue,
+				};
+				match query.poll_next_unpin(cx) {
+					Poll::Pending => (),
+					Poll::Ready(None) => this.queries[ndx] = None,
+					Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+					Poll::Ready(Some(Ok(result))) => {
+						progress = true;
+						if let Some(item) = this.handle(result) {
+							return Poll::Ready(Some(item));
+						}
+					},
+				}
+			}
+			if this.queries.iter().all(Option::is_none) {
+				return Poll::Ready(None);
+			}
+			if !progress {
+				return Poll::Pending;
+			}
+		}
+	}
+}
+
+/// Look up the IPv4 and/or IPv6 addresses of a host
+///
+/// Queries `A` and/or `AAAA` records (as selected by `protocol`; an
+/// empty set selects both) of `hostname` concurrently on all interfaces
+/// (unlike [`get_addr_info`](fn.get_addr_info.html) this also works
+/// with avahi) and yields each address once when it is first found and
+/// once when it is gone on all interfaces.
+pub fn resolve_host_addresses(hostname: &str, protocol: Protocols) -> io::Result<HostAddresses> {
+	let both = protocol == Protocols::none();
+	let query = |selected: Protocol, rr_type| -> io::Result<Option<QueryRecord>> {
+		if !both && !(protocol & selected) {
+			return Ok(None);
+		}
+		Ok(Some(query_record(QueryRecordFlags::none(), Interface::Any, hostname, rr_type, RecordClass::IN)?))
+	};
+	Ok(HostAddresses {
+		queries: [query(Protocol::IPv4, RecordType::A)?, query(Protocol::IPv6, RecordType::AAAA)?],
+		addresses: HashMap::new(),
+	})
+}
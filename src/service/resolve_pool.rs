@@ -0,0 +1,170 @@
+use futures::{self,FutureExt,StreamExt};
+use futures_timer::Delay;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::service::{Browse,BrowseResult,BrowsedFlag,Resolve,ResolveResult};
+
+/// Resolve services found by a [`Browse`](struct.Browse.html) with a
+/// limited number of concurrent resolve requests
+///
+/// Like [`ResolveEach`](struct.ResolveEach.html), but only runs up to
+/// `max_in_flight` resolve requests at once (each of them uses its own
+/// daemon connection); further services are queued until a running
+/// request finishes.  Services removed before they were resolved are
+/// dropped from the queue.
+///
+/// Results are yielded in the order the resolve requests complete.
+pub struct ResolvePool {
+	browse: Option<Browse>,
+	queue: VecDeque<BrowseResult>,
+	in_flight: Vec<(BrowseResult, Resolve, Option<Delay>)>,
+	max_in_flight: usize,
+	resolve_timeout: Option<Duration>,
+}
+
+impl Browse {
+	/// Resolve all services found while browsing, with at most
+	/// `max_in_flight` concurrent resolve requests
+	///
+	/// A limit of `0` is treated as `1`.
+	///
+	/// See [`ResolvePool`](struct.ResolvePool.html).
+	pub fn resolve_pool(self, max_in_flight: usize) -> ResolvePool {
+		ResolvePool {
+			browse: Some(self),
+			queue: VecDeque::new(),
+			in_flight: Vec::new(),
+			max_in_flight: max_in_flight.max(1),
+			resolve_timeout: None,
+		}
+	}
+}
+
+fn same_service(a: &BrowseResult, b: &BrowseResult) -> bool {
+	a.interface == b.interface
+		&& a.service_name == b.service_name
+		&& a.reg_type == b.reg_type
+		&& a.domain == b.domain
+}
+
+impl ResolvePool {
+	/// Give up resolving a service after the given duration (default:
+	/// never)
+	///
+	/// Otherwise services which never resolve (e.g. because the host
+	/// went away without a goodbye) block a slot until they are removed.
+	pub fn resolve_timeout(mut self, timeout: Duration) -> Self {
+		self.resolve_timeout = Some(timeout);
+		self
+	}
+
+	/// Number of services waiting for a free slot
+	pub fn queued(&self) -> usize {
+		self.queue.len()
+	}
+
+	/// Number of running resolve requests
+	pub fn in_flight(&self) -> usize {
+		self.in_flight.len()
+	}
+
+	// returns errors from browsing; `None` if browsing is pending or
+	// finished.
+	fn poll_browse(&mut self, cx: &mut Context) -> Option<Error> {
+		while let Some(browse) = self.browse.as_mut() {
+			let result = match browse.poll_next_unpin(cx) {
+				Poll::Pending => return None,
+				Poll::Ready(None) => {
+					self.browse = None;
+					return None;
+				},
+				Poll::Ready(Some(Err(e))) => return Some(e),
+				Poll::Ready(Some(Ok(result))) => result,
+			};
+
+			self.queue.retain(|r| !same_service(r, &result));
+			self.in_flight.retain(|(r, _, _)| !same_service(r, &result));
+			if result.flags & BrowsedFlag::Add {
+				self.queue.push_back(result);
+			}
+		}
+		None
+	}
+
+	// start queued requests while slots are free; returns errors from
+	// starting a request.
+	fn start_queued(&mut self) -> Option<Error> {
+		while self.in_flight.len() < self.max_in_flight {
+			let browsed = self.queue.pop_front()?;
+			match browsed.resolve() {
+				Ok(resolve) => {
+					let timeout = self.resolve_timeout.map(Delay::new);
+					self.in_flight.push((browsed, resolve, timeout));
+				},
+				Err(e) => return Some(e.into()),
+			}
+		}
+		None
+	}
+}
+
+impl futures::Stream for ResolvePool {
+	type Item = Result<(BrowseResult, ResolveResult), Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		if let Some(e) = this.poll_browse(cx) {
+			return Poll::Ready(Some(Err(e)));
+		}
+
+		loop {
+			if let Some(e) = this.start_queued() {
+				return Poll::Ready(Some(Err(e)));
+			}
+
+			let mut finished = false;
+			let mut ndx = 0;
+			while ndx < this.in_flight.len() {
+				let (_, resolve, timeout) = &mut this.in_flight[ndx];
+				match resolve.poll_next_unpin(cx) {
+					Poll::Pending => {
+						let timed_out = timeout.as_mut().is_some_and(|t| t.poll_unpin(cx).is_ready());
+						if timed_out {
+							this.in_flight.swap_remove(ndx);
+							finished = true;
+						} else {
+							ndx += 1;
+						}
+					},
+					Poll::Ready(None) => {
+						this.in_flight.swap_remove(ndx);
+						finished = true;
+					},
+					Poll::Ready(Some(Err(e))) => {
+						this.in_flight.swap_remove(ndx);
+						return Poll::Ready(Some(Err(e)));
+					},
+					Poll::Ready(Some(Ok(resolved))) => {
+						let (browsed, _, _) = this.in_flight.swap_remove(ndx);
+						return Poll::Ready(Some(Ok((browsed, resolved))));
+					},
+				}
+			}
+
+			// freed slots might start (and complete) queued requests
+			if !finished || this.queue.is_empty() {
+				break;
+			}
+		}
+
+		if this.browse.is_none() && this.queue.is_empty() && this.in_flight.is_empty() {
+			Poll::Ready(None)
+		} else {
+			Poll::Pending
+		}
+	}
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::net::{IpAddr,SocketAddr};
+
+use crate::interface::Interface;
+use crate::service::{
+	BrowsedFlag,
+	BrowsedFlags,
+	BrowseResult,
+	DiscoveredService,
+	FullName,
+	ResolveResult,
+};
+use crate::txt_record::TxtRecord;
+
+/// Everything known about a service: where it is registered, how to
+/// reach it and its TXT record
+///
+/// Produced by [`resolve_all`](fn.resolve_all.html), or from a
+/// [`DiscoveredService`](struct.DiscoveredService.html); converts back
+/// into [`BrowseResult`](struct.BrowseResult.html)s and
+/// [`ResolveResult`](struct.ResolveResult.html)s.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub struct ServiceInfo {
+	/// Name of the service instance
+	pub name: String,
+	/// Type of the service (e.g. `_http._tcp.`)
+	pub service_type: String,
+	/// Domain the service was found in
+	pub domain: String,
+	/// Name of the host the service is running on
+	pub host: String,
+	/// Port the service is listening on
+	pub port: u16,
+	/// Addresses of the host
+	pub addresses: Vec<IpAddr>,
+	/// TXT record of the service
+	pub txt: TxtRecord,
+	/// Interfaces the service was found on
+	pub interfaces: Vec<Interface>,
+}
+
+impl ServiceInfo {
+	/// Whether both describe the same service instance (same name, type
+	/// and domain), e.g. found on different interfaces
+	pub fn is_same_service(&self, other: &ServiceInfo) -> bool {
+		self.name == other.name
+			&& self.service_type.eq_ignore_ascii_case(&other.service_type)
+			&& self.domain.eq_ignore_ascii_case(&other.domain)
+	}
+
+	/// Add interfaces and addresses of `other` (describing the same
+	/// service) which aren't known yet
+	pub fn merge(&mut self, other: ServiceInfo) {
+		for interface in other.interfaces {
+			if !self.interfaces.contains(&interface) {
+				self.interfaces.push(interface);
+			}
+		}
+		for address in other.addresses {
+			if !self.addresses.contains(&address) {
+				self.addresses.push(address);
+			}
+		}
+	}
+
+	/// Socket addresses (address and port) of the service
+	pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+		self.addresses.iter().map(|&address| SocketAddr::new(address, self.port)).collect()
+	}
+
+	/// Full name of the service (escaped)
+	///
+	/// See [`FullName::construct`](struct.FullName.html#method.construct).
+	pub fn fullname(&self) -> io::Result<String> {
+		FullName {
+			service: Some(&self.name),
+			reg_type: &self.service_type,
+			domain: &self.domain,
+		}.construct()
+	}
+
+	/// Browse results announcing the service, one per interface
+	pub fn browse_results(&self) -> Vec<BrowseResult> {
+		let count = self.interfaces.len();
+		self.interfaces.iter().enumerate().map(|(i, &interface)| {
+			let mut flags: BrowsedFlags = BrowsedFlag::Add.into();
+			if i + 1 < count {
+				flags |= BrowsedFlag::MoreComing;
+			}
+			BrowseResult {
+				flags,
+				interface,
+				service_name: self.name.clone(),
+				reg_type: self.service_type.clone(),
+				domain: self.domain.clone(),
+				received_at: crate::Timestamp::now(),
+			}
+		}).collect()
+	}
+
+	/// Resolve results for the service, one per interface
+	pub fn resolve_results(&self) -> io::Result<Vec<ResolveResult>> {
+		let fullname = self.fullname()?;
+		let txt = self.txt.to_bytes();
+		Ok(self.interfaces.iter().map(|&interface| ResolveResult {
+			interface,
+			fullname: fullname.clone(),
+			host_target: self.host.clone(),
+			port: self.port,
+			txt: txt.clone(),
+			received_at: crate::Timestamp::now(),
+		}).collect())
+	}
+}
+
+impl From<DiscoveredService> for ServiceInfo {
+	fn from(found: DiscoveredService) -> Self {
+		ServiceInfo {
+			name: found.service_name,
+			service_type: found.reg_type,
+			domain: found.domain,
+			host: found.host_target,
+			port: found.addresses.first().map_or(0, SocketAddr::port),
+			addresses: found.addresses.iter().map(SocketAddr::ip).collect(),
+			txt: found.txt,
+			interfaces: vec![found.interface],
+		}
+	}
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use crate::interface::Interface;
+use crate::service::{
+	browse,
+	subtype_label,
+	Browse,
+	BrowseFlags,
+	Register,
+	RegisterBuilder,
+};
+
+fn invalid_input(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// service names follow RFC 6335 section 5.1: 1-15 letters, digits
+// and hyphens, at least one letter, no hyphen at start or end and no
+// two hyphens in a row
+fn valid_service_name(name: &str) -> bool {
+	let bytes = name.as_bytes();
+	!bytes.is_empty()
+		&& bytes.len() <= 15
+		&& bytes.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'-')
+		&& bytes.iter().any(u8::is_ascii_alphabetic)
+		&& bytes[0] != b'-'
+		&& bytes[bytes.len() - 1] != b'-'
+		&& !name.contains("--")
+}
+
+fn valid_subtype(subtype: &str) -> bool {
+	!subtype.is_empty() && subtype.len() <= 63 && !subtype.contains(',') && !subtype.contains('.')
+}
+
+/// Service type like `_http._tcp`, optionally with subtypes
+///
+/// Parsed from the forms accepted by the C API: `_http._tcp`,
+/// `_http._tcp,_printer` (subtypes separated by commas) and
+/// `_printer._sub._http._tcp`.  Malformed types are rejected when
+/// parsing instead of by the daemon; a trailing dot is ignored.
+///
+/// Displays in the comma separated form.
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct ServiceType {
+	service: String,
+	protocol: String,
+	subtypes: Vec<String>,
+}
+
+impl ServiceType {
+	/// Create service type from service name (with or without leading
+	/// underscore, e.g. `"http"`) and protocol (`"tcp"` or `"udp"`)
+	pub fn new(service: &str, protocol: &str) -> io::Result<Self> {
+		let service = service.strip_prefix('_').unwrap_or(service);
+		if !valid_service_name(service) {
+			return Err(invalid_input("invalid service name in service type"));
+		}
+		let protocol = protocol.strip_prefix('_').unwrap_or(protocol);
+		let protocol = if protocol.eq_ignore_ascii_case("tcp") {
+			"_tcp"
+		} else if protocol.eq_ignore_ascii_case("udp") {
+			"_udp"
+		} else {
+			return Err(invalid_input("service type protocol must be _tcp or _udp"));
+		};
+		Ok(ServiceType {
+			service: format!("_{}", service),
+			protocol: protocol.to_string(),
+			subtypes: Vec::new(),
+		})
+	}
+
+	/// Add subtype (either as single label `"_printer"` or in the full
+	/// `"_printer._sub._http._tcp"` form)
+	pub fn with_subtype(mut self, subtype: &str) -> io::Result<Self> {
+		let label = subtype_label(subtype);
+		if !valid_subtype(label) {
+			return Err(invalid_input("invalid subtype"));
+		}
+		if !self.subtypes.iter().any(|s| s == label) {
+			self.subtypes.push(label.to_string());
+		}
+		Ok(self)
+	}
+
+	/// Service name with leading underscore, e.g. `"_http"`
+	pub fn service(&self) -> &str {
+		&self.service
+	}
+
+	/// `"_tcp"` or `"_udp"`
+	pub fn protocol(&self) -> &str {
+		&self.protocol
+	}
+
+	/// Subtypes (single labels)
+	pub fn subtypes(&self) -> &[String] {
+		&self.subtypes
+	}
+
+	/// Type without subtypes, e.g. `"_http._tcp"`
+	pub fn reg_type(&self) -> String {
+		format!("{}.{}", self.service, self.protocol)
+	}
+
+	/// Browse for services of this type
+	///
+	/// Browses for the subtype if there is one; fails if there are
+	/// multiple subtypes.
+	///
+	/// See [`browse`](fn.browse.html).
+	pub fn browse(&self, flags: BrowseFlags, interface: Interface, domain: Option<&str>) -> io::Result<Browse> {
+		let subtype = match self.subtypes.as_slice() {
+			[] => None,
+			[subtype] => Some(subtype.as_str()),
+			_ => return Err(invalid_input("can only browse for a single subtype")),
+		};
+		browse(flags, interface, &self.reg_type(), subtype, domain)
+	}
+
+	/// Start building a registration of a service of this type (with
+	/// all subtypes)
+	///
+	/// See [`Register::builder`](struct.Register.html#method.builder).
+	pub fn register(&self, port: u16) -> RegisterBuilder {
+		self.subtypes.iter().fold(
+			Register::builder(&self.reg_type(), port),
+			|builder, subtype| builder.subtype(subtype)
+		)
+	}
+}
+
+impl FromStr for ServiceType {
+	type Err = io::Error;
+
+	fn from_str(s: &str) -> io::Result<Self> {
+		let mut parts = s.split(',');
+		let base = parts.next().unwrap_or("");
+		let base = base.strip_suffix('.').unwrap_or(base);
+		let (subtype, base) = match base.find("._sub.") {
+			Some(pos) => (Some(&base[..pos]), &base[pos + 6..]),
+			None => (None, base),
+		};
+		let (service, protocol) = match base.split_once('.') {
+			Some((service, protocol)) if service.starts_with('_') && protocol.starts_with('_') => (service, protocol),
+			_ => return Err(invalid_input("service type must look like _name._tcp or _name._udp")),
+		};
+		let mut service_type = ServiceType::new(service, protocol)?;
+		for subtype in subtype.into_iter().chain(parts) {
+			service_type = service_type.with_subtype(subtype)?;
+		}
+		Ok(service_type)
+	}
+}
+
+impl fmt::Display for ServiceType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}.{}", self.service, self.protocol)?;
+		for subtype in &self.subtypes {
+			write!(f, ",{}", subtype)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(s: &str) -> ServiceType {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn parse_forms() {
+		let t = parse("_http._tcp");
+		assert_eq!(t.service(), "_http");
+		assert_eq!(t.protocol(), "_tcp");
+		assert!(t.subtypes().is_empty());
+		assert_eq!(parse("_http._tcp."), t);
+		assert_eq!(parse("_http._TCP"), t);
+
+		let t = parse("_http._tcp,_printer,_scanner");
+		assert_eq!(t.subtypes(), ["_printer", "_scanner"]);
+		assert_eq!(t.reg_type(), "_http._tcp");
+
+		assert_eq!(parse("_printer._sub._http._tcp"), parse("_http._tcp,_printer"));
+		assert_eq!(parse("_printer._sub._http._tcp.,_scanner"), parse("_http._tcp,_printer,_scanner"));
+		// duplicate subtypes are only kept once
+		assert_eq!(parse("_http._tcp,_printer,_printer"), parse("_http._tcp,_printer"));
+	}
+
+	#[test]
+	fn parse_invalid() {
+		for s in &[
+			"",
+			"_http",
+			"http._tcp",
+			"_http.tcp",
+			"_http._sctp",
+			"_._tcp",
+			"_123._tcp",
+			"_-http._tcp",
+			"_http-._tcp",
+			"_ht--tp._tcp",
+			"_sixteen-chars-xx._tcp",
+			"_http._tcp,",
+			"_http._tcp,,_printer",
+		] {
+			let e = s.parse::<ServiceType>().unwrap_err();
+			assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{:?}", s);
+		}
+		assert!("_fifteen-chars-x._tcp".parse::<ServiceType>().is_ok());
+	}
+
+	#[test]
+	fn new() {
+		assert_eq!(ServiceType::new("http", "tcp").unwrap(), parse("_http._tcp"));
+		assert_eq!(ServiceType::new("_ipp", "_UDP").unwrap(), parse("_ipp._udp"));
+		assert!(ServiceType::new("http", "sctp").is_err());
+		assert!(ServiceType::new("", "tcp").is_err());
+
+		let t = ServiceType::new("http", "tcp").unwrap()
+			.with_subtype("_printer._sub._http._tcp").unwrap()
+			.with_subtype("_scanner").unwrap();
+		assert_eq!(t.subtypes(), ["_printer", "_scanner"]);
+		assert!(t.clone().with_subtype("").is_err());
+		assert!(t.clone().with_subtype("a,b").is_err());
+		assert!(t.with_subtype(&"x".repeat(64)).is_err());
+	}
+
+	#[test]
+	fn display_round_trip() {
+		for s in &["_http._tcp", "_ipp._udp", "_http._tcp,_printer", "_http._tcp,_printer,_scanner"] {
+			let t = parse(s);
+			assert_eq!(t.to_string(), *s);
+			assert_eq!(parse(&t.to_string()), t);
+		}
+		assert_eq!(parse("_printer._sub._http._tcp.").to_string(), "_http._tcp,_printer");
+	}
+}
@@ -0,0 +1,48 @@
+use std::io;
+
+use crate::evented::{EventedDNSService,SharedEvented};
+use crate::ffi;
+use crate::raw;
+
+/// Connection shared by multiple browse, resolve and query operations
+///
+/// Each operation usually opens its own connection (and socket) to the
+/// daemon; operations started through a `SharedConnection` are
+/// multiplexed over a single connection instead (using
+/// `kDNSServiceFlagsShareConnection`), which helps with many concurrent
+/// operations.
+///
+/// The operations behave like the ones from the free functions
+/// ([`browse`](fn.browse.html), [`resolve`](fn.resolve.html) and
+/// [`query_record`](fn.query_record.html)); polling any of them
+/// processes results for all of them.  They keep the connection alive,
+/// so the `SharedConnection` can be dropped once all operations are
+/// started.
+///
+/// Only available with the DNS-SD library; the avahi D-Bus, dnsapi and
+/// built-in mDNS backends are not used.
+///
+/// See [`DNSServiceCreateConnection`](https://developer.apple.com/documentation/dnssd/1804724-dnsservicecreateconnection).
+pub struct SharedConnection(SharedEvented);
+
+impl SharedConnection {
+	/// Create new connection to share
+	pub fn new() -> io::Result<SharedConnection> {
+		let con = raw::DNSService::create_connection()?;
+		Ok(SharedConnection(SharedEvented::new(
+			EventedDNSService::new(con)?
+		)))
+	}
+
+	/// Underlying `DNSServiceRef`
+	///
+	/// See [`Connection::as_raw`](struct.Connection.html#method.as_raw)
+	/// for the rules.
+	pub fn as_raw(&self) -> ffi::DNSServiceRef {
+		self.0.service().as_raw()
+	}
+
+	pub(crate) fn evented(&self) -> &SharedEvented {
+		&self.0
+	}
+}
@@ -0,0 +1,82 @@
+use futures::channel::mpsc;
+use futures::{self,FutureExt};
+use std::future::Future;
+use std::io;
+use std::os::raw::c_void;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::error::Error;
+use crate::evented::EventedDNSService;
+use crate::ffi;
+use crate::future::ServiceFuture;
+use crate::raw;
+use crate::stream::ServiceStream;
+
+/// Pending sleep proxy keepalive registration
+///
+/// Resolves to a [`SleepKeepaliveHandle`](struct.SleepKeepaliveHandle.html)
+/// once the daemon registered the keepalive.
+pub struct SleepKeepalive(ServiceFuture<()>);
+
+impl SleepKeepalive {
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> crate::StopHandle {
+		self.0.stop_handle()
+	}
+}
+
+impl Future for SleepKeepalive {
+	type Output = Result<SleepKeepaliveHandle, Error>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let (service, ()) = futures::ready!(self.0.poll_unpin(cx))?;
+		Poll::Ready(Ok(SleepKeepaliveHandle { _stream: service }))
+	}
+}
+
+/// Registered sleep proxy keepalive
+///
+/// The keepalive is cancelled when this is dropped.
+pub struct SleepKeepaliveHandle {
+	_stream: ServiceStream<()>,
+}
+
+extern "C" fn sleep_keepalive_callback(
+	_sd_ref: ffi::DNSServiceRef,
+	error_code: ffi::DNSServiceErrorType,
+	context: *mut c_void
+) {
+	trace_callback!("sleep_keepalive", error_code);
+	let sender = context as *mut mpsc::UnboundedSender<Result<(), Error>>;
+	let sender : &mpsc::UnboundedSender<Result<(), Error>> = unsafe { &*sender };
+
+	sender.unbounded_send(Error::from(error_code)).unwrap();
+}
+
+/// Register a TCP keepalive with the Bonjour Sleep Proxy
+///
+/// While the machine sleeps the sleep proxy keeps the connection of
+/// `socket` (a connected TCP socket) alive, sending keepalive packets
+/// every `timeout` seconds.
+///
+/// Only supported by mDNSResponder (macOS); fails with
+/// [`DNSServiceError::Unsupported`](enum.DNSServiceError.html#variant.Unsupported)
+/// on avahi.
+pub fn sleep_keepalive<S: AsRawFd>(socket: &S, timeout: u32) -> io::Result<SleepKeepalive> {
+	let fd = socket.as_raw_fd();
+	Ok(SleepKeepalive(ServiceFuture::new(move |sender|
+		EventedDNSService::new(
+			raw::DNSService::sleep_keepalive(
+				0, /* no flags */
+				fd,
+				timeout,
+				Some(sleep_keepalive_callback),
+				sender as *mut c_void,
+			)?
+		)
+	)?))
+}
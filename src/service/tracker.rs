@@ -0,0 +1,112 @@
+use futures::{self,StreamExt};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::batched::{Batched,BatchedTrait};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{Browse,BrowseResult,BrowsedFlag};
+
+/// Identifies a service found by browsing
+#[derive(Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct ServiceKey {
+	/// Interface the service was found on.
+	pub interface: Interface,
+	/// Name of the service.
+	pub service_name: String,
+	/// Type of the service
+	pub reg_type: String,
+	/// Domain the service was found in
+	pub domain: String,
+}
+
+impl<'a> From<&'a BrowseResult> for ServiceKey {
+	fn from(result: &'a BrowseResult) -> Self {
+		ServiceKey {
+			interface: result.interface,
+			service_name: result.service_name.clone(),
+			reg_type: result.reg_type.clone(),
+			domain: result.domain.clone(),
+		}
+	}
+}
+
+/// Changes to the set of services tracked by a
+/// [`ServiceTracker`](struct.ServiceTracker.html)
+#[derive(Clone,PartialEq,Eq,Hash,Debug,Default)]
+pub struct ServiceDiff {
+	/// Services which were added
+	pub added: Vec<BrowseResult>,
+	/// Services which were removed
+	pub removed: Vec<BrowseResult>,
+}
+
+/// Set of currently available services
+///
+/// Applies the results of a [`Browse`](struct.Browse.html) to a set of
+/// services, and yields the changes once per burst of results (see
+/// [`Batched`](struct.Batched.html)).
+pub struct ServiceTracker {
+	browse: Batched<Browse, BrowseResult>,
+	services: HashMap<ServiceKey, BrowseResult>,
+}
+
+impl Browse {
+	/// Track set of available services
+	///
+	/// See [`ServiceTracker`](struct.ServiceTracker.html).
+	pub fn track(self) -> ServiceTracker {
+		ServiceTracker {
+			browse: self.batched(),
+			services: HashMap::new(),
+		}
+	}
+}
+
+impl ServiceTracker {
+	/// Currently available services
+	pub fn services(&self) -> &HashMap<ServiceKey, BrowseResult> {
+		&self.services
+	}
+
+	fn apply(&mut self, results: Vec<BrowseResult>) -> ServiceDiff {
+		let mut diff = ServiceDiff::default();
+		for result in results {
+			let key = ServiceKey::from(&result);
+			if result.flags & BrowsedFlag::Add {
+				if let Entry::Vacant(entry) = self.services.entry(key) {
+					diff.added.push(result.clone());
+					entry.insert(result);
+				}
+			} else if let Some(old) = self.services.remove(&key) {
+				// might have been added in the same batch
+				match diff.added.iter().position(|r| ServiceKey::from(r) == key) {
+					Some(pos) => { diff.added.remove(pos); },
+					None => diff.removed.push(old),
+				}
+			}
+		}
+		diff
+	}
+}
+
+impl futures::Stream for ServiceTracker {
+	type Item = Result<ServiceDiff, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		loop {
+			match futures::ready!(self.browse.poll_next_unpin(cx)) {
+				None => return Poll::Ready(None),
+				Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+				Some(Ok(results)) => {
+					let diff = self.apply(results);
+					if !diff.added.is_empty() || !diff.removed.is_empty() {
+						return Poll::Ready(Some(Ok(diff)));
+					}
+				},
+			}
+		}
+	}
+}
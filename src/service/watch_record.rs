@@ -0,0 +1,139 @@
+use futures::{self,FutureExt,StreamExt};
+use futures_timer::Delay;
+use std::collections::HashSet;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::reconnect::is_disconnect;
+use crate::service::{
+	query_record,
+	QueriedRecordFlag,
+	QueryRecord,
+	QueryRecordFlag,
+	QueryRecordResult,
+};
+
+/// Durable long-lived query for a single record
+///
+/// See [`watch_record`](fn.watch_record.html).
+pub struct WatchRecord {
+	fullname: String,
+	rr_type: RecordType,
+	query: Option<QueryRecord>,
+	retry: Option<Delay>,
+	retry_interval: Duration,
+	// (interface, class, rdata) of records reported as added
+	known: HashSet<(Interface, RecordClass, Vec<u8>)>,
+}
+
+impl WatchRecord {
+	/// Set interval to wait before starting the query again after an
+	/// error (default 1 second)
+	pub fn retry_interval(mut self, interval: Duration) -> Self {
+		self.retry_interval = interval;
+		self
+	}
+
+	fn start(&self) -> io::Result<QueryRecord> {
+		query_record(
+			QueryRecordFlag::LongLivedQuery.into(),
+			Interface::Any,
+			&self.fullname,
+			self.rr_type,
+			RecordClass::IN,
+		)
+	}
+
+	// whether the result changes the set of known records
+	fn is_new(&mut self, result: &QueryRecordResult) -> bool {
+		let key = (result.interface, result.rr_class, result.rdata.clone());
+		if result.flags & QueriedRecordFlag::Add {
+			self.known.insert(key)
+		} else {
+			self.known.remove(&key)
+		}
+	}
+}
+
+impl futures::Stream for WatchRecord {
+	type Item = Result<QueryRecordResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		loop {
+			if let Some(query) = this.query.as_mut() {
+				match query.poll_next_unpin(cx) {
+					Poll::Ready(Some(Ok(result))) => {
+						if this.is_new(&result) {
+							return Poll::Ready(Some(Ok(result)));
+						}
+						continue;
+					},
+					Poll::Ready(Some(Err(e))) => {
+						this.query = None;
+						if is_disconnect(&e) {
+							// start again right away; the daemon might
+							// already be back
+							this.retry = None;
+							continue;
+						}
+						this.retry = Some(Delay::new(this.retry_interval));
+						return Poll::Ready(Some(Err(e)));
+					},
+					Poll::Ready(None) => {
+						this.query = None;
+						this.retry = Some(Delay::new(this.retry_interval));
+					},
+					Poll::Pending => return Poll::Pending,
+				}
+			}
+
+			if let Some(retry) = this.retry.as_mut() {
+				match retry.poll_unpin(cx) {
+					Poll::Pending => return Poll::Pending,
+					Poll::Ready(()) => this.retry = None,
+				}
+			}
+
+			match this.start() {
+				Ok(query) => this.query = Some(query),
+				Err(e) => {
+					this.retry = Some(Delay::new(this.retry_interval));
+					let e : Error = e.into();
+					if !is_disconnect(&e) {
+						return Poll::Ready(Some(Err(e)));
+					}
+				},
+			}
+		}
+	}
+}
+
+/// Watch a single record with a long-lived query
+///
+/// Queries for the record (class `IN`, on all interfaces) with
+/// [`LongLivedQuery`](enum.QueryRecordFlag.html#variant.LongLivedQuery)
+/// set.  When the daemon restarts or the query fails the query is
+/// started again (errors other than a lost daemon connection are
+/// yielded first, but don't end the stream).
+///
+/// Answers replayed after starting the query again aren't reported
+/// again: only additions of new records and removals of known records
+/// are yielded.
+pub fn watch_record(fullname: &str, rr_type: RecordType) -> io::Result<WatchRecord> {
+	let mut watch = WatchRecord {
+		fullname: fullname.to_string(),
+		rr_type,
+		query: None,
+		retry: None,
+		retry_interval: Duration::from_secs(1),
+		known: HashSet::new(),
+	};
+	watch.query = Some(watch.start()?);
+	Ok(watch)
+}
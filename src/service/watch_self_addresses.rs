@@ -0,0 +1,109 @@
+use futures::{self,StreamExt};
+use std::io;
+use std::net::{IpAddr,Ipv4Addr,Ipv6Addr};
+use std::pin::Pin;
+use std::task::{Context,Poll};
+
+use crate::dns_types::RecordType;
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	watch_record,
+	QueriedRecordFlag,
+	QueryRecordResult,
+	WatchRecord,
+};
+
+/// Change of an address of this host
+///
+/// See [`watch_self_addresses`](fn.watch_self_addresses.html).
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub enum AddressEvent {
+	/// Address is now published on the interface
+	Added(Interface, IpAddr),
+	/// Address is no longer published on the interface
+	Removed(Interface, IpAddr),
+}
+
+// first label of the system host name, as published in `.local`
+pub(crate) fn host_name() -> String {
+	let mut buf = [0u8; 256];
+	if 0 != unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } {
+		return "localhost".to_string();
+	}
+	let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+	let name = String::from_utf8_lossy(&buf[..len]).into_owned();
+	// only the first label of the host name is used in .local
+	match name.split('.').next() {
+		Some(label) if !label.is_empty() => label.to_string(),
+		_ => "localhost".to_string(),
+	}
+}
+
+/// Stream of address changes of this host
+///
+/// See [`watch_self_addresses`](fn.watch_self_addresses.html).
+pub struct WatchSelfAddresses {
+	v4: WatchRecord,
+	v6: WatchRecord,
+}
+
+fn address_event(result: QueryRecordResult) -> Option<AddressEvent> {
+	let address = match result.rdata.len() {
+		4 => {
+			let mut octets = [0u8; 4];
+			octets.copy_from_slice(&result.rdata);
+			IpAddr::V4(Ipv4Addr::from(octets))
+		},
+		16 => {
+			let mut octets = [0u8; 16];
+			octets.copy_from_slice(&result.rdata);
+			IpAddr::V6(Ipv6Addr::from(octets))
+		},
+		_ => return None,
+	};
+	if result.flags & QueriedRecordFlag::Add {
+		Some(AddressEvent::Added(result.interface, address))
+	} else {
+		Some(AddressEvent::Removed(result.interface, address))
+	}
+}
+
+impl futures::Stream for WatchSelfAddresses {
+	type Item = Result<AddressEvent, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		// both queries are restarted on errors and never end
+		for watch in [&mut this.v4, &mut this.v6] {
+			while let Poll::Ready(Some(item)) = watch.poll_next_unpin(cx) {
+				match item {
+					Ok(result) => {
+						if let Some(event) = address_event(result) {
+							return Poll::Ready(Some(Ok(event)));
+						}
+					},
+					Err(e) => return Poll::Ready(Some(Err(e))),
+				}
+			}
+		}
+		Poll::Pending
+	}
+}
+
+/// Watch the addresses published for this host
+///
+/// Queries the `A` and `AAAA` records of `<hostname>.local.` (see
+/// [`watch_record`](fn.watch_record.html)) and reports addresses
+/// appearing and disappearing, e.g. after a DHCP lease changed.
+///
+/// The host name is taken from the system; if the daemon had to rename
+/// the host after a conflict the records of the other host are
+/// watched instead.
+pub fn watch_self_addresses() -> io::Result<WatchSelfAddresses> {
+	let fullname = format!("{}.local.", super::escape_instance_name(&host_name()));
+	Ok(WatchSelfAddresses {
+		v4: watch_record(&fullname, RecordType::A)?,
+		v6: watch_record(&fullname, RecordType::AAAA)?,
+	})
+}
@@ -0,0 +1,345 @@
+use futures::channel::mpsc;
+use futures::{self,FutureExt,Stream,StreamExt};
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicU64,Ordering};
+use std::task::{Context,Poll};
+
+use crate::context::{DnsSd,Shutdown};
+use crate::dns_types::{RecordClass,RecordType};
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::reconnect::{reconnect,ReconnectEvent};
+use crate::service::{
+	browse,
+	query_record,
+	resolve,
+	BrowseFlags,
+	BrowseResult,
+	QueryRecordFlags,
+	QueryRecordResult,
+	Register,
+	RegisterBuilder,
+	RegisterResult,
+	Registration,
+	ResolveFlags,
+	ResolveResult,
+};
+use crate::stop_handle::{Release,StopHandle};
+
+/// Identifies an operation of a [`Session`](struct.Session.html)
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct OperationId(u64);
+
+impl fmt::Display for OperationId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{}", self.0)
+	}
+}
+
+/// Event of a [`Session`](struct.Session.html)
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub enum SessionEvent {
+	/// An operation lost the connection to the daemon (e.g. because it
+	/// restarted)
+	Disconnected(OperationId),
+	/// An operation was started again with its original arguments
+	Restored(OperationId),
+}
+
+/// Operation of a [`Session`](struct.Session.html)
+///
+/// Yields the results of the operation; restarts are not reported here
+/// but through the session.  Ends when stopped (also by dropping or
+/// shutting down the session).
+pub struct SessionOperation<T> {
+	id: OperationId,
+	// shared with the stop handle to release it right away; `None`
+	// when stopped
+	stream: Arc<OperationStream<T>>,
+	stop: StopHandle,
+	events: mpsc::UnboundedSender<SessionEvent>,
+}
+
+type OperationStream<T> = Mutex<Option<Pin<Box<dyn Stream<Item = Result<ReconnectEvent<T>, Error>> + Send>>>>;
+
+impl<T> Release for OperationStream<T> {
+	fn release(&self) {
+		let stream = self.lock().unwrap_or_else(|e| e.into_inner()).take();
+		// deallocate after unlocking
+		drop(stream);
+	}
+}
+
+impl<T> SessionOperation<T> {
+	/// Id used in [`SessionEvent`](enum.SessionEvent.html)s for this
+	/// operation
+	pub fn id(&self) -> OperationId {
+		self.id
+	}
+
+	/// Handle to stop the operation from any task or thread
+	///
+	/// See [`StopHandle`](struct.StopHandle.html).
+	pub fn stop_handle(&self) -> StopHandle {
+		self.stop.clone()
+	}
+}
+
+impl<T> futures::Stream for SessionOperation<T> {
+	type Item = Result<T, Error>;
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		if self.stop.poll_stopped(cx) {
+			self.stream.release();
+			return Poll::Ready(None);
+		}
+		let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+		let stream = match stream.as_mut() {
+			Some(stream) => stream,
+			None => return Poll::Ready(None),
+		};
+		loop {
+			match futures::ready!(stream.poll_next_unpin(cx)) {
+				Some(Ok(ReconnectEvent::Reconnected)) => {
+					// the session might be gone
+					let _ = self.events.unbounded_send(SessionEvent::Restored(self.id));
+				},
+				Some(Ok(ReconnectEvent::Result(item))) => return Poll::Ready(Some(Ok(item))),
+				Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+				None => return Poll::Ready(None),
+			}
+		}
+	}
+}
+
+// registration as stream of (re)names
+enum RegisterStream {
+	Pending(Register),
+	Registered(Registration),
+}
+
+impl futures::Stream for RegisterStream {
+	type Item = Result<RegisterResult, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		match *self {
+			RegisterStream::Pending(ref mut register) => {
+				let (registration, result) = match futures::ready!(register.poll_unpin(cx)) {
+					Ok(registered) => registered,
+					Err(e) => return Poll::Ready(Some(Err(e))),
+				};
+				*self = RegisterStream::Registered(registration);
+				Poll::Ready(Some(Ok(result)))
+			},
+			RegisterStream::Registered(ref mut registration) => registration.events().poll_next_unpin(cx),
+		}
+	}
+}
+
+/// Operations restored after the daemon restarts
+///
+/// Operations started through a session are started again with their
+/// original arguments after the connection to the daemon broke (see
+/// [`reconnect`](fn.reconnect.html)); the session is a stream of
+/// [`SessionEvent`](enum.SessionEvent.html)s reporting disconnects and
+/// restores of all its operations.
+///
+/// The session owns its operations: they are stopped when the session
+/// is dropped or [shut down](#method.shutdown) (like with a
+/// [`DnsSd`](struct.DnsSd.html) context).
+pub struct Session {
+	next_id: Arc<AtomicU64>,
+	sender: mpsc::UnboundedSender<SessionEvent>,
+	receiver: mpsc::UnboundedReceiver<SessionEvent>,
+	context: DnsSd,
+}
+
+impl Default for Session {
+	fn default() -> Self {
+		Session::new()
+	}
+}
+
+impl Session {
+	/// Create new session without operations
+	pub fn new() -> Self {
+		let (sender, receiver) = mpsc::unbounded();
+		Session {
+			next_id: Arc::new(AtomicU64::new(1)),
+			sender,
+			receiver,
+			context: DnsSd::new(),
+		}
+	}
+
+	/// Stop all operations of the session
+	///
+	/// See [`DnsSd::shutdown`](struct.DnsSd.html#method.shutdown);
+	/// dropping the session does the same without waiting.  Operations
+	/// can't be started afterwards.
+	pub fn shutdown(&self) -> Shutdown {
+		self.context.shutdown()
+	}
+
+	fn start<T, S, F>(&self, factory: F) -> io::Result<SessionOperation<T>>
+	where
+		T: 'static,
+		S: Stream<Item = Result<T, Error>> + Unpin + Send + 'static,
+		F: FnMut() -> io::Result<S> + Unpin + Send + 'static,
+	{
+		let id = OperationId(self.next_id.fetch_add(1, Ordering::Relaxed));
+		let events = self.sender.clone();
+		let stream = reconnect(factory)?.on_disconnect(Box::new(move || {
+			let _ = events.unbounded_send(SessionEvent::Disconnected(id));
+		}));
+		let stream: Arc<OperationStream<T>> = Arc::new(Mutex::new(Some(Box::pin(stream))));
+		let stop = StopHandle::new();
+		stop.attach(Arc::downgrade(&stream) as std::sync::Weak<OperationStream<T>>);
+		self.context.track(stop.clone())?;
+		Ok(SessionOperation {
+			id,
+			stream,
+			stop,
+			events: self.sender.clone(),
+		})
+	}
+
+	/// Browse for services
+	///
+	/// See [`browse`](fn.browse.html).
+	pub fn browse(
+		&self,
+		flags: BrowseFlags,
+		interface: Interface,
+		reg_type: &str,
+		subtype: Option<&str>,
+		domain: Option<&str>
+	) -> io::Result<SessionOperation<BrowseResult>> {
+		let reg_type = reg_type.to_string();
+		let subtype = subtype.map(str::to_string);
+		let domain = domain.map(str::to_string);
+		self.start(move || browse(flags, interface, &reg_type, subtype.as_deref(), domain.as_deref()))
+	}
+
+	/// Resolve a service
+	///
+	/// See [`resolve`](fn.resolve.html).
+	pub fn resolve(
+		&self,
+		flags: ResolveFlags,
+		interface: Interface,
+		name: &str,
+		reg_type: &str,
+		domain: &str
+	) -> io::Result<SessionOperation<ResolveResult>> {
+		let name = name.to_string();
+		let reg_type = reg_type.to_string();
+		let domain = domain.to_string();
+		self.start(move || resolve(flags, interface, &name, &reg_type, &domain))
+	}
+
+	/// Query for a record
+	///
+	/// See [`query_record`](fn.query_record.html).
+	pub fn query_record(
+		&self,
+		flags: QueryRecordFlags,
+		interface: Interface,
+		fullname: &str,
+		rr_type: RecordType,
+		rr_class: RecordClass
+	) -> io::Result<SessionOperation<QueryRecordResult>> {
+		let fullname = fullname.to_string();
+		self.start(move || query_record(flags, interface, &fullname, rr_type, rr_class))
+	}
+
+	/// Register a service
+	///
+	/// Yields the result of the registration (again after each restore)
+	/// and renames; the service is removed when the operation is
+	/// dropped.  Records can't be added to the registration.
+	pub fn register(&self, builder: &RegisterBuilder) -> io::Result<SessionOperation<RegisterResult>> {
+		let builder = builder.clone();
+		self.start(move || Ok(RegisterStream::Pending(builder.start()?)))
+	}
+}
+
+impl Drop for Session {
+	fn drop(&mut self) {
+		// completes in the background
+		drop(self.context.shutdown());
+	}
+}
+
+impl futures::Stream for Session {
+	type Item = SessionEvent;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		// the session holds a sender itself, so this never ends
+		self.receiver.poll_next_unpin(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::executor::block_on;
+	use std::time::{Duration,Instant};
+
+	use super::*;
+	use crate::mock::MockDaemon;
+	use crate::service::RegisterFlags;
+
+	fn register(session: &Session, daemon: &MockDaemon, name: &str) -> SessionOperation<RegisterResult> {
+		let daemon = daemon.clone();
+		let name = name.to_string();
+		session.start(move || {
+			let register = daemon.register(RegisterFlags::none(), Interface::Any, Some(&name), "_http._tcp", &[], None, None, 80, &[])?;
+			Ok(RegisterStream::Pending(register))
+		}).unwrap()
+	}
+
+	#[test]
+	fn shutdown_stops_operations() {
+		let daemon = MockDaemon::new();
+		let session = Session::new();
+		let mut op = register(&session, &daemon, "a");
+		let result = block_on(op.next()).unwrap().unwrap();
+		assert_eq!(result.name, "a");
+		assert_eq!(daemon.services().len(), 1);
+
+		block_on(session.shutdown());
+		assert!(daemon.services().is_empty());
+		assert!(op.stop_handle().is_stopped());
+		assert!(block_on(op.next()).is_none());
+		assert!(session.start(|| Ok(futures::stream::pending::<Result<(), Error>>())).is_err());
+	}
+
+	#[test]
+	fn drop_stops_operations() {
+		let daemon = MockDaemon::new();
+		let session = Session::new();
+		let _op = register(&session, &daemon, "a");
+		assert_eq!(daemon.services().len(), 1);
+		drop(session);
+		// stopped in the background
+		let started = Instant::now();
+		while !daemon.services().is_empty() {
+			assert!(started.elapsed() < Duration::from_secs(5));
+			std::thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	#[test]
+	fn stop_single_operation() {
+		let daemon = MockDaemon::new();
+		let session = Session::new();
+		let mut a = register(&session, &daemon, "a");
+		let _b = register(&session, &daemon, "b");
+		a.stop_handle().stop();
+		assert_eq!(daemon.services().len(), 1);
+		assert!(block_on(a.next()).is_none());
+	}
+}
@@ -0,0 +1,78 @@
+use futures::task::AtomicWaker;
+use std::fmt;
+use std::sync::{Arc,Mutex,Weak};
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::task::Context;
+
+// resources of an operation which can be released from any thread
+pub(crate) trait Release: Send + Sync {
+	fn release(&self);
+}
+
+#[derive(Default)]
+struct Inner {
+	stopped: AtomicBool,
+	waker: AtomicWaker,
+	resources: Mutex<Option<Weak<dyn Release>>>,
+}
+
+impl fmt::Debug for Inner {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Inner")
+			.field("stopped", &self.stopped)
+			.finish()
+	}
+}
+
+/// Stop an operation from any task or thread
+///
+/// Obtained through the `stop_handle` method of an operation.  After
+/// [`stop`](#method.stop) the stream of the operation ends (futures
+/// fail with an [`Interrupted`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.Interrupted)
+/// error) the next time it is polled; the owning task gets woken up for
+/// that.  The daemon resources are released by `stop` itself, so they
+/// don't outlive an operation nobody polls anymore.
+///
+/// Cloning is cheap.
+#[derive(Clone,Debug)]
+pub struct StopHandle(Arc<Inner>);
+
+impl StopHandle {
+	pub(crate) fn new() -> Self {
+		StopHandle(Arc::new(Inner::default()))
+	}
+
+	// resources to release on `stop`
+	pub(crate) fn attach(&self, resources: Weak<dyn Release>) {
+		*self.0.resources.lock().unwrap_or_else(|e| e.into_inner()) = Some(resources);
+	}
+
+	/// Stop the operation
+	pub fn stop(&self) {
+		self.0.stopped.store(true, Ordering::SeqCst);
+		let resources = self.0.resources.lock().unwrap_or_else(|e| e.into_inner()).take();
+		if let Some(resources) = resources.and_then(|r| r.upgrade()) {
+			resources.release();
+		}
+		self.0.waker.wake();
+	}
+
+	/// Whether [`stop`](#method.stop) was called
+	pub fn is_stopped(&self) -> bool {
+		self.0.stopped.load(Ordering::SeqCst)
+	}
+
+	/// Whether the operation is gone: either stopped or dropped
+	pub(crate) fn is_finished(&self) -> bool {
+		self.is_stopped() || match *self.0.resources.lock().unwrap_or_else(|e| e.into_inner()) {
+			Some(ref resources) => resources.strong_count() == 0,
+			None => false,
+		}
+	}
+
+	// registers for wakeup by `stop`
+	pub(crate) fn poll_stopped(&self, cx: &mut Context) -> bool {
+		self.0.waker.register(cx.waker());
+		self.is_stopped()
+	}
+}
@@ -1,52 +1,425 @@
-use futures::sync::mpsc;
-use futures::{self,Async};
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::collections::VecDeque;
 use std::io;
-use tokio_core::reactor::{Remote};
+use std::pin::Pin;
+use std::sync::{Arc,Mutex,MutexGuard,Weak};
+use std::task::{Context,Poll};
+use std::time::Instant;
 
-use evented::EventedDNSService;
-use raw_box::RawBox;
-use remote::GetRemote;
+use crate::error::Error;
+use crate::evented::{EventedDNSService,SharedChild,SharedEvented};
+use crate::raw::DNSService;
+use crate::raw_box::RawBox;
+use crate::stop_handle::{Release,StopHandle};
+
+// default number of results to queue before results are not processed
+// anymore until the consumer catches up
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
+
+/// What to do with new results when the result queue of an operation
+/// is full
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug,Default)]
+pub enum QueuePolicy {
+	/// Stop processing results until the consumer catches up; they stay
+	/// buffered in the daemon connection (default)
+	#[default]
+	Block,
+	/// Keep processing results and drop the oldest queued result for
+	/// each new one
+	DropOldest,
+	/// Keep processing results, drop new results which don't fit and
+	/// report the loss as error (of kind `Other`) on the next poll
+	Error,
+}
+
+/// Why an operation ended
+///
+/// See e.g. [`Browse::termination_reason`](struct.Browse.html#method.termination_reason).
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
+pub enum TerminationReason {
+	/// Stopped through a [`StopHandle`](struct.StopHandle.html)
+	Stopped,
+	/// The daemon exited (or the connection to it broke)
+	DaemonExited,
+	/// The daemon timed out the operation (see e.g.
+	/// [`QueryRecordFlag::Timeout`](enum.QueryRecordFlag.html#variant.Timeout));
+	/// the stream ends without an error
+	TimedOut,
+	/// The backend finished the operation (e.g. the mock daemon
+	/// dropped it)
+	Finished,
+	/// Processing results failed with an error which can't be recovered
+	/// from (e.g. reading from the daemon connection failed)
+	Failed,
+}
+
+impl TerminationReason {
+	// error for futures waiting on an operation which ended (`None`
+	// when the reason is unknown)
+	pub(crate) fn into_error(reason: Option<TerminationReason>) -> Error {
+		match reason {
+			Some(TerminationReason::TimedOut) => Error::KnownError(crate::ffi::DNSServiceError::Timeout),
+			Some(TerminationReason::DaemonExited) => io::Error::new(io::ErrorKind::NotConnected, "daemon connection closed").into(),
+			Some(TerminationReason::Finished) => io::Error::new(io::ErrorKind::NotConnected, "operation finished").into(),
+			Some(TerminationReason::Failed) => io::Error::other("operation failed").into(),
+			Some(TerminationReason::Stopped) | None => io::Error::new(io::ErrorKind::Interrupted, "operation was stopped").into(),
+		}
+	}
+}
+
+enum Source {
+	Service(EventedDNSService),
+	// operation on a shared connection
+	Shared(SharedChild),
+	// results are sent by the mock daemon; the guard is dropped together
+	// with the stream
+	#[cfg(any(test, feature = "mock"))]
+	Mock(#[allow(dead_code)] Box<dyn std::any::Any + Send>),
+	// avahi through D-Bus
+	#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+	Dbus(crate::avahi_dbus::Operation),
+	// built-in mDNS engine
+	#[cfg(all(unix, feature = "mdns"))]
+	Mdns(crate::mdns::Operation),
+	// dnsapi.dll; results are sent from system threads, the operation
+	// is cancelled when dropped
+	#[cfg(all(windows, feature = "windows-dnsapi"))]
+	Dnsapi(#[allow(dead_code)] crate::windows_dnsapi::Operation),
+	// released after a stop through a `StopHandle`
+	Stopped,
+}
+
+impl Release for Mutex<Source> {
+	fn release(&self) {
+		let source = std::mem::replace(&mut *self.lock().unwrap_or_else(|e| e.into_inner()), Source::Stopped);
+		// deallocate after unlocking
+		drop(source);
+	}
+}
 
 pub struct ServiceStream<T> {
-	service: EventedDNSService,
-	_sender: RawBox<mpsc::UnboundedSender<io::Result<T>>>,
-	receiver: mpsc::UnboundedReceiver<io::Result<T>>,
+	// shared with the stop handle to release it right away
+	source: Arc<Mutex<Source>>,
+	// the callbacks can't block, so they always send through an
+	// unbounded channel; results are moved to `queue` right after
+	// processing, which is bounded by only processing new results while
+	// it has room.
+	receiver: mpsc::UnboundedReceiver<Result<T, Error>>,
+	queue: VecDeque<Result<T, Error>>,
+	capacity: usize,
+	policy: QueuePolicy,
+	// results were dropped with `QueuePolicy::Error`
+	overflowed: bool,
+	stop: StopHandle,
+	terminated: Option<TerminationReason>,
+	filter: Option<Box<Filter<T>>>,
+	// with a filter the last queued result of an unfinished batch is
+	// held back, so the filter can still end the batch with it
+	more_coming: Option<fn(&T) -> bool>,
 }
 
-impl<T> ServiceStream<T> {
+// decides whether to keep a result; gets the last queued result to fix
+// up batch flags when dropping the end of a batch (a result with
+// `MoreComing` stays queued until the next result was filtered)
+pub type Filter<T> = dyn FnMut(&T, Option<&mut T>) -> bool + Send + Sync;
+
+impl<T: Send + 'static> ServiceStream<T> {
 	pub fn new<F>(f: F) -> io::Result<Self>
-	where F: FnOnce(*mut mpsc::UnboundedSender<io::Result<T>>) -> io::Result<EventedDNSService>
+	where F: FnOnce(*mut mpsc::UnboundedSender<Result<T, Error>>) -> io::Result<EventedDNSService>
+	{
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
+		let sender = RawBox::new(sender);
+
+		let service = f(sender.get_ptr())?;
+		// the callback context must live as long as the service, which
+		// might outlive the stream (e.g. through records)
+		service.service().keep_alive(Box::new(sender));
+
+		Ok(ServiceStream::from_source(Source::Service(service), receiver))
+	}
+
+	pub fn new_shared<F>(connection: &SharedEvented, f: F) -> io::Result<Self>
+	where F: FnOnce(*mut mpsc::UnboundedSender<Result<T, Error>>) -> io::Result<DNSService>
 	{
-		let (sender, receiver) = mpsc::unbounded::<io::Result<T>>();
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
 		let sender = RawBox::new(sender);
 
 		let service = f(sender.get_ptr())?;
+		service.keep_alive(Box::new(sender));
 
-		Ok(ServiceStream{
-			service: service,
-			_sender: sender,
-			receiver: receiver,
-		})
+		Ok(ServiceStream::from_source(Source::Shared(connection.child(service)), receiver))
 	}
 }
 
-impl<T> futures::Stream for ServiceStream<T> {
-	type Item = T;
-	type Error = io::Error;
+impl<T> ServiceStream<T> {
+	fn from_source(source: Source, receiver: mpsc::UnboundedReceiver<Result<T, Error>>) -> Self {
+		let source = Arc::new(Mutex::new(source));
+		let stop = StopHandle::new();
+		let weak: Weak<dyn Release> = Arc::downgrade(&source) as Weak<Mutex<Source>>;
+		stop.attach(weak);
+		ServiceStream{
+			source,
+			receiver,
+			queue: VecDeque::new(),
+			capacity: DEFAULT_QUEUE_CAPACITY,
+			policy: QueuePolicy::Block,
+			overflowed: false,
+			stop,
+			terminated: None,
+			filter: None,
+			more_coming: None,
+		}
+	}
+
+	fn lock_source(&self) -> MutexGuard<'_, Source> {
+		// nothing to poison: a panic while polling leaves the source
+		// usable (or stopped)
+		self.source.lock().unwrap_or_else(|e| e.into_inner())
+	}
 
-	fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-		self.service.poll()?;
-		match self.receiver.poll() {
-			Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
-			Ok(Async::Ready(Some(item))) => Ok(Async::Ready(Some(item?))),
-			Ok(Async::NotReady) => Ok(Async::NotReady),
-			Err(()) => unreachable!(),
+	#[cfg(any(test, feature = "mock"))]
+	pub fn new_mock(guard: Box<dyn std::any::Any + Send>) -> (mpsc::UnboundedSender<Result<T, Error>>, Self) {
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
+		(sender, ServiceStream::from_source(Source::Mock(guard), receiver))
+	}
+
+	#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+	pub fn new_dbus<F>(f: F) -> io::Result<Self>
+	where F: FnOnce(mpsc::UnboundedSender<Result<T, Error>>) -> Result<crate::avahi_dbus::Operation, Error>
+	{
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
+		Ok(ServiceStream::from_source(Source::Dbus(f(sender)?), receiver))
+	}
+
+	#[cfg(all(unix, feature = "mdns"))]
+	pub fn new_mdns<F>(f: F) -> io::Result<Self>
+	where F: FnOnce(mpsc::UnboundedSender<Result<T, Error>>) -> io::Result<crate::mdns::Operation>
+	{
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
+		Ok(ServiceStream::from_source(Source::Mdns(f(sender)?), receiver))
+	}
+
+	#[cfg(all(windows, feature = "windows-dnsapi"))]
+	pub fn new_dnsapi<F>(f: F) -> io::Result<Self>
+	where F: FnOnce(mpsc::UnboundedSender<Result<T, Error>>) -> io::Result<crate::windows_dnsapi::Operation>
+	{
+		let (sender, receiver) = mpsc::unbounded::<Result<T, Error>>();
+		Ok(ServiceStream::from_source(Source::Dnsapi(f(sender)?), receiver))
+	}
+
+	/// Fails for backends without a `DNSServiceRef`
+	pub fn try_service(&self) -> io::Result<DNSService> {
+		match *self.lock_source() {
+			Source::Service(ref service) => Ok(service.service().clone()),
+			Source::Shared(ref child) => Ok(child.service().clone()),
+			Source::Stopped => Err(io::Error::new(io::ErrorKind::NotConnected, "operation was stopped")),
+			#[cfg(any(test, feature = "mock", all(avahi_compat, feature = "avahi-dbus"), all(unix, feature = "mdns"), all(windows, feature = "windows-dnsapi")))]
+			_ => Err(io::Error::new(io::ErrorKind::Unsupported, "not supported by this backend")),
 		}
 	}
+
+	pub fn service(&self) -> DNSService {
+		self.try_service().expect("operation without DNSServiceRef")
+	}
+
+	pub fn as_raw(&self) -> Option<crate::ffi::DNSServiceRef> {
+		self.try_service().ok().map(|service| service.as_raw())
+	}
+
+	pub fn stop_handle(&self) -> StopHandle {
+		self.stop.clone()
+	}
+
+	// a single reply can trigger more than one callback, so the queue
+	// might exceed the capacity slightly
+	pub fn set_capacity(&mut self, capacity: usize) {
+		self.capacity = capacity.max(1);
+	}
+
+	pub fn set_policy(&mut self, policy: QueuePolicy) {
+		self.policy = policy;
+	}
+
+	// filtered results don't take up room in the queue; results must
+	// pass all added filters
+	pub fn add_filter(&mut self, mut filter: Box<Filter<T>>) where T: crate::MoreComing + 'static {
+		self.more_coming = Some(T::more_coming);
+		self.filter = Some(match self.filter.take() {
+			None => filter,
+			Some(mut previous) => Box::new(move |item, mut last| {
+				previous(item, last.as_deref_mut()) && filter(item, last)
+			}),
+		});
+	}
+
+	pub fn termination_reason(&self) -> Option<TerminationReason> {
+		self.terminated
+	}
+
+	// release the daemon resources now; the stream ends on the next poll
+	// unless a new source is set
+	pub fn release(&self) {
+		self.source.release();
+	}
+
+	// continue with the source and results of `other`, keeping settings
+	// and stop handle
+	pub fn replace_source(&mut self, other: ServiceStream<T>) {
+		let source = std::mem::replace(&mut *other.lock_source(), Source::Stopped);
+		let previous = std::mem::replace(&mut *self.lock_source(), source);
+		drop(previous);
+		self.receiver = other.receiver;
+		self.queue.clear();
+		self.overflowed = false;
+		self.terminated = None;
+	}
+
+	// record why the operation ended if `item` ends it
+	fn check_termination(&mut self, item: &Result<T, Error>) {
+		match *item {
+			Err(ref e) if crate::reconnect::is_disconnect(e) => self.terminate(TerminationReason::DaemonExited),
+			_ => (),
+		}
+	}
+
+	// end the stream: it yields `None` from now on, so the daemon
+	// resources aren't needed anymore
+	fn terminate(&mut self, reason: TerminationReason) {
+		self.terminated.get_or_insert(reason);
+		self.queue.clear();
+		self.release();
+	}
+}
+
+// results are never pinned
+impl<T> Unpin for ServiceStream<T> {}
+
+impl<T> futures::stream::FusedStream for ServiceStream<T> {
+	fn is_terminated(&self) -> bool {
+		self.terminated.is_some()
+	}
 }
 
-impl<T> GetRemote for ServiceStream<T> {
-	fn remote(&self) -> &Remote {
-		self.service.remote()
+impl<T> futures::Stream for ServiceStream<T> {
+	type Item = Result<T, Error>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		if this.stop.poll_stopped(cx) {
+			this.terminate(TerminationReason::Stopped);
+			return Poll::Ready(None);
+		}
+		if this.terminated.is_some() {
+			return Poll::Ready(None);
+		}
+		let mut finished = false;
+		let block = this.policy == QueuePolicy::Block;
+		if !block || this.queue.len() < this.capacity {
+			let metrics = crate::metrics::metrics();
+			let started = metrics.map(|_| Instant::now());
+			// registers for wakeup if no reply is pending
+			let polled = match *this.lock_source() {
+				Source::Service(ref service) => service.poll(cx),
+				Source::Shared(ref child) => child.poll(cx),
+				#[cfg(any(test, feature = "mock"))]
+				Source::Mock(_) => Ok(()),
+				#[cfg(all(avahi_compat, feature = "avahi-dbus"))]
+				Source::Dbus(ref mut operation) => operation.poll(cx),
+				#[cfg(all(unix, feature = "mdns"))]
+				Source::Mdns(ref mut operation) => operation.poll(cx),
+				#[cfg(all(windows, feature = "windows-dnsapi"))]
+				Source::Dnsapi(_) => Ok(()),
+				Source::Stopped => Ok(()),
+			};
+			if let Err(e) = polled {
+				let e: Error = e.into();
+				if let Some(metrics) = metrics {
+					metrics.error(crate::metrics::operation_name::<T>(), &e);
+				}
+				// the connection can't be used anymore
+				let reason = if crate::reconnect::is_disconnect(&e) {
+					TerminationReason::DaemonExited
+				} else {
+					TerminationReason::Failed
+				};
+				this.terminate(reason);
+				return Poll::Ready(Some(Err(e)));
+			}
+			let processed = started.map(|started| started.elapsed());
+			let mut received = 0;
+			while !block || this.queue.len() < this.capacity {
+				match this.receiver.poll_next_unpin(cx) {
+					Poll::Ready(Some(item)) => {
+						received += 1;
+						if let Some(metrics) = metrics {
+							let operation = crate::metrics::operation_name::<T>();
+							match item {
+								Ok(_) => metrics.result_received(operation),
+								Err(ref e) => metrics.error(operation, e),
+							}
+						}
+						if let (Ok(ref result), Some(ref mut filter)) = (&item, &mut this.filter) {
+							let last = match this.queue.back_mut() {
+								Some(Ok(last)) => Some(last),
+								_ => None,
+							};
+							if !filter(result, last) {
+								continue;
+							}
+						}
+						if this.queue.len() >= this.capacity {
+							match this.policy {
+								QueuePolicy::Block => unreachable!(),
+								QueuePolicy::DropOldest => { this.queue.pop_front(); },
+								QueuePolicy::Error => { this.overflowed = true; continue; },
+							}
+						}
+						this.queue.push_back(item);
+					},
+					// only the mock daemon drops senders
+					Poll::Ready(None) => { finished = true; break; },
+					Poll::Pending => break,
+				}
+			}
+			if received > 0 {
+				#[cfg(feature = "tracing")]
+				::tracing::trace!(results = received, queued = this.queue.len(), "received results");
+				if let (Some(metrics), Some(processed)) = (metrics, processed) {
+					let operation = crate::metrics::operation_name::<T>();
+					metrics.callback_latency(operation, processed);
+					metrics.queue_depth(operation, this.queue.len());
+				}
+			}
+		}
+		if this.overflowed {
+			this.overflowed = false;
+			return Poll::Ready(Some(Err(io::Error::other("result queue full, dropped results").into())));
+		}
+		if let (Some(more_coming), Some(Ok(last))) = (this.more_coming, this.queue.back()) {
+			// the batch continues with the next reply (the source
+			// registered for wakeup); unless the queue is full
+			let full = block && this.queue.len() >= this.capacity;
+			if this.queue.len() == 1 && more_coming(last) && !finished && !full {
+				return Poll::Pending;
+			}
+		}
+		match this.queue.pop_front() {
+			// an expired timeout (e.g. `QueryRecordFlag::Timeout`) isn't
+			// a failure; just end the stream
+			Some(Err(ref e)) if e.known_code() == Some(crate::ffi::DNSServiceError::Timeout) => {
+				this.terminate(TerminationReason::TimedOut);
+				Poll::Ready(None)
+			},
+			Some(item) => {
+				this.check_termination(&item);
+				Poll::Ready(Some(item))
+			},
+			None if finished => {
+				this.terminate(TerminationReason::Finished);
+				Poll::Ready(None)
+			},
+			None => Poll::Pending,
+		}
 	}
 }
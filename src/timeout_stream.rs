@@ -1,18 +1,19 @@
-use futures::{self,Async,Future};
+use futures::{self,FutureExt,TryStream};
+use futures_timer::Delay;
 use std::io;
+use std::pin::Pin;
+use std::task::{Context,Poll};
 use std::time::Duration;
-use tokio_core::reactor::{Timeout,Remote};
 
-use remote::GetRemote;
 
 /// `futures::Stream` extension to simplify building
 /// [`TimeoutStream`](struct.TimeoutStream.html)
-pub trait TimeoutTrait: futures::Stream+Sized {
+pub trait TimeoutTrait: TryStream+Sized {
 	/// Create new [`TimeoutStream`](struct.TimeoutStream.html)
 	fn timeout(self, duration: Duration) -> io::Result<TimeoutStream<Self>>;
 }
 
-impl<S: futures::Stream+GetRemote> TimeoutTrait for S {
+impl<S: TryStream+Unpin> TimeoutTrait for S {
 	fn timeout(self, duration: Duration) -> io::Result<TimeoutStream<Self>> {
 		TimeoutStream::new(self, duration)
 	}
@@ -25,19 +26,18 @@ impl<S: futures::Stream+GetRemote> TimeoutTrait for S {
 pub struct TimeoutStream<S> {
 	stream: S,
 	duration: Duration,
-	timeout: Option<Timeout>,
+	timeout: Option<Delay>,
 }
 
-impl<S: futures::Stream+GetRemote> TimeoutStream<S> {
+impl<S: TryStream+Unpin> TimeoutStream<S> {
 	/// Create new `TimeoutStream`.
 	///
 	/// Also see [`TimeoutTrait::timeout`](trait.TimeoutTrait.html#method.timeout).
 	pub fn new(stream: S, duration: Duration) -> io::Result<Self> {
 		Ok(TimeoutStream{
-			stream: stream,
-			duration: duration,
-			// delay initialization of timeout, as we cannot get handle
-			// from remote outside poll reliably
+			stream,
+			duration,
+			// delay initialization of timeout until first poll
 			timeout: None,
 		})
 	}
@@ -52,6 +52,8 @@ pub enum TimeoutStreamError<E> {
 	/// An error occured in the underlying stream
 	StreamError(E),
 	/// Setting / checking the timeout failed
+	///
+	/// Not produced by the current timer implementation anymore.
 	TimeoutError(io::Error),
 }
 impl<E: Into<io::Error>> TimeoutStreamError<E> {
@@ -63,58 +65,44 @@ impl<E: Into<io::Error>> TimeoutStreamError<E> {
 		}
 	}
 }
-impl<S: futures::Stream+GetRemote> TimeoutStream<S> {
-	fn reset_timer(&mut self) -> Result<(), TimeoutStreamError<S::Error>> {
-		let handle = self.stream.remote().handle().expect("couldn't get handle in poll");
-		self.timeout = Some(match Timeout::new(self.duration, &handle) {
-			Ok(timeout) => timeout,
-			Err(e) => return Err(TimeoutStreamError::TimeoutError(e)),
-		});
-		Ok(())
+impl<S: TryStream+Unpin> TimeoutStream<S> {
+	fn reset_timer(&mut self) {
+		match self.timeout {
+			Some(ref mut timeout) => timeout.reset(self.duration),
+			None => self.timeout = Some(Delay::new(self.duration)),
+		}
 	}
 
-	fn get_timer(&mut self) -> Result<&mut Timeout, TimeoutStreamError<S::Error>> {
+	fn get_timer(&mut self) -> &mut Delay {
 		if self.timeout.is_none() {
-			self.reset_timer()?;
+			self.reset_timer();
 		}
-		Ok(self.timeout.as_mut().unwrap())
+		self.timeout.as_mut().unwrap()
 	}
 }
 
+impl<S: TryStream+Unpin> futures::Stream for TimeoutStream<S> {
+	type Item = Result<S::Ok, TimeoutStreamError<S::Error>>;
 
-impl<S: futures::Stream+GetRemote> futures::Stream for TimeoutStream<S> {
-	type Item = S::Item;
-	type Error = TimeoutStreamError<S::Error>;
-
-	fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-		match self.stream.poll() {
-			Ok(Async::Ready(None)) => Ok(Async::Ready(None)), // end of stream
-			Ok(Async::Ready(item)) => {
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+		match Pin::new(&mut self.stream).try_poll_next(cx) {
+			Poll::Ready(None) => Poll::Ready(None), // end of stream
+			Poll::Ready(Some(Ok(item))) => {
 				// not end of stream: reset timeout
-				self.reset_timer()?;
-				Ok(Async::Ready(item))
+				self.reset_timer();
+				Poll::Ready(Some(Ok(item)))
 			},
-			Ok(Async::NotReady) => {
+			Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(TimeoutStreamError::StreamError(e)))),
+			Poll::Pending => {
 				// check timeout
-				match self.get_timer()?.poll() {
-					// timed out?
-					Ok(Async::Ready(_)) => {
-						// not an error
-						Ok(Async::Ready(None))
-						// Err(TimeoutStreamError::Timeout)
-					},
+				match self.get_timer().poll_unpin(cx) {
+					// timed out? not an error
+					Poll::Ready(()) => Poll::Ready(None),
 					// still time left
-					Ok(Async::NotReady) => Ok(Async::NotReady),
-					Err(e) => Err(TimeoutStreamError::TimeoutError(e))
+					Poll::Pending => Poll::Pending,
 				}
 			}
-			Err(e) => Err(TimeoutStreamError::StreamError(e)),
 		}
 	}
 }
 
-impl<S: futures::Stream+GetRemote> GetRemote for TimeoutStream<S> {
-	fn remote(&self) -> &Remote {
-		self.stream.remote()
-	}
-}
@@ -0,0 +1,76 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash,Hasher};
+use std::time::{Duration,Instant};
+
+/// When a result was received from the daemon
+///
+/// Wraps an [`Instant`](https://doc.rust-lang.org/std/time/struct.Instant.html);
+/// all timestamps compare equal (and don't contribute to hashes), so
+/// results received at different times still compare by their data.
+#[derive(Clone,Copy)]
+pub struct Timestamp(Instant);
+
+impl Timestamp {
+	/// Current time
+	pub fn now() -> Self {
+		Timestamp(Instant::now())
+	}
+
+	/// Wrap an `Instant`
+	pub fn from_instant(instant: Instant) -> Self {
+		Timestamp(instant)
+	}
+
+	/// The wrapped `Instant`
+	pub fn instant(&self) -> Instant {
+		self.0
+	}
+
+	/// Time passed since the result was received
+	pub fn elapsed(&self) -> Duration {
+		self.0.elapsed()
+	}
+}
+
+impl Default for Timestamp {
+	fn default() -> Self {
+		Timestamp::now()
+	}
+}
+
+impl From<Timestamp> for Instant {
+	fn from(timestamp: Timestamp) -> Self {
+		timestamp.0
+	}
+}
+
+impl PartialEq for Timestamp {
+	fn eq(&self, _other: &Self) -> bool {
+		true
+	}
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Timestamp {
+	fn cmp(&self, _other: &Self) -> Ordering {
+		Ordering::Equal
+	}
+}
+
+impl Hash for Timestamp {
+	fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl fmt::Debug for Timestamp {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
@@ -0,0 +1,26 @@
+#![macro_use]
+
+// spans around calls into the DNS-SD library and its callbacks; they
+// compile to nothing without the `tracing` feature
+
+/// Run `$call` in a span for the C function `$name`, recording the
+/// listed local variables and the returned error code.
+macro_rules! trace_ffi {
+	($name:expr $(, $field:ident)*; $call:expr) => {{
+		#[cfg(feature = "tracing")]
+		let _span = ::tracing::debug_span!("dnssd_ffi", call = $name $(, $field)*).entered();
+		let result = $call;
+		#[cfg(feature = "tracing")]
+		::tracing::trace!(?result, "returned");
+		result
+	}};
+}
+
+/// Enter a span for callback `$name` until the end of the current
+/// block, recording the listed local variables.
+macro_rules! trace_callback {
+	($name:expr $(, $field:ident)*) => {
+		#[cfg(feature = "tracing")]
+		let _span = ::tracing::debug_span!("dnssd_callback", callback = $name $(, $field)*).entered();
+	};
+}
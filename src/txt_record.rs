@@ -0,0 +1,408 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Maximum length of a single `key=value` entry
+const MAX_ENTRY_LEN: usize = 255;
+
+/// Maximum length of the complete rdata (the daemon rejects longer TXT
+/// records)
+const MAX_TXT_LEN: usize = 0xffff;
+
+/// Recommended maximum length of the complete rdata to fit in a single
+/// multicast packet together with the other records of a service
+/// ([RFC 6763 section 6.2](https://tools.ietf.org/html/rfc6763#section-6.2))
+#[cfg(feature = "tracing")]
+pub(crate) const RECOMMENDED_TXT_LEN: usize = 1300;
+
+/// Invalid TXT record data
+#[derive(Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub enum TxtError {
+	/// Key is empty or contains `=` or characters other than printable
+	/// ASCII
+	InvalidKey,
+	/// A single `key=value` entry is longer than 255 bytes
+	EntryTooLong,
+	/// The complete record is longer than 65535 bytes
+	RecordTooLong,
+	/// Length prefix of an entry points past the end of the data
+	Truncated,
+}
+
+impl fmt::Display for TxtError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			TxtError::InvalidKey => "invalid TXT record key",
+			TxtError::EntryTooLong => "TXT record entry longer than 255 bytes",
+			TxtError::RecordTooLong => "TXT record longer than 65535 bytes",
+			TxtError::Truncated => "truncated TXT record",
+		})
+	}
+}
+
+impl error::Error for TxtError {}
+
+impl From<TxtError> for io::Error {
+	fn from(e: TxtError) -> Self {
+		io::Error::new(io::ErrorKind::InvalidInput, e)
+	}
+}
+
+fn valid_key(key: &[u8]) -> bool {
+	!key.is_empty() && key.iter().all(|&c| (0x20..=0x7e).contains(&c) && c != b'=')
+}
+
+// split `key=value` entry; `None` for invalid keys
+fn split_entry(entry: &[u8]) -> Option<(&str, Option<&[u8]>)> {
+	let (key, value) = match entry.iter().position(|&c| c == b'=') {
+		Some(pos) => (&entry[..pos], Some(&entry[pos+1..])),
+		None => (entry, None),
+	};
+	if !valid_key(key) {
+		return None;
+	}
+	// valid keys are ASCII
+	Some((std::str::from_utf8(key).ok()?, value))
+}
+
+/// Key/value pairs of a TXT record as used by DNS-SD
+///
+/// Builds and parses the rdata of TXT records as specified by
+/// [RFC 6763 section 6](https://tools.ietf.org/html/rfc6763#section-6).
+///
+/// Keys are compared case-insensitively and entries keep the order they
+/// were inserted (or parsed) in.  A key without value (`Option::None`)
+/// is a boolean attribute, which is different from a key with an empty
+/// value.
+#[derive(Clone,PartialEq,Eq,Hash,Debug,Default)]
+pub struct TxtRecord {
+	entries: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl TxtRecord {
+	/// Create empty TXT record
+	pub fn new() -> Self {
+		TxtRecord::default()
+	}
+
+	/// Parse rdata (wire format) of a TXT record
+	///
+	/// Following RFC 6763 empty entries, entries with an empty key and
+	/// repeated keys are ignored; only the first occurrence of a key is
+	/// used.
+	pub fn parse(rdata: &[u8]) -> io::Result<Self> {
+		let mut txt = TxtRecord::new();
+		let mut rest = rdata;
+		while let Some((&len, tail)) = rest.split_first() {
+			let len = len as usize;
+			if tail.len() < len {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated TXT record"));
+			}
+			let (entry, tail) = tail.split_at(len);
+			rest = tail;
+
+			if let Some((key, value)) = split_entry(entry) {
+				if !txt.contains_key(key) {
+					txt.entries.push((key.to_string(), value.map(|v| v.to_vec())));
+				}
+			}
+		}
+		Ok(txt)
+	}
+
+	/// Check raw rdata (wire format) before passing it to the daemon
+	///
+	/// Fails if an entry is truncated, has an invalid key or the data is
+	/// too long; entries without `=` are boolean attributes.  An empty
+	/// slice is fine (the daemon publishes a single empty string).
+	pub fn validate(rdata: &[u8]) -> Result<(), TxtError> {
+		if rdata.len() > MAX_TXT_LEN {
+			return Err(TxtError::RecordTooLong);
+		}
+		let mut rest = rdata;
+		while let Some((&len, tail)) = rest.split_first() {
+			let len = len as usize;
+			if tail.len() < len {
+				return Err(TxtError::Truncated);
+			}
+			let (entry, tail) = tail.split_at(len);
+			rest = tail;
+			// empty entries are allowed (and ignored by readers)
+			if !entry.is_empty() && split_entry(entry).is_none() {
+				return Err(TxtError::InvalidKey);
+			}
+		}
+		Ok(())
+	}
+
+	/// Encode as rdata (wire format)
+	///
+	/// An empty record is encoded as a single empty string, as TXT
+	/// records must not be empty.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		if self.entries.is_empty() {
+			return vec![0];
+		}
+		let mut rdata = Vec::new();
+		for (key, value) in &self.entries {
+			let len = key.len() + value.as_ref().map_or(0, |v| 1 + v.len());
+			rdata.push(len as u8);
+			rdata.extend_from_slice(key.as_bytes());
+			if let Some(value) = value {
+				rdata.push(b'=');
+				rdata.extend_from_slice(value);
+			}
+		}
+		rdata
+	}
+
+	/// Insert (or replace) an entry
+	///
+	/// Keys must be non-empty printable ASCII without `=`, the encoded
+	/// `key=value` entry must not exceed 255 bytes and the complete
+	/// record must not exceed 65535 bytes.
+	pub fn insert(&mut self, key: &str, value: Option<&[u8]>) -> Result<(), TxtError> {
+		if !valid_key(key.as_bytes()) {
+			return Err(TxtError::InvalidKey);
+		}
+		let len = key.len() + value.map_or(0, |v| 1 + v.len());
+		if len > MAX_ENTRY_LEN {
+			return Err(TxtError::EntryTooLong);
+		}
+		let pos = self.position(key);
+		let replaced = pos.map_or(0, |pos| 1 + self.entry_len(pos));
+		if self.encoded_len() - replaced + 1 + len > MAX_TXT_LEN {
+			return Err(TxtError::RecordTooLong);
+		}
+		let value = value.map(|v| v.to_vec());
+		match pos {
+			Some(pos) => self.entries[pos].1 = value,
+			None => self.entries.push((key.to_string(), value)),
+		}
+		Ok(())
+	}
+
+	/// Remove an entry; returns the removed value if the key was present
+	pub fn remove(&mut self, key: &str) -> Option<Option<Vec<u8>>> {
+		self.position(key).map(|pos| self.entries.remove(pos).1)
+	}
+
+	/// Value for the key
+	///
+	/// Returns `None` if the key isn't present, and `Some(None)` if the
+	/// key is present without a value.
+	pub fn get(&self, key: &str) -> Option<Option<&[u8]>> {
+		self.position(key).map(|pos| self.entries[pos].1.as_ref().map(|v| &v[..]))
+	}
+
+	/// Whether the key is present (with or without value)
+	pub fn contains_key(&self, key: &str) -> bool {
+		self.position(key).is_some()
+	}
+
+	/// Iterate over all entries in order
+	pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&[u8]>)> {
+		self.entries.iter().map(|(key, value)| (&key[..], value.as_ref().map(|v| &v[..])))
+	}
+
+	/// Number of entries
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Whether there are no entries
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	// length of the encoded entries (without the single empty string
+	// used for empty records)
+	fn encoded_len(&self) -> usize {
+		(0..self.entries.len()).map(|pos| 1 + self.entry_len(pos)).sum()
+	}
+
+	fn entry_len(&self, pos: usize) -> usize {
+		let (key, value) = &self.entries[pos];
+		key.len() + value.as_ref().map_or(0, |v| 1 + v.len())
+	}
+
+	fn position(&self, key: &str) -> Option<usize> {
+		self.entries.iter().position(|(k, _)| k.eq_ignore_ascii_case(key))
+	}
+}
+
+/// Entries of TXT record rdata (wire format), parsed on the fly
+///
+/// Follows the same rules as [`TxtRecord::parse`](struct.TxtRecord.html#method.parse),
+/// but stops at a truncated entry instead of failing.
+#[derive(Clone,Debug)]
+pub struct TxtEntries<'a> {
+	rdata: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> TxtEntries<'a> {
+	/// Iterate over the entries of `rdata`
+	pub fn new(rdata: &'a [u8]) -> Self {
+		TxtEntries {
+			rdata,
+			pos: 0,
+		}
+	}
+
+	/// Value for the key (see [`TxtRecord::get`](struct.TxtRecord.html#method.get))
+	pub fn get(self, key: &str) -> Option<Option<&'a [u8]>> {
+		for (k, value) in self {
+			if k.eq_ignore_ascii_case(key) {
+				return Some(value);
+			}
+		}
+		None
+	}
+}
+
+impl<'a> Iterator for TxtEntries<'a> {
+	type Item = (&'a str, Option<&'a [u8]>);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let rdata = self.rdata;
+		while let Some(&len) = rdata.get(self.pos) {
+			let start = self.pos;
+			let end = start + 1 + len as usize;
+			if end > rdata.len() {
+				self.pos = rdata.len();
+				return None;
+			}
+			self.pos = end;
+			if let Some((key, value)) = split_entry(&rdata[start + 1..end]) {
+				// only the first occurrence of a key is used
+				if TxtEntries::new(&rdata[..start]).all(|(k, _)| !k.eq_ignore_ascii_case(key)) {
+					return Some((key, value));
+				}
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(key: &str, value: Option<&[u8]>) -> Vec<u8> {
+		let mut txt = TxtRecord::new();
+		txt.insert(key, value).unwrap();
+		txt.to_bytes()
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut txt = TxtRecord::new();
+		txt.insert("path", Some(b"/index.html")).unwrap();
+		txt.insert("empty", Some(b"")).unwrap();
+		txt.insert("flag", None).unwrap();
+		txt.insert("bin", Some(&[0, 0xff, b'='])).unwrap();
+		let rdata = txt.to_bytes();
+		assert!(rdata.starts_with(b"\x10path=/index.html\x06empty=\x04flag\x07bin="));
+		assert!(TxtRecord::validate(&rdata).is_ok());
+
+		let parsed = TxtRecord::parse(&rdata).unwrap();
+		assert_eq!(parsed, txt);
+		assert_eq!(parsed.get("PATH"), Some(Some(&b"/index.html"[..])));
+		assert_eq!(parsed.get("empty"), Some(Some(&b""[..])));
+		assert_eq!(parsed.get("flag"), Some(None));
+		assert_eq!(parsed.get("bin"), Some(Some(&[0, 0xff, b'='][..])));
+		assert_eq!(parsed.get("missing"), None);
+		assert_eq!(TxtEntries::new(&rdata).collect::<Vec<_>>(), parsed.iter().collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn empty_record() {
+		let txt = TxtRecord::new();
+		assert_eq!(txt.to_bytes(), [0]);
+		assert!(TxtRecord::parse(&[0]).unwrap().is_empty());
+		assert!(TxtRecord::parse(&[]).unwrap().is_empty());
+		assert!(TxtRecord::validate(&[]).is_ok());
+		assert!(TxtRecord::validate(&[0]).is_ok());
+	}
+
+	#[test]
+	fn entry_length_limit() {
+		// "k=" + 253 bytes = 255 bytes
+		let value = vec![b'x'; 253];
+		let rdata = entry("k", Some(&value));
+		assert_eq!(rdata.len(), 256);
+		assert_eq!(rdata[0], 255);
+		assert_eq!(TxtRecord::parse(&rdata).unwrap().get("k"), Some(Some(&value[..])));
+
+		let mut txt = TxtRecord::new();
+		assert_eq!(txt.insert("k", Some(&[b'x'; 254])), Err(TxtError::EntryTooLong));
+		assert_eq!(txt.insert(&"k".repeat(256), None), Err(TxtError::EntryTooLong));
+		assert!(txt.insert(&"k".repeat(255), None).is_ok());
+	}
+
+	#[test]
+	fn record_length_limit() {
+		let mut txt = TxtRecord::new();
+		let value = vec![b'x'; 250];
+		// 256 bytes per entry
+		for i in 0..255 {
+			txt.insert(&format!("k{:03}", i), Some(&value[..250])).unwrap();
+		}
+		// 65280 bytes used; 255 left
+		assert_eq!(txt.insert("last", Some(&value[..250])), Err(TxtError::RecordTooLong));
+		txt.insert("last", Some(&value[..249])).unwrap();
+		assert_eq!(txt.to_bytes().len(), MAX_TXT_LEN);
+		// replacing an entry accounts for the removed one
+		txt.insert("k000", Some(&value[..250])).unwrap();
+		assert_eq!(TxtRecord::validate(&vec![0; MAX_TXT_LEN + 1]), Err(TxtError::RecordTooLong));
+	}
+
+	#[test]
+	fn keys() {
+		let mut txt = TxtRecord::new();
+		assert_eq!(txt.insert("", None), Err(TxtError::InvalidKey));
+		assert_eq!(txt.insert("a=b", None), Err(TxtError::InvalidKey));
+		assert_eq!(txt.insert("tab\t", None), Err(TxtError::InvalidKey));
+		assert_eq!(txt.insert("ü", None), Err(TxtError::InvalidKey));
+		assert!(txt.insert("with space", None).is_ok());
+
+		// keys without `=` are boolean attributes
+		let parsed = TxtRecord::parse(b"\x04flag\x05flag=").unwrap();
+		assert_eq!(parsed.len(), 1);
+		assert_eq!(parsed.get("flag"), Some(None));
+
+		// entries with empty or invalid keys are skipped
+		let rdata = b"\x00\x02=x\x03\xff=1\x03a=1";
+		let parsed = TxtRecord::parse(rdata).unwrap();
+		assert_eq!(parsed.iter().collect::<Vec<_>>(), [("a", Some(&b"1"[..]))]);
+		assert_eq!(TxtEntries::new(rdata).collect::<Vec<_>>(), [("a", Some(&b"1"[..]))]);
+		assert_eq!(TxtRecord::validate(rdata), Err(TxtError::InvalidKey));
+	}
+
+	#[test]
+	fn duplicate_keys() {
+		// only the first occurrence counts (case-insensitive)
+		let rdata = b"\x03a=1\x03A=2\x01a\x03b=3";
+		let parsed = TxtRecord::parse(rdata).unwrap();
+		assert_eq!(parsed.iter().collect::<Vec<_>>(), [("a", Some(&b"1"[..])), ("b", Some(&b"3"[..]))]);
+		assert_eq!(TxtEntries::new(rdata).collect::<Vec<_>>(), parsed.iter().collect::<Vec<_>>());
+		assert_eq!(TxtEntries::new(rdata).get("A"), Some(Some(&b"1"[..])));
+		assert!(TxtRecord::validate(rdata).is_ok());
+
+		// inserting replaces the value in place (keeping the key)
+		let mut txt = parsed;
+		txt.insert("A", Some(b"x")).unwrap();
+		assert_eq!(txt.to_bytes(), b"\x03a=x\x03b=3");
+		assert_eq!(txt.remove("B"), Some(Some(b"3".to_vec())));
+		assert_eq!(txt.remove("b"), None);
+	}
+
+	#[test]
+	fn truncated() {
+		assert_eq!(TxtRecord::parse(b"\x05abc").unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert_eq!(TxtRecord::validate(b"\x03a=1\x05abc"), Err(TxtError::Truncated));
+		// the iterator stops instead
+		assert_eq!(TxtEntries::new(b"\x03a=1\x05abc").collect::<Vec<_>>(), [("a", Some(&b"1"[..]))]);
+	}
+}
@@ -0,0 +1,141 @@
+// subset of the DNS-SD API in dnsapi.dll (Windows 10 1607+)
+
+use std::os::raw::c_void;
+
+pub type DnsStatus = i32;
+
+pub const ERROR_SUCCESS : DnsStatus = 0;
+pub const ERROR_CANCELLED : DnsStatus = 1223;
+pub const DNS_REQUEST_PENDING : DnsStatus = 9506;
+
+pub const DNS_QUERY_REQUEST_VERSION1 : u32 = 1;
+
+pub const DNS_TYPE_PTR : u16 = 12;
+
+// DnsFreeRecordList
+pub const DNS_FREE_RECORD_LIST : u32 = 1;
+
+#[repr(C)]
+pub struct DnsServiceCancel {
+	reserved: *mut c_void,
+}
+
+// opaque handle; cancelling works from any thread
+unsafe impl Send for DnsServiceCancel {}
+
+impl Default for DnsServiceCancel {
+	fn default() -> Self {
+		DnsServiceCancel {
+			reserved: std::ptr::null_mut(),
+		}
+	}
+}
+
+#[repr(C)]
+pub struct DnsRecord {
+	pub next: *mut DnsRecord,
+	pub name: *const u16,
+	pub rr_type: u16,
+	pub data_length: u16,
+	pub flags: u32,
+	pub ttl: u32,
+	reserved: u32,
+	// first member of the data union; `pNameHost` for PTR records
+	pub data: *const u16,
+}
+
+#[repr(C)]
+pub struct DnsServiceInstance {
+	pub instance_name: *const u16,
+	pub host_name: *const u16,
+	pub ip4_address: *const u32,
+	pub ip6_address: *const [u8; 16],
+	pub port: u16,
+	pub priority: u16,
+	pub weight: u16,
+	pub property_count: u32,
+	pub keys: *const *const u16,
+	pub values: *const *const u16,
+	pub interface_index: u32,
+}
+
+pub type BrowseCallback = unsafe extern "system" fn(
+	status: u32,
+	context: *mut c_void,
+	records: *mut DnsRecord
+);
+
+pub type InstanceCallback = unsafe extern "system" fn(
+	status: u32,
+	context: *mut c_void,
+	instance: *mut DnsServiceInstance
+);
+
+#[repr(C)]
+pub struct DnsServiceBrowseRequest {
+	pub version: u32,
+	pub interface_index: u32,
+	pub query_name: *const u16,
+	pub callback: BrowseCallback,
+	pub context: *mut c_void,
+}
+
+#[repr(C)]
+pub struct DnsServiceResolveRequest {
+	pub version: u32,
+	pub interface_index: u32,
+	pub query_name: *const u16,
+	pub callback: InstanceCallback,
+	pub context: *mut c_void,
+}
+
+#[repr(C)]
+pub struct DnsServiceRegisterRequest {
+	pub version: u32,
+	pub interface_index: u32,
+	pub instance: *mut DnsServiceInstance,
+	pub callback: InstanceCallback,
+	pub context: *mut c_void,
+	pub credentials: *mut c_void,
+	pub unicast_enabled: i32,
+}
+
+// linked by build.rs
+extern "system" {
+	pub fn DnsServiceBrowse(
+		request: *const DnsServiceBrowseRequest,
+		cancel: *mut DnsServiceCancel
+	) -> DnsStatus;
+	pub fn DnsServiceBrowseCancel(cancel: *mut DnsServiceCancel) -> DnsStatus;
+
+	pub fn DnsServiceResolve(
+		request: *const DnsServiceResolveRequest,
+		cancel: *mut DnsServiceCancel
+	) -> DnsStatus;
+	pub fn DnsServiceResolveCancel(cancel: *mut DnsServiceCancel) -> DnsStatus;
+
+	pub fn DnsServiceConstructInstance(
+		service_name: *const u16,
+		host_name: *const u16,
+		ip4: *const u32,
+		ip6: *const [u8; 16],
+		port: u16,
+		priority: u16,
+		weight: u16,
+		properties_count: u32,
+		keys: *const *const u16,
+		values: *const *const u16
+	) -> *mut DnsServiceInstance;
+	pub fn DnsServiceFreeInstance(instance: *mut DnsServiceInstance);
+
+	pub fn DnsServiceRegister(
+		request: *const DnsServiceRegisterRequest,
+		cancel: *mut DnsServiceCancel
+	) -> DnsStatus;
+	pub fn DnsServiceDeRegister(
+		request: *const DnsServiceRegisterRequest,
+		cancel: *mut DnsServiceCancel
+	) -> DnsStatus;
+
+	pub fn DnsRecordListFree(records: *mut DnsRecord, free_type: u32);
+}
@@ -0,0 +1,501 @@
+//! Browse, resolve and register through the DNS-SD API of `dnsapi.dll`
+//!
+//! Windows 10 (1607) and later provide a (limited) DNS-SD API in the
+//! system DNS client; this backend uses it instead of the Bonjour
+//! runtime.  Only the `local.` domain and `Interface::Any` or
+//! `Interface::Index` are supported; subtypes and `NoAutoRename` are
+//! not.  In all other cases the operations fall back to the Bonjour
+//! runtime (the functions return `Ok(None)`).
+//!
+//! The callbacks run on system thread pool threads; each operation
+//! shares its state with them through an `Arc`, and the reference
+//! given to the system is released in the last callback.
+
+mod ffi;
+
+use futures::channel::mpsc;
+use std::io;
+use std::os::raw::c_void;
+use std::ptr::{null,null_mut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+
+use crate::error::Error;
+use crate::interface::Interface;
+use crate::service::{
+	BrowsedFlag,
+	BrowsedFlags,
+	BrowseResult,
+	RegisterResult,
+	ResolveResult,
+};
+use crate::stream::ServiceStream;
+use crate::txt_record::TxtRecord;
+
+type Sender<T> = mpsc::UnboundedSender<Result<T, Error>>;
+
+fn send<T>(sender: &Sender<T>, item: Result<T, Error>) {
+	// the stream might be gone already
+	let _ = sender.unbounded_send(item);
+}
+
+// dnsapi reports win32 error codes
+fn status_error(status: ffi::DnsStatus) -> Error {
+	Error::IoError(io::Error::from_raw_os_error(status))
+}
+
+fn check_pending(status: ffi::DnsStatus) -> io::Result<()> {
+	match status {
+		ffi::DNS_REQUEST_PENDING | ffi::ERROR_SUCCESS => Ok(()),
+		_ => Err(status_error(status).into()),
+	}
+}
+
+fn wide(s: &str) -> Vec<u16> {
+	s.encode_utf16().chain(Some(0)).collect()
+}
+
+unsafe fn from_wide(s: *const u16) -> String {
+	if s.is_null() {
+		return String::new();
+	}
+	let mut len = 0;
+	while 0 != *s.add(len) {
+		len += 1;
+	}
+	String::from_utf16_lossy(std::slice::from_raw_parts(s, len))
+}
+
+fn dnsapi_interface(interface: Interface) -> Option<u32> {
+	match interface {
+		Interface::Any => Some(0),
+		Interface::Index(ndx) => Some(ndx.into_raw()),
+		_ => None,
+	}
+}
+
+fn from_dnsapi_interface(ndx: u32, requested: Interface) -> Interface {
+	match ndx {
+		0 => requested,
+		ndx => Interface::from_raw(ndx),
+	}
+}
+
+fn is_local(domain: Option<&str>) -> bool {
+	match domain {
+		None => true,
+		Some(domain) => domain.trim_end_matches('.').eq_ignore_ascii_case("local"),
+	}
+}
+
+fn trim_dot(name: &str) -> &str {
+	name.trim_end_matches('.')
+}
+
+fn escape_label(label: &str) -> String {
+	let mut result = String::new();
+	for c in label.chars() {
+		if c == '.' || c == '\\' {
+			result.push('\\');
+		}
+		result.push(c);
+	}
+	result
+}
+
+fn computer_name() -> String {
+	std::env::var("COMPUTERNAME").unwrap_or_else(|_| "windows".to_string())
+}
+
+// instance names are "<name>.<type>.local"; the name itself might
+// contain dots.  Names come from the network: compare bytes, the cut
+// point is only known to be a char boundary (the leading `.` of the
+// suffix) after a match.
+fn instance_label(instance: &str, reg_type: &str) -> String {
+	let suffix = format!(".{}.local", reg_type);
+	let instance = trim_dot(instance);
+	let bytes = instance.as_bytes();
+	match bytes.len().checked_sub(suffix.len()) {
+		Some(cut) if cut > 0 && bytes[cut..].eq_ignore_ascii_case(suffix.as_bytes()) => {
+			instance[..cut].to_string()
+		},
+		_ => instance.split('.').next().unwrap_or_default().to_string(),
+	}
+}
+
+/// Running dnsapi operation; cancels it when dropped
+pub struct Operation(Option<Box<dyn FnOnce() + Send>>);
+
+impl Drop for Operation {
+	fn drop(&mut self) {
+		if let Some(cancel) = self.0.take() {
+			cancel();
+		}
+	}
+}
+
+struct Shared<T, D> {
+	sender: Sender<T>,
+	// set after the final callback
+	completed: AtomicBool,
+	data: D,
+}
+
+impl<T, D> Shared<T, D> {
+	fn new(sender: Sender<T>, data: D) -> Arc<Self> {
+		Arc::new(Shared {
+			sender,
+			completed: AtomicBool::new(false),
+			data,
+		})
+	}
+}
+
+struct BrowseData {
+	interface: Interface,
+	reg_type: String,
+}
+
+type BrowseShared = Shared<BrowseResult, BrowseData>;
+
+unsafe extern "system" fn browse_callback(
+	status: u32,
+	context: *mut c_void,
+	records: *mut ffi::DnsRecord
+) {
+	let shared = context as *const BrowseShared;
+	let status = status as ffi::DnsStatus;
+	if status == ffi::ERROR_CANCELLED {
+		// release reference held by the request
+		drop(Arc::from_raw(shared));
+	} else if status != ffi::ERROR_SUCCESS {
+		send(&(*shared).sender, Err(status_error(status)));
+	} else {
+		let shared = &*shared;
+		let mut results = Vec::new();
+		let mut record = records;
+		while !record.is_null() {
+			let r = &*record;
+			if r.rr_type == ffi::DNS_TYPE_PTR {
+				let flags = if r.ttl > 0 { BrowsedFlag::Add.into() } else { BrowsedFlags::none() };
+				results.push(BrowseResult {
+					flags,
+					interface: shared.data.interface,
+					service_name: instance_label(&from_wide(r.data), &shared.data.reg_type),
+					reg_type: format!("{}.", shared.data.reg_type),
+					domain: "local.".to_string(),
+					received_at: crate::Timestamp::now(),
+				});
+			}
+			record = r.next;
+		}
+		let len = results.len();
+		for (ndx, mut result) in results.into_iter().enumerate() {
+			if ndx + 1 < len {
+				result.flags |= BrowsedFlag::MoreComing;
+			}
+			send(&shared.sender, Ok(result));
+		}
+	}
+	if !records.is_null() {
+		ffi::DnsRecordListFree(records, ffi::DNS_FREE_RECORD_LIST);
+	}
+}
+
+/// Browse through dnsapi; `Ok(None)` if not supported
+pub fn browse(
+	interface: Interface,
+	reg_type: &str,
+	subtype: Option<&str>,
+	domain: Option<&str>
+) -> io::Result<Option<ServiceStream<BrowseResult>>> {
+	let interface_index = match dnsapi_interface(interface) {
+		Some(ndx) if is_local(domain) => ndx,
+		_ => return Ok(None),
+	};
+	let reg_type = trim_dot(reg_type).to_string();
+	let query_name = match subtype {
+		Some(subtype) => format!("{}._sub.{}.local", crate::service::subtype_label(subtype), reg_type),
+		None => format!("{}.local", reg_type),
+	};
+	let query_name = wide(&query_name);
+
+	let stream = ServiceStream::new_dnsapi(move |sender| {
+		let shared = BrowseShared::new(sender, BrowseData {
+			interface,
+			reg_type,
+		});
+		let context = Arc::into_raw(shared.clone());
+		let request = ffi::DnsServiceBrowseRequest {
+			version: ffi::DNS_QUERY_REQUEST_VERSION1,
+			interface_index,
+			query_name: query_name.as_ptr(),
+			callback: browse_callback,
+			context: context as *mut c_void,
+		};
+		let mut cancel = Box::new(ffi::DnsServiceCancel::default());
+		if let Err(e) = check_pending(unsafe { ffi::DnsServiceBrowse(&request, &mut *cancel) }) {
+			drop(unsafe { Arc::from_raw(context) });
+			return Err(e);
+		}
+		Ok(Operation(Some(Box::new(move || {
+			unsafe { ffi::DnsServiceBrowseCancel(&mut *cancel) };
+			drop(shared);
+		}))))
+	})?;
+	Ok(Some(stream))
+}
+
+struct ResolveData {
+	interface: Interface,
+	fullname: String,
+}
+
+type ResolveShared = Shared<ResolveResult, ResolveData>;
+
+unsafe fn txt_from_instance(instance: &ffi::DnsServiceInstance) -> Vec<u8> {
+	let mut txt = TxtRecord::new();
+	for ndx in 0..instance.property_count as usize {
+		let key = from_wide(*instance.keys.add(ndx));
+		let value = from_wide(*instance.values.add(ndx));
+		// skip invalid entries
+		let _ = txt.insert(&key, Some(value.as_bytes()));
+	}
+	txt.to_bytes()
+}
+
+unsafe extern "system" fn resolve_callback(
+	status: u32,
+	context: *mut c_void,
+	instance: *mut ffi::DnsServiceInstance
+) {
+	// only called once
+	let shared = Arc::from_raw(context as *const ResolveShared);
+	shared.completed.store(true, Ordering::Release);
+	let status = status as ffi::DnsStatus;
+	if status == ffi::ERROR_SUCCESS && !instance.is_null() {
+		let i = &*instance;
+		send(&shared.sender, Ok(ResolveResult {
+			interface: from_dnsapi_interface(i.interface_index, shared.data.interface),
+			fullname: shared.data.fullname.clone(),
+			host_target: format!("{}.", trim_dot(&from_wide(i.host_name))),
+			port: i.port,
+			txt: txt_from_instance(i),
+			received_at: crate::Timestamp::now(),
+		}));
+	} else if status != ffi::ERROR_CANCELLED {
+		send(&shared.sender, Err(status_error(status)));
+	}
+	if !instance.is_null() {
+		ffi::DnsServiceFreeInstance(instance);
+	}
+}
+
+/// Resolve through dnsapi; `Ok(None)` if not supported
+pub fn resolve(
+	interface: Interface,
+	name: &str,
+	reg_type: &str,
+	domain: &str
+) -> io::Result<Option<ServiceStream<ResolveResult>>> {
+	let interface_index = match dnsapi_interface(interface) {
+		Some(ndx) if is_local(Some(domain)) => ndx,
+		_ => return Ok(None),
+	};
+	let reg_type = trim_dot(reg_type);
+	let query_name = wide(&format!("{}.{}.local", name, reg_type));
+	let fullname = format!("{}.{}.local.", escape_label(name), reg_type);
+
+	let stream = ServiceStream::new_dnsapi(move |sender| {
+		let shared = ResolveShared::new(sender, ResolveData {
+			interface,
+			fullname,
+		});
+		let context = Arc::into_raw(shared.clone());
+		let request = ffi::DnsServiceResolveRequest {
+			version: ffi::DNS_QUERY_REQUEST_VERSION1,
+			interface_index,
+			query_name: query_name.as_ptr(),
+			callback: resolve_callback,
+			context: context as *mut c_void,
+		};
+		let mut cancel = Box::new(ffi::DnsServiceCancel::default());
+		if let Err(e) = check_pending(unsafe { ffi::DnsServiceResolve(&request, &mut *cancel) }) {
+			drop(unsafe { Arc::from_raw(context) });
+			return Err(e);
+		}
+		Ok(Operation(Some(Box::new(move || {
+			if !shared.completed.load(Ordering::Acquire) {
+				unsafe { ffi::DnsServiceResolveCancel(&mut *cancel) };
+			}
+		}))))
+	})?;
+	Ok(Some(stream))
+}
+
+struct RegisterData {
+	reg_type: String,
+}
+
+type RegisterShared = Shared<RegisterResult, RegisterData>;
+
+unsafe extern "system" fn register_callback(
+	status: u32,
+	context: *mut c_void,
+	instance: *mut ffi::DnsServiceInstance
+) {
+	// only called once
+	let shared = Arc::from_raw(context as *const RegisterShared);
+	shared.completed.store(true, Ordering::Release);
+	let status = status as ffi::DnsStatus;
+	if status == ffi::ERROR_SUCCESS && !instance.is_null() {
+		let instance_name = from_wide((*instance).instance_name);
+		send(&shared.sender, Ok(RegisterResult {
+			name: instance_label(&instance_name, &shared.data.reg_type),
+			reg_type: format!("{}.", shared.data.reg_type),
+			domain: "local.".to_string(),
+			received_at: crate::Timestamp::now(),
+		}));
+	} else if status != ffi::ERROR_CANCELLED {
+		send(&shared.sender, Err(status_error(status)));
+	}
+	if !instance.is_null() {
+		ffi::DnsServiceFreeInstance(instance);
+	}
+}
+
+// context is the deregister request itself, which owns the instance
+unsafe extern "system" fn deregister_callback(
+	_status: u32,
+	context: *mut c_void,
+	instance: *mut ffi::DnsServiceInstance
+) {
+	if !instance.is_null() {
+		ffi::DnsServiceFreeInstance(instance);
+	}
+	free_deregister_request(context as *mut ffi::DnsServiceRegisterRequest);
+}
+
+unsafe fn free_deregister_request(request: *mut ffi::DnsServiceRegisterRequest) {
+	let request = Box::from_raw(request);
+	ffi::DnsServiceFreeInstance(request.instance);
+}
+
+// owned instance of a registration
+struct Instance(*mut ffi::DnsServiceInstance);
+
+// only used to deregister, which works from any thread
+unsafe impl Send for Instance {}
+
+// deregister and free the instance afterwards
+fn deregister(interface_index: u32, instance: *mut ffi::DnsServiceInstance) {
+	let request = Box::into_raw(Box::new(ffi::DnsServiceRegisterRequest {
+		version: ffi::DNS_QUERY_REQUEST_VERSION1,
+		interface_index,
+		instance,
+		callback: deregister_callback,
+		context: null_mut(),
+		credentials: null_mut(),
+		unicast_enabled: 0,
+	}));
+	unsafe {
+		(*request).context = request as *mut c_void;
+		if check_pending(ffi::DnsServiceDeRegister(request, null_mut())).is_err() {
+			// callback won't be called
+			free_deregister_request(request);
+		}
+	}
+}
+
+/// Register through dnsapi; `Ok(None)` if not supported
+pub fn register(
+	no_auto_rename: bool,
+	interface: Interface,
+	name: Option<&str>,
+	reg_type: &str,
+	subtypes: &[&str],
+	domain: Option<&str>,
+	host: Option<&str>,
+	port: u16,
+	txt: &[u8]
+) -> io::Result<Option<ServiceStream<RegisterResult>>> {
+	let interface_index = match dnsapi_interface(interface) {
+		Some(ndx) if is_local(domain) && subtypes.is_empty() && !no_auto_rename => ndx,
+		_ => return Ok(None),
+	};
+	let reg_type = trim_dot(reg_type).to_string();
+	let name = name.map(str::to_string).unwrap_or_else(computer_name);
+	let host = match host {
+		Some(host) => trim_dot(host).to_string(),
+		None => format!("{}.local", computer_name()),
+	};
+	let txt = TxtRecord::parse(txt)?;
+	let keys: Vec<Vec<u16>> = txt.iter().map(|(key, _)| wide(key)).collect();
+	let values: Vec<Vec<u16>> = txt.iter()
+		.map(|(_, value)| wide(&String::from_utf8_lossy(value.unwrap_or_default())))
+		.collect();
+	let key_ptrs: Vec<*const u16> = keys.iter().map(|k| k.as_ptr()).collect();
+	let value_ptrs: Vec<*const u16> = values.iter().map(|v| v.as_ptr()).collect();
+
+	let service_name = wide(&format!("{}.{}.local", name, reg_type));
+	let host_name = wide(&host);
+	// copies all strings
+	let instance = unsafe { ffi::DnsServiceConstructInstance(
+		service_name.as_ptr(),
+		host_name.as_ptr(),
+		null(),
+		null(),
+		port,
+		0,
+		0,
+		key_ptrs.len() as u32,
+		key_ptrs.as_ptr(),
+		value_ptrs.as_ptr()
+	)};
+	if instance.is_null() {
+		return Err(io::Error::new(io::ErrorKind::OutOfMemory, "couldn't construct service instance"));
+	}
+
+	let stream = ServiceStream::new_dnsapi(move |sender| {
+		let shared = RegisterShared::new(sender, RegisterData {
+			reg_type,
+		});
+		let context = Arc::into_raw(shared);
+		let request = ffi::DnsServiceRegisterRequest {
+			version: ffi::DNS_QUERY_REQUEST_VERSION1,
+			interface_index,
+			instance,
+			callback: register_callback,
+			context: context as *mut c_void,
+			credentials: null_mut(),
+			unicast_enabled: 0,
+		};
+		if let Err(e) = check_pending(unsafe { ffi::DnsServiceRegister(&request, null_mut()) }) {
+			unsafe {
+				drop(Arc::from_raw(context));
+				ffi::DnsServiceFreeInstance(instance);
+			}
+			return Err(e);
+		}
+		let instance = Instance(instance);
+		Ok(Operation(Some(Box::new(move || deregister(interface_index, instance.0)))))
+	})?;
+	Ok(Some(stream))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn instance_labels() {
+		assert_eq!(instance_label("Web.Server._http._tcp.local.", "_http._tcp"), "Web.Server");
+		assert_eq!(instance_label("web._HTTP._tcp.LOCAL", "_http._tcp"), "web");
+		assert_eq!(instance_label("web.other.local", "_http._tcp"), "web");
+		assert_eq!(instance_label("_http._tcp.local", "_http._tcp"), "_http");
+		// suffix length ends inside a multi-byte character
+		assert_eq!(instance_label("Xé_http._tcp.local", "_http._tcp"), "Xé_http");
+		assert_eq!(instance_label("Müller._http._tcp.local", "_http._tcp"), "Müller");
+		assert_eq!(instance_label("é", "_http._tcp"), "é");
+	}
+}